@@ -42,7 +42,8 @@ struct Config {
     /// The log level.
     log_level: String,
     /// The path to this node's data directory. The Raft log is stored in
-    /// the file "raft", and the SQL state machine in "sql".
+    /// the file "raft", the SQL state machine in "sql", and temporary query
+    /// spill files in the directory "tmp".
     data_dir: String,
     /// The Raft storage engine: bitcask or memory.
     storage_raft: String,
@@ -56,6 +57,10 @@ struct Config {
     compact_threshold: f64,
     /// The minimum bytes of garbage before triggering compaction.
     compact_min_bytes: u64,
+    /// The memory budget for each SQL statement, in bytes. Beyond it, sorts,
+    /// aggregates, and hash joins spill to temporary files in the data
+    /// directory under "tmp". 0 means unlimited.
+    query_memory_budget: usize,
 }
 
 impl Config {
@@ -72,6 +77,7 @@ impl Config {
             .set_default("fsync", true)?
             .set_default("compact_threshold", 0.2)?
             .set_default("compact_min_bytes", 1_000_000)?
+            .set_default("query_memory_budget", 64 * 1024 * 1024)?
             .add_source(config::File::with_name(file))
             .add_source(config::Environment::with_prefix("TOYDB"))
             .build()?
@@ -132,8 +138,14 @@ impl Command {
             name => return errinput!("invalid SQL storage engine {name}"),
         };
 
+        // Set up the SQL memory budget, spilling to the tmp directory.
+        let budget = match cfg.query_memory_budget {
+            0 => sql::execution::MemoryBudget::unlimited(),
+            limit => sql::execution::MemoryBudget::new(limit, datadir.join("tmp"))?,
+        };
+
         // Start the server.
-        Server::new(cfg.id, cfg.peers, raft_log, raft_state)?
+        Server::new(cfg.id, cfg.peers, raft_log, raft_state, budget)?
             .serve(&cfg.listen_raft, &cfg.listen_sql)
     }
 }
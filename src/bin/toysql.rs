@@ -182,14 +182,28 @@ Transactions: {active_txns} active, {versions} total
             },
             Commit { version } => println!("Committed transaction {version}"),
             Rollback { version } => println!("Rolled back transaction {version}"),
+            Prepare { name } => println!("Prepared statement {name}"),
+            Deallocate { name } => println!("Deallocated statement {name}"),
             Insert { count } => println!("Inserted {count} rows"),
             Delete { count } => println!("Deleted {count} rows"),
             Update { count } => println!("Updated {count} rows"),
+            AlterTable { name } => println!("Altered table {name}"),
             CreateTable { name } => println!("Created table {name}"),
             DropTable { name, existed } => match existed {
                 true => println!("Dropped table {name}"),
                 false => println!("Table {name} does not exist"),
             },
+            CreateIndex { name } => println!("Created index {name}"),
+            DropIndex { name, existed } => match existed {
+                true => println!("Dropped index {name}"),
+                false => println!("Index {name} does not exist"),
+            },
+            CreateSequence { name } => println!("Created sequence {name}"),
+            DropSequence { name, existed } => match existed {
+                true => println!("Dropped sequence {name}"),
+                false => println!("Sequence {name} does not exist"),
+            },
+            Analyze { tables } => tables.iter().for_each(|t| println!("Analyzed table {t}")),
             Explain(plan) => println!("{plan}"),
             Select { columns, rows } => {
                 if self.show_headers {
@@ -22,7 +22,7 @@ use rand::rngs::StdRng;
 use rand::seq::IndexedRandom as _;
 
 use toydb::error::Result;
-use toydb::sql::types::{Row, Rows};
+use toydb::sql::types::{Row, Rows, Value};
 use toydb::{Client, StatementResult};
 
 fn main() {
@@ -432,21 +432,22 @@ impl Workload for Bank {
                     balance INTEGER NOT NULL
                 )",
         )?;
-        client.execute(&format!(
+        let insert = client.prepare(&format!(
             "INSERT INTO customer VALUES {}",
-            (1..=self.customers)
-                .map(|id| {
-                    let name = [
-                        *petnames.adverbs.choose(rng).expect("no adverb"),
-                        *petnames.adjectives.choose(rng).expect("no adjective"),
-                        *petnames.nouns.choose(rng).expect("no noun"),
-                    ]
-                    .join(" ");
-                    (id, name)
-                })
-                .map(|(id, name)| format!("({}, '{}')", id, name))
-                .join(", ")
+            (1..=self.customers).map(|id| format!("(${}, ${})", 2 * id - 1, 2 * id)).join(", ")
         ))?;
+        let params = (1..=self.customers)
+            .flat_map(|id| {
+                let name = [
+                    *petnames.adverbs.choose(rng).expect("no adverb"),
+                    *petnames.adjectives.choose(rng).expect("no adjective"),
+                    *petnames.nouns.choose(rng).expect("no noun"),
+                ]
+                .join(" ");
+                [Value::Integer(id as i64), Value::String(name)]
+            })
+            .collect_vec();
+        client.execute_prepared(&insert, &params)?;
         client.execute(&format!(
             "INSERT INTO account VALUES {}",
             (1..=self.customers)
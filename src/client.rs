@@ -9,7 +9,7 @@ use crate::errdata;
 use crate::error::{Error, Result};
 use crate::server::{Request, Response, Status};
 use crate::sql::execution::StatementResult;
-use crate::sql::types::Table;
+use crate::sql::types::{Table, Value};
 use crate::storage::mvcc;
 
 /// A toyDB client. Connects to a server via TCP and submits SQL statements and
@@ -45,14 +45,46 @@ impl Client {
             Response::Execute(result) => result,
             response => return errdata!("unexpected response {response:?}"),
         };
-        // Update the transaction state.
-        match &result {
+        self.update_txn(&result);
+        Ok(result)
+    }
+
+    /// Prepares a SQL statement with parameter placeholders ($1 or ?). The
+    /// server caches the statement's plan for the session, until the schema
+    /// changes.
+    pub fn prepare(&mut self, statement: &str) -> Result<PreparedStatement> {
+        match self.request(Request::Prepare(statement.to_string()))? {
+            Response::Prepare(parameters) => {
+                Ok(PreparedStatement { statement: statement.to_string(), parameters })
+            }
+            response => errdata!("unexpected response {response:?}"),
+        }
+    }
+
+    /// Executes a prepared statement with the given parameter values. The
+    /// values are bound to the plan, not interpolated into the SQL text.
+    pub fn execute_prepared(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+    ) -> Result<StatementResult> {
+        let request = Request::ExecutePrepared(statement.statement.clone(), params.to_vec());
+        let result = match self.request(request)? {
+            Response::Execute(result) => result,
+            response => return errdata!("unexpected response {response:?}"),
+        };
+        self.update_txn(&result);
+        Ok(result)
+    }
+
+    /// Updates the transaction state from a statement result.
+    fn update_txn(&mut self, result: &StatementResult) {
+        match result {
             StatementResult::Begin(state) => self.txn = Some(state.clone()),
             StatementResult::Commit { .. } => self.txn = None,
             StatementResult::Rollback { .. } => self.txn = None,
             _ => {}
         }
-        Ok(result)
     }
 
     /// Fetches a table schema.
@@ -118,3 +150,19 @@ impl Client {
         }
     }
 }
+
+/// A statement prepared via Client::prepare().
+#[derive(Clone, Debug)]
+pub struct PreparedStatement {
+    /// The statement text.
+    statement: String,
+    /// The number of parameters.
+    parameters: usize,
+}
+
+impl PreparedStatement {
+    /// Returns the number of parameters.
+    pub fn parameters(&self) -> usize {
+        self.parameters
+    }
+}
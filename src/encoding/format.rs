@@ -181,12 +181,22 @@ impl Formatter for SQL {
         };
         match key {
             sql::engine::Key::Table(name) => format!("sql:Table({name})"),
-            sql::engine::Key::Index(table, column, value) => {
-                format!("sql:Index({table}.{column}, {value})")
+            sql::engine::Key::Index(table, index, values) => {
+                format!("sql:Index({table}.{index}, {})", sql::types::format_tuple(&values))
             }
             sql::engine::Key::Row(table, id) => {
-                format!("sql:Row({table}, {id})")
+                format!("sql:Row({table}, {})", sql::types::format_tuple(&id))
             }
+            sql::engine::Key::TableVersion(table, version) => {
+                format!("sql:TableVersion({table}, {version})")
+            }
+            sql::engine::Key::Statistics(table) => format!("sql:Statistics({table})"),
+            sql::engine::Key::Sequence(name) => format!("sql:Sequence({name})"),
+            sql::engine::Key::SequenceValue(name, version) => {
+                format!("sql:SequenceValue({name}, {version})")
+            }
+            sql::engine::Key::CatalogVersion => "sql:CatalogVersion".to_string(),
+            sql::engine::Key::CatalogVersionCounter => "sql:CatalogVersionCounter".to_string(),
         }
     }
 
@@ -209,16 +219,56 @@ impl Formatter for SQL {
                 Self::schema(table)
             }
             sql::engine::Key::Row(_, _) => {
-                let Ok(row) = bincode::deserialize::<sql::types::Row>(value) else {
+                let Ok((version, row)) = bincode::deserialize::<(u64, sql::types::Row)>(value)
+                else {
+                    return Raw::bytes(value);
+                };
+                match version {
+                    0 => Self::values(row),
+                    version => format!("{} (v{version})", Self::values(row)),
+                }
+            }
+            sql::engine::Key::TableVersion(_, _) => {
+                match bincode::deserialize::<sql::engine::LayoutChange>(value) {
+                    Ok(sql::engine::LayoutChange::AddColumn(value)) => {
+                        format!("add column {value}")
+                    }
+                    Ok(sql::engine::LayoutChange::DropColumn(index)) => {
+                        format!("drop column {index}")
+                    }
+                    Err(_) => Raw::bytes(value),
+                }
+            }
+            sql::engine::Key::Statistics(_) => {
+                let Ok(stats) = bincode::deserialize::<sql::types::Statistics>(value) else {
+                    return Raw::bytes(value);
+                };
+                format!("{} rows", stats.rows)
+            }
+            sql::engine::Key::Sequence(_) => {
+                let Ok(sequence) = bincode::deserialize::<sql::types::Sequence>(value) else {
                     return Raw::bytes(value);
                 };
-                Self::values(row)
+                sequence.to_string()
+            }
+            sql::engine::Key::SequenceValue(_, _) => {
+                let Ok(value) = bincode::deserialize::<i64>(value) else {
+                    return Raw::bytes(value);
+                };
+                value.to_string()
+            }
+            sql::engine::Key::CatalogVersion | sql::engine::Key::CatalogVersionCounter => {
+                let Ok(version) = bincode::deserialize::<u64>(value) else {
+                    return Raw::bytes(value);
+                };
+                version.to_string()
             }
             sql::engine::Key::Index(_, _, _) => {
-                let Ok(index) = bincode::deserialize::<BTreeSet<sql::types::Value>>(value) else {
+                let Ok(index) = bincode::deserialize::<BTreeSet<Vec<sql::types::Value>>>(value)
+                else {
                     return Raw::bytes(value);
                 };
-                Self::values(index)
+                index.iter().map(|id| sql::types::format_tuple(id)).join(",")
             }
         }
     }
@@ -245,8 +295,15 @@ impl Formatter for SQLCommand {
             | sql::engine::Write::Delete { txn, .. }
             | sql::engine::Write::Insert { txn, .. }
             | sql::engine::Write::Update { txn, .. }
+            | sql::engine::Write::NextSequenceValue { txn, .. }
+            | sql::engine::Write::AlterTable { txn, .. }
+            | sql::engine::Write::Analyze { txn, .. }
             | sql::engine::Write::CreateTable { txn, .. }
-            | sql::engine::Write::DropTable { txn, .. } => Some(txn),
+            | sql::engine::Write::DropTable { txn, .. }
+            | sql::engine::Write::CreateIndex { txn, .. }
+            | sql::engine::Write::DropIndex { txn, .. }
+            | sql::engine::Write::CreateSequence { txn, .. }
+            | sql::engine::Write::DropSequence { txn, .. } => Some(txn),
         };
         let fmttxn =
             txn.filter(|t| !t.read_only).map_or("".to_string(), |t| format!("t{} ", t.version));
@@ -256,7 +313,10 @@ impl Formatter for SQLCommand {
             sql::engine::Write::Commit(_) => "COMMIT".to_string(),
             sql::engine::Write::Rollback(_) => "ROLLBACK".to_string(),
             sql::engine::Write::Delete { table, ids, .. } => {
-                format!("DELETE {table} {}", ids.iter().map(|id| id.to_string()).join(","))
+                format!(
+                    "DELETE {table} {}",
+                    ids.iter().map(|id| sql::types::format_tuple(id)).join(",")
+                )
             }
             sql::engine::Write::Insert { table, rows, .. } => {
                 format!(
@@ -266,10 +326,28 @@ impl Formatter for SQLCommand {
             }
             sql::engine::Write::Update { table, rows, .. } => format!(
                 "UPDATE {table} {}",
-                rows.into_iter().map(|(id, row)| format!("{id}→({})", SQL::values(row))).join(" ")
+                rows.into_iter()
+                    .map(|(id, row)| format!(
+                        "{}→({})",
+                        sql::types::format_tuple(&id),
+                        SQL::values(row)
+                    ))
+                    .join(" ")
             ),
+            sql::engine::Write::NextSequenceValue { name, .. } => format!("NEXTVAL {name}"),
+            sql::engine::Write::AlterTable { table, alteration, .. } => {
+                format!("ALTER TABLE {table} {alteration}")
+            }
+            sql::engine::Write::Analyze { table, .. } => format!("ANALYZE {table}"),
             sql::engine::Write::CreateTable { schema, .. } => SQL::schema(schema),
             sql::engine::Write::DropTable { table, .. } => format!("DROP TABLE {table}"),
+            sql::engine::Write::CreateIndex { name, table, columns, unique, .. } => {
+                let unique = if unique { "UNIQUE " } else { "" };
+                format!("CREATE {unique}INDEX {name} ON {table} ({})", columns.join(", "))
+            }
+            sql::engine::Write::DropIndex { name, .. } => format!("DROP INDEX {name}"),
+            sql::engine::Write::CreateSequence { sequence, .. } => sequence.to_string(),
+            sql::engine::Write::DropSequence { name, .. } => format!("DROP SEQUENCE {name}"),
         };
         format!("{fmttxn}{fmtcommand}")
     }
@@ -11,6 +11,6 @@ pub mod server;
 pub mod sql;
 pub mod storage;
 
-pub use client::Client;
+pub use client::{Client, PreparedStatement};
 pub use server::Server;
 pub use sql::execution::StatementResult;
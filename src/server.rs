@@ -13,8 +13,8 @@ use crate::error::Result;
 use crate::raft;
 use crate::sql;
 use crate::sql::engine::{Catalog as _, Engine as _};
-use crate::sql::execution::StatementResult;
-use crate::sql::types::{Row, Table};
+use crate::sql::execution::{MemoryBudget, StatementResult};
+use crate::sql::types::{Row, Table, Value};
 use crate::storage;
 
 /// The outbound Raft peer channel capacity. This buffers messages when a Raft
@@ -41,6 +41,8 @@ pub struct Server {
     node_rx: Receiver<raft::Envelope>,
     /// Raft peer IDs and addresses.
     peers: HashMap<raft::NodeID, String>,
+    /// The memory budget for each SQL statement.
+    budget: MemoryBudget,
 }
 
 impl Server {
@@ -50,6 +52,7 @@ impl Server {
         peers: HashMap<raft::NodeID, String>,
         raft_log: raft::Log,
         raft_state: Box<dyn raft::State>,
+        budget: MemoryBudget,
     ) -> Result<Self> {
         let (node_tx, node_rx) = crossbeam::channel::unbounded();
         let node = raft::Node::new(
@@ -60,7 +63,7 @@ impl Server {
             node_tx,
             raft::Options::default(),
         )?;
-        Ok(Self { node, peers, node_rx })
+        Ok(Self { node, peers, node_rx, budget })
     }
 
     /// Serves Raft and SQL requests indefinitely. Consumes the server.
@@ -103,7 +106,7 @@ impl Server {
 
             // Serve inbound SQL connections.
             let sql_engine = sql::engine::Raft::new(raft_request_tx);
-            s.spawn(move || Self::sql_accept(id, sql_listener, sql_engine));
+            s.spawn(move || Self::sql_accept(id, sql_listener, sql_engine, self.budget));
         });
 
         Ok(())
@@ -249,7 +252,12 @@ impl Server {
     }
 
     /// Accepts new SQL client connections and spawns session threads for them.
-    fn sql_accept(id: raft::NodeID, listener: TcpListener, sql_engine: sql::engine::Raft) {
+    fn sql_accept(
+        id: raft::NodeID,
+        listener: TcpListener,
+        sql_engine: sql::engine::Raft,
+        budget: MemoryBudget,
+    ) {
         std::thread::scope(|s| {
             loop {
                 let (socket, peer) = match listener.accept() {
@@ -259,7 +267,8 @@ impl Server {
                         continue;
                     }
                 };
-                let session = sql_engine.session();
+                let mut session = sql_engine.session();
+                session.set_memory_budget(budget.clone());
                 s.spawn(move || {
                     debug!("Client {peer} connected");
                     match Self::sql_session(id, socket, session) {
@@ -298,6 +307,10 @@ impl Server {
                     .status()
                     .map(|s| Status { server: id, raft: s.raft, mvcc: s.mvcc })
                     .map(Response::Status),
+                Request::Prepare(statement) => session.prepare(&statement).map(Response::Prepare),
+                Request::ExecutePrepared(statement, params) => {
+                    session.execute_prepared(&statement, params).map(Response::Execute)
+                }
             };
 
             // Process response.
@@ -320,6 +333,11 @@ pub enum Request {
     ListTables,
     /// Returns server status.
     Status,
+    /// Prepares a SQL statement with parameter placeholders, caching its plan.
+    /// Returns the number of parameters.
+    Prepare(String),
+    /// Executes a prepared SQL statement with the given parameter values.
+    ExecutePrepared(String, Vec<Value>),
 }
 
 impl encoding::Value for Request {}
@@ -332,6 +350,7 @@ pub enum Response {
     GetTable(Table),
     ListTables(Vec<String>),
     Status(Status),
+    Prepare(usize),
 }
 
 impl encoding::Value for Response {}
@@ -1,9 +1,12 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::errinput;
 use crate::error::Result;
 use crate::sql::execution::Session;
-use crate::sql::types::{Expression, Row, Rows, Table, Value};
+use crate::sql::types::{Alteration, Expression, Row, Rows, Sequence, Statistics, Table, Value};
 use crate::storage::mvcc;
 
 /// A SQL engine. This provides low-level CRUD (create, read, update, delete)
@@ -37,36 +40,130 @@ pub trait Engine<'a>: Sized {
 pub trait Transaction: Catalog {
     /// The transaction's internal MVCC state.
     fn state(&self) -> &mvcc::TransactionState;
+    /// The transaction's I/O counters, used by EXPLAIN ANALYZE.
+    fn counters(&self) -> &IoCounters;
 
     /// Commits the transaction.
     fn commit(self) -> Result<()>;
     /// Rolls back the transaction.
     fn rollback(self) -> Result<()>;
 
-    /// Deletes table rows by primary key, if they exist.
-    fn delete(&self, table: &str, ids: &[Value]) -> Result<()>;
+    /// Deletes table rows by primary key, if they exist. Primary keys are given
+    /// as a vector of the key column values.
+    fn delete(&self, table: &str, ids: &[Vec<Value>]) -> Result<()>;
     /// Fetches table rows by primary key, if they exist.
-    fn get(&self, table: &str, ids: &[Value]) -> Result<Vec<Row>>;
+    fn get(&self, table: &str, ids: &[Vec<Value>]) -> Result<Vec<Row>>;
     /// Inserts new table rows.
     fn insert(&self, table: &str, rows: Vec<Row>) -> Result<()>;
-    /// Looks up a set of primary keys by index values. BTreeSet for testing.
-    fn lookup_index(&self, table: &str, column: &str, values: &[Value]) -> Result<BTreeSet<Value>>;
+    /// Looks up a set of primary keys by secondary index values. The values
+    /// can be a prefix of the index columns. BTreeSet for testing.
+    fn lookup_index(
+        &self,
+        table: &str,
+        index: &str,
+        values: &[Vec<Value>],
+    ) -> Result<BTreeSet<Vec<Value>>>;
+    /// Draws the next value from a sequence. This is not transactional: the
+    /// value is never reused even if the transaction rolls back, and
+    /// concurrent transactions don't conflict. It's also allowed in read-only
+    /// transactions. Errors if the sequence does not exist or overflows.
+    fn next_sequence_value(&self, sequence: &str) -> Result<i64>;
     /// Scans a table's rows, optionally applying the given filter.
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Rows>;
+    /// Scans a range of table rows by primary key, in primary key order. The
+    /// bounds can be a prefix of the primary key columns, in which case they
+    /// include or exclude all keys with that prefix.
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Rows>;
+    /// Scans a range of secondary index entries, returning the primary keys
+    /// in index order, and in primary key order within each entry. The bounds
+    /// can be a prefix of the index columns, like for scan_range().
+    fn scan_index_range(
+        &self,
+        table: &str,
+        index: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Vec<Vec<Value>>>;
     /// Updates table rows by primary key. BTreeMap for testing.
-    fn update(&self, table: &str, rows: BTreeMap<Value, Row>) -> Result<()>;
+    fn update(&self, table: &str, rows: BTreeMap<Vec<Value>, Row>) -> Result<()>;
+}
+
+/// I/O counters for a transaction: the number of row and index keys read from
+/// storage, and the number of Raft round trips. Clones share the counters, so
+/// lazy row iterators can keep counting after the call that created them.
+#[derive(Clone, Debug, Default)]
+pub struct IoCounters {
+    keys_read: Arc<AtomicU64>,
+    round_trips: Arc<AtomicU64>,
+}
+
+impl IoCounters {
+    /// Returns the number of row and index keys read from storage.
+    pub fn keys_read(&self) -> u64 {
+        self.keys_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of Raft round trips.
+    pub fn round_trips(&self) -> u64 {
+        self.round_trips.load(Ordering::Relaxed)
+    }
+
+    /// Records the given number of keys read from storage.
+    pub fn add_keys_read(&self, keys: u64) {
+        self.keys_read.fetch_add(keys, Ordering::Relaxed);
+    }
+
+    /// Records a Raft round trip.
+    pub fn add_round_trip(&self) {
+        self.round_trips.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 /// The catalog stores table schema information. It must be implemented for
-/// Transaction, and is thus fully transactional. For simplicity, it only
-/// supports creating and dropping tables -- there are no ALTER TABLE schema
-/// changes, nor CREATE INDEX.
+/// Transaction, and is thus fully transactional. It supports creating,
+/// altering, and dropping tables, creating and dropping named indexes, and
+/// creating and dropping sequences (but sequence values are not transactional,
+/// see `Transaction::next_sequence_value`).
 pub trait Catalog {
+    /// Alters a table's schema. Errors if it does not exist, or if the change
+    /// is invalid (including for existing rows). Adding or dropping columns
+    /// discards the table's statistics.
+    fn alter_table(&self, table: &str, alteration: Alteration) -> Result<()>;
+    /// Collects and stores statistics for a table (ANALYZE), replacing any
+    /// existing statistics. Errors if the table does not exist.
+    fn analyze(&self, table: &str) -> Result<Statistics>;
+    /// Returns the catalog version, which changes whenever a table schema or
+    /// index changes. Versions are never reused, even if the schema change
+    /// rolls back. Used to invalidate cached plans. Concurrent schema changes
+    /// will conflict on it, which is fine for our purposes.
+    fn catalog_version(&self) -> Result<u64>;
     /// Creates a new table. Errors if it already exists.
     fn create_table(&self, table: Table) -> Result<()>;
     /// Drops a table. Errors if it does not exist, unless if_exists is true.
     /// Returns true if the table existed and was deleted.
     fn drop_table(&self, table: &str, if_exists: bool) -> Result<bool>;
+    /// Creates a named secondary index on the given table columns, backfilling
+    /// it from existing rows. If unique is true, the column values must be
+    /// unique. Errors if the index name already exists, or if the columns
+    /// already have an index.
+    fn create_index(&self, name: &str, table: &str, columns: &[String], unique: bool)
+    -> Result<()>;
+    /// Drops a named index. Errors if it does not exist, unless if_exists is
+    /// true. Returns true if the index existed and was dropped.
+    fn drop_index(&self, name: &str, if_exists: bool) -> Result<bool>;
+    /// Creates a new sequence. Errors if it already exists.
+    fn create_sequence(&self, sequence: Sequence) -> Result<()>;
+    /// Drops a sequence. Errors if it does not exist, unless if_exists is true,
+    /// or if it's owned by a table column. Returns true if the sequence existed
+    /// and was dropped.
+    fn drop_sequence(&self, name: &str, if_exists: bool) -> Result<bool>;
+    /// Fetches a table's statistics, or None if it hasn't been analyzed.
+    fn get_statistics(&self, table: &str) -> Result<Option<Statistics>>;
+    /// Fetches a sequence, or None if it doesn't exist.
+    fn get_sequence(&self, name: &str) -> Result<Option<Sequence>>;
     /// Fetches a table schema, or None if it doesn't exist.
     fn get_table(&self, table: &str) -> Result<Option<Table>>;
     /// Returns a list of all table schemas.
@@ -76,4 +173,9 @@ pub trait Catalog {
     fn must_get_table(&self, table: &str) -> Result<Table> {
         self.get_table(table)?.ok_or_else(|| errinput!("table {table} does not exist"))
     }
+
+    /// Fetches a sequence, or errors if it does not exist.
+    fn must_get_sequence(&self, name: &str) -> Result<Sequence> {
+        self.get_sequence(name)?.ok_or_else(|| errinput!("sequence {name} does not exist"))
+    }
 }
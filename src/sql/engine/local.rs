@@ -1,33 +1,67 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Bound;
 use std::slice;
 
 use itertools::Itertools as _;
 use serde::{Deserialize, Serialize};
 
-use super::Catalog;
-use crate::encoding::{self, Key as _, Value as _};
-use crate::errinput;
+use super::{Catalog, IoCounters, Transaction as _};
+use crate::encoding::{self, Key as _, Value as _, bincode, keycode};
 use crate::error::Result;
-use crate::sql::types::{Expression, Row, Rows, Table, Value};
+use crate::sql::types::{
+    Alteration, Column, Expression, ForeignKey, Index, ReferenceAction, Row, Rows, Sequence,
+    Statistics, Table, Value, format_tuple,
+};
 use crate::storage::{self, mvcc};
+use crate::{errdata, errinput};
 
 /// SQL engine keys, using the Keycode order-preserving encoding. For
 /// simplicity, table and column names are used directly as identifiers, instead
-/// of e.g. numeric IDs. It is not possible to change table/column names, so
-/// this is fine, if somewhat inefficient.
+/// of e.g. numeric IDs. This means that renaming a table or indexed column
+/// (ALTER TABLE RENAME) must move all of its keys, which is fine for our
+/// purposes.
+///
+/// Primary keys and index values are tuples of column values, which Keycode
+/// encodes as the concatenation of the values. They're placed at the end of the
+/// key, so a prefix of the values is also a key prefix. This allows prefix
+/// scans of composite keys.
 ///
 /// Uses Cow to allow encoding borrowed values but decoding owned values.
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Key<'a> {
     /// A table schema, keyed by table name. The value is a `sql::types::Table`.
     Table(Cow<'a, str>),
-    /// A column index entry, keyed by table name, column name, and index value.
-    /// The value is a `BTreeSet` of `sql::types::Value` primary key values.
-    Index(Cow<'a, str>, Cow<'a, str>, Cow<'a, Value>),
-    /// A table row, keyed by table name and primary key value. The value is a
-    /// `sql::types::Row`.
-    Row(Cow<'a, str>, Cow<'a, Value>),
+    /// A secondary index entry, keyed by table name, index name, and index
+    /// values. Inline column indexes are named after their column. The value is
+    /// a `BTreeSet` of primary keys, as `Vec<sql::types::Value>`.
+    Index(Cow<'a, str>, Cow<'a, str>, Cow<'a, [Value]>),
+    /// A table row, keyed by table name and primary key values. The value is a
+    /// (schema version, `sql::types::Row`) tuple, see `LayoutChange`.
+    Row(Cow<'a, str>, Cow<'a, [Value]>),
+    /// A table schema version, keyed by table name and version (starting at
+    /// 1). The value is the `LayoutChange` from the previous version.
+    TableVersion(Cow<'a, str>, u64),
+    /// Table statistics, keyed by table name. The value is a
+    /// `sql::types::Statistics`, see ANALYZE.
+    Statistics(Cow<'a, str>),
+    /// A sequence definition, keyed by sequence name. The value is a
+    /// `sql::types::Sequence`.
+    Sequence(Cow<'a, str>),
+    /// A sequence's current value, keyed by sequence name and version (see
+    /// `Sequence::version`). This is an unversioned key, written outside of
+    /// transactions, and the value is a bincode-encoded i64. The key is left
+    /// behind when the sequence is dropped, since the drop may roll back.
+    SequenceValue(Cow<'a, str>, u64),
+    /// The catalog version, which is set to a new value from
+    /// CatalogVersionCounter whenever a table schema is written or deleted.
+    /// The value is a bincode-encoded u64.
+    CatalogVersion,
+    /// The catalog version counter. This is an unversioned key, written
+    /// outside of transactions, such that a catalog version is never reused
+    /// even if the schema change that drew it rolls back. The value is a
+    /// bincode-encoded u64.
+    CatalogVersionCounter,
 }
 
 impl<'a> encoding::Key<'a> for Key<'a> {}
@@ -39,14 +73,43 @@ impl<'a> encoding::Key<'a> for Key<'a> {}
 enum KeyPrefix<'a> {
     /// All table schemas.
     Table,
-    /// All column index entries, keyed by table and column name.
+    /// All secondary index entries, keyed by table and index name.
     Index(Cow<'a, str>, Cow<'a, str>),
     /// All table rows, keyed by table name.
     Row(Cow<'a, str>),
+    /// All table schema versions, keyed by table name.
+    TableVersion(Cow<'a, str>),
 }
 
 impl<'a> encoding::Key<'a> for KeyPrefix<'a> {}
 
+/// A change to a table's row layout, stored under Key::TableVersion for the
+/// schema version that introduced it (see `Table::version`).
+///
+/// Rows are stored along with the schema version they were written with. When
+/// columns are added or dropped, existing rows are not rewritten. Instead, rows
+/// written with an older schema version are upgraded when read, by applying
+/// the layout changes of all subsequent versions in order.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum LayoutChange {
+    /// A column was appended. Existing rows get the given value.
+    AddColumn(Value),
+    /// The column at the given index was removed.
+    DropColumn(usize),
+}
+
+impl encoding::Value for LayoutChange {}
+
+impl LayoutChange {
+    /// Applies the layout change to a row.
+    fn apply(&self, row: &mut Row) {
+        match self {
+            Self::AddColumn(value) => row.push(value.clone()),
+            Self::DropColumn(index) => drop(row.remove(*index)),
+        }
+    }
+}
+
 /// A SQL engine using local storage. This provides the main SQL storage logic.
 /// The Raft SQL engine dispatches to this for node-local SQL storage, executing
 /// the same writes across each nodes' instance of `Local`.
@@ -55,6 +118,24 @@ pub struct Local<E: storage::Engine + 'static> {
     pub mvcc: mvcc::MVCC<E>,
 }
 
+/// Rows referencing rows that are being deleted or having their primary key
+/// changed: a source table, with the referencing row IDs for each of its
+/// foreign keys. See Transaction::find_references().
+type ReferencingRows = (Table, Vec<(ForeignKey, BTreeSet<Vec<Value>>)>);
+
+/// Foreign key actions that are pending application, see
+/// Transaction::apply_cascades(). These are applied iteratively rather than
+/// recursively, since a long chain of cascading references could otherwise
+/// overflow the stack.
+#[derive(Default)]
+struct Cascades {
+    /// Referencing rows to apply actions to, along with the new primary key of
+    /// the referenced rows if they were updated rather than deleted.
+    pending: VecDeque<(Vec<ReferencingRows>, Option<Vec<Value>>)>,
+    /// Rows that have already been deleted, by table.
+    deleted: BTreeMap<String, BTreeSet<Vec<Value>>>,
+}
+
 impl<E: storage::Engine> Local<E> {
     /// Creates a new local SQL engine using the given storage engine.
     pub fn new(engine: E) -> Self {
@@ -99,12 +180,13 @@ impl<E: storage::Engine> super::Engine<'_> for Local<E> {
 /// A SQL transaction, wrapping an MVCC transaction.
 pub struct Transaction<E: storage::Engine + 'static> {
     txn: mvcc::Transaction<E>,
+    counters: IoCounters,
 }
 
 impl<E: storage::Engine> Transaction<E> {
     /// Creates a new SQL transaction using the given MVCC transaction.
     fn new(txn: mvcc::Transaction<E>) -> Self {
-        Self { txn }
+        Self { txn, counters: IoCounters::default() }
     }
 
     /// Returns the transaction's internal state.
@@ -112,43 +194,148 @@ impl<E: storage::Engine> Transaction<E> {
         self.txn.state()
     }
 
-    /// Fetches the matching primary keys for the given secondary index value,
+    /// Fetches the matching primary keys for the given secondary index values,
     /// or an empty set if there is none.
-    fn get_index(&self, table: &str, column: &str, value: &Value) -> Result<BTreeSet<Value>> {
-        debug_assert!(self.has_index(table, column)?, "no index on {table}.{column}");
+    fn get_index(
+        &self,
+        table: &str,
+        index: &str,
+        values: &[Value],
+    ) -> Result<BTreeSet<Vec<Value>>> {
+        debug_assert!(self.has_index(table, index)?, "no index {index} on {table}");
         Ok(self
             .txn
-            .get(&Key::Index(table.into(), column.into(), value.into()).encode())?
+            .get(&Key::Index(table.into(), index.into(), values.into()).encode())?
             .map(|v| BTreeSet::decode(&v))
             .transpose()?
             .unwrap_or_default())
     }
 
-    /// Fetches a single row by primary key, or None if it doesn't exist.
-    fn get_row(&self, table: &str, id: &Value) -> Result<Option<Row>> {
+    /// Fetches a single row by primary key, or None if it doesn't exist. The
+    /// table's layout changes must be given, see layout_changes().
+    fn get_row(&self, table: &str, changes: &[LayoutChange], id: &[Value]) -> Result<Option<Row>> {
         self.txn
             .get(&Key::Row(table.into(), id.into()).encode())?
-            .map(|v| Row::decode(&v))
+            .map(|v| Self::decode_row(&v, changes))
             .transpose()
     }
 
-    /// Returns true if a secondary index exists for the given column.
-    fn has_index(&self, table: &str, column: &str) -> Result<bool> {
+    /// Encodes a row for storage, along with the table's schema version.
+    fn encode_row(table: &Table, row: &Row) -> Vec<u8> {
+        bincode::serialize(&(table.version, row))
+    }
+
+    /// Decodes a stored row, upgrading it to the table's current schema version
+    /// using the table's layout changes (see layout_changes()).
+    fn decode_row(value: &[u8], changes: &[LayoutChange]) -> Result<Row> {
+        let (version, mut row): (u64, Row) = bincode::deserialize(value)?;
+        let Some(changes) = changes.get(version as usize..) else {
+            return errdata!("row schema version {version} is newer than the table's");
+        };
+        for change in changes {
+            change.apply(&mut row);
+        }
+        Ok(row)
+    }
+
+    /// Fetches all layout changes for a table, ordered by schema version. The
+    /// change at index i is for version i+1.
+    fn layout_changes(&self, table: &Table) -> Result<Vec<LayoutChange>> {
+        if table.version == 0 {
+            return Ok(Vec::new());
+        }
+        self.txn
+            .scan_prefix(&KeyPrefix::TableVersion((&table.name).into()).encode())
+            .map(|r| r.and_then(|(_, v)| LayoutChange::decode(&v)))
+            .collect()
+    }
+
+    /// Deletes all keys with the given prefix.
+    fn delete_prefix(&self, prefix: &[u8]) -> Result<()> {
+        let mut keys = self.txn.scan_prefix(prefix).map_ok(|(key, _)| key);
+        while let Some(key) = keys.next().transpose()? {
+            self.txn.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Moves all row, index, schema version, and statistics keys with the given
+    /// prefix to the given table name, and index keys to the given column name
+    /// if any.
+    fn move_prefix(&self, prefix: &[u8], table: &str, column: Option<&str>) -> Result<()> {
+        let mut scan = self.txn.scan_prefix(prefix);
+        while let Some((key, value)) = scan.next().transpose()? {
+            let new_key = match Key::decode(&key)? {
+                Key::Index(_, c, v) => Key::Index(table.into(), column.map_or(c, Cow::from), v),
+                Key::Row(_, id) => Key::Row(table.into(), id),
+                Key::TableVersion(_, version) => Key::TableVersion(table.into(), version),
+                Key::Statistics(_) => Key::Statistics(table.into()),
+                key @ (Key::Table(_)
+                | Key::Sequence(_)
+                | Key::SequenceValue(_, _)
+                | Key::CatalogVersion
+                | Key::CatalogVersionCounter) => panic!("unexpected key {key:?}"),
+            };
+            self.txn.set(&new_key.encode(), value)?;
+            self.txn.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Converts a range of value tuples into a range of keys under the given
+    /// key prefix, encoding tuples with the given closure. Returns None if the
+    /// range is empty.
+    ///
+    /// The bounds may be tuple prefixes, which match all keys with that
+    /// prefix. Since a key prefix sorts before all keys it's a prefix of,
+    /// included end bounds and excluded start bounds use the end of the prefix
+    /// range. Unbounded ends use the bounds of the key prefix.
+    fn key_range(
+        prefix: &[u8],
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+        key: impl Fn(Vec<Value>) -> Vec<u8>,
+    ) -> Option<(Bound<Vec<u8>>, Bound<Vec<u8>>)> {
+        let (prefix_start, prefix_end) = keycode::prefix_range(prefix);
+        let start = match range.0 {
+            Bound::Included(values) => Bound::Included(key(values)),
+            Bound::Excluded(values) => match keycode::prefix_range(&key(values)).1 {
+                Bound::Excluded(next) => Bound::Included(next),
+                _ => return None, // no keys after prefix
+            },
+            Bound::Unbounded => prefix_start,
+        };
+        let end = match range.1 {
+            Bound::Included(values) => keycode::prefix_range(&key(values)).1,
+            Bound::Excluded(values) => Bound::Excluded(key(values)),
+            Bound::Unbounded => prefix_end,
+        };
+        // The storage engines don't allow inverted ranges, which are empty.
+        let empty = match (&start, &end) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+            (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        };
+        (!empty).then_some((start, end))
+    }
+
+    /// Returns true if the given secondary index exists.
+    fn has_index(&self, table: &str, index: &str) -> Result<bool> {
         let table = self.must_get_table(table)?;
-        Ok(table.columns.iter().find(|c| c.name == column).map(|c| c.index).unwrap_or(false))
+        Ok(table.secondary_indexes().iter().any(|i| i.name == index))
     }
 
-    /// Stores a secondary index entry for the given column value, replacing the
+    /// Stores a secondary index entry for the given index values, replacing the
     /// existing entry if any.
     fn set_index(
         &self,
         table: &str,
-        column: &str,
-        value: &Value,
-        ids: BTreeSet<Value>,
+        index: &str,
+        values: &[Value],
+        ids: BTreeSet<Vec<Value>>,
     ) -> Result<()> {
-        debug_assert!(self.has_index(table, column)?, "no index on {table}.{column}");
-        let key = Key::Index(table.into(), column.into(), value.into()).encode();
+        debug_assert!(self.has_index(table, index)?, "no index {index} on {table}");
+        let key = Key::Index(table.into(), index.into(), values.into()).encode();
         if ids.is_empty() {
             self.txn.delete(&key)?;
         } else {
@@ -157,85 +344,425 @@ impl<E: storage::Engine> Transaction<E> {
         Ok(())
     }
 
-    /// Returns all tables referencing a table, as (table, column index) pairs.
-    /// This includes any references from the table itself.
-    fn table_references(&self, table: &str) -> Result<Vec<(Table, Vec<usize>)>> {
+    /// Adds a column to a table (ALTER TABLE ADD COLUMN). Existing rows aren't
+    /// rewritten, but are given the column's default value via a new schema
+    /// version. Any secondary index is backfilled, and the existing rows are
+    /// validated with the new column value.
+    fn add_column(&self, mut table: Table, column: Column) -> Result<()> {
+        let rows: Vec<Row> = self.scan(&table.name, None)?.try_collect()?;
+        let value = match &column.default {
+            Some(value) => value.clone(),
+            None if rows.is_empty() => Value::Null, // unused
+            None => return errinput!("column {} requires a default value", column.name),
+        };
+
+        if let Some(sequence) = &column.sequence {
+            self.create_sequence(Sequence::new(sequence))?;
+        }
+        table.columns.push(column);
+        table.version += 1;
+        table.validate(self)?;
+        let change = LayoutChange::AddColumn(value.clone());
+        self.txn.set(
+            &Key::TableVersion((&table.name).into(), table.version).encode(),
+            change.encode(),
+        )?;
+        self.set_table(&table)?;
+
+        let column = table.columns.last().expect("no columns");
+        if column.index && !rows.is_empty() {
+            let ids = rows.iter().map(|row| table.primary_key_values(row)).collect();
+            self.set_index(&table.name, &column.name, slice::from_ref(&value), ids)?;
+        }
+        for mut row in rows {
+            change.apply(&mut row);
+            table.validate_row(&row, true, self)?;
+        }
+        Ok(())
+    }
+
+    /// Drops a column from a table (ALTER TABLE DROP COLUMN), along with any
+    /// secondary indexes, foreign keys, and check constraints that include it.
+    /// Existing rows aren't rewritten, but the column is removed when they're
+    /// read via a new schema version.
+    fn drop_column(&self, mut table: Table, name: &str) -> Result<()> {
+        let index = table.column_index(name)?;
+        if table.primary_key.contains(&index) {
+            return errinput!("can't drop primary key column {name}");
+        }
+        let dropped = table
+            .secondary_indexes()
+            .into_iter()
+            .filter(|i| i.columns.contains(&index))
+            .map(|i| i.name)
+            .collect_vec();
+        let column = table.columns.remove(index);
+        table.indexes.retain(|i| !i.columns.contains(&index));
+        table.foreign_keys.retain(|fk| !fk.columns.contains(&index));
+        table.checks.retain(|c| !c.expression.contains(&|e| e == &Expression::Column(index)));
+        let shift = |columns: &mut Vec<usize>| {
+            columns.iter_mut().filter(|c| **c > index).for_each(|c| *c -= 1);
+        };
+        shift(&mut table.primary_key);
+        table.indexes.iter_mut().for_each(|i| shift(&mut i.columns));
+        table.foreign_keys.iter_mut().for_each(|fk| shift(&mut fk.columns));
+        for check in &mut table.checks {
+            check.expression =
+                check.expression.clone().map_column(|c| if c > index { c - 1 } else { c });
+        }
+        table.version += 1;
+        table.validate(self)?;
+
+        for index in dropped {
+            self.delete_prefix(&KeyPrefix::Index((&table.name).into(), index.into()).encode())?;
+        }
+        if let Some(sequence) = column.sequence {
+            self.txn.delete(&Key::Sequence(sequence.into()).encode())?;
+        }
+        self.txn.set(
+            &Key::TableVersion((&table.name).into(), table.version).encode(),
+            LayoutChange::DropColumn(index).encode(),
+        )?;
+        self.set_table(&table)
+    }
+
+    /// Renames a column (ALTER TABLE RENAME COLUMN). Secondary index entries are
+    /// keyed by column name, so they're moved to the new name.
+    fn rename_column(&self, mut table: Table, from: &str, to: String) -> Result<()> {
+        let index = table.column_index(from)?;
+        table.columns[index].name = to.clone();
+        table.validate(self)?;
+
+        if table.columns[index].index {
+            let prefix = KeyPrefix::Index((&table.name).into(), from.into()).encode();
+            self.move_prefix(&prefix, &table.name, Some(&to))?;
+        }
+        self.set_table(&table)
+    }
+
+    /// Renames a table (ALTER TABLE RENAME TO). Rows, index entries, and schema
+    /// versions are keyed by table name, so they're moved to the new name.
+    /// Foreign key references to the table are updated.
+    fn rename_table(&self, mut table: Table, to: String) -> Result<()> {
+        if self.get_table(&to)?.is_some() {
+            return errinput!("table {to} already exists");
+        }
+        let from = std::mem::replace(&mut table.name, to.clone());
+
+        // Update references from the table itself, and from other tables.
+        table.rename_references(&from, &to);
+        table.validate(self)?;
+        for (mut source, _) in self.table_references(&from)? {
+            if source.name == from {
+                continue;
+            }
+            source.rename_references(&from, &to);
+            self.set_table(&source)?;
+        }
+
+        // Move the rows, index entries, schema versions, and statistics.
+        self.move_prefix(&Key::Statistics(from.as_str().into()).encode(), &to, None)?;
+        self.move_prefix(&KeyPrefix::Row(from.as_str().into()).encode(), &to, None)?;
+        self.move_prefix(&KeyPrefix::TableVersion(from.as_str().into()).encode(), &to, None)?;
+        for index in table.secondary_indexes() {
+            let prefix = KeyPrefix::Index(from.as_str().into(), index.name.into()).encode();
+            self.move_prefix(&prefix, &to, None)?;
+        }
+
+        self.delete_table(&from)?;
+        self.set_table(&table)
+    }
+
+    /// Writes a table schema, incrementing the catalog version.
+    fn set_table(&self, table: &Table) -> Result<()> {
+        self.increment_catalog_version()?;
+        self.txn.set(&Key::Table((&table.name).into()).encode(), table.encode())
+    }
+
+    /// Deletes a table schema, incrementing the catalog version.
+    fn delete_table(&self, name: &str) -> Result<()> {
+        self.increment_catalog_version()?;
+        self.txn.delete(&Key::Table(name.into()).encode())
+    }
+
+    /// Sets a new catalog version, see Catalog::catalog_version(). The version
+    /// is drawn from an unversioned counter, such that it is never reused.
+    fn increment_catalog_version(&self) -> Result<()> {
+        let version = self.txn.update_unversioned(&Key::CatalogVersionCounter.encode(), |v| {
+            let version = v.map(|v| bincode::deserialize::<u64>(&v)).transpose()?;
+            Ok(bincode::serialize(&(version.unwrap_or(0) + 1)))
+        })?;
+        self.txn.set(&Key::CatalogVersion.encode(), version)
+    }
+
+    /// Returns the table containing the given named index, if any. Index names
+    /// are unique across all tables.
+    fn index_table(&self, name: &str) -> Result<Option<Table>> {
+        Ok(self.list_tables()?.into_iter().find(|t| t.indexes.iter().any(|i| i.name == name)))
+    }
+
+    /// Returns all tables referencing a table, along with their foreign keys to
+    /// it. This includes any references from the table itself.
+    fn table_references(&self, table: &str) -> Result<Vec<(Table, Vec<ForeignKey>)>> {
         Ok(self
             .list_tables()?
             .into_iter()
             .map(|t| {
                 let references = t
-                    .columns
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, c)| c.references.as_deref() == Some(table))
-                    .map(|(i, _)| i)
+                    .references()
+                    .into_iter()
+                    .filter(|fk| fk.reference.table == table)
                     .collect_vec();
                 (t, references)
             })
             .filter(|(_, references)| !references.is_empty())
             .collect())
     }
-}
 
-impl<E: storage::Engine> super::Transaction for Transaction<E> {
-    fn state(&self) -> &mvcc::TransactionState {
-        self.txn.state()
-    }
-
-    fn commit(self) -> Result<()> {
-        self.txn.commit()
-    }
-
-    fn rollback(self) -> Result<()> {
-        self.txn.rollback()
+    /// Looks up the primary keys of the source table's rows whose foreign key
+    /// references any of the given primary keys, via the source's primary key
+    /// or a secondary index on the foreign key columns. Falls back to a table
+    /// scan if there is no such index.
+    fn lookup_references(
+        &self,
+        source: &Table,
+        foreign_key: &ForeignKey,
+        ids: &[Vec<Value>],
+    ) -> Result<BTreeSet<Vec<Value>>> {
+        // References target the primary key, so the referenced row IDs are also
+        // the foreign key values.
+        if foreign_key.columns == source.primary_key {
+            let rows = self.get(&source.name, ids)?;
+            return Ok(rows.iter().map(|row| source.primary_key_values(row)).collect());
+        }
+        let indexes = source.secondary_indexes();
+        if let Some(index) = indexes.iter().find(|i| i.columns.starts_with(&foreign_key.columns)) {
+            return self.lookup_index(&source.name, &index.name, ids);
+        }
+        let ids: BTreeSet<&Vec<Value>> = ids.iter().collect();
+        let mut source_ids = BTreeSet::new();
+        let mut rows = self.scan(&source.name, None)?;
+        while let Some(row) = rows.next().transpose()? {
+            if foreign_key.values(&row).is_some_and(|values| ids.contains(&values)) {
+                source_ids.insert(source.primary_key_values(&row));
+            }
+        }
+        Ok(source_ids)
     }
 
-    fn delete(&self, table: &str, ids: &[Value]) -> Result<()> {
-        let table = self.must_get_table(table)?;
-        let indexes = table.columns.iter().enumerate().filter(|(_, c)| c.index).collect_vec();
-
-        // Check for foreign key references to the deleted rows.
-        for (source, refs) in self.table_references(&table.name)? {
-            let self_reference = source.name == table.name;
-            for i in refs {
-                let column = &source.columns[i];
-                let mut source_ids = if i == source.primary_key {
-                    // If the reference is from a primary key column, do a lookup.
-                    self.get(&source.name, ids)?
-                        .into_iter()
-                        .map(|row| row.into_iter().nth(i).expect("short row"))
-                        .collect()
-                } else {
-                    // Otherwise (commonly), do a secondary index lookup.
-                    // All foreign keys have a secondary index.
-                    self.lookup_index(&source.name, &column.name, ids)?
-                };
-                // We can ignore any references between the deleted rows,
-                // including a row referencing itself.
-                if self_reference {
+    /// Finds rows referencing the given rows of a table, before the rows are
+    /// deleted or (if update is true) have their primary key changed. Errors if
+    /// a referencing foreign key has a RESTRICT action. Otherwise, returns the
+    /// referencing row IDs for each source table and foreign key, to apply the
+    /// foreign key actions to once the rows have been written, see
+    /// apply_references().
+    ///
+    /// References between the given rows are ignored, including a row
+    /// referencing itself.
+    fn find_references(
+        &self,
+        table: &Table,
+        ids: &[Vec<Value>],
+        update: bool,
+    ) -> Result<Vec<ReferencingRows>> {
+        let mut references = Vec::new();
+        for (source, foreign_keys) in self.table_references(&table.name)? {
+            let mut source_references = Vec::new();
+            for foreign_key in foreign_keys {
+                let mut source_ids = self.lookup_references(&source, &foreign_key, ids)?;
+                if source.name == table.name {
                     for id in ids {
                         source_ids.remove(id);
                     }
                 }
-                // Error if the delete would violate referential integrity.
-                if let Some(source_id) = source_ids.first() {
+                let Some(source_id) = source_ids.first() else {
+                    continue;
+                };
+                let reference = &foreign_key.reference;
+                let action = if update { reference.on_update } else { reference.on_delete };
+                // Error if the write would violate referential integrity.
+                if action == ReferenceAction::Restrict {
                     let table = source.name;
-                    let column = &source.columns[source.primary_key].name;
+                    let mut columns = source.primary_key.iter().map(|&i| &source.columns[i].name);
+                    let column = match source.primary_key.len() {
+                        1 => columns.join(""),
+                        _ => format!("({})", columns.join(", ")),
+                    };
+                    let source_id = format_tuple(source_id);
                     return errinput!("row referenced by {table}.{column}={source_id}");
                 }
+                source_references.push((foreign_key, source_ids));
+            }
+            if !source_references.is_empty() {
+                references.push((source, source_references));
             }
         }
+        Ok(references)
+    }
 
+    /// Applies pending foreign key actions until there are none left.
+    fn apply_cascades(&self, mut cascades: Cascades) -> Result<()> {
+        while let Some((references, new_id)) = cascades.pending.pop_front() {
+            self.apply_references(references, new_id.as_deref(), &mut cascades)?;
+        }
+        Ok(())
+    }
+
+    /// Applies foreign key actions to the referencing rows returned by
+    /// find_references(), once the referenced rows have been deleted or (if
+    /// new_id is given) had their primary key changed to new_id. Any further
+    /// actions on the referencing rows' own references are added to the
+    /// cascades.
+    ///
+    /// Cascading deletes are applied first. Then, all changes to a referencing
+    /// row are applied as a single update, since the row is validated against
+    /// all of its references.
+    fn apply_references(
+        &self,
+        references: Vec<ReferencingRows>,
+        new_id: Option<&[Value]>,
+        cascades: &mut Cascades,
+    ) -> Result<()> {
+        for (source, foreign_keys) in references {
+            let action = |foreign_key: &ForeignKey| match new_id {
+                Some(_) => foreign_key.reference.on_update,
+                None => foreign_key.reference.on_delete,
+            };
+
+            // Skip rows that have already been deleted.
+            let deleted = cascades.deleted.get(&source.name);
+            let mut deletes = BTreeSet::new();
+            for (foreign_key, ids) in &foreign_keys {
+                if action(foreign_key) == ReferenceAction::Cascade && new_id.is_none() {
+                    let ids = ids.iter().filter(|id| !deleted.is_some_and(|d| d.contains(*id)));
+                    deletes.extend(ids.cloned());
+                }
+            }
+            if !deletes.is_empty() {
+                self.delete_rows_cascading(&source, &deletes.into_iter().collect_vec(), cascades)?;
+            }
+
+            let mut updates: BTreeMap<Vec<Value>, Row> = BTreeMap::new();
+            for (foreign_key, ids) in foreign_keys {
+                let action = action(&foreign_key);
+                if action == ReferenceAction::Cascade && new_id.is_none() {
+                    continue;
+                }
+                // Rows may have been deleted by previous actions, so look them
+                // up, unless they're already updated.
+                for id in ids {
+                    let row = match updates.get_mut(&id) {
+                        Some(row) => row,
+                        None => match self.get(&source.name, slice::from_ref(&id))?.pop() {
+                            Some(row) => updates.entry(id).or_insert(row),
+                            None => continue,
+                        },
+                    };
+                    for (i, &column) in foreign_key.columns.iter().enumerate() {
+                        row[column] = match action {
+                            ReferenceAction::Cascade => new_id.expect("no new id")[i].clone(),
+                            ReferenceAction::SetNull => Value::Null,
+                            // SET DEFAULT columns are validated to have a default.
+                            ReferenceAction::SetDefault => {
+                                source.columns[column].default.clone().expect("no default")
+                            }
+                            ReferenceAction::Restrict => panic!("unexpected RESTRICT action"),
+                        };
+                    }
+                }
+            }
+            if !updates.is_empty() {
+                self.update_rows(&source, updates, cascades)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates rows in a table. If a row's primary key changes, any ON UPDATE
+    /// actions for references to it are added to the cascades.
+    fn update_rows(
+        &self,
+        table: &Table,
+        rows: BTreeMap<Vec<Value>, Row>,
+        cascades: &mut Cascades,
+    ) -> Result<()> {
+        let indexes = table.secondary_indexes();
+        for (id, row) in rows {
+            // If the primary key changes, we simply do a delete and insert.
+            // This simplifies constraint validation. Any references to the
+            // old primary key are handled via the ON UPDATE actions.
+            let new_id = table.primary_key_values(&row);
+            if id != new_id {
+                let references = self.find_references(table, slice::from_ref(&id), true)?;
+                self.delete_rows(table, slice::from_ref(&id))?;
+                self.insert(&table.name, vec![row])?;
+                cascades.pending.push_back((references, Some(new_id)));
+                continue;
+            }
+
+            // Validate the row, but don't write it yet since we may need to
+            // read the existing value to update secondary indexes.
+            table.validate_row(&row, true, self)?;
+
+            // Update indexes, knowing that the primary key has not changed.
+            if !indexes.is_empty() {
+                let old = self.get(&table.name, slice::from_ref(&id))?.remove(0);
+                for index in &indexes {
+                    // If the values didn't change, we don't have to do anything.
+                    let (old_values, new_values) = (index.values(&old), index.values(&row));
+                    if old_values == new_values {
+                        continue;
+                    }
+
+                    // Remove the old values from the index entry.
+                    let mut ids = self.get_index(&table.name, &index.name, &old_values)?;
+                    ids.remove(&id);
+                    self.set_index(&table.name, &index.name, &old_values, ids)?;
+
+                    // Insert the new values into the index entry.
+                    let mut ids = self.get_index(&table.name, &index.name, &new_values)?;
+                    ids.insert(id.clone());
+                    self.set_index(&table.name, &index.name, &new_values, ids)?;
+                }
+            }
+
+            // Update the row.
+            let value = Self::encode_row(table, &row);
+            self.txn.set(&Key::Row((&table.name).into(), (&id).into()).encode(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes rows from a table, after checking for foreign key references to
+    /// them. Any ON DELETE actions are added to the cascades.
+    fn delete_rows_cascading(
+        &self,
+        table: &Table,
+        ids: &[Vec<Value>],
+        cascades: &mut Cascades,
+    ) -> Result<()> {
+        let references = self.find_references(table, ids, false)?;
+        self.delete_rows(table, ids)?;
+        cascades.deleted.entry(table.name.clone()).or_default().extend(ids.iter().cloned());
+        cascades.pending.push_back((references, None));
+        Ok(())
+    }
+
+    /// Deletes rows from a table, along with their secondary index entries.
+    /// Doesn't check for foreign key references.
+    fn delete_rows(&self, table: &Table, ids: &[Vec<Value>]) -> Result<()> {
+        let indexes = table.secondary_indexes();
+        let changes = self.layout_changes(table)?;
         for id in ids {
             // Update any secondary index entries.
             if !indexes.is_empty()
-                && let Some(row) = self.get_row(&table.name, id)?
+                && let Some(row) = self.get_row(&table.name, &changes, id)?
             {
-                for (i, column) in indexes.iter().copied() {
-                    let mut ids = self.get_index(&table.name, &column.name, &row[i])?;
+                for index in &indexes {
+                    let values = index.values(&row);
+                    let mut ids = self.get_index(&table.name, &index.name, &values)?;
                     ids.remove(id);
-                    self.set_index(&table.name, &column.name, &row[i], ids)?;
+                    self.set_index(&table.name, &index.name, &values, ids)?;
                 }
             }
 
@@ -244,9 +771,37 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
         }
         Ok(())
     }
+}
 
-    fn get(&self, table: &str, ids: &[Value]) -> Result<Vec<Row>> {
-        ids.iter().filter_map(|id| self.get_row(table, id).transpose()).collect()
+impl<E: storage::Engine> super::Transaction for Transaction<E> {
+    fn state(&self) -> &mvcc::TransactionState {
+        self.txn.state()
+    }
+
+    fn counters(&self) -> &IoCounters {
+        &self.counters
+    }
+
+    fn commit(self) -> Result<()> {
+        self.txn.commit()
+    }
+
+    fn rollback(self) -> Result<()> {
+        self.txn.rollback()
+    }
+
+    fn delete(&self, table: &str, ids: &[Vec<Value>]) -> Result<()> {
+        let table = self.must_get_table(table)?;
+        let mut cascades = Cascades::default();
+        self.delete_rows_cascading(&table, ids, &mut cascades)?;
+        self.apply_cascades(cascades)
+    }
+
+    fn get(&self, table: &str, ids: &[Vec<Value>]) -> Result<Vec<Row>> {
+        let table = self.must_get_table(table)?;
+        let changes = self.layout_changes(&table)?;
+        self.counters.add_keys_read(ids.len() as u64);
+        ids.iter().filter_map(|id| self.get_row(&table.name, &changes, id).transpose()).collect()
     }
 
     fn insert(&self, table: &str, rows: Vec<Row>) -> Result<()> {
@@ -254,30 +809,69 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
         for row in rows {
             // Insert the row.
             table.validate_row(&row, false, self)?;
-            let id = &row[table.primary_key];
-            self.txn.set(&Key::Row((&table.name).into(), id.into()).encode(), row.encode())?;
+            let id = table.primary_key_values(&row);
+            let value = Self::encode_row(&table, &row);
+            self.txn.set(&Key::Row((&table.name).into(), (&id).into()).encode(), value)?;
 
             // Update any secondary index entries.
-            for (i, column) in table.columns.iter().enumerate().filter(|(_, c)| c.index) {
-                let mut ids = self.get_index(&table.name, &column.name, &row[i])?;
+            for index in table.secondary_indexes() {
+                let values = index.values(&row);
+                let mut ids = self.get_index(&table.name, &index.name, &values)?;
                 ids.insert(id.clone());
-                self.set_index(&table.name, &column.name, &row[i], ids)?;
+                self.set_index(&table.name, &index.name, &values, ids)?;
             }
         }
         Ok(())
     }
 
-    fn lookup_index(&self, table: &str, column: &str, values: &[Value]) -> Result<BTreeSet<Value>> {
-        debug_assert!(self.has_index(table, column)?, "no index on {table}.{column}");
-        values.iter().map(|v| self.get_index(table, column, v)).flatten_ok().collect()
+    fn lookup_index(
+        &self,
+        table: &str,
+        index: &str,
+        values: &[Vec<Value>],
+    ) -> Result<BTreeSet<Vec<Value>>> {
+        debug_assert!(self.has_index(table, index)?, "no index {index} on {table}");
+        // The values may be a prefix of the index columns, so do a prefix scan
+        // of the index entries. Keycode encodes tuples as concatenated values,
+        // so a prefix of the values is also a prefix of the key.
+        let mut ids = BTreeSet::new();
+        for values in values {
+            let prefix = Key::Index(table.into(), index.into(), values.into()).encode();
+            let mut entries = self.txn.scan_prefix(&prefix);
+            while let Some((_, value)) = entries.next().transpose()? {
+                self.counters.add_keys_read(1);
+                ids.extend(BTreeSet::<Vec<Value>>::decode(&value)?);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn next_sequence_value(&self, name: &str) -> Result<i64> {
+        let sequence = self.must_get_sequence(name)?;
+        let key = Key::SequenceValue(name.into(), sequence.version).encode();
+        let value = self.txn.update_unversioned(&key, |value| {
+            let Some(value) = value else {
+                return Ok(bincode::serialize(&sequence.start));
+            };
+            match bincode::deserialize::<i64>(&value)?.checked_add(sequence.increment) {
+                Some(next) => Ok(bincode::serialize(&next)),
+                None => errinput!("sequence {name} reached its limit"),
+            }
+        })?;
+        bincode::deserialize(&value)
     }
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Rows> {
+        let table = self.must_get_table(table)?;
+        let changes = self.layout_changes(&table)?;
+        let counters = self.counters.clone();
         // TODO: this could be simpler if process_results() implemented Clone.
-        let rows = self
-            .txn
-            .scan_prefix(&KeyPrefix::Row(table.into()).encode())
-            .map(|result| result.and_then(|(_, value)| Row::decode(&value)));
+        let rows = self.txn.scan_prefix(&KeyPrefix::Row((&table.name).into()).encode()).map(
+            move |result| {
+                counters.add_keys_read(1);
+                result.and_then(|(_, value)| Self::decode_row(&value, &changes))
+            },
+        );
         let Some(filter) = filter else {
             return Ok(Box::new(rows));
         };
@@ -293,57 +887,128 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
         Ok(Box::new(rows))
     }
 
-    fn update(&self, table: &str, rows: BTreeMap<Value, Row>) -> Result<()> {
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Rows> {
+        let changes = self.layout_changes(&self.must_get_table(table)?)?;
+        let prefix = KeyPrefix::Row(table.into()).encode();
+        let key = |id: Vec<Value>| Key::Row(table.into(), Cow::Owned(id)).encode();
+        let Some(range) = Self::key_range(&prefix, range, key) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let counters = self.counters.clone();
+        let rows = self.txn.scan(range).map(move |result| {
+            counters.add_keys_read(1);
+            result.and_then(|(_, value)| Self::decode_row(&value, &changes))
+        });
+        Ok(Box::new(rows))
+    }
+
+    fn scan_index_range(
+        &self,
+        table: &str,
+        index: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Vec<Vec<Value>>> {
+        debug_assert!(self.has_index(table, index)?, "no index {index} on {table}");
+        let prefix = KeyPrefix::Index(table.into(), index.into()).encode();
+        let key = |values: Vec<Value>| {
+            Key::Index(table.into(), index.into(), Cow::Owned(values)).encode()
+        };
+        let Some(range) = Self::key_range(&prefix, range, key) else {
+            return Ok(Vec::new());
+        };
+        let mut ids = Vec::new();
+        let mut entries = self.txn.scan(range);
+        while let Some((_, value)) = entries.next().transpose()? {
+            self.counters.add_keys_read(1);
+            ids.extend(BTreeSet::<Vec<Value>>::decode(&value)?);
+        }
+        Ok(ids)
+    }
+
+    fn update(&self, table: &str, rows: BTreeMap<Vec<Value>, Row>) -> Result<()> {
         let table = self.must_get_table(table)?;
-        for (id, row) in rows {
-            // If the primary key changes, we simply do a delete and insert.
-            // This simplifies constraint validation.
-            if id != row[table.primary_key] {
-                self.delete(&table.name, &[id])?;
-                self.insert(&table.name, vec![row])?;
-                continue;
+        let mut cascades = Cascades::default();
+        self.update_rows(&table, rows, &mut cascades)?;
+        self.apply_cascades(cascades)
+    }
+}
+
+impl<E: storage::Engine> Catalog for Transaction<E> {
+    fn alter_table(&self, table: &str, alteration: Alteration) -> Result<()> {
+        let mut table = self.must_get_table(table)?;
+        match alteration {
+            // Statistics are positional, and dropped when the layout changes.
+            Alteration::AddColumn(column) => {
+                self.delete_prefix(&Key::Statistics((&table.name).into()).encode())?;
+                return self.add_column(table, column);
             }
+            Alteration::DropColumn(column) => {
+                self.delete_prefix(&Key::Statistics((&table.name).into()).encode())?;
+                return self.drop_column(table, &column);
+            }
+            Alteration::RenameColumn { from, to } => return self.rename_column(table, &from, to),
+            Alteration::RenameTable(to) => return self.rename_table(table, to),
 
-            // Validate the row, but don't write it yet since we may need to
-            // read the existing value to update secondary indexes.
-            table.validate_row(&row, true, self)?;
+            Alteration::SetDefault(name, default) => {
+                let index = table.column_index(&name)?;
+                let column = &mut table.columns[index];
+                let nullable = column.nullable && column.sequence.is_none();
+                column.default = default.or_else(|| nullable.then_some(Value::Null));
+                table.validate(self)?;
+            }
 
-            // Update indexes, knowing that the primary key has not changed.
-            let indexes = table.columns.iter().enumerate().filter(|(_, c)| c.index).collect_vec();
-            if !indexes.is_empty() {
-                let old = self.get(&table.name, slice::from_ref(&id))?.remove(0);
-                for (i, column) in indexes {
-                    // If the value didn't change, we don't have to do anything.
-                    if old[i] == row[i] {
-                        continue;
+            Alteration::SetNullable(name, nullable) => {
+                let index = table.column_index(&name)?;
+                let column = &mut table.columns[index];
+                column.nullable = nullable;
+                // Nullable columns default to NULL, and non-nullable columns
+                // can't, so adjust the default accordingly. Identity columns
+                // take their default from the sequence.
+                match (nullable, &column.default) {
+                    (true, None) if column.sequence.is_none() => column.default = Some(Value::Null),
+                    (false, Some(Value::Null)) => column.default = None,
+                    _ => {}
+                }
+                table.validate(self)?;
+                if !nullable {
+                    let mut rows = self.scan(&table.name, None)?;
+                    while let Some(row) = rows.next().transpose()? {
+                        if row[index] == Value::Null {
+                            return errinput!("column {name} contains NULL values");
+                        }
                     }
-
-                    // Remove the old value from the index entry.
-                    let mut ids = self.get_index(&table.name, &column.name, &old[i])?;
-                    ids.remove(&id);
-                    self.set_index(&table.name, &column.name, &old[i], ids)?;
-
-                    // Insert the new value into the index entry.
-                    let mut ids = self.get_index(&table.name, &column.name, &row[i])?;
-                    ids.insert(id.clone());
-                    self.set_index(&table.name, &column.name, &row[i], ids)?;
                 }
             }
-
-            // Update the row.
-            self.txn.set(&Key::Row((&table.name).into(), (&id).into()).encode(), row.encode())?;
         }
-        Ok(())
+        self.set_table(&table)
+    }
+
+    fn analyze(&self, table: &str) -> Result<Statistics> {
+        let table = self.must_get_table(table)?;
+        let stats = Statistics::collect(&table, self.scan(&table.name, None)?)?;
+        self.txn.set(&Key::Statistics((&table.name).into()).encode(), stats.encode())?;
+        Ok(stats)
     }
-}
 
-impl<E: storage::Engine> Catalog for Transaction<E> {
     fn create_table(&self, table: Table) -> Result<()> {
         if self.get_table(&table.name)?.is_some() {
             return errinput!("table {} already exists", table.name);
         }
+        if table.version != 0 {
+            return errinput!("new table must have schema version 0");
+        }
+        if !table.indexes.is_empty() {
+            return errinput!("new table can't have named indexes");
+        }
         table.validate(self)?;
-        self.txn.set(&Key::Table((&table.name).into()).encode(), table.encode())
+        for sequence in table.columns.iter().filter_map(|c| c.sequence.as_ref()) {
+            self.create_sequence(Sequence::new(sequence))?;
+        }
+        self.set_table(&table)
     }
 
     fn drop_table(&self, table: &str, if_exists: bool) -> Result<bool> {
@@ -355,38 +1020,143 @@ impl<E: storage::Engine> Catalog for Transaction<E> {
         };
 
         // Check for foreign key references.
-        if let Some((source, refs)) =
+        if let Some((source, foreign_keys)) =
             self.table_references(&table.name)?.iter().find(|(t, _)| t.name != table.name)
         {
-            return errinput!(
-                "table {} is referenced from {}.{}",
-                table.name,
-                source.name,
-                source.columns[refs[0]].name
-            );
+            let mut columns = foreign_keys[0].columns.iter().map(|&i| &source.columns[i].name);
+            let column = match foreign_keys[0].columns.len() {
+                1 => columns.join(""),
+                _ => format!("({})", columns.join(", ")),
+            };
+            return errinput!("table {} is referenced from {}.{column}", table.name, source.name);
         }
 
         // Delete the table schema entry.
-        self.txn.delete(&Key::Table((&table.name).into()).encode())?;
+        self.delete_table(&table.name)?;
 
-        // Delete the table rows.
-        let prefix = &KeyPrefix::Row((&table.name).into()).encode();
-        let mut keys = self.txn.scan_prefix(prefix).map_ok(|(key, _)| key);
-        while let Some(key) = keys.next().transpose()? {
-            self.txn.delete(&key)?;
-        }
+        // Delete the table rows, schema versions, and statistics (if any).
+        self.delete_prefix(&Key::Statistics((&table.name).into()).encode())?;
+        self.delete_prefix(&KeyPrefix::Row((&table.name).into()).encode())?;
+        self.delete_prefix(&KeyPrefix::TableVersion((&table.name).into()).encode())?;
 
         // Delete any secondary index entries.
-        for column in table.columns.iter().filter(|c| c.index) {
-            let prefix = &KeyPrefix::Index((&table.name).into(), (&column.name).into()).encode();
-            let mut keys = self.txn.scan_prefix(prefix).map_ok(|(key, _)| key);
-            while let Some(key) = keys.next().transpose()? {
-                self.txn.delete(&key)?;
+        for index in table.secondary_indexes() {
+            self.delete_prefix(
+                &KeyPrefix::Index((&table.name).into(), index.name.into()).encode(),
+            )?;
+        }
+
+        // Delete any owned sequences.
+        for sequence in table.columns.into_iter().filter_map(|c| c.sequence) {
+            self.txn.delete(&Key::Sequence(sequence.into()).encode())?;
+        }
+        Ok(true)
+    }
+
+    fn catalog_version(&self) -> Result<u64> {
+        let version = self.txn.get(&Key::CatalogVersion.encode())?;
+        Ok(version.map(|v| bincode::deserialize(&v)).transpose()?.unwrap_or(0))
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[String],
+        unique: bool,
+    ) -> Result<()> {
+        if self.index_table(name)?.is_some() {
+            return errinput!("index {name} already exists");
+        }
+        let mut table = self.must_get_table(table)?;
+        let columns: Vec<usize> = columns.iter().map(|c| table.column_index(c)).try_collect()?;
+        if let Some(existing) = table.secondary_indexes().iter().find(|i| i.columns == columns) {
+            return errinput!("index {} already exists on the same columns", existing.name);
+        }
+        let index = Index { name: name.to_string(), columns, unique };
+        table.indexes.push(index.clone());
+        table.validate(self)?;
+
+        // Build the index entries from the existing rows, checking uniqueness
+        // before writing anything.
+        let mut entries: BTreeMap<Vec<Value>, BTreeSet<Vec<Value>>> = BTreeMap::new();
+        let mut rows = self.scan(&table.name, None)?;
+        while let Some(row) = rows.next().transpose()? {
+            let values = index.values(&row);
+            let ids = entries.entry(values.clone()).or_default();
+            ids.insert(table.primary_key_values(&row));
+            if unique && ids.len() > 1 && !values.iter().any(|v| v.is_undefined()) {
+                return table.unique_error(&index, &values);
+            }
+        }
+
+        // Store the schema, then backfill the index.
+        self.set_table(&table)?;
+        for (values, ids) in entries {
+            self.set_index(&table.name, name, &values, ids)?;
+        }
+        Ok(())
+    }
+
+    fn drop_index(&self, name: &str, if_exists: bool) -> Result<bool> {
+        let Some(mut table) = self.index_table(name)? else {
+            if if_exists {
+                return Ok(false);
+            }
+            return errinput!("index {name} does not exist");
+        };
+        table.indexes.retain(|i| i.name != name);
+        table.validate(self)?;
+
+        self.delete_prefix(&KeyPrefix::Index((&table.name).into(), name.into()).encode())?;
+        self.set_table(&table)?;
+        Ok(true)
+    }
+
+    fn create_sequence(&self, mut sequence: Sequence) -> Result<()> {
+        if self.get_sequence(&sequence.name)?.is_some() {
+            return errinput!("sequence {} already exists", sequence.name);
+        }
+        sequence.validate()?;
+        sequence.version = self.txn.version();
+        self.txn.set(&Key::Sequence((&sequence.name).into()).encode(), sequence.encode())
+    }
+
+    fn drop_sequence(&self, name: &str, if_exists: bool) -> Result<bool> {
+        if self.get_sequence(name)?.is_none() {
+            if if_exists {
+                return Ok(false);
+            }
+            return errinput!("sequence {name} does not exist");
+        }
+        for table in self.list_tables()? {
+            if let Some(column) = table.columns.iter().find(|c| c.sequence.as_deref() == Some(name))
+            {
+                return errinput!(
+                    "sequence {name} is used by column {}.{}",
+                    table.name,
+                    column.name
+                );
             }
         }
+        self.txn.delete(&Key::Sequence(name.into()).encode())?;
         Ok(true)
     }
 
+    fn get_sequence(&self, name: &str) -> Result<Option<Sequence>> {
+        self.txn
+            .get(&Key::Sequence(name.into()).encode())?
+            .map(|v| Sequence::decode(&v))
+            .transpose()
+    }
+
+    fn get_statistics(&self, table: &str) -> Result<Option<Statistics>> {
+        self.txn
+            .get(&Key::Statistics(table.into()).encode())?
+            .map(|v| Statistics::decode(&v))
+            .transpose()
+    }
+
     fn get_table(&self, table: &str) -> Result<Option<Table>> {
         self.txn.get(&Key::Table(table.into()).encode())?.map(|v| Table::decode(&v)).transpose()
     }
@@ -7,6 +7,6 @@ mod engine;
 mod local;
 mod raft;
 
-pub use engine::{Catalog, Engine, Transaction};
-pub use local::{Key, Local};
+pub use engine::{Catalog, Engine, IoCounters, Transaction};
+pub use local::{Key, LayoutChange, Local};
 pub use raft::{Raft, Status, Write};
@@ -1,16 +1,18 @@
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
 
 use crossbeam::channel::Sender;
+use itertools::Itertools as _;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-use super::{Catalog, Engine as _, Transaction as _};
+use super::{Catalog, Engine as _, IoCounters, Transaction as _};
 use crate::encoding::{self, Value as _, bincode};
 use crate::errdata;
 use crate::error::Result;
 use crate::raft;
-use crate::sql::types::{Expression, Row, Rows, Table, Value};
+use crate::sql::types::{Alteration, Expression, Row, Rows, Sequence, Statistics, Table, Value};
 use crate::storage::{self, mvcc};
 
 /// A read command, submitted via Raft and executed on the leader. Each command
@@ -26,20 +28,42 @@ pub enum Read<'a> {
     Get {
         txn: Cow<'a, mvcc::TransactionState>,
         table: Cow<'a, str>,
-        ids: Cow<'a, [Value]>,
+        ids: Cow<'a, [Vec<Value>]>,
     },
     LookupIndex {
         txn: Cow<'a, mvcc::TransactionState>,
         table: Cow<'a, str>,
-        column: Cow<'a, str>,
-        values: Cow<'a, [Value]>,
+        index: Cow<'a, str>,
+        values: Cow<'a, [Vec<Value>]>,
     },
     Scan {
         txn: Cow<'a, mvcc::TransactionState>,
         table: Cow<'a, str>,
         filter: Option<Expression>,
     },
+    ScanRange {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    },
+    ScanIndexRange {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        index: Cow<'a, str>,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    },
 
+    CatalogVersion {
+        txn: Cow<'a, mvcc::TransactionState>,
+    },
+    GetSequence {
+        txn: Cow<'a, mvcc::TransactionState>,
+        name: Cow<'a, str>,
+    },
+    GetStatistics {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+    },
     GetTable {
         txn: Cow<'a, mvcc::TransactionState>,
         table: Cow<'a, str>,
@@ -60,12 +84,65 @@ pub enum Write<'a> {
     Commit(Cow<'a, mvcc::TransactionState>),
     Rollback(Cow<'a, mvcc::TransactionState>),
 
-    Delete { txn: Cow<'a, mvcc::TransactionState>, table: Cow<'a, str>, ids: Cow<'a, [Value]> },
-    Insert { txn: Cow<'a, mvcc::TransactionState>, table: Cow<'a, str>, rows: Vec<Row> },
-    Update { txn: Cow<'a, mvcc::TransactionState>, table: Cow<'a, str>, rows: BTreeMap<Value, Row> },
+    Delete {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        ids: Cow<'a, [Vec<Value>]>,
+    },
+    Insert {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        rows: Vec<Row>,
+    },
+    Update {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        rows: BTreeMap<Vec<Value>, Row>,
+    },
+    NextSequenceValue {
+        txn: Cow<'a, mvcc::TransactionState>,
+        name: Cow<'a, str>,
+    },
 
-    CreateTable { txn: Cow<'a, mvcc::TransactionState>, schema: Table },
-    DropTable { txn: Cow<'a, mvcc::TransactionState>, table: Cow<'a, str>, if_exists: bool },
+    AlterTable {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        alteration: Alteration,
+    },
+    Analyze {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+    },
+    CreateTable {
+        txn: Cow<'a, mvcc::TransactionState>,
+        schema: Table,
+    },
+    DropTable {
+        txn: Cow<'a, mvcc::TransactionState>,
+        table: Cow<'a, str>,
+        if_exists: bool,
+    },
+    CreateIndex {
+        txn: Cow<'a, mvcc::TransactionState>,
+        name: Cow<'a, str>,
+        table: Cow<'a, str>,
+        columns: Cow<'a, [String]>,
+        unique: bool,
+    },
+    DropIndex {
+        txn: Cow<'a, mvcc::TransactionState>,
+        name: Cow<'a, str>,
+        if_exists: bool,
+    },
+    CreateSequence {
+        txn: Cow<'a, mvcc::TransactionState>,
+        sequence: Sequence,
+    },
+    DropSequence {
+        txn: Cow<'a, mvcc::TransactionState>,
+        name: Cow<'a, str>,
+        if_exists: bool,
+    },
 }
 
 impl encoding::Value for Write<'_> {}
@@ -173,6 +250,8 @@ pub struct Transaction<'a> {
     raft: &'a Raft,
     /// The MVCC transaction state.
     state: mvcc::TransactionState,
+    /// The transaction's I/O counters.
+    counters: IoCounters,
 }
 
 impl<'a> Transaction<'a> {
@@ -187,7 +266,28 @@ impl<'a> Transaction<'a> {
         } else {
             raft.write(Write::Begin)?
         };
-        Ok(Self { raft, state })
+        Ok(Self { raft, state, counters: IoCounters::default() })
+    }
+
+    /// Writes through Raft, counting the round trip.
+    fn write<V: DeserializeOwned>(&self, write: Write) -> Result<V> {
+        self.counters.add_round_trip();
+        self.raft.write(write)
+    }
+
+    /// Reads from Raft, counting the round trip.
+    fn read<V: DeserializeOwned>(&self, read: Read) -> Result<V> {
+        self.counters.add_round_trip();
+        self.raft.read(read)
+    }
+
+    /// Reads rows or index entries from Raft, counting the round trip and the
+    /// number of keys the state machine read from storage, which it returns
+    /// along with the response.
+    fn read_keys<V: DeserializeOwned>(&self, read: Read) -> Result<V> {
+        let (value, keys_read): (V, u64) = self.read(read)?;
+        self.counters.add_keys_read(keys_read);
+        Ok(value)
     }
 }
 
@@ -196,30 +296,34 @@ impl super::Transaction for Transaction<'_> {
         &self.state
     }
 
+    fn counters(&self) -> &IoCounters {
+        &self.counters
+    }
+
     fn commit(self) -> Result<()> {
         if self.state.read_only {
             return Ok(()); // noop
         }
-        self.raft.write(Write::Commit(self.state.into()))
+        self.write(Write::Commit((&self.state).into()))
     }
 
     fn rollback(self) -> Result<()> {
         if self.state.read_only {
             return Ok(()); // noop
         }
-        self.raft.write(Write::Rollback(self.state.into()))
+        self.write(Write::Rollback((&self.state).into()))
     }
 
-    fn delete(&self, table: &str, ids: &[Value]) -> Result<()> {
-        self.raft.write(Write::Delete {
+    fn delete(&self, table: &str, ids: &[Vec<Value>]) -> Result<()> {
+        self.write(Write::Delete {
             txn: (&self.state).into(),
             table: table.into(),
             ids: ids.into(),
         })
     }
 
-    fn get(&self, table: &str, ids: &[Value]) -> Result<Vec<Row>> {
-        self.raft.read(Read::Get {
+    fn get(&self, table: &str, ids: &[Vec<Value>]) -> Result<Vec<Row>> {
+        self.read_keys(Read::Get {
             txn: (&self.state).into(),
             table: table.into(),
             ids: ids.into(),
@@ -227,51 +331,128 @@ impl super::Transaction for Transaction<'_> {
     }
 
     fn insert(&self, table: &str, rows: Vec<Row>) -> Result<()> {
-        self.raft.write(Write::Insert { txn: (&self.state).into(), table: table.into(), rows })
+        self.write(Write::Insert { txn: (&self.state).into(), table: table.into(), rows })
     }
 
-    fn lookup_index(&self, table: &str, column: &str, values: &[Value]) -> Result<BTreeSet<Value>> {
-        self.raft.read(Read::LookupIndex {
+    fn lookup_index(
+        &self,
+        table: &str,
+        index: &str,
+        values: &[Vec<Value>],
+    ) -> Result<BTreeSet<Vec<Value>>> {
+        self.read_keys(Read::LookupIndex {
             txn: (&self.state).into(),
             table: table.into(),
-            column: column.into(),
+            index: index.into(),
             values: values.into(),
         })
     }
 
+    fn next_sequence_value(&self, name: &str) -> Result<i64> {
+        self.write(Write::NextSequenceValue { txn: (&self.state).into(), name: name.into() })
+    }
+
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Rows> {
-        let scan: Vec<Row> = self.raft.read(Read::Scan {
+        let scan: Vec<Row> =
+            self.read_keys(Read::Scan { txn: (&self.state).into(), table: table.into(), filter })?;
+        Ok(Box::new(scan.into_iter().map(Ok)))
+    }
+
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Rows> {
+        let scan: Vec<Row> = self.read_keys(Read::ScanRange {
             txn: (&self.state).into(),
             table: table.into(),
-            filter,
+            range,
         })?;
         Ok(Box::new(scan.into_iter().map(Ok)))
     }
 
-    fn update(&self, table: &str, rows: BTreeMap<Value, Row>) -> Result<()> {
-        self.raft.write(Write::Update { txn: (&self.state).into(), table: table.into(), rows })
+    fn scan_index_range(
+        &self,
+        table: &str,
+        index: &str,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> Result<Vec<Vec<Value>>> {
+        self.read_keys(Read::ScanIndexRange {
+            txn: (&self.state).into(),
+            table: table.into(),
+            index: index.into(),
+            range,
+        })
+    }
+
+    fn update(&self, table: &str, rows: BTreeMap<Vec<Value>, Row>) -> Result<()> {
+        self.write(Write::Update { txn: (&self.state).into(), table: table.into(), rows })
     }
 }
 
 impl Catalog for Transaction<'_> {
+    fn alter_table(&self, table: &str, alteration: Alteration) -> Result<()> {
+        self.write(Write::AlterTable { txn: (&self.state).into(), table: table.into(), alteration })
+    }
+
+    fn analyze(&self, table: &str) -> Result<Statistics> {
+        self.write(Write::Analyze { txn: (&self.state).into(), table: table.into() })
+    }
+
     fn create_table(&self, schema: Table) -> Result<()> {
-        self.raft.write(Write::CreateTable { txn: (&self.state).into(), schema })
+        self.write(Write::CreateTable { txn: (&self.state).into(), schema })
     }
 
     fn drop_table(&self, table: &str, if_exists: bool) -> Result<bool> {
-        self.raft.write(Write::DropTable {
+        self.write(Write::DropTable { txn: (&self.state).into(), table: table.into(), if_exists })
+    }
+
+    fn create_index(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[String],
+        unique: bool,
+    ) -> Result<()> {
+        self.write(Write::CreateIndex {
             txn: (&self.state).into(),
+            name: name.into(),
             table: table.into(),
-            if_exists,
+            columns: columns.into(),
+            unique,
         })
     }
 
+    fn drop_index(&self, name: &str, if_exists: bool) -> Result<bool> {
+        self.write(Write::DropIndex { txn: (&self.state).into(), name: name.into(), if_exists })
+    }
+
+    fn create_sequence(&self, sequence: Sequence) -> Result<()> {
+        self.write(Write::CreateSequence { txn: (&self.state).into(), sequence })
+    }
+
+    fn drop_sequence(&self, name: &str, if_exists: bool) -> Result<bool> {
+        self.write(Write::DropSequence { txn: (&self.state).into(), name: name.into(), if_exists })
+    }
+
+    fn catalog_version(&self) -> Result<u64> {
+        self.read(Read::CatalogVersion { txn: (&self.state).into() })
+    }
+
+    fn get_sequence(&self, name: &str) -> Result<Option<Sequence>> {
+        self.read(Read::GetSequence { txn: (&self.state).into(), name: name.into() })
+    }
+
+    fn get_statistics(&self, table: &str) -> Result<Option<Statistics>> {
+        self.read(Read::GetStatistics { txn: (&self.state).into(), table: table.into() })
+    }
+
     fn get_table(&self, table: &str) -> Result<Option<Table>> {
-        self.raft.read(Read::GetTable { txn: (&self.state).into(), table: table.into() })
+        self.read(Read::GetTable { txn: (&self.state).into(), table: table.into() })
     }
 
     fn list_tables(&self) -> Result<Vec<Table>> {
-        self.raft.read(Read::ListTables { txn: (&self.state).into() })
+        self.read(Read::ListTables { txn: (&self.state).into() })
     }
 }
 
@@ -327,13 +508,37 @@ impl<E: storage::Engine> State<E> {
             Write::Update { txn, table, rows } => {
                 bincode::serialize(&self.local.resume(txn.into_owned())?.update(&table, rows)?)
             }
+            Write::NextSequenceValue { txn, name } => bincode::serialize(
+                &self.local.resume(txn.into_owned())?.next_sequence_value(&name)?,
+            ),
 
+            Write::AlterTable { txn, table, alteration } => bincode::serialize(
+                &self.local.resume(txn.into_owned())?.alter_table(&table, alteration)?,
+            ),
+            Write::Analyze { txn, table } => {
+                bincode::serialize(&self.local.resume(txn.into_owned())?.analyze(&table)?)
+            }
             Write::CreateTable { txn, schema } => {
                 bincode::serialize(&self.local.resume(txn.into_owned())?.create_table(schema)?)
             }
             Write::DropTable { txn, table, if_exists } => bincode::serialize(
                 &self.local.resume(txn.into_owned())?.drop_table(&table, if_exists)?,
             ),
+            Write::CreateIndex { txn, name, table, columns, unique } => bincode::serialize(
+                &self
+                    .local
+                    .resume(txn.into_owned())?
+                    .create_index(&name, &table, &columns, unique)?,
+            ),
+            Write::DropIndex { txn, name, if_exists } => bincode::serialize(
+                &self.local.resume(txn.into_owned())?.drop_index(&name, if_exists)?,
+            ),
+            Write::CreateSequence { txn, sequence } => {
+                bincode::serialize(&self.local.resume(txn.into_owned())?.create_sequence(sequence)?)
+            }
+            Write::DropSequence { txn, name, if_exists } => bincode::serialize(
+                &self.local.resume(txn.into_owned())?.drop_sequence(&name, if_exists)?,
+            ),
         })
     }
 }
@@ -377,23 +582,44 @@ impl<E: storage::Engine> raft::State for State<E> {
             }
             Read::Status => self.local.mvcc.status()?.encode(),
 
+            // Row and index reads also return the number of keys read from
+            // storage, for the transaction's I/O counters.
             Read::Get { txn, table, ids } => {
-                self.local.resume(txn.into_owned())?.get(&table, &ids)?.encode()
+                let txn = self.local.resume(txn.into_owned())?;
+                let rows = txn.get(&table, &ids)?;
+                bincode::serialize(&(rows, txn.counters().keys_read()))
+            }
+            Read::LookupIndex { txn, table, index, values } => {
+                let txn = self.local.resume(txn.into_owned())?;
+                let ids = txn.lookup_index(&table, &index, &values)?;
+                bincode::serialize(&(ids, txn.counters().keys_read()))
             }
-            Read::LookupIndex { txn, table, column, values } => self
-                .local
-                .resume(txn.into_owned())?
-                .lookup_index(&table, &column, &values)?
-                .encode(),
             Read::Scan { txn, table, filter } => {
                 // For simplicity, buffer the entire scan. See `State` comment.
-                self.local
-                    .resume(txn.into_owned())?
-                    .scan(&table, filter)?
-                    .collect::<Result<Vec<Row>>>()?
-                    .encode()
+                let txn = self.local.resume(txn.into_owned())?;
+                let rows: Vec<Row> = txn.scan(&table, filter)?.try_collect()?;
+                bincode::serialize(&(rows, txn.counters().keys_read()))
+            }
+            Read::ScanRange { txn, table, range } => {
+                let txn = self.local.resume(txn.into_owned())?;
+                let rows: Vec<Row> = txn.scan_range(&table, range)?.try_collect()?;
+                bincode::serialize(&(rows, txn.counters().keys_read()))
+            }
+            Read::ScanIndexRange { txn, table, index, range } => {
+                let txn = self.local.resume(txn.into_owned())?;
+                let ids = txn.scan_index_range(&table, &index, range)?;
+                bincode::serialize(&(ids, txn.counters().keys_read()))
             }
 
+            Read::CatalogVersion { txn } => {
+                self.local.resume(txn.into_owned())?.catalog_version()?.encode()
+            }
+            Read::GetSequence { txn, name } => {
+                self.local.resume(txn.into_owned())?.get_sequence(&name)?.encode()
+            }
+            Read::GetStatistics { txn, table } => {
+                self.local.resume(txn.into_owned())?.get_statistics(&table)?.encode()
+            }
             Read::GetTable { txn, table } => {
                 self.local.resume(txn.into_owned())?.get_table(&table)?.encode()
             }
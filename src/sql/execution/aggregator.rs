@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 
 use itertools::Itertools as _;
 
+use super::spill::{Memory, Partitioner, Reservation, row_size};
 use crate::error::Result;
 use crate::sql::planner::Aggregate;
 use crate::sql::types::{Expression, Row, Rows, Value};
@@ -10,6 +11,11 @@ use crate::sql::types::{Expression, Row, Rows, Value};
 /// compute COUNT and SUM aggregates bucketed by category and brand:
 ///
 /// SELECT COUNT(*), SUM(price) FROM products GROUP BY category, brand
+///
+/// If the buckets exceed the query's memory budget, no new buckets are added
+/// in memory. Instead, rows for new buckets are spilled to disk in hash
+/// partitions by bucket, and each partition is aggregated separately once the
+/// in-memory buckets have been emitted.
 pub struct Aggregator {
     /// GROUP BY expressions.
     group_by: Vec<Expression>,
@@ -17,20 +23,58 @@ pub struct Aggregator {
     aggregates: Vec<Aggregate>,
     /// Accumulators indexed by group_by bucket.
     buckets: BTreeMap<Vec<Value>, Vec<Accumulator>>,
+    /// The memory reservation for the buckets.
+    reservation: Reservation,
+    /// The memory tracker, used to spill partitions.
+    memory: Memory,
+    /// The spill depth of the input rows (0 unless this is a partition).
+    depth: usize,
+    /// Spill partitions for rows in new buckets, once over budget.
+    partitions: Option<Partitioner>,
 }
 
 impl Aggregator {
     /// Creates a new aggregator for the given GROUP BY buckets and aggregates.
-    pub fn new(group_by: Vec<Expression>, aggregates: Vec<Aggregate>) -> Self {
-        Self { group_by, aggregates, buckets: BTreeMap::new() }
+    pub fn new(group_by: Vec<Expression>, aggregates: Vec<Aggregate>, memory: Memory) -> Self {
+        Self::new_partition(group_by, aggregates, memory, 0)
+    }
+
+    /// Creates a new aggregator for a spill partition at the given depth.
+    fn new_partition(
+        group_by: Vec<Expression>,
+        aggregates: Vec<Aggregate>,
+        memory: Memory,
+        depth: usize,
+    ) -> Self {
+        let reservation = memory.reserve();
+        Self {
+            group_by,
+            aggregates,
+            buckets: BTreeMap::new(),
+            reservation,
+            memory,
+            depth,
+            partitions: None,
+        }
     }
 
     /// Adds a row to the aggregator.
     pub fn add(&mut self, row: &Row) -> Result<()> {
         // Compute the bucket values.
-        let bucket = self.group_by.iter().map(|expr| expr.evaluate(Some(row))).try_collect()?;
+        let bucket: Vec<Value> =
+            self.group_by.iter().map(|expr| expr.evaluate(Some(row))).try_collect()?;
 
-        // Look up the bucket accumulators, or create a new bucket.
+        // Look up the bucket accumulators. If there is no such bucket, and
+        // we're over budget, spill the row. Otherwise, create a new bucket.
+        if !self.buckets.contains_key(&bucket) {
+            if let Some(partitions) = &mut self.partitions {
+                return partitions.add(&bucket, row);
+            }
+            let size = row_size(&bucket) + self.aggregates.len() * size_of::<Accumulator>();
+            if self.reservation.grow(size) {
+                self.partitions = self.memory.partition(self.depth);
+            }
+        }
         let accumulators = self
             .buckets
             .entry(bucket)
@@ -56,26 +100,47 @@ impl Aggregator {
     }
 
     /// Returns a row iterator over the aggregate result.
-    pub fn into_rows(self) -> Rows {
+    pub fn into_rows(self) -> Result<Rows> {
         // If there were no rows and no group_by expressions, return a row of
         // empty accumulators (e.g. SELECT COUNT(*) FROM t WHERE FALSE).
         if self.buckets.is_empty() && self.group_by.is_empty() {
             let result =
                 self.aggregates.iter().map(Accumulator::new).map(|acc| acc.value()).try_collect();
-            return Box::new(std::iter::once(result));
+            return Ok(Box::new(std::iter::once(result)));
         }
 
         // Emit the group_by and aggregate values for each bucket. We use an
         // intermediate vec since btree_map::IntoIter doesn't implement Clone
         // (required by Rows).
         let buckets = self.buckets.into_iter().collect_vec();
-        Box::new(buckets.into_iter().map(|(bucket, accumulators)| {
-            bucket
-                .into_iter()
-                .map(Ok)
-                .chain(accumulators.into_iter().map(|acc| acc.value()))
-                .collect()
-        }))
+        let rows =
+            self.reservation.hold(Box::new(buckets.into_iter().map(|(bucket, accumulators)| {
+                bucket
+                    .into_iter()
+                    .map(Ok)
+                    .chain(accumulators.into_iter().map(|acc| acc.value()))
+                    .collect()
+            })));
+        let Some(partitions) = self.partitions else {
+            return Ok(rows);
+        };
+
+        // Then aggregate and emit each spilled partition. These only contain
+        // rows for buckets that weren't in memory.
+        let (group_by, aggregates, memory) = (self.group_by, self.aggregates, self.memory);
+        let depth = self.depth + 1;
+        let partitions = partitions.finish()?.into_iter().flatten();
+        Ok(Box::new(rows.chain(partitions.flat_map(move |partition| -> Rows {
+            let (group_by, aggregates) = (group_by.clone(), aggregates.clone());
+            let mut aggregator = Self::new_partition(group_by, aggregates, memory.clone(), depth);
+            match aggregator
+                .add_rows(Box::new(partition.read()))
+                .and_then(|_| aggregator.into_rows())
+            {
+                Ok(rows) => rows,
+                Err(err) => Box::new(std::iter::once(Err(err))),
+            }
+        }))))
     }
 }
 
@@ -83,8 +148,17 @@ impl Aggregator {
 /// to keep these in a vector (could use boxed trait objects too).
 #[derive(Clone)]
 enum Accumulator {
-    Average { count: i64, sum: Value },
+    Average {
+        count: i64,
+        sum: Value,
+    },
     Count(i64),
+    /// Passes distinct values through to the inner accumulator, keeping track
+    /// of values that have already been seen.
+    Distinct {
+        seen: HashSet<Value>,
+        inner: Box<Accumulator>,
+    },
     Max(Option<Value>),
     Min(Option<Value>),
     Sum(Option<Value>),
@@ -93,12 +167,16 @@ enum Accumulator {
 impl Accumulator {
     /// Creates a new accumulator from an aggregate kind.
     fn new(aggregate: &Aggregate) -> Self {
-        match aggregate {
-            Aggregate::Average(_) => Self::Average { count: 0, sum: Value::Integer(0) },
-            Aggregate::Count(_) => Self::Count(0),
-            Aggregate::Max(_) => Self::Max(None),
-            Aggregate::Min(_) => Self::Min(None),
-            Aggregate::Sum(_) => Self::Sum(None),
+        let accumulator = match aggregate {
+            Aggregate::Average { .. } => Self::Average { count: 0, sum: Value::Integer(0) },
+            Aggregate::Count { .. } => Self::Count(0),
+            Aggregate::Max { .. } => Self::Max(None),
+            Aggregate::Min { .. } => Self::Min(None),
+            Aggregate::Sum { .. } => Self::Sum(None),
+        };
+        match aggregate.distinct() {
+            true => Self::Distinct { seen: HashSet::new(), inner: Box::new(accumulator) },
+            false => accumulator,
         }
     }
 
@@ -111,6 +189,12 @@ impl Accumulator {
         match self {
             Self::Average { sum, count } => (*sum, *count) = (sum.checked_add(&value)?, *count + 1),
             Self::Count(count) => *count += 1,
+            Self::Distinct { seen, inner } => {
+                if !seen.contains(&value) {
+                    seen.insert(value.clone());
+                    inner.add(value)?;
+                }
+            }
             Self::Max(max @ None) => *max = Some(value),
             Self::Max(Some(max)) if value > *max => *max = value,
             Self::Max(Some(_)) => {}
@@ -129,6 +213,7 @@ impl Accumulator {
             Self::Average { count: 0, sum: _ } => Value::Null,
             Self::Average { count, sum } => sum.checked_div(&Value::Integer(count))?,
             Self::Count(count) => count.into(),
+            Self::Distinct { inner, .. } => inner.value()?,
             Self::Max(Some(value)) | Self::Min(Some(value)) | Self::Sum(Some(value)) => value,
             Self::Max(None) | Self::Min(None) | Self::Sum(None) => Value::Null,
         })
@@ -1,15 +1,23 @@
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use itertools::{Itertools as _, izip};
+use itertools::Itertools as _;
 
 use super::aggregator::Aggregator;
-use super::join::{HashJoiner, NestedLoopJoiner};
+use super::join::{
+    HashJoiner, HashSemiJoiner, MergeJoiner, NestedLoopJoiner, NestedLoopSemiJoiner,
+};
+use super::sorter::{Sorter, TopN};
+use super::spill::{Memory, MemoryBudget};
 use crate::errinput;
 use crate::error::Result;
-use crate::sql::engine::Transaction;
-use crate::sql::planner::{Direction, Node, Plan};
-use crate::sql::types::{Expression, Label, Row, Rows, Table, Value};
+use crate::sql::engine::{IoCounters, Transaction};
+use crate::sql::planner::{ConflictAction, ConflictTarget, Node, OnConflict, Plan, Returning};
+use crate::sql::types::{Expression, Label, Row, Rows, Table, Value, format_tuple};
 
 /// Executes statement plans.
 ///
@@ -43,15 +51,43 @@ use crate::sql::types::{Expression, Label, Row, Rows, Table, Value};
 /// 4. Projection picks out the requested column values from the rows.
 /// 5. Order sorts the rows by release date.
 /// 6. Select returns the final rows to the client.
+///
+/// Sorts, aggregates, and hash joins buffer rows in memory. These spill to
+/// disk when the plan exceeds the given memory budget, see MemoryBudget.
+///
+/// For EXPLAIN ANALYZE, the executor can also record runtime statistics for
+/// each node, by wrapping the node's row iterator. See analyze().
 pub struct Executor<'a, T: Transaction> {
     /// The transaction used to execute the plan.
     txn: &'a T,
+    /// Tracks the plan's memory usage.
+    memory: Memory,
+    /// Runtime statistics for each executed node in plan pre-order, if
+    /// analyzing. Shared with the nodes' row iterators.
+    stats: Option<Vec<Rc<Cell<NodeStats>>>>,
+    /// The session's sequence values, for sequence function calls.
+    sequences: SequenceValues,
 }
 
 impl<'a, T: Transaction> Executor<'a, T> {
-    /// Creates a new executor.
-    pub fn new(txn: &'a T) -> Self {
-        Self { txn }
+    /// Creates a new executor with the given memory budget and session
+    /// sequence values.
+    pub fn new(txn: &'a T, budget: MemoryBudget, sequences: SequenceValues) -> Self {
+        Self { txn, memory: Memory::new(budget), stats: None, sequences }
+    }
+
+    /// Records runtime statistics for each plan node (EXPLAIN ANALYZE). The
+    /// statistics are available via stats() once the result rows have been
+    /// consumed.
+    pub fn analyze(mut self) -> Self {
+        self.stats = Some(Vec::new());
+        self
+    }
+
+    /// Returns the recorded runtime statistics for each executed node, in plan
+    /// pre-order (i.e. the order of Plan::explain() nodes).
+    pub fn stats(&self) -> Vec<NodeStats> {
+        self.stats.iter().flatten().map(|stats| stats.get()).collect()
     }
 
     /// Executes a plan, returning an execution result.
@@ -64,24 +100,69 @@ impl<'a, T: Transaction> Executor<'a, T> {
                 ExecutionResult::CreateTable { name }
             }
 
+            // ALTER TABLE
+            Plan::AlterTable { name, alteration } => {
+                self.txn.alter_table(&name, alteration)?;
+                ExecutionResult::AlterTable { name }
+            }
+
             // DROP TABLE
             Plan::DropTable { name, if_exists } => {
                 let existed = self.txn.drop_table(&name, if_exists)?;
                 ExecutionResult::DropTable { name, existed }
             }
 
+            // CREATE INDEX
+            Plan::CreateIndex { name, table, columns, unique } => {
+                self.txn.create_index(&name, &table, &columns, unique)?;
+                ExecutionResult::CreateIndex { name }
+            }
+
+            // DROP INDEX
+            Plan::DropIndex { name, if_exists } => {
+                let existed = self.txn.drop_index(&name, if_exists)?;
+                ExecutionResult::DropIndex { name, existed }
+            }
+
+            // CREATE SEQUENCE
+            Plan::CreateSequence { sequence } => {
+                let name = sequence.name.clone();
+                self.txn.create_sequence(sequence)?;
+                ExecutionResult::CreateSequence { name }
+            }
+
+            // DROP SEQUENCE
+            Plan::DropSequence { name, if_exists } => {
+                let existed = self.txn.drop_sequence(&name, if_exists)?;
+                ExecutionResult::DropSequence { name, existed }
+            }
+
+            // ANALYZE
+            Plan::Analyze { tables } => {
+                for table in &tables {
+                    self.txn.analyze(table)?;
+                }
+                ExecutionResult::Analyze { tables }
+            }
+
             // DELETE
-            Plan::Delete { table, primary_key, source } => {
+            Plan::Delete { table, primary_key, source, returning } => {
                 let source = self.execute_node(source)?;
-                let count = self.delete(&table, primary_key, source)?;
-                ExecutionResult::Delete { count }
+                let rows = self.delete(&table, &primary_key, source)?;
+                match returning {
+                    Some(returning) => Self::returning(rows, returning)?,
+                    None => ExecutionResult::Delete { count: rows.len() as u64 },
+                }
             }
 
             // INSERT
-            Plan::Insert { table, column_map, source } => {
+            Plan::Insert { table, column_map, source, on_conflict, returning } => {
                 let source = self.execute_node(source)?;
-                let count = self.insert(table, column_map, source)?;
-                ExecutionResult::Insert { count }
+                let rows = self.insert(table, column_map, source, on_conflict)?;
+                match returning {
+                    Some(returning) => Self::returning(rows, returning)?,
+                    None => ExecutionResult::Insert { count: rows.len() as u64 },
+                }
             }
 
             // SELECT
@@ -92,23 +173,47 @@ impl<'a, T: Transaction> Executor<'a, T> {
             }
 
             // UPDATE
-            Plan::Update { table, primary_key, source, expressions } => {
+            Plan::Update { table, primary_key, source, expressions, returning } => {
                 let source = self.execute_node(source)?;
-                let count = self.update(&table.name, primary_key, source, expressions)?;
-                ExecutionResult::Update { count }
+                let rows = self.update(&table, &primary_key, source, expressions)?;
+                match returning {
+                    Some(returning) => Self::returning(rows, returning)?,
+                    None => ExecutionResult::Update { count: rows.len() as u64 },
+                }
             }
         })
     }
 
-    /// Recursively executes a query plan node, returning a row iterator.
+    /// Recursively executes a query plan node, returning a row iterator. If
+    /// analyzing, records the node's runtime statistics.
     fn execute_node(&mut self, node: Node) -> Result<Rows> {
+        let Some(stats) = &mut self.stats else {
+            return self.build_node(node);
+        };
+        // Register the node before its children are built, for pre-order.
+        let node_stats = Rc::new(Cell::new(NodeStats::default()));
+        stats.push(node_stats.clone());
+        let counters = self.txn.counters().clone();
+        let rows = record(&node_stats, &counters, || self.build_node(node))?;
+        Ok(Box::new(AnalyzedRows { rows, stats: node_stats, counters }))
+    }
+
+    /// Builds a query plan node's row iterator, recursively executing its
+    /// child nodes.
+    fn build_node(&mut self, node: Node) -> Result<Rows> {
         Ok(match node {
             // GROUP BY and aggregate functions.
             Node::Aggregate { source, group_by, aggregates } => {
                 let source = self.execute_node(*source)?;
-                let mut aggregator = Aggregator::new(group_by, aggregates);
+                let mut aggregator = Aggregator::new(group_by, aggregates, self.memory.clone());
                 aggregator.add_rows(source)?;
-                aggregator.into_rows()
+                aggregator.into_rows()?
+            }
+
+            // DISTINCT
+            Node::Distinct { source, sorted } => {
+                let source = self.execute_node(*source)?;
+                Self::distinct(source, sorted)
             }
 
             // WHERE and similar filtering.
@@ -126,42 +231,111 @@ impl<'a, T: Transaction> Executor<'a, T> {
             }
 
             // JOIN using a hash join.
-            Node::HashJoin { left, left_column, right, right_column, outer } => {
-                let right_columns = right.columns();
+            Node::HashJoin { left, left_column, right, right_column, outer, full } => {
+                let (left_columns, right_columns) = (left.columns(), right.columns());
                 let left = self.execute_node(*left)?;
                 let right = self.execute_node(*right)?;
                 Box::new(HashJoiner::new(
                     left,
                     left_column,
+                    left_columns,
                     right,
                     right_column,
                     right_columns,
                     outer,
+                    full,
+                    self.memory.clone(),
+                )?)
+            }
+
+            // Semi or anti join using a hash join.
+            Node::HashSemiJoin { left, left_column, right, right_column, anti, null_aware } => {
+                let left = self.execute_node(*left)?;
+                let right = self.execute_node(*right)?;
+                Box::new(HashSemiJoiner::new(
+                    left,
+                    left_column,
+                    right,
+                    right_column,
+                    anti,
+                    null_aware,
+                    self.memory.clone(),
                 )?)
             }
 
             // Looks up primary keys by secondary index values.
-            Node::IndexLookup { table, column, values, alias: _ } => {
-                let column = table.columns.into_iter().nth(column).expect("invalid column").name;
+            Node::IndexLookup { table, index, values, alias: _ } => {
                 let ids =
-                    self.txn.lookup_index(&table.name, &column, &values)?.into_iter().collect_vec();
+                    self.txn.lookup_index(&table.name, &index, &values)?.into_iter().collect_vec();
                 Box::new(self.txn.get(&table.name, &ids)?.into_iter().map(Ok))
             }
 
-            // Looks up rows by primary key.
+            // Scans a range of secondary index values, in index order.
+            Node::IndexRangeScan { table, index, range, alias: _ } => {
+                let ids = self.txn.scan_index_range(&table.name, &index, range)?;
+                Box::new(self.txn.get(&table.name, &ids)?.into_iter().map(Ok))
+            }
+
+            // Looks up rows by primary key. If the keys are prefixes of a
+            // composite primary key, scan the rows with each prefix instead.
             Node::KeyLookup { table, keys, alias: _ } => {
-                Box::new(self.txn.get(&table.name, &keys)?.into_iter().map(Ok))
+                if keys.iter().all(|key| key.len() == table.primary_key.len()) {
+                    return Ok(Box::new(self.txn.get(&table.name, &keys)?.into_iter().map(Ok)));
+                }
+                let scans: Vec<Rows> = keys
+                    .into_iter()
+                    .map(|key| {
+                        let range = (Bound::Included(key.clone()), Bound::Included(key));
+                        self.txn.scan_range(&table.name, range)
+                    })
+                    .try_collect()?;
+                Box::new(scans.into_iter().flatten())
+            }
+
+            // Scans a range of rows by primary key.
+            Node::KeyRangeScan { table, range, alias: _ } => {
+                self.txn.scan_range(&table.name, range)?
             }
 
             // LIMIT
             Node::Limit { source, limit } => Box::new(self.execute_node(*source)?.take(limit)),
 
-            // JOIN using a nested loop join.
-            Node::NestedLoopJoin { left, right, predicate, outer } => {
+            // JOIN using a merge join.
+            Node::MergeJoin { left, left_column, right, right_column, outer } => {
                 let right_columns = right.columns();
                 let left = self.execute_node(*left)?;
                 let right = self.execute_node(*right)?;
-                Box::new(NestedLoopJoiner::new(left, right, right_columns, predicate, outer))
+                Box::new(MergeJoiner::new(
+                    left,
+                    left_column,
+                    right,
+                    right_column,
+                    right_columns,
+                    outer,
+                ))
+            }
+
+            // JOIN using a nested loop join.
+            Node::NestedLoopJoin { left, right, predicate, outer, full } => {
+                let (left_columns, right_columns) = (left.columns(), right.columns());
+                let left = self.execute_node(*left)?;
+                let right = self.execute_node(*right)?;
+                Box::new(NestedLoopJoiner::new(
+                    left,
+                    left_columns,
+                    right,
+                    right_columns,
+                    predicate,
+                    outer,
+                    full,
+                ))
+            }
+
+            // Semi or anti join using a nested loop join.
+            Node::NestedLoopSemiJoin { left, right, predicate, anti, null_aware } => {
+                let left = self.execute_node(*left)?;
+                let right = self.execute_node(*right)?;
+                Box::new(NestedLoopSemiJoiner::new(left, right, predicate, anti, null_aware))
             }
 
             // An empty row iterator.
@@ -173,10 +347,25 @@ impl<'a, T: Transaction> Executor<'a, T> {
             // ORDER BY
             Node::Order { source, key } => {
                 let source = self.execute_node(*source)?;
-                Box::new(Self::order(source, key)?)
+                let mut sorter = Sorter::new(key, self.memory.clone());
+                sorter.add_rows(source)?;
+                sorter.into_rows()?
             }
 
             // Projects columns from the source, and evaluates expressions.
+            // Sequence calls need the transaction, so evaluate them eagerly.
+            Node::Projection { source, expressions, aliases: _ }
+                if expressions.iter().any(|expr| expr.has_sequence()) =>
+            {
+                let rows: Vec<Row> = self
+                    .execute_node(*source)?
+                    .map(|result| {
+                        let row = result?;
+                        expressions.iter().map(|expr| self.evaluate(expr, Some(&row))).collect()
+                    })
+                    .try_collect()?;
+                Box::new(rows.into_iter().map(Ok))
+            }
             Node::Projection { source, expressions, aliases: _ } => {
                 let source = self.execute_node(*source)?;
                 Box::new(source.map(move |result| {
@@ -203,7 +392,22 @@ impl<'a, T: Transaction> Executor<'a, T> {
             // Scans a table, optionally filtering rows.
             Node::Scan { table, filter, alias: _ } => Box::new(self.txn.scan(&table.name, filter)?),
 
-            // Emits constant values.
+            // ORDER BY with LIMIT and OFFSET.
+            Node::TopN { source, key, limit, offset } => {
+                let source = self.execute_node(*source)?;
+                let mut top_n = TopN::new(key, limit, offset, self.memory.clone());
+                top_n.add_rows(source)?;
+                top_n.into_rows()?
+            }
+
+            // Emits constant values. Sequence calls are evaluated eagerly.
+            Node::Values { rows } if rows.iter().flatten().any(|expr| expr.has_sequence()) => {
+                let rows: Vec<Row> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|expr| self.evaluate(expr, None)).collect())
+                    .try_collect()?;
+                Box::new(rows.into_iter().map(Ok))
+            }
             Node::Values { rows } => Box::new(
                 rows.into_iter()
                     .map(|row| row.into_iter().map(|expr| expr.evaluate(None)).collect()),
@@ -212,14 +416,18 @@ impl<'a, T: Transaction> Executor<'a, T> {
     }
 
     /// DELETE: deletes rows, taking primary keys from the source at the given
-    /// primary_key column index. Returns the number of rows deleted.
-    fn delete(&self, table: &str, primary_key: usize, source: Rows) -> Result<u64> {
-        let ids: Vec<Value> = source
-            .map_ok(|row| row.into_iter().nth(primary_key).expect("short row"))
-            .try_collect()?;
-        let count = ids.len() as u64;
-        self.txn.delete(table, &ids)?;
-        Ok(count)
+    /// primary_key column indexes. The source rows may contain duplicates and
+    /// trailing columns from joined tables. Returns the deleted rows, including
+    /// the joined columns of the first matching source row.
+    fn delete(&self, table: &Table, primary_key: &[usize], mut source: Rows) -> Result<Vec<Row>> {
+        let mut deletes = BTreeMap::new();
+        while let Some(row) = source.next().transpose()? {
+            let id = primary_key.iter().map(|&i| row[i].clone()).collect_vec();
+            deletes.entry(id).or_insert(row);
+        }
+        let ids = deletes.keys().cloned().collect_vec();
+        self.txn.delete(&table.name, &ids)?;
+        Ok(deletes.into_values().collect())
     }
 
     /// INSERT: inserts rows into a table from the given source.
@@ -227,12 +435,16 @@ impl<'a, T: Transaction> Executor<'a, T> {
     /// If given, column_map contains the mapping of table → source columns for
     /// all columns in source. Otherwise, every column in source corresponds to
     /// those in table, but a tail of source columns may be missing.
+    ///
+    /// If given, on_conflict handles rows that conflict with existing rows, see
+    /// upsert(). Returns the inserted rows.
     fn insert(
         &self,
         table: Table,
         column_map: Option<HashMap<usize, usize>>,
         mut source: Rows,
-    ) -> Result<u64> {
+        on_conflict: Option<OnConflict>,
+    ) -> Result<Vec<Row>> {
         let mut rows = Vec::new();
         while let Some(values) = source.next().transpose()? {
             // Fast path: the row is already complete, with no column mapping.
@@ -258,6 +470,9 @@ impl<'a, T: Transaction> Executor<'a, T> {
                 } else if let Some(vi) = column_map.as_ref().and_then(|c| c.get(&i)).copied() {
                     // Map the source column to the table column.
                     row.push(values[vi].clone())
+                } else if let Some(sequence) = &column.sequence {
+                    // Column not given in source, draw from its sequence.
+                    row.push(Value::Integer(self.next_sequence_value(sequence)?))
                 } else if let Some(default) = &column.default {
                     // Column not given in source, use the default.
                     row.push(default.clone())
@@ -267,73 +482,260 @@ impl<'a, T: Transaction> Executor<'a, T> {
             }
             rows.push(row);
         }
-        let count = rows.len() as u64;
-        self.txn.insert(&table.name, rows)?;
-        Ok(count)
+        if let Some(on_conflict) = on_conflict {
+            return self.upsert(&table, rows, &on_conflict);
+        }
+        self.txn.insert(&table.name, rows.clone())?;
+        Ok(rows)
+    }
+
+    /// INSERT ON CONFLICT: inserts rows, but skips or updates rows that
+    /// conflict with an existing row on the primary key or unique index
+    /// targets. Returns the inserted and updated rows.
+    ///
+    /// Rows are written one at a time, so a row can also conflict with a
+    /// previous row in the same statement. This isn't performant, but it's
+    /// fine for our purposes. Like PostgreSQL, DO UPDATE errors if it would
+    /// affect a row written by the same statement, since the result would
+    /// depend on the row order.
+    fn upsert(&self, table: &Table, rows: Vec<Row>, on_conflict: &OnConflict) -> Result<Vec<Row>> {
+        let mut written = Vec::new();
+        let mut written_ids = HashSet::new();
+        for row in rows {
+            let Some(existing) = self.find_conflict(table, &row, &on_conflict.targets)? else {
+                self.txn.insert(&table.name, vec![row.clone()])?;
+                written_ids.insert(table.primary_key_values(&row));
+                written.push(row);
+                continue;
+            };
+            let ConflictAction::Update { expressions, filter } = &on_conflict.action else {
+                continue; // DO NOTHING
+            };
+            let id = table.primary_key_values(&existing);
+            if written_ids.contains(&id) {
+                return errinput!(
+                    "ON CONFLICT DO UPDATE can't affect row {} twice",
+                    format_tuple(&id)
+                );
+            }
+            // Evaluate the update on the existing row followed by the inserted
+            // (excluded) row.
+            let input = existing.iter().chain(&row).cloned().collect();
+            if let Some(filter) = filter {
+                match filter.evaluate(Some(&input))? {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) | Value::Null => continue,
+                    value => return errinput!("filter returned {value}, expected boolean"),
+                }
+            }
+            let mut update = existing.clone();
+            for (column, expr) in expressions {
+                update[*column] = self.evaluate(expr, Some(&input))?;
+            }
+            written_ids.insert(table.primary_key_values(&update));
+            self.txn.update(&table.name, BTreeMap::from([(id, update.clone())]))?;
+            written.push(update);
+        }
+        Ok(written)
+    }
+
+    /// Returns an existing row that conflicts with the given row on one of the
+    /// target constraints, if any.
+    fn find_conflict(
+        &self,
+        table: &Table,
+        row: &Row,
+        targets: &[ConflictTarget],
+    ) -> Result<Option<Row>> {
+        for target in targets {
+            let ids = match target {
+                ConflictTarget::PrimaryKey => vec![table.primary_key_values(row)],
+                ConflictTarget::Index(index) => {
+                    // NULL and NaN values never conflict.
+                    let values = index.values(row);
+                    if values.iter().any(|v| v.is_undefined()) {
+                        continue;
+                    }
+                    let ids = self.txn.lookup_index(&table.name, &index.name, &[values])?;
+                    ids.into_iter().collect()
+                }
+            };
+            if let Some(existing) = self.txn.get(&table.name, &ids)?.into_iter().next() {
+                return Ok(Some(existing));
+            }
+        }
+        Ok(None)
     }
 
-    /// UPDATE: updates rows passed in from the source. Returns the number of
-    /// rows updated.
+    /// UPDATE: updates rows passed in from the source. The source rows may
+    /// have trailing columns from joined tables, which are not written. If a
+    /// row occurs multiple times, the last update wins. Returns the updated
+    /// rows, including the joined columns of the winning source row.
     fn update(
         &self,
-        table: &str,
-        primary_key: usize,
+        table: &Table,
+        primary_key: &[usize],
         mut source: Rows,
         expressions: Vec<(usize, Expression)>,
-    ) -> Result<u64> {
-        let mut updates = BTreeMap::new();
+    ) -> Result<Vec<Row>> {
+        let (mut updates, mut rows) = (BTreeMap::new(), BTreeMap::new());
         while let Some(row) = source.next().transpose()? {
-            let mut update = row.clone();
+            let mut update = row[..table.columns.len()].to_vec();
             for (column, expr) in &expressions {
-                update[*column] = expr.evaluate(Some(&row))?;
+                update[*column] = self.evaluate(expr, Some(&row))?;
             }
-            let id = row.into_iter().nth(primary_key).expect("short row");
+            let id = primary_key.iter().map(|&i| row[i].clone()).collect_vec();
+            let joined = row[table.columns.len()..].iter();
+            rows.insert(id.clone(), update.iter().chain(joined).cloned().collect_vec());
             updates.insert(id, update);
         }
-        let count = updates.len() as u64;
-        self.txn.update(table, updates)?;
-        Ok(count)
+        let rows = rows.into_values().collect();
+        self.txn.update(&table.name, updates)?;
+        Ok(rows)
     }
 
-    /// Sorts the input rows.
-    fn order(source: Rows, order: Vec<(Expression, Direction)>) -> Result<Rows> {
-        // We can't use sorted_by_cached_key(), since expression evaluation is
-        // fallible, and since we may have to vary the sort direction of each
-        // expression. Collect the rows and pre-computed sort keys into a vec.
-        let mut rows: Vec<(Row, Vec<Value>)> = source
-            .map(|result| {
-                result.and_then(|row| {
-                    let sort_keys =
-                        order.iter().map(|(expr, _)| expr.evaluate(Some(&row))).try_collect()?;
-                    Ok((row, sort_keys))
-                })
-            })
+    /// Evaluates an expression, first replacing any sequence function calls
+    /// with their values. Sequence calls are evaluated depth-first from left to
+    /// right, so currval() sees the value of a preceding nextval().
+    fn evaluate(&self, expr: &Expression, row: Option<&Row>) -> Result<Value> {
+        if !expr.has_sequence() {
+            return expr.evaluate(row);
+        }
+        let expr = expr.clone().transform(&Ok, &|expr| match expr {
+            Expression::NextValue(sequence) => {
+                Ok(Value::Integer(self.next_sequence_value(&sequence)?).into())
+            }
+            Expression::CurrentValue(sequence) => {
+                Ok(Value::Integer(self.sequences.get(&sequence)?).into())
+            }
+            expr => Ok(expr),
+        })?;
+        expr.evaluate(row)
+    }
+
+    /// Draws the next value from a sequence, recording it in the session's
+    /// sequence values for currval().
+    fn next_sequence_value(&self, sequence: &str) -> Result<i64> {
+        let value = self.txn.next_sequence_value(sequence)?;
+        self.sequences.set(sequence, value)?;
+        Ok(value)
+    }
+
+    /// RETURNING: returns the affected rows of an INSERT, UPDATE, or DELETE,
+    /// projected through the RETURNING expressions.
+    fn returning(rows: Vec<Row>, returning: Returning) -> Result<ExecutionResult> {
+        let Returning { expressions, labels } = returning;
+        let rows: Vec<Row> = rows
+            .into_iter()
+            .map(|row| expressions.iter().map(|expr| expr.evaluate(Some(&row))).collect())
             .try_collect()?;
+        Ok(ExecutionResult::Select { columns: labels, rows: Box::new(rows.into_iter().map(Ok)) })
+    }
 
-        rows.sort_by(|(_, a_keys), (_, b_keys)| {
-            let dirs = order.iter().map(|(_, dir)| dir).copied();
-            for (a_key, b_key, dir) in izip!(a_keys, b_keys, dirs) {
-                let mut ordering = a_key.cmp(b_key);
-                if dir == Direction::Descending {
-                    ordering = ordering.reverse();
+    /// Discards duplicate input rows. If sorted is true, duplicate rows are
+    /// adjacent, and only need to be compared with the previous row.
+    fn distinct(source: Rows, sorted: bool) -> Rows {
+        if sorted {
+            let mut previous = None;
+            return Box::new(source.filter_ok(move |row| {
+                if previous.as_ref() == Some(row) {
+                    return false;
                 }
-                if ordering != Ordering::Equal {
-                    return ordering;
-                }
-            }
-            Ordering::Equal
-        });
-
-        Ok(Box::new(rows.into_iter().map(|(row, _)| Ok(row))))
+                previous = Some(row.clone());
+                true
+            }));
+        }
+        let mut seen = HashSet::new();
+        Box::new(source.filter_ok(move |row| seen.insert(row.clone())))
     }
 }
 
 /// A plan execution result.
 pub enum ExecutionResult {
+    AlterTable { name: String },
     CreateTable { name: String },
     DropTable { name: String, existed: bool },
+    CreateIndex { name: String },
+    DropIndex { name: String, existed: bool },
+    CreateSequence { name: String },
+    DropSequence { name: String, existed: bool },
+    Analyze { tables: Vec<String> },
     Delete { count: u64 },
     Insert { count: u64 },
     Update { count: u64 },
     Select { columns: Vec<Label>, rows: Rows },
 }
+
+/// A session's sequence values: the value last drawn from each sequence via
+/// nextval() (including for SERIAL columns), returned by currval(). Clones
+/// share the values.
+#[derive(Clone, Debug, Default)]
+pub struct SequenceValues(Arc<Mutex<HashMap<String, i64>>>);
+
+impl SequenceValues {
+    /// Returns the last value drawn from the sequence in this session.
+    fn get(&self, sequence: &str) -> Result<i64> {
+        match self.0.lock()?.get(sequence) {
+            Some(value) => Ok(*value),
+            None => errinput!("currval of sequence {sequence} is not yet defined in this session"),
+        }
+    }
+
+    /// Records the last value drawn from the sequence.
+    fn set(&self, sequence: &str, value: i64) -> Result<()> {
+        self.0.lock()?.insert(sequence.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Runtime statistics for a plan node, recorded by EXPLAIN ANALYZE. These
+/// include the node's children, e.g. the time spent in child nodes, and are
+/// summed across repeated scans of the node (e.g. the inner side of a nested
+/// loop join).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NodeStats {
+    /// The number of rows emitted by the node.
+    pub rows: u64,
+    /// The time spent executing the node.
+    pub time: Duration,
+    /// The number of row and index keys read from storage.
+    pub keys_read: u64,
+    /// The number of Raft round trips.
+    pub round_trips: u64,
+}
+
+/// A row iterator that records runtime statistics for EXPLAIN ANALYZE. See
+/// Executor::analyze().
+#[derive(Clone)]
+struct AnalyzedRows {
+    rows: Rows,
+    stats: Rc<Cell<NodeStats>>,
+    counters: IoCounters,
+}
+
+impl Iterator for AnalyzedRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = record(&self.stats, &self.counters, || self.rows.next());
+        if let Some(Ok(_)) = next {
+            let mut stats = self.stats.get();
+            stats.rows += 1;
+            self.stats.set(stats);
+        }
+        next
+    }
+}
+
+/// Runs the closure, adding its time and I/O to the given node stats.
+fn record<R>(stats: &Cell<NodeStats>, counters: &IoCounters, f: impl FnOnce() -> R) -> R {
+    let (keys_read, round_trips) = (counters.keys_read(), counters.round_trips());
+    let start = Instant::now();
+    let result = f();
+    let mut update = stats.get();
+    update.time += start.elapsed();
+    update.keys_read += counters.keys_read() - keys_read;
+    update.round_trips += counters.round_trips() - round_trips;
+    stats.set(update);
+    result
+}
@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::iter::Peekable;
 
+use itertools::Itertools as _;
+
+use super::spill::{Memory, Reservation, SpillFile, row_size};
 use crate::errinput;
 use crate::error::Result;
 use crate::sql::types::{Expression, Row, Rows, Value};
@@ -14,13 +17,18 @@ use crate::sql::types::{Expression, Row, Rows, Value};
 /// the left source, a joined row with NULL values for the right source is
 /// returned (typically used for a LEFT JOIN).
 ///
+/// If full is true, the positions of matched right rows are also tracked, and
+/// once the left source is exhausted, a joined row with NULL values for the
+/// left source is returned for every unmatched right row (for a FULL JOIN).
+///
 /// This could be trivially implemented with carthesian_product(), but we need
-/// to handle the left outer join case where there is no match in the right
-/// source.
+/// to handle the outer join cases where there is no match in the other source.
 #[derive(Clone)]
 pub struct NestedLoopJoiner {
     /// The left source.
     left: Peekable<Rows>,
+    /// The number of columns in the left source.
+    left_columns: usize,
     /// The right source.
     right: Rows,
     /// The original right iterator state. Can be cloned to reset the
@@ -28,26 +36,46 @@ pub struct NestedLoopJoiner {
     right_original: Rows,
     /// The number of columns in the right source.
     right_columns: usize,
+    /// The position of the next row in the right source.
+    right_index: usize,
     /// True if a right match has been seen for the current left row.
     right_matched: bool,
+    /// For full joins, the positions of right rows that have been matched.
+    right_matches: HashSet<usize>,
     /// The join predicate.
     predicate: Option<Expression>,
     /// If true, emit a row when there is no match in the right source.
     outer: bool,
+    /// If true, emit a row when there is no match in the left source.
+    full: bool,
 }
 
 impl NestedLoopJoiner {
     /// Creates a new nested loop joiner.
     pub fn new(
         left: Rows,
+        left_columns: usize,
         right: Rows,
         right_columns: usize,
         predicate: Option<Expression>,
         outer: bool,
+        full: bool,
     ) -> Self {
         let left = left.peekable();
         let right_original = right.clone();
-        Self { left, right, right_original, right_columns, right_matched: false, predicate, outer }
+        Self {
+            left,
+            left_columns,
+            right,
+            right_original,
+            right_columns,
+            right_index: 0,
+            right_matched: false,
+            right_matches: HashSet::new(),
+            predicate,
+            outer,
+            full,
+        }
     }
 
     // Returns the next joined row, if any.
@@ -56,6 +84,7 @@ impl NestedLoopJoiner {
         while let Some(Ok(left)) = self.left.peek() {
             // If there is a match in the remaining right rows, return it.
             while let Some(right) = self.right.next().transpose()? {
+                self.right_index += 1;
                 let row = left.iter().cloned().chain(right).collect();
                 if let Some(predicate) = &self.predicate {
                     match predicate.evaluate(Some(&row))? {
@@ -65,6 +94,9 @@ impl NestedLoopJoiner {
                     }
                 }
                 self.right_matched = true;
+                if self.full {
+                    self.right_matches.insert(self.right_index - 1);
+                }
                 return Ok(Some(row));
             }
 
@@ -83,12 +115,29 @@ impl NestedLoopJoiner {
             // We reached the end of the right source. Reset it and move onto
             // the next left row.
             self.right = self.right_original.clone();
+            self.right_index = 0;
             self.right_matched = false;
             self.left.next().transpose()?;
         }
 
-        // Otherwise, there's either a None or Err in left. Return it.
-        self.left.next().transpose()
+        // Otherwise, there's either a None or Err in left. Return any error.
+        if let Some(Err(_)) = self.left.peek() {
+            return self.left.next().transpose();
+        }
+
+        // If this is a full join, make a final pass over the right source and
+        // emit rows with left NULLs for the unmatched right rows.
+        if self.full {
+            while let Some(right) = self.right.next().transpose()? {
+                self.right_index += 1;
+                if !self.right_matches.contains(&(self.right_index - 1)) {
+                    return Ok(Some(
+                        std::iter::repeat_n(Value::Null, self.left_columns).chain(right).collect(),
+                    ));
+                }
+            }
+        }
+        Ok(None)
     }
 }
 
@@ -108,45 +157,152 @@ impl Iterator for NestedLoopJoiner {
 ///
 /// If outer is true, and there is no match in the right source for a row in the
 /// left source, a row with NULL values for the right source is emitted instead.
+///
+/// If full is true, matched right rows are tracked, and once the left source is
+/// exhausted, a row with NULL values for the left source is emitted for every
+/// unmatched right row.
+///
+/// If the right rows exceed the query's memory budget, both sources are spilled
+/// to disk in hash partitions by join value, and each pair of left and right
+/// partitions is joined separately (i.e. a grace hash join). Rows are then no
+/// longer emitted in left source order.
 #[derive(Clone)]
 pub struct HashJoiner {
     /// The left source.
     left: Rows,
     /// The left column to join on.
     left_column: usize,
-    /// The right hash map to join on.
-    right: HashMap<Value, Vec<Row>>,
+    /// The number of columns in the left source.
+    left_columns: usize,
+    /// The right rows, in order.
+    right: Vec<Row>,
+    /// The right hash map to join on, with positions in the right rows.
+    right_map: HashMap<Value, Vec<usize>>,
     /// The number of columns in the right source.
     right_columns: usize,
+    /// The right column to join on.
+    right_column: usize,
+    /// For full joins, the positions of right rows that have been matched.
+    right_matches: HashSet<usize>,
     /// If true, emit a row when there is no match in the right source.
     outer: bool,
+    /// If true, emit a row when there is no match in the left source.
+    full: bool,
     /// Any pending matches to emit.
     pending: Rows,
+    /// The memory reservation for the right rows.
+    reservation: Reservation,
+    /// The memory tracker, used to spill partitions.
+    memory: Memory,
+    /// The spill depth of the sources (0 unless this is a partition).
+    depth: usize,
+    /// Spilled left and right partitions to join. Empty partitions are None.
+    partitions: VecDeque<(Option<SpillFile>, Option<SpillFile>)>,
 }
 
 impl HashJoiner {
     /// Creates a new hash joiner.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         left: Rows,
         left_column: usize,
-        mut right: Rows,
+        left_columns: usize,
+        right: Rows,
         right_column: usize,
         right_columns: usize,
         outer: bool,
+        full: bool,
+        memory: Memory,
     ) -> Result<Self> {
-        // Build a hash map from the right source.
-        let mut right_map: HashMap<Value, Vec<Row>> = HashMap::new();
+        let mut joiner = Self {
+            left,
+            left_column,
+            left_columns,
+            right: Vec::new(),
+            right_map: HashMap::new(),
+            right_columns,
+            right_column,
+            right_matches: HashSet::new(),
+            outer,
+            full,
+            pending: Box::new(std::iter::empty()),
+            reservation: memory.reserve(),
+            memory,
+            depth: 0,
+            partitions: VecDeque::new(),
+        };
+        joiner.build(right)?;
+        Ok(joiner)
+    }
+
+    /// Builds a hash map from the right source. Rows with undefined join
+    /// values will never match anything, so they're only retained for full
+    /// joins, which emit them with left NULLs.
+    ///
+    /// If the query exceeds its memory budget, the sources are partitioned
+    /// instead.
+    fn build(&mut self, mut right: Rows) -> Result<()> {
         while let Some(row) = right.next().transpose()? {
-            let value = row[right_column].clone();
-            if value.is_undefined() {
-                continue; // undefined will never match anything
+            let value = row[self.right_column].clone();
+            if !value.is_undefined() {
+                self.right_map.entry(value).or_default().push(self.right.len());
+            } else if !self.full {
+                continue;
+            }
+            let size = row_size(&row);
+            self.right.push(row);
+            if self.reservation.grow(size) && self.partition(&mut right)? {
+                break;
             }
-            right_map.entry(value).or_default().push(row);
         }
+        Ok(())
+    }
 
-        let pending = Box::new(std::iter::empty());
+    /// Spills the buffered right rows and the rest of the right and left
+    /// sources to disk in hash partitions by join value. Returns false if
+    /// the maximum spill depth has been reached.
+    fn partition(&mut self, right: &mut Rows) -> Result<bool> {
+        let (Some(mut left_partitions), Some(mut right_partitions)) =
+            (self.memory.partition(self.depth), self.memory.partition(self.depth))
+        else {
+            return Ok(false);
+        };
+        for row in std::mem::take(&mut self.right).into_iter().map(Ok).chain(right) {
+            let row = row?;
+            right_partitions.add(&row[self.right_column], &row)?;
+        }
+        self.right_map.clear();
+        self.reservation.clear();
 
-        Ok(Self { left, left_column, right: right_map, right_columns, outer, pending })
+        let left = std::mem::replace(&mut self.left, Box::new(std::iter::empty()));
+        for row in left {
+            let row = row?;
+            left_partitions.add(&row[self.left_column], &row)?;
+        }
+        self.partitions =
+            left_partitions.finish()?.into_iter().zip(right_partitions.finish()?).collect();
+        Ok(true)
+    }
+
+    /// Creates a hash joiner for a pair of spilled partitions.
+    fn join_partition(&self, left: Option<SpillFile>, right: Option<SpillFile>) -> Result<Self> {
+        let empty = || -> Rows { Box::new(std::iter::empty()) };
+        let left: Rows = left.map_or_else(empty, |left| Box::new(left.read()));
+        let right: Rows = right.map_or_else(empty, |right| Box::new(right.read()));
+        let mut joiner = Self {
+            left,
+            right: Vec::new(),
+            right_map: HashMap::new(),
+            right_matches: HashSet::new(),
+            pending: empty(),
+            reservation: self.memory.reserve(),
+            memory: self.memory.clone(),
+            depth: self.depth + 1,
+            partitions: VecDeque::new(),
+            ..*self
+        };
+        joiner.build(right)?;
+        Ok(joiner)
     }
 
     // Returns the next joined row, if any.
@@ -158,8 +314,12 @@ impl HashJoiner {
 
         // Find the next left row to join with.
         while let Some(left) = self.left.next().transpose()? {
-            if let Some(right) = self.right.get(&left[self.left_column]).cloned() {
+            if let Some(matches) = self.right_map.get(&left[self.left_column]) {
                 // Join with all right matches and stash them in pending.
+                if self.full {
+                    self.right_matches.extend(matches);
+                }
+                let right = matches.iter().map(|i| self.right[*i].clone()).collect_vec();
                 self.pending = Box::new(
                     right
                         .into_iter()
@@ -178,6 +338,39 @@ impl HashJoiner {
             }
         }
 
+        // If the sources were spilled, join each pair of partitions and stash
+        // the results in pending. Partitions without left rows can only
+        // produce rows for full joins, and without right rows for outer joins.
+        while let Some((left, right)) = self.partitions.pop_front() {
+            if (left.is_none() && !self.full) || (right.is_none() && !self.outer) {
+                continue;
+            }
+            self.pending = Box::new(self.join_partition(left, right)?);
+            if let Some(row) = self.pending.next().transpose()? {
+                return Ok(Some(row));
+            }
+        }
+
+        // If this is a full join, emit rows with left NULLs for the unmatched
+        // right rows, and stash them in pending. Take the right rows, such
+        // that this only happens once.
+        if self.full {
+            let right = std::mem::take(&mut self.right);
+            let right_matches = std::mem::take(&mut self.right_matches);
+            let left_columns = self.left_columns;
+            self.pending = Box::new(
+                right
+                    .into_iter()
+                    .enumerate()
+                    .filter(move |(i, _)| !right_matches.contains(i))
+                    .map(move |(_, right)| {
+                        std::iter::repeat_n(Value::Null, left_columns).chain(right).collect()
+                    })
+                    .map(Ok),
+            );
+            return self.pending.next().transpose();
+        }
+
         Ok(None)
     }
 }
@@ -189,3 +382,354 @@ impl Iterator for HashJoiner {
         self.try_next().transpose()
     }
 }
+
+/// MergeJoiner implements sort-merge joins.
+///
+/// Both sources must be sorted in ascending order by their join column. For
+/// each left row, the right source is advanced past lower join values, and the
+/// run of right rows with an equal join value is buffered and joined with the
+/// left row. Since the left source is sorted, the run can be reused for
+/// subsequent left rows with the same join value, and discarded afterwards.
+/// Undefined join values (NULL and NaN) never match anything.
+///
+/// If outer is true, and there is no match in the right source for a row in the
+/// left source, a row with NULL values for the right source is emitted instead.
+#[derive(Clone)]
+pub struct MergeJoiner {
+    /// The left source.
+    left: Rows,
+    /// The left column to join on.
+    left_column: usize,
+    /// The right source.
+    right: Peekable<Rows>,
+    /// The right column to join on.
+    right_column: usize,
+    /// The number of columns in the right source.
+    right_columns: usize,
+    /// If true, emit a row when there is no match in the right source.
+    outer: bool,
+    /// The current run of right rows with the same join value.
+    run: Vec<Row>,
+    /// The current left row, and the next run row to join it with.
+    current: Option<(Row, usize)>,
+}
+
+impl MergeJoiner {
+    /// Creates a new merge joiner.
+    pub fn new(
+        left: Rows,
+        left_column: usize,
+        right: Rows,
+        right_column: usize,
+        right_columns: usize,
+        outer: bool,
+    ) -> Self {
+        let right = right.peekable();
+        Self {
+            left,
+            left_column,
+            right,
+            right_column,
+            right_columns,
+            outer,
+            run: Vec::new(),
+            current: None,
+        }
+    }
+
+    // Returns the next joined row, if any.
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        // Join the current left row with the next row in the run, if any.
+        if let Some((left, index)) = &mut self.current {
+            if let Some(right) = self.run.get(*index) {
+                *index += 1;
+                return Ok(Some(left.iter().chain(right).cloned().collect()));
+            }
+            self.current = None;
+        }
+
+        // Find the next left row to join with.
+        while let Some(left) = self.left.next().transpose()? {
+            let value = &left[self.left_column];
+            if !value.is_undefined() {
+                // Unless the run already matches the left value, skip right
+                // rows with lower (or undefined) values and buffer the run of
+                // right rows with an equal value.
+                if self.run.first().is_none_or(|right| right[self.right_column] != *value) {
+                    self.run.clear();
+                    let column = self.right_column;
+                    while self
+                        .right
+                        .next_if(|r| {
+                            r.as_ref().is_ok_and(|r| r[column].is_undefined() || r[column] < *value)
+                        })
+                        .is_some()
+                    {}
+                    while let Some(right) =
+                        self.right.next_if(|r| r.as_ref().is_ok_and(|r| r[column] == *value))
+                    {
+                        self.run.push(right?);
+                    }
+                    if let Some(Err(_)) = self.right.peek() {
+                        return self.right.next().transpose();
+                    }
+                }
+
+                // Join the left row with the first row in the run, and stash
+                // it to join with the rest of the run.
+                if let Some(right) = self.run.first() {
+                    let row = left.iter().chain(right).cloned().collect();
+                    self.current = Some((left, 1));
+                    return Ok(Some(row));
+                }
+            }
+
+            // If there is no match for the left row, but it's an outer join,
+            // emit a row with right NULLs.
+            if self.outer {
+                return Ok(Some(
+                    left.into_iter()
+                        .chain(std::iter::repeat_n(Value::Null, self.right_columns))
+                        .collect(),
+                ));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Iterator for MergeJoiner {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// NestedLoopSemiJoiner implements nested loop semi joins and anti joins.
+///
+/// For every row in the left source, iterate over the right source until a
+/// row matches the join predicate (if any). Emit the left row if there was a
+/// match, or if anti is true, if there wasn't.
+///
+/// If null_aware is true, a NULL predicate result is also considered a match.
+/// This implements NOT IN semantics, where e.g. 1 NOT IN (2, NULL) is NULL
+/// rather than true, since the NULL could be 1.
+#[derive(Clone)]
+pub struct NestedLoopSemiJoiner {
+    /// The left source.
+    left: Rows,
+    /// The right source. Cloned to iterate over it for each left row.
+    right: Rows,
+    /// The join predicate.
+    predicate: Option<Expression>,
+    /// If true, emit left rows without a right match.
+    anti: bool,
+    /// If true, NULL predicate results are matches.
+    null_aware: bool,
+}
+
+impl NestedLoopSemiJoiner {
+    /// Creates a new nested loop semi joiner.
+    pub fn new(
+        left: Rows,
+        right: Rows,
+        predicate: Option<Expression>,
+        anti: bool,
+        null_aware: bool,
+    ) -> Self {
+        Self { left, right, predicate, anti, null_aware }
+    }
+
+    /// Returns true if the left row has a match in the right source.
+    fn matches(&self, left: &Row) -> Result<bool> {
+        for right in self.right.clone() {
+            let right = right?;
+            let Some(predicate) = &self.predicate else {
+                return Ok(true);
+            };
+            let row: Row = left.iter().cloned().chain(right).collect();
+            match predicate.evaluate(Some(&row))? {
+                Value::Boolean(true) => return Ok(true),
+                Value::Null if self.null_aware => return Ok(true),
+                Value::Boolean(false) | Value::Null => {}
+                v => return errinput!("join predicate returned {v}, expected boolean"),
+            }
+        }
+        Ok(false)
+    }
+
+    // Returns the next left row, if any.
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        while let Some(left) = self.left.next().transpose()? {
+            if self.matches(&left)? != self.anti {
+                return Ok(Some(left));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for NestedLoopSemiJoiner {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// HashSemiJoiner implements hash semi joins and anti joins.
+///
+/// This builds a hash set of the join values in the right source, then
+/// iterates over the left source and emits left rows whose join value is in
+/// the set, or if anti is true, those whose join value isn't.
+///
+/// If null_aware is true, NULL join values are considered to match anything,
+/// like a NULL result of the equality predicate in NestedLoopSemiJoiner. This
+/// implements NOT IN semantics: if the right source is non-empty, a left row
+/// with a NULL value is never emitted, and if the right source contains a
+/// NULL value, no left rows are emitted at all.
+///
+/// If the right join values exceed the query's memory budget, they're spilled
+/// to disk along with the rest of the right source, and each left row is
+/// instead matched by iterating over the spilled values (like
+/// NestedLoopSemiJoiner). Unlike a grace hash join, this retains the left
+/// source order.
+#[derive(Clone)]
+pub struct HashSemiJoiner {
+    /// The left source.
+    left: Rows,
+    /// The left column to join on.
+    left_column: usize,
+    /// The spilled right join values, if they exceeded the memory budget.
+    /// Cloned to iterate over them for each left row.
+    right: Option<Rows>,
+    /// The right column to join on.
+    right_column: usize,
+    /// The right join values, unless they exceeded the memory budget.
+    right_values: HashSet<Value>,
+    /// True if the right source is empty.
+    right_empty: bool,
+    /// True if the right source has a NULL join value.
+    right_null: bool,
+    /// If true, emit left rows without a right match.
+    anti: bool,
+    /// If true, NULL join values match anything.
+    null_aware: bool,
+    /// The memory reservation for the right join values.
+    reservation: Reservation,
+}
+
+impl HashSemiJoiner {
+    /// Creates a new hash semi joiner.
+    pub fn new(
+        left: Rows,
+        left_column: usize,
+        right: Rows,
+        right_column: usize,
+        anti: bool,
+        null_aware: bool,
+        memory: Memory,
+    ) -> Result<Self> {
+        let mut joiner = Self {
+            left,
+            left_column,
+            right: None,
+            right_column,
+            right_values: HashSet::new(),
+            right_empty: true,
+            right_null: false,
+            anti,
+            null_aware,
+            reservation: memory.reserve(),
+        };
+        joiner.build(right, &memory)?;
+        Ok(joiner)
+    }
+
+    /// Builds a hash set from the right source. Undefined join values never
+    /// match anything, but NULLs are tracked for null-aware joins.
+    ///
+    /// If the query exceeds its memory budget, the join values are spilled
+    /// instead.
+    fn build(&mut self, mut right: Rows, memory: &Memory) -> Result<()> {
+        while let Some(row) = right.next().transpose()? {
+            self.right_empty = false;
+            let Some(value) = self.join_value(row) else {
+                continue;
+            };
+            let size = row_size(std::slice::from_ref(&value));
+            if self.right_values.insert(value) && self.reservation.grow(size) {
+                return self.spill(&mut right, memory);
+            }
+        }
+        Ok(())
+    }
+
+    /// Spills the hash set and the rest of the right source's join values to
+    /// disk, and releases the hash set.
+    fn spill(&mut self, right: &mut Rows, memory: &Memory) -> Result<()> {
+        let mut spill = memory.spill::<Row>()?;
+        for value in std::mem::take(&mut self.right_values) {
+            spill.write(&vec![value])?;
+        }
+        self.reservation.clear();
+        for row in right {
+            if let Some(value) = self.join_value(row?) {
+                spill.write(&vec![value])?;
+            }
+        }
+        self.right = Some(Box::new(spill.finish()?.read()));
+        self.right_column = 0;
+        Ok(())
+    }
+
+    /// Returns a right row's join value, unless it's undefined. Records
+    /// whether the right source has a NULL value.
+    fn join_value(&mut self, mut row: Row) -> Option<Value> {
+        let value = row.swap_remove(self.right_column);
+        if value == Value::Null {
+            self.right_null = true;
+        }
+        (!value.is_undefined()).then_some(value)
+    }
+
+    /// Returns true if the left row has a match in the right source.
+    fn matches(&self, left: &Row) -> Result<bool> {
+        let value = &left[self.left_column];
+        if self.null_aware && !self.right_empty && (self.right_null || *value == Value::Null) {
+            return Ok(true);
+        }
+        if value.is_undefined() {
+            return Ok(false);
+        }
+        let Some(right) = &self.right else {
+            return Ok(self.right_values.contains(value));
+        };
+        for row in right.clone() {
+            if row?[self.right_column] == *value {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Returns the next left row, if any.
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        while let Some(left) = self.left.next().transpose()? {
+            if self.matches(&left)? != self.anti {
+                return Ok(Some(left));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for HashSemiJoiner {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
@@ -4,6 +4,9 @@ mod aggregator;
 mod executor;
 mod join;
 mod session;
+mod sorter;
+mod spill;
 
-pub use executor::{ExecutionResult, Executor};
+pub use executor::{ExecutionResult, Executor, NodeStats, SequenceValues};
 pub use session::{Session, StatementResult};
+pub use spill::MemoryBudget;
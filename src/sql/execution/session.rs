@@ -1,12 +1,14 @@
+use std::collections::{HashMap, VecDeque};
+
 use itertools::Itertools as _;
 use log::error;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
-use crate::sql::engine::{Engine, Raft, Status, Transaction as _};
-use crate::sql::execution::ExecutionResult;
+use crate::sql::engine::{Catalog, Engine, Raft, Status, Transaction as _};
+use crate::sql::execution::{ExecutionResult, MemoryBudget, SequenceValues};
 use crate::sql::parser::{Parser, ast};
-use crate::sql::planner::Plan;
+use crate::sql::planner::{Plan, Planner};
 use crate::sql::types::{Label, Row, Rows, Value};
 use crate::storage::mvcc;
 use crate::{errdata, errinput};
@@ -18,12 +20,98 @@ pub struct Session<'a, E: Engine<'a>> {
     engine: &'a E,
     /// The current transaction, if any.
     txn: Option<E::Transaction>,
+    /// The memory budget for each statement.
+    budget: MemoryBudget,
+    /// Named prepared statements, from SQL PREPARE statements.
+    prepared: HashMap<String, Prepared>,
+    /// The plan cache for statements prepared via prepare(), keyed by
+    /// statement text.
+    plans: HashMap<String, Prepared>,
+    /// The cached statements, in order of last use (least recent first).
+    plans_used: VecDeque<String>,
+    /// The values last drawn from each sequence, for currval().
+    sequences: SequenceValues,
 }
 
+/// The maximum number of cached plans per session. When full, the least
+/// recently used plan is evicted.
+const PLAN_CACHE_SIZE: usize = 64;
+
 impl<'a, E: Engine<'a>> Session<'a, E> {
-    /// Creates a new session using the given SQL engine.
+    /// Creates a new session using the given SQL engine. Statements have an
+    /// unlimited memory budget, see set_memory_budget().
     pub fn new(engine: &'a E) -> Self {
-        Self { engine, txn: None }
+        Self {
+            engine,
+            txn: None,
+            budget: MemoryBudget::unlimited(),
+            prepared: HashMap::new(),
+            plans: HashMap::new(),
+            plans_used: VecDeque::new(),
+            sequences: SequenceValues::default(),
+        }
+    }
+
+    /// Sets the memory budget for each statement. Beyond it, sorts,
+    /// aggregates, and hash joins spill to disk.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.budget = budget;
+    }
+
+    /// Prepares a statement with parameter placeholders ($1 or ?), caching its
+    /// plan for execute_prepared(). Returns the number of parameters.
+    pub fn prepare(&mut self, statement: &str) -> Result<usize> {
+        let mut prepared = match self.plans.remove(statement) {
+            Some(prepared) => prepared,
+            None => Prepared::new(Parser::parse_parameterized(statement)?)?,
+        };
+        self.with_txn(true, |txn| prepared.plan(txn).map(|_| ()))?;
+        let parameters = prepared.parameters;
+        self.cache_plan(statement, prepared);
+        Ok(parameters)
+    }
+
+    /// Executes a statement with the given parameter values, using the cached
+    /// plan from prepare() if any. The plan is rebuilt if the catalog changed.
+    pub fn execute_prepared(
+        &mut self,
+        statement: &str,
+        params: Vec<Value>,
+    ) -> Result<StatementResult> {
+        let mut prepared = match self.plans.remove(statement) {
+            Some(prepared) => prepared,
+            None => Prepared::new(Parser::parse_parameterized(statement)?)?,
+        };
+        let result = self.execute_plan(&mut prepared, params);
+        self.cache_plan(statement, prepared);
+        result
+    }
+
+    /// Caches a prepared statement plan, evicting the least recently used plan
+    /// if full.
+    fn cache_plan(&mut self, statement: &str, prepared: Prepared) {
+        self.plans_used.retain(|used| used != statement);
+        while self.plans.len() >= PLAN_CACHE_SIZE
+            && let Some(used) = self.plans_used.pop_front()
+        {
+            self.plans.remove(&used);
+        }
+        self.plans.insert(statement.to_string(), prepared);
+        self.plans_used.push_back(statement.to_string());
+    }
+
+    /// Binds parameters to a prepared statement and executes it.
+    fn execute_plan(
+        &mut self,
+        prepared: &mut Prepared,
+        params: Vec<Value>,
+    ) -> Result<StatementResult> {
+        let read_only = prepared.read_only();
+        let (budget, sequences) = (self.budget.clone(), self.sequences.clone());
+        self.with_txn(read_only, |txn| {
+            let plan = prepared.bind(txn, &params)?.optimize(txn)?;
+            plan.execute(txn, budget, sequences)?.try_into()
+        })
     }
 
     /// Executes a client statement.
@@ -70,15 +158,65 @@ impl<'a, E: Engine<'a>> Session<'a, E> {
             }
 
             // EXPLAIN: returns the given SQL query's plan.
-            ast::Statement::Explain(statement) => self.with_txn(true, |txn| {
-                Ok(StatementResult::Explain(Plan::build(*statement, txn)?.optimize()?))
-            })?,
+            ast::Statement::Explain { statement, analyze: false } => {
+                self.with_txn(true, |txn| {
+                    let plan = Plan::build(*statement, txn)?.optimize(txn)?;
+                    Ok(StatementResult::Explain(plan.explain(txn)?))
+                })?
+            }
+
+            // EXPLAIN ANALYZE: executes the statement, and returns its plan
+            // with runtime statistics. Writes are applied.
+            ast::Statement::Explain { statement, analyze: true } => {
+                let read_only = matches!(*statement, ast::Statement::Select { .. });
+                let (budget, sequences) = (self.budget.clone(), self.sequences.clone());
+                self.with_txn(read_only, |txn| {
+                    let plan = Plan::build(*statement, txn)?.optimize(txn)?;
+                    Ok(StatementResult::Explain(plan.explain_analyze(txn, budget, sequences)?))
+                })?
+            }
+
+            // PREPARE: prepares a named statement, planning it to validate it.
+            ast::Statement::Prepare { name, statement, parameters } => {
+                if self.prepared.contains_key(&name) {
+                    return errinput!("prepared statement {name} already exists");
+                }
+                let mut prepared = Prepared::new((*statement, parameters))?;
+                self.with_txn(true, |txn| prepared.plan(txn).map(|_| ()))?;
+                self.prepared.insert(name.clone(), prepared);
+                StatementResult::Prepare { name }
+            }
+
+            // EXECUTE: executes a named prepared statement with the given
+            // parameter values.
+            ast::Statement::Execute { name, params } => {
+                let params: Vec<Value> = params
+                    .into_iter()
+                    .map(Planner::<E::Transaction>::build_constant_value)
+                    .try_collect()?;
+                let Some(mut prepared) = self.prepared.remove(&name) else {
+                    return errinput!("prepared statement {name} does not exist");
+                };
+                let result = self.execute_plan(&mut prepared, params);
+                self.prepared.insert(name, prepared);
+                result?
+            }
+
+            // DEALLOCATE: removes a named prepared statement.
+            ast::Statement::Deallocate { name } => {
+                if self.prepared.remove(&name).is_none() {
+                    return errinput!("prepared statement {name} does not exist");
+                }
+                StatementResult::Deallocate { name }
+            }
 
             // Other statements (SELECT etc.) are handled by the SQL executor.
             statement => {
                 let read_only = matches!(statement, ast::Statement::Select { .. });
+                let (budget, sequences) = (self.budget.clone(), self.sequences.clone());
                 self.with_txn(read_only, |txn| {
-                    Plan::build(statement, txn)?.optimize()?.execute(txn)?.try_into()
+                    let plan = Plan::build(statement, txn)?.optimize(txn)?;
+                    plan.execute(txn, budget, sequences)?.try_into()
                 })?
             }
         })
@@ -119,6 +257,60 @@ impl Session<'_, Raft> {
     }
 }
 
+/// A prepared statement, with a cached plan.
+struct Prepared {
+    /// The parsed statement.
+    statement: ast::Statement,
+    /// The number of parameters.
+    parameters: usize,
+    /// The cached unbound and unoptimized plan, along with the catalog version
+    /// it was built for.
+    plan: Option<(u64, Plan)>,
+}
+
+impl Prepared {
+    /// Creates a new prepared statement from a parsed statement and its number
+    /// of parameters.
+    fn new((statement, parameters): (ast::Statement, usize)) -> Result<Self> {
+        match statement {
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return errinput!("cannot prepare transaction statements");
+            }
+            ast::Statement::Explain { .. }
+            | ast::Statement::Prepare { .. }
+            | ast::Statement::Execute { .. }
+            | ast::Statement::Deallocate { .. } => {
+                return errinput!("cannot prepare EXPLAIN or prepared statement commands");
+            }
+            _ => {}
+        }
+        Ok(Self { statement, parameters, plan: None })
+    }
+
+    /// Returns true if the statement is read-only.
+    fn read_only(&self) -> bool {
+        matches!(self.statement, ast::Statement::Select { .. })
+    }
+
+    /// Returns the cached plan, (re)building it if the catalog has changed
+    /// since it was built.
+    fn plan(&mut self, catalog: &impl Catalog) -> Result<&Plan> {
+        let version = catalog.catalog_version()?;
+        if self.plan.as_ref().is_none_or(|(v, _)| *v != version) {
+            self.plan = Some((version, Plan::build(self.statement.clone(), catalog)?));
+        }
+        Ok(&self.plan.as_ref().expect("no plan").1)
+    }
+
+    /// Returns a plan with the given parameter values bound to it.
+    fn bind(&mut self, catalog: &impl Catalog, params: &[Value]) -> Result<Plan> {
+        if params.len() != self.parameters {
+            return errinput!("expected {} parameters, got {}", self.parameters, params.len());
+        }
+        self.plan(catalog)?.clone().bind(params)
+    }
+}
+
 /// If the session has an open transaction when dropped, roll it back.
 impl<'a, E: Engine<'a>> Drop for Session<'a, E> {
     fn drop(&mut self) {
@@ -135,9 +327,17 @@ pub enum StatementResult {
     Begin(mvcc::TransactionState),
     Commit { version: mvcc::Version },
     Rollback { version: mvcc::Version },
-    Explain(Plan),
+    Explain(String),
+    Prepare { name: String },
+    Deallocate { name: String },
+    AlterTable { name: String },
     CreateTable { name: String },
     DropTable { name: String, existed: bool },
+    CreateIndex { name: String },
+    DropIndex { name: String, existed: bool },
+    CreateSequence { name: String },
+    DropSequence { name: String, existed: bool },
+    Analyze { tables: Vec<String> },
     Delete { count: u64 },
     Insert { count: u64 },
     Update { count: u64 },
@@ -153,8 +353,14 @@ impl TryFrom<ExecutionResult> for StatementResult {
 
     fn try_from(result: ExecutionResult) -> Result<Self> {
         Ok(match result {
+            ExecutionResult::AlterTable { name } => Self::AlterTable { name },
             ExecutionResult::CreateTable { name } => Self::CreateTable { name },
             ExecutionResult::DropTable { name, existed } => Self::DropTable { name, existed },
+            ExecutionResult::CreateIndex { name } => Self::CreateIndex { name },
+            ExecutionResult::DropIndex { name, existed } => Self::DropIndex { name, existed },
+            ExecutionResult::CreateSequence { name } => Self::CreateSequence { name },
+            ExecutionResult::DropSequence { name, existed } => Self::DropSequence { name, existed },
+            ExecutionResult::Analyze { tables } => Self::Analyze { tables },
             ExecutionResult::Delete { count } => Self::Delete { count },
             ExecutionResult::Insert { count } => Self::Insert { count },
             ExecutionResult::Update { count } => Self::Update { count },
@@ -233,3 +439,32 @@ impl TryFrom<StatementResult> for String {
         Value::try_from(result)?.try_into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::engine::Local;
+    use crate::storage::Memory;
+
+    /// Tests that the plan cache evicts the least recently used plan when full.
+    #[test]
+    fn plan_cache_lru() -> Result<()> {
+        let engine = Local::new(Memory::new());
+        let mut session = engine.session();
+        let statement = |i: usize| format!("SELECT {i}");
+
+        // Fill the cache, then use the oldest plan again.
+        for i in 0..PLAN_CACHE_SIZE {
+            session.prepare(&statement(i))?;
+        }
+        session.execute_prepared(&statement(0), Vec::new())?;
+
+        // Caching another plan evicts the least recently used one.
+        session.prepare(&statement(PLAN_CACHE_SIZE))?;
+        assert_eq!(session.plans.len(), PLAN_CACHE_SIZE);
+        assert!(session.plans.contains_key(&statement(0)));
+        assert!(!session.plans.contains_key(&statement(1)));
+        assert!(session.plans.contains_key(&statement(PLAN_CACHE_SIZE)));
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+
+use itertools::{Itertools as _, izip};
+
+use super::spill::{Memory, Reservation, SpillFile, SpillReader, row_size};
+use crate::error::Result;
+use crate::sql::planner::Direction;
+use crate::sql::types::{Expression, Row, Rows, Value};
+
+/// The maximum number of runs to merge at once. If there are more runs, they
+/// are merged in multiple passes, to limit the number of open files.
+const MAX_MERGE_RUNS: usize = 64;
+
+/// Sorts input rows by a set of sort keys.
+///
+/// Rows are buffered in memory along with their sort keys. If the query
+/// exceeds its memory budget, and the buffered rows are large enough to make a
+/// reasonable run, they are sorted and spilled to disk as a sorted run. Once all rows have been added, the runs are merged into a
+/// single sorted output (i.e. an external merge sort). The sort is stable.
+pub struct Sorter {
+    /// The sort key expressions and directions.
+    order: Vec<(Expression, Direction)>,
+    /// The buffered rows and their sort keys.
+    rows: Vec<(Row, Vec<Value>)>,
+    /// The memory reservation for the buffered rows.
+    reservation: Reservation,
+    /// The memory tracker, used to spill runs.
+    memory: Memory,
+    /// Sorted runs that have been spilled to disk, in input order.
+    runs: Vec<SpillFile>,
+}
+
+impl Sorter {
+    /// Creates a new sorter for the given sort keys.
+    pub fn new(order: Vec<(Expression, Direction)>, memory: Memory) -> Self {
+        Self { order, rows: Vec::new(), reservation: memory.reserve(), memory, runs: Vec::new() }
+    }
+
+    /// Adds a row to the sorter.
+    pub fn add(&mut self, row: Row) -> Result<()> {
+        // We can't use sorted_by_cached_key(), since expression evaluation is
+        // fallible, and since we may have to vary the sort direction of each
+        // expression. Pre-compute the sort keys.
+        let keys = sort_keys(&self.order, &row)?;
+        self.push(row, keys)
+    }
+
+    /// Adds a row with pre-computed sort keys to the sorter.
+    fn push(&mut self, row: Row, keys: Vec<Value>) -> Result<()> {
+        let size = row_size(&row) + row_size(&keys);
+        self.rows.push((row, keys));
+        // If other operators have used up the budget, don't spill a run for
+        // every row, only once the buffered rows fill a run.
+        if self.reservation.grow(size) && self.reservation.fills_run() {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Adds rows to the sorter.
+    pub fn add_rows(&mut self, rows: Rows) -> Result<()> {
+        for row in rows {
+            self.add(row?)?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the buffered rows and spills them to disk as a sorted run.
+    fn spill(&mut self) -> Result<()> {
+        self.sort();
+        let mut run = self.memory.spill()?;
+        for item in self.rows.drain(..) {
+            run.write(&item)?;
+        }
+        self.runs.push(run.finish()?);
+        self.reservation.clear();
+        Ok(())
+    }
+
+    /// Sorts the buffered rows.
+    fn sort(&mut self) {
+        let directions = self.order.iter().map(|(_, dir)| *dir).collect_vec();
+        self.rows.sort_by(|(_, a), (_, b)| compare(a, b, &directions));
+    }
+
+    /// Returns a row iterator over the sorted rows.
+    pub fn into_rows(mut self) -> Result<Rows> {
+        // If nothing was spilled, emit the buffered rows.
+        if self.runs.is_empty() {
+            self.sort();
+            let rows = Box::new(self.rows.into_iter().map(|(row, _)| Ok(row)));
+            return Ok(self.reservation.hold(rows));
+        }
+
+        // Otherwise, spill the remaining rows and merge the runs.
+        if !self.rows.is_empty() {
+            self.spill()?;
+        }
+        let directions = self.order.iter().map(|(_, dir)| *dir).collect_vec();
+        while self.runs.len() > MAX_MERGE_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            for runs in runs.chunks(MAX_MERGE_RUNS) {
+                let mut run = self.memory.spill()?;
+                for item in Merger::new(runs, directions.clone())? {
+                    run.write(&item?)?;
+                }
+                self.runs.push(run.finish()?);
+            }
+        }
+        Ok(Box::new(Merger::new(&self.runs, directions)?.map_ok(|(row, _)| row)))
+    }
+}
+
+/// Evaluates the sort keys for a row.
+fn sort_keys(order: &[(Expression, Direction)], row: &Row) -> Result<Vec<Value>> {
+    order.iter().map(|(expr, _)| expr.evaluate(Some(row))).try_collect()
+}
+
+/// Compares sort keys in the given directions.
+fn compare(a: &[Value], b: &[Value], directions: &[Direction]) -> Ordering {
+    for (a, b, dir) in izip!(a, b, directions) {
+        let mut ordering = a.cmp(b);
+        if *dir == Direction::Descending {
+            ordering = ordering.reverse();
+        }
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Merges sorted runs into a single sorted output. For equal sort keys, rows
+/// from earlier runs are emitted first, which keeps the sort stable.
+#[derive(Clone)]
+struct Merger {
+    /// The sorted runs, and the next row and sort keys of each run.
+    runs: Vec<(SpillReader<(Row, Vec<Value>)>, Option<(Row, Vec<Value>)>)>,
+    /// The sort directions.
+    directions: Vec<Direction>,
+}
+
+impl Merger {
+    /// Creates a new merger for the given runs.
+    fn new(runs: &[SpillFile], directions: Vec<Direction>) -> Result<Self> {
+        let runs = runs
+            .iter()
+            .map(|run| -> Result<_> {
+                let mut reader = run.read();
+                let head = reader.next().transpose()?;
+                Ok((reader, head))
+            })
+            .try_collect()?;
+        Ok(Self { runs, directions })
+    }
+
+    // Returns the next row and its sort keys, if any.
+    fn try_next(&mut self) -> Result<Option<(Row, Vec<Value>)>> {
+        // Find the run with the lowest next sort key. The number of runs is
+        // small, so a linear search is fine.
+        let mut next: Option<(usize, &Vec<Value>)> = None;
+        for (i, (_, head)) in self.runs.iter().enumerate() {
+            let Some((_, keys)) = head else { continue };
+            if next.is_none_or(|(_, min)| compare(keys, min, &self.directions).is_lt()) {
+                next = Some((i, keys));
+            }
+        }
+        let Some((i, _)) = next else {
+            return Ok(None);
+        };
+
+        // Emit its row, and read the next one from the run.
+        let (reader, head) = &mut self.runs[i];
+        let next = reader.next().transpose()?;
+        Ok(std::mem::replace(head, next))
+    }
+}
+
+impl Iterator for Merger {
+    type Item = Result<(Row, Vec<Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Sorts input rows by a set of sort keys, skips the first offset rows, and
+/// emits the next limit rows (i.e. ORDER BY with OFFSET and LIMIT).
+///
+/// Rather than sorting all rows, this keeps the limit + offset lowest rows
+/// seen so far in a bounded max-heap. Once the heap is full, each new row is
+/// compared with the highest row in the heap, and either replaces it or is
+/// discarded. Rows with equal sort keys are ordered by input position, which
+/// keeps the sort stable.
+///
+/// If the heap exceeds the query's memory budget (i.e. with a large limit),
+/// it falls back to a Sorter, which can spill to disk.
+pub struct TopN {
+    /// The sort key expressions and directions.
+    order: Vec<(Expression, Direction)>,
+    /// The number of rows to emit.
+    limit: usize,
+    /// The number of rows to skip.
+    offset: usize,
+    /// The lowest limit + offset rows seen so far.
+    heap: BinaryHeap<HeapRow>,
+    /// The sort directions, shared by the heap rows.
+    directions: Rc<[Direction]>,
+    /// The input position of the next row.
+    position: usize,
+    /// The memory reservation for the heap rows.
+    reservation: Reservation,
+    /// The memory tracker, used to fall back to a Sorter.
+    memory: Memory,
+    /// The fallback sorter, if the heap exceeded the memory budget.
+    sorter: Option<Sorter>,
+}
+
+impl TopN {
+    /// Creates a new top-N sorter for the given sort keys, limit, and offset.
+    pub fn new(
+        order: Vec<(Expression, Direction)>,
+        limit: usize,
+        offset: usize,
+        memory: Memory,
+    ) -> Self {
+        let directions = order.iter().map(|(_, dir)| *dir).collect();
+        Self {
+            order,
+            limit,
+            offset,
+            heap: BinaryHeap::new(),
+            directions,
+            position: 0,
+            reservation: memory.reserve(),
+            memory,
+            sorter: None,
+        }
+    }
+
+    /// Adds a row to the top-N sorter.
+    pub fn add(&mut self, row: Row) -> Result<()> {
+        if let Some(sorter) = &mut self.sorter {
+            return sorter.add(row);
+        }
+        let capacity = self.limit.saturating_add(self.offset);
+        if capacity == 0 {
+            return Ok(());
+        }
+
+        let keys = sort_keys(&self.order, &row)?;
+        let entry =
+            HeapRow { row, keys, position: self.position, directions: self.directions.clone() };
+        self.position += 1;
+
+        // If the heap is full, replace the highest row if the new row is
+        // lower, otherwise discard the new row.
+        if self.heap.len() >= capacity {
+            if self.heap.peek().is_some_and(|highest| entry >= *highest) {
+                return Ok(());
+            }
+            if let Some(highest) = self.heap.pop() {
+                self.reservation.shrink(highest.size());
+            }
+        }
+        let size = entry.size();
+        self.heap.push(entry);
+
+        // If we exceeded the memory budget, fall back to a sorter. Add the
+        // heap rows in input order, to keep the sort stable.
+        if self.reservation.grow(size) {
+            let mut sorter = Sorter::new(self.order.clone(), self.memory.clone());
+            let rows = std::mem::take(&mut self.heap).into_iter().sorted_by_key(|r| r.position);
+            self.reservation.clear();
+            for HeapRow { row, keys, .. } in rows {
+                sorter.push(row, keys)?;
+            }
+            self.sorter = Some(sorter);
+        }
+        Ok(())
+    }
+
+    /// Adds rows to the top-N sorter.
+    pub fn add_rows(&mut self, rows: Rows) -> Result<()> {
+        for row in rows {
+            self.add(row?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a row iterator over the limit rows after the offset.
+    pub fn into_rows(self) -> Result<Rows> {
+        if let Some(sorter) = self.sorter {
+            return Ok(Box::new(sorter.into_rows()?.skip(self.offset).take(self.limit)));
+        }
+        let rows = self.heap.into_sorted_vec().into_iter().skip(self.offset).map(|r| r.row);
+        Ok(self.reservation.hold(Box::new(rows.collect_vec().into_iter().map(Ok))))
+    }
+}
+
+/// A heap row for TopN, ordered by its sort keys and then input position.
+struct HeapRow {
+    row: Row,
+    keys: Vec<Value>,
+    position: usize,
+    directions: Rc<[Direction]>,
+}
+
+impl HeapRow {
+    /// Returns the estimated in-memory size of the row and its keys.
+    fn size(&self) -> usize {
+        row_size(&self.row) + row_size(&self.keys)
+    }
+}
+
+impl Ord for HeapRow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(&self.keys, &other.keys, &self.directions).then(self.position.cmp(&other.position))
+    }
+}
+
+impl PartialOrd for HeapRow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapRow {}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::super::spill::MemoryBudget;
+    use super::*;
+
+    /// Tests that a sorter doesn't spill a run for every row when another
+    /// operator has used up the query's memory budget, but only once its own
+    /// rows fill the budget.
+    #[test]
+    fn spill_over_budget() -> Result<()> {
+        let dir = TempDir::with_prefix("toydb")?;
+        let memory = Memory::new(MemoryBudget::new(10_000, dir.path())?);
+
+        // Another operator uses up the budget.
+        let mut other = memory.reserve();
+        assert!(other.grow(20_000));
+
+        // Add rows worth 1.5 times the budget. This spills a single run.
+        let order = vec![(Expression::Column(0), Direction::Descending)];
+        let mut sorter = Sorter::new(order, memory);
+        let count = 15_000 / (2 * row_size(&[Value::Integer(0)])) as i64;
+        for i in 0..count {
+            sorter.add(vec![Value::Integer(i)])?;
+        }
+        assert_eq!(sorter.runs.len(), 1);
+
+        // The rows are still sorted.
+        let rows: Vec<Row> = sorter.into_rows()?.try_collect()?;
+        let expect = (0..count).rev().map(|i| vec![Value::Integer(i)]).collect_vec();
+        assert_eq!(rows, expect);
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher as _};
+use std::io::{BufReader, BufWriter, Read, Seek as _, SeekFrom, Write as _};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::error;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::encoding::bincode;
+use crate::error::Result;
+use crate::sql::types::{Row, Rows, Value};
+
+/// The number of partitions to spill hash tables into.
+const SPILL_PARTITIONS: usize = 16;
+
+/// The maximum number of times spilled partitions are repartitioned. Beyond
+/// this, partitions are processed in memory regardless of the budget, e.g. if
+/// a single join value has more rows than fit in memory.
+const MAX_SPILL_DEPTH: usize = 4;
+
+/// The minimum size of a sorted run to spill, in bytes, unless the query's
+/// entire budget is smaller. Sorts only spill once their own buffered rows
+/// reach this size, so they don't spill tiny runs when other operators have
+/// used up the budget.
+const MIN_SPILL_RUN_SIZE: usize = 1 << 20;
+
+/// The file name prefix of spill files.
+const SPILL_FILE_PREFIX: &str = "spill-";
+
+/// A per-query memory budget for operators that buffer rows in memory:
+/// sorts, aggregates, and hash joins. When a query's buffered rows exceed the
+/// budget, these operators spill rows to temporary files in the spill
+/// directory: sorts spill sorted runs and merge them (external merge sort),
+/// aggregates and hash joins spill hash partitions and process them one at a
+/// time, and hash semi joins spill their join values and scan them.
+///
+/// Memory usage is estimated from the size of the buffered values, so the
+/// actual usage is somewhat higher.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBudget {
+    /// The budget in bytes, or None if unlimited.
+    limit: Option<usize>,
+    /// The directory to write spill files to.
+    dir: PathBuf,
+}
+
+impl MemoryBudget {
+    /// Creates a memory budget with the given limit in bytes, spilling to the
+    /// given directory. The directory is created if it doesn't exist, and any
+    /// stale spill files (e.g. from a crash) are removed.
+    pub fn new(limit: usize, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(SPILL_FILE_PREFIX) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(Self { limit: Some(limit), dir })
+    }
+
+    /// Creates an unlimited memory budget, which never spills to disk.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+}
+
+/// Tracks a query's memory usage against its budget. Cloning it shares the
+/// usage, and a new one is created for each query.
+#[derive(Clone)]
+pub struct Memory {
+    /// The query's memory budget.
+    budget: Arc<MemoryBudget>,
+    /// The estimated bytes currently used by the query.
+    used: Arc<AtomicUsize>,
+}
+
+impl Memory {
+    /// Creates a memory tracker for a new query.
+    pub fn new(budget: MemoryBudget) -> Self {
+        Self { budget: Arc::new(budget), used: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Returns an empty memory reservation, which operators grow as they
+    /// buffer rows.
+    pub fn reserve(&self) -> Reservation {
+        Reservation { memory: self.clone(), size: 0 }
+    }
+
+    /// Returns true if the query exceeds its memory budget.
+    fn exceeded(&self) -> bool {
+        self.budget.limit.is_some_and(|limit| self.used.load(Ordering::Relaxed) > limit)
+    }
+
+    /// Creates a new spill file writer.
+    pub fn spill<T: Serialize>(&self) -> Result<SpillWriter<T>> {
+        let path = self.budget.dir.join(format!("{SPILL_FILE_PREFIX}{}", Uuid::new_v4()));
+        let writer = BufWriter::new(File::create_new(&path)?);
+        Ok(SpillWriter { file: SpillFile(Arc::new(SpillPath(path))), writer, _item: PhantomData })
+    }
+
+    /// Creates a hash partitioner for the given spill depth (0 for the
+    /// original input), or None if partitions can't be spilled any further.
+    pub fn partition(&self, depth: usize) -> Option<Partitioner> {
+        if depth >= MAX_SPILL_DEPTH {
+            return None;
+        }
+        let writers = std::iter::repeat_with(|| None).take(SPILL_PARTITIONS).collect();
+        Some(Partitioner { memory: self.clone(), depth, writers })
+    }
+}
+
+/// A memory reservation for an operator's buffered rows. The memory is
+/// released when the reservation is dropped.
+pub struct Reservation {
+    /// The query's memory tracker.
+    memory: Memory,
+    /// The reserved bytes.
+    size: usize,
+}
+
+impl Reservation {
+    /// Grows the reservation by the given bytes. Returns true if the query
+    /// now exceeds its memory budget, in which case the operator should spill
+    /// its rows to disk and release them.
+    pub fn grow(&mut self, bytes: usize) -> bool {
+        self.memory.used.fetch_add(bytes, Ordering::Relaxed);
+        self.size += bytes;
+        self.memory.exceeded()
+    }
+
+    /// Returns true if the reservation is large enough to spill as a sorted
+    /// run, i.e. at least MIN_SPILL_RUN_SIZE or the query's entire budget.
+    pub fn fills_run(&self) -> bool {
+        let limit = self.memory.budget.limit.unwrap_or(usize::MAX);
+        self.size >= limit.min(MIN_SPILL_RUN_SIZE)
+    }
+
+    /// Shrinks the reservation by the given bytes, e.g. when a buffered row is
+    /// discarded.
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.size);
+        self.memory.used.fetch_sub(bytes, Ordering::Relaxed);
+        self.size -= bytes;
+    }
+
+    /// Releases all reserved memory.
+    pub fn clear(&mut self) {
+        self.memory.used.fetch_sub(self.size, Ordering::Relaxed);
+        self.size = 0;
+    }
+
+    /// Holds the reservation until the given row iterator is dropped, for
+    /// operators that emit their buffered rows.
+    pub fn hold(self, rows: Rows) -> Rows {
+        Box::new(ReservedRows { rows, _reservation: self })
+    }
+}
+
+/// Cloning a reservation (e.g. along with a row iterator) also clones the
+/// buffered rows, so it reserves the same amount of memory again.
+impl Clone for Reservation {
+    fn clone(&self) -> Self {
+        let mut reservation = self.memory.reserve();
+        reservation.grow(self.size);
+        reservation
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+/// A row iterator that holds a memory reservation. See Reservation::hold().
+#[derive(Clone)]
+struct ReservedRows {
+    rows: Rows,
+    _reservation: Reservation,
+}
+
+impl Iterator for ReservedRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
+/// Returns the estimated in-memory size of a row, in bytes.
+pub fn row_size(row: &[Value]) -> usize {
+    let heap = |value: &Value| match value {
+        Value::String(s) => s.len(),
+        _ => 0,
+    };
+    size_of::<Row>() + row.iter().map(|v| size_of::<Value>() + heap(v)).sum::<usize>()
+}
+
+/// A temporary spill file. The file is removed when the last clone is dropped.
+#[derive(Clone)]
+pub struct SpillFile(Arc<SpillPath>);
+
+/// Removes the spill file path when dropped.
+struct SpillPath(PathBuf);
+
+impl Drop for SpillPath {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            error!("failed to remove spill file {}: {err}", self.0.display())
+        }
+    }
+}
+
+impl SpillFile {
+    /// Returns an iterator over the items in the spill file.
+    pub fn read<T: DeserializeOwned>(&self) -> SpillReader<T> {
+        SpillReader { file: self.clone(), reader: None, offset: 0, _item: PhantomData }
+    }
+}
+
+/// Writes items to a spill file.
+pub struct SpillWriter<T: Serialize> {
+    file: SpillFile,
+    writer: BufWriter<File>,
+    _item: PhantomData<T>,
+}
+
+impl<T: Serialize> SpillWriter<T> {
+    /// Writes an item to the spill file.
+    pub fn write(&mut self, item: &T) -> Result<()> {
+        bincode::serialize_into(&mut self.writer, item)
+    }
+
+    /// Flushes the spill file, returning it for reading.
+    pub fn finish(mut self) -> Result<SpillFile> {
+        self.writer.flush()?;
+        Ok(self.file)
+    }
+}
+
+/// Iterates over the items in a spill file. Row iterators must be cloneable,
+/// so the reader keeps track of its file offset, and clones open a new file
+/// handle at that offset when first read from.
+pub struct SpillReader<T: DeserializeOwned> {
+    file: SpillFile,
+    reader: Option<BufReader<File>>,
+    offset: u64,
+    _item: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> SpillReader<T> {
+    // Returns the next item, if any.
+    fn try_next(&mut self) -> Result<Option<T>> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => {
+                let mut file = File::open(&self.file.0.0)?;
+                file.seek(SeekFrom::Start(self.offset))?;
+                self.reader.insert(BufReader::new(file))
+            }
+        };
+        // Track the number of bytes read, to keep the offset up to date.
+        struct Counter<'a, R: Read>(&'a mut R, &'a mut u64);
+        impl<R: Read> Read for Counter<'_, R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.0.read(buf)?;
+                *self.1 += n as u64;
+                Ok(n)
+            }
+        }
+        bincode::maybe_deserialize_from(Counter(reader, &mut self.offset))
+    }
+}
+
+impl<T: DeserializeOwned> Clone for SpillReader<T> {
+    fn clone(&self) -> Self {
+        Self { file: self.file.clone(), reader: None, offset: self.offset, _item: PhantomData }
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for SpillReader<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Partitions rows into spill files by the hash of a key, such that rows with
+/// equal keys end up in the same partition. The hash includes the spill depth,
+/// so that repartitioning a partition distributes its rows.
+pub struct Partitioner {
+    memory: Memory,
+    depth: usize,
+    writers: Vec<Option<SpillWriter<Row>>>,
+}
+
+impl Partitioner {
+    /// Adds a row to the partition of the given key.
+    pub fn add(&mut self, key: &impl Hash, row: &Row) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        (self.depth, key).hash(&mut hasher);
+        let partition = (hasher.finish() % self.writers.len() as u64) as usize;
+        let writer = match &mut self.writers[partition] {
+            Some(writer) => writer,
+            writer @ None => writer.insert(self.memory.spill()?),
+        };
+        writer.write(row)
+    }
+
+    /// Flushes the partitions, returning their spill files in partition
+    /// order. Empty partitions are None.
+    pub fn finish(self) -> Result<Vec<Option<SpillFile>>> {
+        self.writers.into_iter().map(|w| w.map(|w| w.finish()).transpose()).collect()
+    }
+}
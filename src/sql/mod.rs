@@ -78,18 +78,19 @@ mod tests {
     use std::collections::HashMap;
     use std::error::Error;
     use std::fmt::Write as _;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use std::result::Result;
 
     use crossbeam::channel::Receiver;
     use itertools::Itertools as _;
+    use regex::Regex;
     use tempfile::TempDir;
     use test_each_file::test_each_path;
 
     use super::engine::Catalog as _;
-    use super::execution::{Session, StatementResult};
+    use super::execution::{MemoryBudget, Session, StatementResult};
     use super::parser::Parser;
-    use super::planner::{OPTIMIZERS, Plan};
+    use super::planner::{CostModel, OPTIMIZERS, Plan};
     use crate::encoding::format::{self, Formatter as _};
     use crate::sql::engine::{Engine, Local};
     use crate::sql::planner::{Planner, Scope};
@@ -116,7 +117,7 @@ mod tests {
         let memory = storage::Memory::new();
         let engine =
             Local::new(testengine::Emit::new(testengine::Mirror::new(bitcask, memory), op_tx));
-        let mut runner = SQLRunner::new(&engine, op_rx);
+        let mut runner = SQLRunner::new(&engine, op_rx, tempdir.path().join("tmp"));
 
         goldenscript::run(&mut runner, path).expect("goldenscript failed")
     }
@@ -131,14 +132,19 @@ mod tests {
         engine: &'a TestEngine,
         sessions: HashMap<String, Session<'a, TestEngine>>,
         op_rx: Receiver<testengine::Operation>,
+        spill_dir: PathBuf,
     }
 
     type TestEngine =
         Local<testengine::Emit<testengine::Mirror<storage::BitCask, storage::Memory>>>;
 
     impl<'a> SQLRunner<'a> {
-        fn new(engine: &'a TestEngine, op_rx: Receiver<testengine::Operation>) -> Self {
-            Self { engine, sessions: HashMap::new(), op_rx }
+        fn new(
+            engine: &'a TestEngine,
+            op_rx: Receiver<testengine::Operation>,
+            spill_dir: PathBuf,
+        ) -> Self {
+            Self { engine, sessions: HashMap::new(), op_rx, spill_dir }
         }
     }
 
@@ -165,6 +171,15 @@ mod tests {
                     return Ok(output);
                 }
 
+                // memory_budget BYTES
+                "memory_budget" => {
+                    let mut args = command.consume_args();
+                    let limit = args.next_pos().ok_or("limit not given")?.parse()?;
+                    args.reject_rest()?;
+                    session.set_memory_budget(MemoryBudget::new(limit, &self.spill_dir)?);
+                    return Ok(output);
+                }
+
                 // schema [TABLE...]
                 "schema" => {
                     let mut args = command.consume_args();
@@ -197,7 +212,7 @@ mod tests {
             if tags.remove("plan") {
                 let ast = Parser::parse(input)?;
                 let plan =
-                    session.with_txn(true, |txn| Planner::new(txn).build(ast)?.optimize())?;
+                    session.with_txn(true, |txn| Planner::new(txn).build(ast)?.optimize(txn))?;
                 writeln!(output, "{plan}")?;
             }
 
@@ -211,10 +226,11 @@ mod tests {
                 let Plan::Select(mut root) = plan else {
                     return Err("can only use opt with SELECT plans".into());
                 };
+                let cost_model = session.with_txn(true, |txn| CostModel::load(txn, &root))?;
                 writeln!(output, "{}", format!("Initial:\n{root}").replace('\n', "\n   "))?;
                 for optimizer in OPTIMIZERS.iter() {
                     let prev = root.clone();
-                    root = optimizer.optimize(root)?;
+                    root = optimizer.optimize(root, &cost_model)?;
                     if root != prev {
                         writeln!(
                             output,
@@ -247,7 +263,8 @@ mod tests {
                 }
             }
 
-            // Output the result if requested. SELECT results are always output.
+            // Output the result if requested. SELECT and EXPLAIN results are
+            // always output.
             match result {
                 StatementResult::Select { columns, rows } => {
                     if tags.remove("header") {
@@ -257,6 +274,11 @@ mod tests {
                         writeln!(output, "{}", row.into_iter().join(", "))?;
                     }
                 }
+                StatementResult::Explain(plan) => {
+                    // Mask EXPLAIN ANALYZE timings, which aren't deterministic.
+                    let plan = Regex::new(r"time=[0-9.]+ms")?.replace_all(&plan, "time=…");
+                    writeln!(output, "{plan}")?
+                }
                 result if tags.remove("result") => writeln!(output, "{result:?}")?,
                 _ => {}
             }
@@ -269,9 +291,13 @@ mod tests {
             Ok(output)
         }
 
-        /// Drain unprocessed operations after each command.
+        /// Drain unprocessed operations after each command, and check that
+        /// all spill files have been removed.
         fn end_command(&mut self, _: &goldenscript::Command) -> Result<String, Box<dyn Error>> {
             while self.op_rx.try_recv().is_ok() {}
+            if self.spill_dir.exists() && self.spill_dir.read_dir()?.next().is_some() {
+                return Err("spill files were not removed".into());
+            }
             Ok(String::new())
         }
     }
@@ -1,14 +1,14 @@
 use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
 
-use crate::sql::types::DataType;
+use crate::sql::types::{DataType, Reference};
 
 /// SQL statements are represented as an Abstract Syntax Tree (AST). The
 /// statement is the root node of this tree, and describes the syntactic
 /// structure of a SQL statement. It is built from a raw SQL string by the
 /// parser, and passed on to the planner which validates it and builds an
 /// execution plan from it.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Statement {
     /// BEGIN: begins a new transaction.
     Begin {
@@ -22,13 +22,54 @@ pub enum Statement {
     /// ROLLBACK: rolls back a transaction.
     Rollback,
     /// EXPLAIN: explains a SQL statement's execution plan.
-    Explain(Box<Statement>),
+    Explain {
+        /// The statement to explain.
+        statement: Box<Statement>,
+        /// ANALYZE: if true, execute the statement and record runtime
+        /// statistics for each plan node.
+        analyze: bool,
+    },
+    /// PREPARE: prepares a named statement with parameter placeholders.
+    Prepare {
+        /// The prepared statement name.
+        name: String,
+        /// The statement to prepare.
+        statement: Box<Statement>,
+        /// The number of parameters.
+        parameters: usize,
+    },
+    /// EXECUTE: executes a prepared statement with the given parameters.
+    Execute {
+        /// The prepared statement name.
+        name: String,
+        /// The parameter values, as constant expressions.
+        params: Vec<Expression>,
+    },
+    /// DEALLOCATE: removes a prepared statement.
+    Deallocate {
+        /// The prepared statement name.
+        name: String,
+    },
     /// CREATE TABLE: creates a new table.
     CreateTable {
         /// The table name.
         name: String,
         /// Column specifications.
         columns: Vec<Column>,
+        /// PRIMARY KEY (columns): a table-level primary key, which may span
+        /// multiple columns. Can't be combined with a column PRIMARY KEY.
+        primary_key: Option<Vec<String>>,
+        /// FOREIGN KEY (columns) REFERENCES table: table-level foreign keys.
+        foreign_keys: Vec<ForeignKey>,
+        /// CHECK (expr): table-level check constraints.
+        checks: Vec<Check>,
+    },
+    /// ALTER TABLE: changes a table's schema.
+    AlterTable {
+        /// The table to alter.
+        name: String,
+        /// The schema change to make.
+        alteration: Alteration,
     },
     /// DROP TABLE: drops a table.
     DropTable {
@@ -37,12 +78,55 @@ pub enum Statement {
         /// IF EXISTS: if true, don't error if the table doesn't exist.
         if_exists: bool,
     },
+    /// CREATE INDEX: creates a secondary index on one or more table columns.
+    CreateIndex {
+        /// The index name.
+        name: String,
+        /// The table to index.
+        table: String,
+        /// The columns to index, in key order.
+        columns: Vec<String>,
+        /// UNIQUE: if true, the combined column values must be unique.
+        unique: bool,
+    },
+    /// DROP INDEX: drops a secondary index.
+    DropIndex {
+        /// The index to drop.
+        name: String,
+        /// IF EXISTS: if true, don't error if the index doesn't exist.
+        if_exists: bool,
+    },
+    /// CREATE SEQUENCE: creates a new sequence.
+    CreateSequence {
+        /// The sequence name.
+        name: String,
+        /// START WITH: the first value, as a constant expression.
+        start: Option<Expression>,
+        /// INCREMENT BY: the increment, as a constant expression.
+        increment: Option<Expression>,
+    },
+    /// DROP SEQUENCE: drops a sequence.
+    DropSequence {
+        /// The sequence to drop.
+        name: String,
+        /// IF EXISTS: if true, don't error if the sequence doesn't exist.
+        if_exists: bool,
+    },
+    /// ANALYZE: collects table statistics for the query optimizer.
+    Analyze {
+        /// The table to analyze. If None, all tables are analyzed.
+        table: Option<String>,
+    },
     /// DELETE: deletes rows from a table.
     Delete {
         /// The table to delete from.
         table: String,
+        /// USING: additional tables to join with, used in the WHERE condition.
+        using: Vec<From>,
         /// WHERE: optional condition to match rows to delete.
         r#where: Option<Expression>,
+        /// RETURNING: expressions (with aliases) to return for deleted rows.
+        returning: Vec<(Expression, Option<String>)>,
     },
     /// INSERT INTO: inserts new rows into a table.
     Insert {
@@ -50,17 +134,25 @@ pub enum Statement {
         table: String,
         /// Columns to insert values into. If None, all columns are used.
         columns: Option<Vec<String>>,
-        /// Row values to insert.
-        values: Vec<Vec<Expression>>,
+        /// The rows to insert.
+        source: InsertSource,
+        /// ON CONFLICT: how to handle unique constraint conflicts, if given.
+        on_conflict: Option<OnConflict>,
+        /// RETURNING: expressions (with aliases) to return for inserted rows.
+        returning: Vec<(Expression, Option<String>)>,
     },
     /// UPDATE: updates rows in a table.
     Update {
         table: String,
         set: BTreeMap<String, Option<Expression>>, // column → value, None for default value
+        from: Vec<From>,                           // additional tables to join with
         r#where: Option<Expression>,
+        returning: Vec<(Expression, Option<String>)>,
     },
     /// SELECT: selects rows, possibly from a table.
     Select {
+        /// DISTINCT: if true, discard duplicate result rows.
+        distinct: bool,
         /// Expressions to select, with an optional column alias.
         select: Vec<(Expression, Option<String>)>,
         /// FROM: tables to select from.
@@ -81,7 +173,7 @@ pub enum Statement {
 }
 
 /// A FROM item.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum From {
     /// A table.
     Table {
@@ -99,12 +191,60 @@ pub enum From {
         /// The join type.
         r#type: JoinType,
         /// The join condition. None for a cross join.
-        predicate: Option<Expression>,
+        condition: Option<JoinCondition>,
     },
 }
 
-/// A CREATE TABLE column definition.
-#[derive(Debug)]
+/// An INSERT source.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum InsertSource {
+    /// VALUES: literal row values.
+    Values(Vec<Vec<Expression>>),
+    /// SELECT: the rows of a query.
+    Select(Box<Statement>),
+}
+
+/// An INSERT ON CONFLICT clause.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct OnConflict {
+    /// The conflict target columns, which must be the primary key or a unique
+    /// index. If None, conflicts with any of them are handled.
+    pub columns: Option<Vec<String>>,
+    /// The action to take on conflict.
+    pub action: ConflictAction,
+}
+
+/// An ON CONFLICT action.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ConflictAction {
+    /// DO NOTHING: skips the conflicting row.
+    Nothing,
+    /// DO UPDATE: updates the existing row, if the WHERE condition is true.
+    Update {
+        set: BTreeMap<String, Option<Expression>>, // column → value, None for default value
+        r#where: Option<Expression>,
+    },
+}
+
+/// An ALTER TABLE operation.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Alteration {
+    /// ADD COLUMN: adds a column.
+    AddColumn(Column),
+    /// DROP COLUMN: drops a column.
+    DropColumn(String),
+    /// RENAME COLUMN: renames a column.
+    RenameColumn { from: String, to: String },
+    /// RENAME TO: renames the table.
+    RenameTable(String),
+    /// ALTER COLUMN SET DEFAULT or DROP DEFAULT (None).
+    SetDefault(String, Option<Expression>),
+    /// ALTER COLUMN DROP NOT NULL (true) or SET NOT NULL (false).
+    SetNullable(String, bool),
+}
+
+/// A CREATE TABLE or ALTER TABLE ADD COLUMN column definition.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
@@ -113,16 +253,33 @@ pub struct Column {
     pub default: Option<Expression>,
     pub unique: bool,
     pub index: bool,
-    pub references: Option<String>,
+    pub references: Option<Reference>,
+    pub identity: bool, // SERIAL or GENERATED AS IDENTITY
+    pub checks: Vec<Check>,
+}
+
+/// A FOREIGN KEY (columns) REFERENCES table table constraint.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub reference: Reference,
+}
+
+/// A [CONSTRAINT name] CHECK (expr) constraint, at column or table level.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Check {
+    pub name: Option<String>,
+    pub expression: Expression,
 }
 
 /// JOIN types.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum JoinType {
     Cross,
     Inner,
     Left,
     Right,
+    Full,
 }
 
 impl JoinType {
@@ -130,14 +287,26 @@ impl JoinType {
     // emitted with a NULL match.
     pub fn is_outer(&self) -> bool {
         match self {
-            Self::Left | Self::Right => true,
+            Self::Left | Self::Right | Self::Full => true,
             Self::Cross | Self::Inner => false,
         }
     }
 }
 
+/// JOIN conditions.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum JoinCondition {
+    /// ON: joins rows where the predicate is true.
+    On(Expression),
+    /// USING: joins rows where the given columns are equal in both sources.
+    /// Each pair of columns is merged into a single output column.
+    Using(Vec<String>),
+    /// NATURAL: like USING, with all column names that exist in both sources.
+    Natural,
+}
+
 /// ORDER BY direction.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Direction {
     #[default]
     Ascending,
@@ -149,14 +318,30 @@ pub enum Direction {
 pub enum Expression {
     /// All columns, i.e. *.
     All,
+    /// All columns of the given table, i.e. table.*.
+    QualifiedAll(String),
     /// A column reference, optionally qualified with a table name.
     Column(Option<String>, String),
     /// A literal value.
     Literal(Literal),
-    /// A function call (name and parameters).
-    Function(String, Vec<Expression>),
+    /// A parameter placeholder, numbered from 1. See Statement::Prepare.
+    Parameter(usize),
+    /// A function call (name, parameters, and DISTINCT flag). DISTINCT is only
+    /// valid for aggregate functions, e.g. COUNT(DISTINCT a).
+    Function(String, Vec<Expression>, bool),
     /// An operator.
     Operator(Operator),
+    /// CASE [operand] WHEN condition THEN result [...] [ELSE result] END. If
+    /// an operand is given, the WHEN values are compared with it.
+    Case {
+        operand: Option<Box<Expression>>,
+        when: Vec<(Expression, Expression)>,
+        r#else: Option<Box<Expression>>,
+    },
+    /// CAST(expr AS type) or expr::type: converts a value to a datatype.
+    Cast(Box<Expression>, DataType),
+    /// EXISTS (subquery): true if the SELECT subquery returns any rows.
+    Exists(Box<Statement>),
 }
 
 /// Expression literal values.
@@ -212,13 +397,16 @@ pub enum Operator {
     Not(Box<Expression>),                  // NOT a
     Or(Box<Expression>, Box<Expression>),  // a OR b
 
-    Equal(Box<Expression>, Box<Expression>),       // a = b
-    GreaterThan(Box<Expression>, Box<Expression>), // a > b
-    GreaterThanOrEqual(Box<Expression>, Box<Expression>), // a >= b
-    Is(Box<Expression>, Literal),                  // IS NULL or IS NAN
-    LessThan(Box<Expression>, Box<Expression>),    // a < b
-    LessThanOrEqual(Box<Expression>, Box<Expression>), // a <= b
-    NotEqual(Box<Expression>, Box<Expression>),    // a != b
+    Between(Box<Expression>, Box<Expression>, Box<Expression>), // a BETWEEN b AND c
+    Equal(Box<Expression>, Box<Expression>),                    // a = b
+    GreaterThan(Box<Expression>, Box<Expression>),              // a > b
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),       // a >= b
+    In(Box<Expression>, Vec<Expression>),                       // a IN (b, c, ...)
+    InSubquery(Box<Expression>, Box<Statement>),                // a IN (SELECT ...)
+    Is(Box<Expression>, Literal),                               // IS NULL or IS NAN
+    LessThan(Box<Expression>, Box<Expression>),                 // a < b
+    LessThanOrEqual(Box<Expression>, Box<Expression>),          // a <= b
+    NotEqual(Box<Expression>, Box<Expression>),                 // a != b
 
     Add(Box<Expression>, Box<Expression>),          // a + b
     Divide(Box<Expression>, Box<Expression>),       // a / b
@@ -231,6 +419,8 @@ pub enum Operator {
     Subtract(Box<Expression>, Box<Expression>),     // a - b
 
     Like(Box<Expression>, Box<Expression>), // a LIKE b
+    RegexMatch(Box<Expression>, Box<Expression>, bool), // a ~ b, or a ~* b if true
+    SimilarTo(Box<Expression>, Box<Expression>), // a SIMILAR TO b
 }
 
 impl Expression {
@@ -258,17 +448,38 @@ impl Expression {
                 | Multiply(lhs, rhs)
                 | NotEqual(lhs, rhs)
                 | Or(lhs, rhs)
+                | RegexMatch(lhs, rhs, _)
                 | Remainder(lhs, rhs)
+                | SimilarTo(lhs, rhs)
                 | Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
 
                 Factorial(expr) | Identity(expr) | Is(expr, _) | Negate(expr) | Not(expr) => {
                     expr.walk(visitor)
                 }
+
+                Between(expr, low, high) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+                In(expr, list) => expr.walk(visitor) && list.iter().all(|e| e.walk(visitor)),
+                InSubquery(expr, _) => expr.walk(visitor),
             },
 
-            Self::Function(_, exprs) => exprs.iter().any(|expr| expr.walk(visitor)),
+            Self::Function(_, exprs, _) => exprs.iter().any(|expr| expr.walk(visitor)),
+
+            Self::Case { operand, when, r#else } => {
+                operand.iter().all(|expr| expr.walk(visitor))
+                    && when.iter().all(|(cond, result)| cond.walk(visitor) && result.walk(visitor))
+                    && r#else.iter().all(|expr| expr.walk(visitor))
+            }
+            Self::Cast(expr, _) => expr.walk(visitor),
 
-            Self::All | Self::Column(_, _) | Self::Literal(_) => true,
+            // Subqueries have their own scope, and are not walked.
+            Self::All
+            | Self::QualifiedAll(_)
+            | Self::Column(_, _)
+            | Self::Exists(_)
+            | Self::Literal(_)
+            | Self::Parameter(_) => true,
         }
     }
 
@@ -303,7 +514,9 @@ impl Expression {
                 | Multiply(lhs, rhs)
                 | NotEqual(lhs, rhs)
                 | Or(lhs, rhs)
+                | RegexMatch(lhs, rhs, _)
                 | Remainder(lhs, rhs)
+                | SimilarTo(lhs, rhs)
                 | Subtract(lhs, rhs) => {
                     lhs.collect(visitor, exprs);
                     rhs.collect(visitor, exprs);
@@ -311,11 +524,37 @@ impl Expression {
                 Factorial(expr) | Identity(expr) | Is(expr, _) | Negate(expr) | Not(expr) => {
                     expr.collect(visitor, exprs);
                 }
+
+                Between(expr, low, high) => {
+                    expr.collect(visitor, exprs);
+                    low.collect(visitor, exprs);
+                    high.collect(visitor, exprs);
+                }
+                In(expr, list) => {
+                    expr.collect(visitor, exprs);
+                    list.iter().for_each(|e| e.collect(visitor, exprs));
+                }
+                InSubquery(expr, _) => expr.collect(visitor, exprs),
             },
 
-            Self::Function(_, args) => args.iter().for_each(|arg| arg.collect(visitor, exprs)),
+            Self::Function(_, args, _) => args.iter().for_each(|arg| arg.collect(visitor, exprs)),
+
+            Self::Case { operand, when, r#else } => {
+                operand.iter().for_each(|expr| expr.collect(visitor, exprs));
+                for (cond, result) in when {
+                    cond.collect(visitor, exprs);
+                    result.collect(visitor, exprs);
+                }
+                r#else.iter().for_each(|expr| expr.collect(visitor, exprs));
+            }
+            Self::Cast(expr, _) => expr.collect(visitor, exprs),
 
-            Self::All | Self::Column(_, _) | Self::Literal(_) => {}
+            Self::All
+            | Self::QualifiedAll(_)
+            | Self::Column(_, _)
+            | Self::Exists(_)
+            | Self::Literal(_)
+            | Self::Parameter(_) => {}
         }
     }
 }
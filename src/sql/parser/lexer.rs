@@ -24,6 +24,9 @@ pub enum Token {
     Ident(String),
     /// A SQL keyword.
     Keyword(Keyword),
+    /// A numbered parameter placeholder, e.g. $1. Positional placeholders are
+    /// given as Question, and numbered by the parser.
+    Parameter(usize),
     Period,             // .
     Equal,              // =
     NotEqual,           // !=
@@ -39,7 +42,13 @@ pub enum Token {
     Caret,              // ^
     Percent,            // %
     Exclamation,        // !
+    Tilde,              // ~
+    TildeAsterisk,      // ~*
+    NotTilde,           // !~
+    NotTildeAsterisk,   // !~*
     Question,           // ?
+    Colon,              // :
+    DoubleColon,        // ::
     Comma,              // ,
     Semicolon,          // ;
     OpenParen,          // (
@@ -53,6 +62,7 @@ impl Display for Token {
             Self::String(s) => s,
             Self::Ident(s) => s,
             Self::Keyword(k) => return k.fmt(f),
+            Self::Parameter(n) => return write!(f, "${n}"),
             Self::Period => ".",
             Self::Equal => "=",
             Self::NotEqual => "!=",
@@ -68,7 +78,13 @@ impl Display for Token {
             Self::Caret => "^",
             Self::Percent => "%",
             Self::Exclamation => "!",
+            Self::Tilde => "~",
+            Self::TildeAsterisk => "~*",
+            Self::NotTilde => "!~",
+            Self::NotTildeAsterisk => "!~*",
             Self::Question => "?",
+            Self::Colon => ":",
+            Self::DoubleColon => "::",
             Self::Comma => ",",
             Self::Semicolon => ";",
             Self::OpenParen => "(",
@@ -86,29 +102,52 @@ impl From<Keyword> for Token {
 /// Reserved SQL keywords.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Keyword {
+    Add,
+    Alter,
+    Analyze,
     And,
     As,
     Asc,
     Begin,
+    Between,
     Bool,
     Boolean,
     By,
+    Cascade,
+    Case,
+    Cast,
+    Check,
+    Column,
     Commit,
+    Conflict,
+    Constraint,
     Create,
     Cross,
+    Deallocate,
     Default,
     Delete,
     Desc,
+    Distinct,
+    Do,
     Double,
     Drop,
+    Else,
+    End,
+    Execute,
     Exists,
     Explain,
     False,
     Float,
+    Foreign,
     From,
+    Full,
+    Generated,
     Group,
     Having,
+    Identity,
     If,
+    In,
+    Increment,
     Index,
     Infinity,
     Inner,
@@ -123,7 +162,9 @@ pub enum Keyword {
     Like,
     Limit,
     NaN,
+    Natural,
     Not,
+    Nothing,
     Null,
     Of,
     Offset,
@@ -132,25 +173,38 @@ pub enum Keyword {
     Or,
     Order,
     Outer,
+    Prepare,
     Primary,
     Read,
     References,
+    Rename,
+    Restrict,
+    Returning,
     Right,
     Rollback,
     Select,
+    Sequence,
+    Serial,
     Set,
+    Similar,
+    Start,
     String,
     System,
     Table,
     Text,
+    Then,
     Time,
+    To,
     Transaction,
     True,
     Unique,
     Update,
+    Using,
     Values,
     Varchar,
+    When,
     Where,
+    With,
     Write,
 }
 
@@ -163,29 +217,52 @@ impl TryFrom<&str> for Keyword {
         // allocating a string to change the case. Assert this.
         debug_assert!(value.chars().all(|c| !c.is_uppercase()), "keyword must be lowercase");
         Ok(match value {
+            "add" => Self::Add,
+            "alter" => Self::Alter,
+            "analyze" => Self::Analyze,
             "as" => Self::As,
             "asc" => Self::Asc,
             "and" => Self::And,
             "begin" => Self::Begin,
+            "between" => Self::Between,
             "bool" => Self::Bool,
             "boolean" => Self::Boolean,
             "by" => Self::By,
+            "cascade" => Self::Cascade,
+            "case" => Self::Case,
+            "cast" => Self::Cast,
+            "check" => Self::Check,
+            "column" => Self::Column,
             "commit" => Self::Commit,
+            "conflict" => Self::Conflict,
+            "constraint" => Self::Constraint,
             "create" => Self::Create,
             "cross" => Self::Cross,
+            "deallocate" => Self::Deallocate,
             "default" => Self::Default,
             "delete" => Self::Delete,
             "desc" => Self::Desc,
+            "distinct" => Self::Distinct,
+            "do" => Self::Do,
             "double" => Self::Double,
             "drop" => Self::Drop,
+            "else" => Self::Else,
+            "end" => Self::End,
+            "execute" => Self::Execute,
             "exists" => Self::Exists,
             "explain" => Self::Explain,
             "false" => Self::False,
             "float" => Self::Float,
+            "foreign" => Self::Foreign,
             "from" => Self::From,
+            "full" => Self::Full,
+            "generated" => Self::Generated,
             "group" => Self::Group,
             "having" => Self::Having,
+            "identity" => Self::Identity,
             "if" => Self::If,
+            "in" => Self::In,
+            "increment" => Self::Increment,
             "index" => Self::Index,
             "infinity" => Self::Infinity,
             "inner" => Self::Inner,
@@ -200,7 +277,9 @@ impl TryFrom<&str> for Keyword {
             "like" => Self::Like,
             "limit" => Self::Limit,
             "nan" => Self::NaN,
+            "natural" => Self::Natural,
             "not" => Self::Not,
+            "nothing" => Self::Nothing,
             "null" => Self::Null,
             "of" => Self::Of,
             "offset" => Self::Offset,
@@ -209,25 +288,38 @@ impl TryFrom<&str> for Keyword {
             "or" => Self::Or,
             "order" => Self::Order,
             "outer" => Self::Outer,
+            "prepare" => Self::Prepare,
             "primary" => Self::Primary,
             "read" => Self::Read,
             "references" => Self::References,
+            "rename" => Self::Rename,
+            "restrict" => Self::Restrict,
+            "returning" => Self::Returning,
             "right" => Self::Right,
             "rollback" => Self::Rollback,
             "select" => Self::Select,
+            "sequence" => Self::Sequence,
+            "serial" => Self::Serial,
             "set" => Self::Set,
+            "similar" => Self::Similar,
+            "start" => Self::Start,
             "string" => Self::String,
             "system" => Self::System,
             "table" => Self::Table,
             "text" => Self::Text,
+            "then" => Self::Then,
             "time" => Self::Time,
+            "to" => Self::To,
             "transaction" => Self::Transaction,
             "true" => Self::True,
             "unique" => Self::Unique,
             "update" => Self::Update,
+            "using" => Self::Using,
             "values" => Self::Values,
             "varchar" => Self::Varchar,
+            "when" => Self::When,
             "where" => Self::Where,
+            "with" => Self::With,
             "write" => Self::Write,
             _ => return Err("not a keyword"),
         })
@@ -238,29 +330,52 @@ impl Display for Keyword {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         // Display keywords as uppercase.
         f.write_str(match self {
+            Self::Add => "ADD",
+            Self::Alter => "ALTER",
+            Self::Analyze => "ANALYZE",
             Self::As => "AS",
             Self::Asc => "ASC",
             Self::And => "AND",
             Self::Begin => "BEGIN",
+            Self::Between => "BETWEEN",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
+            Self::Cascade => "CASCADE",
+            Self::Case => "CASE",
+            Self::Cast => "CAST",
+            Self::Check => "CHECK",
+            Self::Column => "COLUMN",
             Self::Commit => "COMMIT",
+            Self::Conflict => "CONFLICT",
+            Self::Constraint => "CONSTRAINT",
             Self::Create => "CREATE",
             Self::Cross => "CROSS",
+            Self::Deallocate => "DEALLOCATE",
             Self::Default => "DEFAULT",
             Self::Delete => "DELETE",
             Self::Desc => "DESC",
+            Self::Distinct => "DISTINCT",
+            Self::Do => "DO",
             Self::Double => "DOUBLE",
             Self::Drop => "DROP",
+            Self::Else => "ELSE",
+            Self::End => "END",
+            Self::Execute => "EXECUTE",
             Self::Exists => "EXISTS",
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
+            Self::Foreign => "FOREIGN",
             Self::From => "FROM",
+            Self::Full => "FULL",
+            Self::Generated => "GENERATED",
             Self::Group => "GROUP",
             Self::Having => "HAVING",
+            Self::Identity => "IDENTITY",
             Self::If => "IF",
+            Self::In => "IN",
+            Self::Increment => "INCREMENT",
             Self::Index => "INDEX",
             Self::Infinity => "INFINITY",
             Self::Inner => "INNER",
@@ -275,7 +390,9 @@ impl Display for Keyword {
             Self::Like => "LIKE",
             Self::Limit => "LIMIT",
             Self::NaN => "NAN",
+            Self::Natural => "NATURAL",
             Self::Not => "NOT",
+            Self::Nothing => "NOTHING",
             Self::Null => "NULL",
             Self::Of => "OF",
             Self::Offset => "OFFSET",
@@ -284,25 +401,38 @@ impl Display for Keyword {
             Self::Outer => "OUTER",
             Self::Or => "OR",
             Self::Order => "ORDER",
+            Self::Prepare => "PREPARE",
             Self::Primary => "PRIMARY",
             Self::Read => "READ",
             Self::References => "REFERENCES",
+            Self::Rename => "RENAME",
+            Self::Restrict => "RESTRICT",
+            Self::Returning => "RETURNING",
             Self::Right => "RIGHT",
             Self::Rollback => "ROLLBACK",
             Self::Select => "SELECT",
+            Self::Sequence => "SEQUENCE",
+            Self::Serial => "SERIAL",
             Self::Set => "SET",
+            Self::Similar => "SIMILAR",
+            Self::Start => "START",
             Self::String => "STRING",
             Self::System => "SYSTEM",
             Self::Table => "TABLE",
             Self::Text => "TEXT",
+            Self::Then => "THEN",
             Self::Time => "TIME",
+            Self::To => "TO",
             Self::Transaction => "TRANSACTION",
             Self::True => "TRUE",
             Self::Unique => "UNIQUE",
             Self::Update => "UPDATE",
+            Self::Using => "USING",
             Self::Values => "VALUES",
             Self::Varchar => "VARCHAR",
+            Self::When => "WHEN",
             Self::Where => "WHERE",
+            Self::With => "WITH",
             Self::Write => "WRITE",
         })
     }
@@ -312,6 +442,7 @@ impl Display for Keyword {
 /// lexical tokens (e.g. keyword, number, string, etc), which are passed on to
 /// the SQL parser. In doing so, it strips away basic syntactic noise such as
 /// whitespace, case, and quotes, and performs initial symbol validation.
+#[derive(Clone)]
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
 }
@@ -367,6 +498,7 @@ impl<'a> Lexer<'a> {
             '\'' => self.scan_string(),
             '"' => self.scan_ident_quoted(),
             '0'..='9' => Ok(self.scan_number()),
+            '$' => self.scan_parameter(),
             c if c.is_alphabetic() => Ok(self.scan_ident_or_keyword()),
             _ => Ok(self.scan_symbol()),
         }
@@ -450,6 +582,24 @@ impl<'a> Lexer<'a> {
         Ok(Some(Token::String(string)))
     }
 
+    /// Scans the next numbered parameter placeholder, if any, e.g. $1.
+    fn scan_parameter(&mut self) -> Result<Option<Token>> {
+        if !self.next_is('$') {
+            return Ok(None);
+        }
+        let mut number = String::new();
+        while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
+            number.push(c)
+        }
+        if number.is_empty() {
+            return errinput!("expected parameter number after $");
+        }
+        match number.parse()? {
+            0 => errinput!("parameters are numbered from $1"),
+            n => Ok(Some(Token::Parameter(n))),
+        }
+    }
+
     /// Scans the next symbol token, if any.
     fn scan_symbol(&mut self) -> Option<Token> {
         let mut token = self.next_if_map(|c| {
@@ -465,7 +615,9 @@ impl<'a> Lexer<'a> {
                 '^' => Token::Caret,
                 '%' => Token::Percent,
                 '!' => Token::Exclamation,
+                '~' => Token::Tilde,
                 '?' => Token::Question,
+                ':' => Token::Colon,
                 ',' => Token::Comma,
                 ';' => Token::Semicolon,
                 '(' => Token::OpenParen,
@@ -473,9 +625,15 @@ impl<'a> Lexer<'a> {
                 _ => return None,
             })
         })?;
-        // Handle two-character tokens, e.g. !=.
+        // Handle two- and three-character tokens, e.g. != and !~*.
         token = match token {
+            Token::Colon if self.next_is(':') => Token::DoubleColon,
             Token::Exclamation if self.next_is('=') => Token::NotEqual,
+            Token::Exclamation if self.next_is('~') => match self.next_is('*') {
+                true => Token::NotTildeAsterisk,
+                false => Token::NotTilde,
+            },
+            Token::Tilde if self.next_is('*') => Token::TildeAsterisk,
             Token::GreaterThan if self.next_is('=') => Token::GreaterThanOrEqual,
             Token::LessThan if self.next_is('>') => Token::LessOrGreaterThan,
             Token::LessThan if self.next_is('=') => Token::LessThanOrEqual,
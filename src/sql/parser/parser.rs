@@ -1,10 +1,11 @@
+use std::collections::BTreeMap;
 use std::iter::Peekable;
 use std::ops::Add;
 
 use super::{Keyword, Lexer, Token, ast};
 use crate::errinput;
 use crate::error::Result;
-use crate::sql::types::DataType;
+use crate::sql::types::{DataType, Reference, ReferenceAction};
 
 /// The SQL parser takes tokens from the lexer and parses the SQL syntax into an
 /// Abstract Syntax Tree (AST).
@@ -16,19 +17,30 @@ use crate::sql::types::DataType;
 /// the planner.
 pub struct Parser<'a> {
     pub lexer: Peekable<Lexer<'a>>,
+    /// The number of positional parameter placeholders (?) seen so far.
+    positional: usize,
+    /// The highest numbered parameter placeholder ($n) seen so far.
+    numbered: usize,
 }
 
 impl Parser<'_> {
     /// Parses the input string into a SQL statement AST. The entire string must
     /// be parsed as a single statement, ending with an optional semicolon.
     pub fn parse(statement: &str) -> Result<ast::Statement> {
+        Self::parse_parameterized(statement).map(|(statement, _)| statement)
+    }
+
+    /// Parses the input string into a SQL statement AST, like parse(), also
+    /// returning the number of parameters given by placeholders: either
+    /// positional (?) or numbered ($1), but not both.
+    pub fn parse_parameterized(statement: &str) -> Result<(ast::Statement, usize)> {
         let mut parser = Self::new(statement);
         let statement = parser.parse_statement()?;
         parser.skip(Token::Semicolon);
         if let Some(token) = parser.lexer.next().transpose()? {
             return errinput!("unexpected token {token}");
         }
-        Ok(statement)
+        Ok((statement, parser.parameters()))
     }
 
     /// Parse the input string into a SQL expression AST. The entire string must
@@ -45,7 +57,12 @@ impl Parser<'_> {
 
     /// Creates a new parser for the given raw SQL string.
     fn new(input: &str) -> Parser<'_> {
-        Parser { lexer: Lexer::new(input).peekable() }
+        Parser { lexer: Lexer::new(input).peekable(), positional: 0, numbered: 0 }
+    }
+
+    /// Returns the number of parameters seen so far.
+    fn parameters(&self) -> usize {
+        self.positional.max(self.numbered)
     }
 
     /// Fetches the next lexer token, or errors if none is found.
@@ -106,6 +123,12 @@ impl Parser<'_> {
         self.lexer.peek().map(|r| r.as_ref().map_err(|err| err.clone())).transpose()
     }
 
+    /// Peeks the token after the next token, for the rare cases where we need
+    /// two tokens of lookahead. Clones the lexer, which is cheap.
+    fn peek_second(&self) -> Result<Option<Token>> {
+        self.lexer.clone().nth(1).transpose()
+    }
+
     /// Parses a SQL statement.
     fn parse_statement(&mut self) -> Result<ast::Statement> {
         let Some(token) = self.peek()? else {
@@ -117,8 +140,22 @@ impl Parser<'_> {
             Token::Keyword(Keyword::Rollback) => self.parse_rollback(),
             Token::Keyword(Keyword::Explain) => self.parse_explain(),
 
-            Token::Keyword(Keyword::Create) => self.parse_create_table(),
-            Token::Keyword(Keyword::Drop) => self.parse_drop_table(),
+            Token::Keyword(Keyword::Prepare) => self.parse_prepare(),
+            Token::Keyword(Keyword::Execute) => self.parse_execute(),
+            Token::Keyword(Keyword::Deallocate) => self.parse_deallocate(),
+
+            Token::Keyword(Keyword::Alter) => self.parse_alter_table(),
+            Token::Keyword(Keyword::Analyze) => self.parse_analyze(),
+            Token::Keyword(Keyword::Create) => match self.peek_second()? {
+                Some(Token::Keyword(Keyword::Index | Keyword::Unique)) => self.parse_create_index(),
+                Some(Token::Keyword(Keyword::Sequence)) => self.parse_create_sequence(),
+                _ => self.parse_create_table(),
+            },
+            Token::Keyword(Keyword::Drop) => match self.peek_second()? {
+                Some(Token::Keyword(Keyword::Index)) => self.parse_drop_index(),
+                Some(Token::Keyword(Keyword::Sequence)) => self.parse_drop_sequence(),
+                _ => self.parse_drop_table(),
+            },
 
             Token::Keyword(Keyword::Delete) => self.parse_delete(),
             Token::Keyword(Keyword::Insert) => self.parse_insert(),
@@ -171,10 +208,84 @@ impl Parser<'_> {
     /// Parses an EXPLAIN statement.
     fn parse_explain(&mut self) -> Result<ast::Statement> {
         self.expect(Keyword::Explain.into())?;
+        // EXPLAIN ANALYZE followed by a statement keyword executes the
+        // statement. Otherwise, ANALYZE is the statement being explained.
+        let analyze = self.peek()? == Some(&Keyword::Analyze.into())
+            && matches!(self.peek_second()?, Some(Token::Keyword(_)));
+        if analyze {
+            self.next()?;
+        }
         if self.next_is(Keyword::Explain.into()) {
             return errinput!("cannot nest EXPLAIN statements");
         }
-        Ok(ast::Statement::Explain(Box::new(self.parse_statement()?)))
+        let statement = Box::new(self.parse_statement()?);
+        if let ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback =
+            *statement
+        {
+            return errinput!("cannot explain transaction statements");
+        }
+        if let ast::Statement::Prepare { .. }
+        | ast::Statement::Execute { .. }
+        | ast::Statement::Deallocate { .. } = *statement
+        {
+            return errinput!("cannot explain prepared statement commands");
+        }
+        Ok(ast::Statement::Explain { statement, analyze })
+    }
+
+    /// Parses a PREPARE statement.
+    fn parse_prepare(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Prepare.into())?;
+        let name = self.next_ident()?;
+        self.expect(Keyword::As.into())?;
+        let statement = Box::new(self.parse_statement()?);
+        match *statement {
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return errinput!("cannot prepare transaction statements");
+            }
+            ast::Statement::Explain { .. }
+            | ast::Statement::Prepare { .. }
+            | ast::Statement::Execute { .. }
+            | ast::Statement::Deallocate { .. } => {
+                return errinput!("cannot prepare EXPLAIN or prepared statement commands");
+            }
+            _ => {}
+        }
+        Ok(ast::Statement::Prepare { name, statement, parameters: self.parameters() })
+    }
+
+    /// Parses an EXECUTE statement.
+    fn parse_execute(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Execute.into())?;
+        let name = self.next_ident()?;
+        let mut params = Vec::new();
+        if self.next_is(Token::OpenParen) {
+            loop {
+                params.push(self.parse_expression()?);
+                if !self.next_is(Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(Token::CloseParen)?;
+        }
+        Ok(ast::Statement::Execute { name, params })
+    }
+
+    /// Parses a DEALLOCATE statement.
+    fn parse_deallocate(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Deallocate.into())?;
+        self.skip(Keyword::Prepare.into());
+        Ok(ast::Statement::Deallocate { name: self.next_ident()? })
+    }
+
+    /// Parses an ANALYZE statement.
+    fn parse_analyze(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Analyze.into())?;
+        let table = self.next_if_map(|token| match token {
+            Token::Ident(table) => Some(table.clone()),
+            _ => None,
+        });
+        Ok(ast::Statement::Analyze { table })
     }
 
     /// Parses a CREATE TABLE statement.
@@ -182,28 +293,181 @@ impl Parser<'_> {
         self.expect(Keyword::Create.into())?;
         self.expect(Keyword::Table.into())?;
         let name = self.next_ident()?;
+        self.expect(Token::OpenParen)?;
+        let (mut columns, mut primary_key) = (Vec::new(), None);
+        let (mut foreign_keys, mut checks) = (Vec::new(), Vec::new());
+        loop {
+            // A PRIMARY KEY (columns), FOREIGN KEY (columns), or CHECK (expr)
+            // table constraint, or a column definition.
+            if self.next_is(Keyword::Primary.into()) {
+                self.expect(Keyword::Key.into())?;
+                if primary_key.is_some() {
+                    return errinput!("multiple primary keys for table {name}");
+                }
+                primary_key = Some(self.parse_column_list()?);
+            } else if self.next_is(Keyword::Foreign.into()) {
+                self.expect(Keyword::Key.into())?;
+                let columns = self.parse_column_list()?;
+                self.expect(Keyword::References.into())?;
+                foreign_keys.push(ast::ForeignKey { columns, reference: self.parse_reference()? });
+            } else if self.next_is(Keyword::Constraint.into()) {
+                let name = self.next_ident()?;
+                self.expect(Keyword::Check.into())?;
+                checks.push(self.parse_check(Some(name))?);
+            } else if self.next_is(Keyword::Check.into()) {
+                checks.push(self.parse_check(None)?);
+            } else {
+                columns.push(self.parse_create_table_column()?);
+            }
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(ast::Statement::CreateTable { name, columns, primary_key, foreign_keys, checks })
+    }
+
+    /// Parses a foreign key reference, following the REFERENCES keyword.
+    fn parse_reference(&mut self) -> Result<Reference> {
+        let mut reference = Reference::new(self.next_ident()?);
+        let (mut on_delete, mut on_update) = (None, None);
+        while self.next_is(Keyword::On.into()) {
+            let (event, action) = match self.next()? {
+                Token::Keyword(Keyword::Delete) => ("DELETE", &mut on_delete),
+                Token::Keyword(Keyword::Update) => ("UPDATE", &mut on_update),
+                token => return errinput!("expected DELETE or UPDATE, found {token}"),
+            };
+            if action.is_some() {
+                return errinput!("multiple ON {event} actions");
+            }
+            *action = Some(match self.next()? {
+                Token::Keyword(Keyword::Cascade) => ReferenceAction::Cascade,
+                Token::Keyword(Keyword::Restrict) => ReferenceAction::Restrict,
+                Token::Keyword(Keyword::Set) => match self.next()? {
+                    Token::Keyword(Keyword::Null) => ReferenceAction::SetNull,
+                    Token::Keyword(Keyword::Default) => ReferenceAction::SetDefault,
+                    token => return errinput!("expected NULL or DEFAULT, found {token}"),
+                },
+                token => return errinput!("unexpected token {token}"),
+            });
+        }
+        reference.on_delete = on_delete.unwrap_or_default();
+        reference.on_update = on_update.unwrap_or_default();
+        Ok(reference)
+    }
+
+    /// Parses the parenthesized expression of a CHECK constraint, following
+    /// the CHECK keyword.
+    fn parse_check(&mut self, name: Option<String>) -> Result<ast::Check> {
+        self.expect(Token::OpenParen)?;
+        let expression = self.parse_expression()?;
+        self.expect(Token::CloseParen)?;
+        Ok(ast::Check { name, expression })
+    }
+
+    /// Parses a CREATE [UNIQUE] INDEX statement.
+    fn parse_create_index(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Create.into())?;
+        let unique = self.next_is(Keyword::Unique.into());
+        self.expect(Keyword::Index.into())?;
+        let name = self.next_ident()?;
+        self.expect(Keyword::On.into())?;
+        let table = self.next_ident()?;
+        let columns = self.parse_column_list()?;
+        Ok(ast::Statement::CreateIndex { name, table, columns, unique })
+    }
+
+    /// Parses a CREATE SEQUENCE statement.
+    fn parse_create_sequence(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Create.into())?;
+        self.expect(Keyword::Sequence.into())?;
+        let name = self.next_ident()?;
+        let (mut start, mut increment) = (None, None);
+        while let Some(keyword) = self.next_if_keyword() {
+            match keyword {
+                Keyword::Start if start.is_none() => {
+                    self.skip(Keyword::With.into());
+                    start = Some(self.parse_expression()?);
+                }
+                Keyword::Increment if increment.is_none() => {
+                    self.skip(Keyword::By.into());
+                    increment = Some(self.parse_expression()?);
+                }
+                keyword => return errinput!("unexpected keyword {keyword}"),
+            }
+        }
+        Ok(ast::Statement::CreateSequence { name, start, increment })
+    }
+
+    /// Parses a parenthesized, comma-separated list of column names.
+    fn parse_column_list(&mut self) -> Result<Vec<String>> {
         self.expect(Token::OpenParen)?;
         let mut columns = Vec::new();
         loop {
-            columns.push(self.parse_create_table_column()?);
+            columns.push(self.next_ident()?);
             if !self.next_is(Token::Comma) {
                 break;
             }
         }
         self.expect(Token::CloseParen)?;
-        Ok(ast::Statement::CreateTable { name, columns })
+        Ok(columns)
     }
 
-    /// Parses a CREATE TABLE column definition.
-    fn parse_create_table_column(&mut self) -> Result<ast::Column> {
+    /// Parses an ALTER TABLE statement.
+    fn parse_alter_table(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Alter.into())?;
+        self.expect(Keyword::Table.into())?;
         let name = self.next_ident()?;
-        let datatype = match self.next()? {
-            Token::Keyword(Keyword::Bool | Keyword::Boolean) => DataType::Boolean,
-            Token::Keyword(Keyword::Float | Keyword::Double) => DataType::Float,
-            Token::Keyword(Keyword::Int | Keyword::Integer) => DataType::Integer,
-            Token::Keyword(Keyword::String | Keyword::Text | Keyword::Varchar) => DataType::String,
+        let alteration = match self.next()? {
+            Token::Keyword(Keyword::Add) => {
+                self.skip(Keyword::Column.into());
+                ast::Alteration::AddColumn(self.parse_create_table_column()?)
+            }
+            Token::Keyword(Keyword::Drop) => {
+                self.skip(Keyword::Column.into());
+                ast::Alteration::DropColumn(self.next_ident()?)
+            }
+            Token::Keyword(Keyword::Rename) => {
+                if self.next_is(Keyword::To.into()) {
+                    ast::Alteration::RenameTable(self.next_ident()?)
+                } else {
+                    self.skip(Keyword::Column.into());
+                    let from = self.next_ident()?;
+                    self.expect(Keyword::To.into())?;
+                    ast::Alteration::RenameColumn { from, to: self.next_ident()? }
+                }
+            }
+            Token::Keyword(Keyword::Alter) => {
+                self.skip(Keyword::Column.into());
+                let column = self.next_ident()?;
+                let set = match self.next()? {
+                    Token::Keyword(Keyword::Set) => true,
+                    Token::Keyword(Keyword::Drop) => false,
+                    token => return errinput!("expected SET or DROP, found {token}"),
+                };
+                match self.next()? {
+                    Token::Keyword(Keyword::Default) if set => {
+                        ast::Alteration::SetDefault(column, Some(self.parse_expression()?))
+                    }
+                    Token::Keyword(Keyword::Default) => ast::Alteration::SetDefault(column, None),
+                    Token::Keyword(Keyword::Not) => {
+                        self.expect(Keyword::Null.into())?;
+                        ast::Alteration::SetNullable(column, !set)
+                    }
+                    token => return errinput!("expected DEFAULT or NOT NULL, found {token}"),
+                }
+            }
             token => return errinput!("unexpected token {token}"),
         };
+        Ok(ast::Statement::AlterTable { name, alteration })
+    }
+
+    /// Parses a CREATE TABLE column definition.
+    fn parse_create_table_column(&mut self) -> Result<ast::Column> {
+        let name = self.next_ident()?;
+        // SERIAL is shorthand for INTEGER GENERATED AS IDENTITY.
+        let identity = self.next_is(Keyword::Serial.into());
+        let datatype = if identity { DataType::Integer } else { self.parse_datatype()? };
         let mut column = ast::Column {
             name,
             datatype,
@@ -213,6 +477,8 @@ impl Parser<'_> {
             unique: false,
             index: false,
             references: None,
+            identity,
+            checks: Vec::new(),
         };
         while let Some(keyword) = self.next_if_keyword() {
             match keyword {
@@ -236,13 +502,38 @@ impl Parser<'_> {
                 Keyword::Default => column.default = Some(self.parse_expression()?),
                 Keyword::Unique => column.unique = true,
                 Keyword::Index => column.index = true,
-                Keyword::References => column.references = Some(self.next_ident()?),
+                Keyword::References => column.references = Some(self.parse_reference()?),
+                Keyword::Generated => {
+                    if self.next_is(Keyword::By.into()) {
+                        self.expect(Keyword::Default.into())?;
+                    }
+                    self.expect(Keyword::As.into())?;
+                    self.expect(Keyword::Identity.into())?;
+                    column.identity = true;
+                }
+                Keyword::Constraint => {
+                    let name = self.next_ident()?;
+                    self.expect(Keyword::Check.into())?;
+                    column.checks.push(self.parse_check(Some(name))?);
+                }
+                Keyword::Check => column.checks.push(self.parse_check(None)?),
                 keyword => return errinput!("unexpected keyword {keyword}"),
             }
         }
         Ok(column)
     }
 
+    /// Parses a datatype name.
+    fn parse_datatype(&mut self) -> Result<DataType> {
+        Ok(match self.next()? {
+            Token::Keyword(Keyword::Bool | Keyword::Boolean) => DataType::Boolean,
+            Token::Keyword(Keyword::Float | Keyword::Double) => DataType::Float,
+            Token::Keyword(Keyword::Int | Keyword::Integer) => DataType::Integer,
+            Token::Keyword(Keyword::String | Keyword::Text | Keyword::Varchar) => DataType::String,
+            token => return errinput!("unexpected token {token}"),
+        })
+    }
+
     /// Parses a DROP TABLE statement.
     fn parse_drop_table(&mut self) -> Result<ast::Statement> {
         self.expect(Token::Keyword(Keyword::Drop))?;
@@ -256,12 +547,44 @@ impl Parser<'_> {
         Ok(ast::Statement::DropTable { name, if_exists })
     }
 
+    /// Parses a DROP INDEX statement.
+    fn parse_drop_index(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Drop.into())?;
+        self.expect(Keyword::Index.into())?;
+        let mut if_exists = false;
+        if self.next_is(Keyword::If.into()) {
+            self.expect(Keyword::Exists.into())?;
+            if_exists = true;
+        }
+        let name = self.next_ident()?;
+        Ok(ast::Statement::DropIndex { name, if_exists })
+    }
+
+    /// Parses a DROP SEQUENCE statement.
+    fn parse_drop_sequence(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Drop.into())?;
+        self.expect(Keyword::Sequence.into())?;
+        let mut if_exists = false;
+        if self.next_is(Keyword::If.into()) {
+            self.expect(Keyword::Exists.into())?;
+            if_exists = true;
+        }
+        let name = self.next_ident()?;
+        Ok(ast::Statement::DropSequence { name, if_exists })
+    }
+
     /// Parses a DELETE statement.
     fn parse_delete(&mut self) -> Result<ast::Statement> {
         self.expect(Keyword::Delete.into())?;
         self.expect(Keyword::From.into())?;
         let table = self.next_ident()?;
-        Ok(ast::Statement::Delete { table, r#where: self.parse_where_clause()? })
+        let mut using = Vec::new();
+        if self.next_is(Keyword::Using.into()) {
+            using = self.parse_from_items()?;
+        }
+        let r#where = self.parse_where_clause()?;
+        let returning = self.parse_returning_clause()?;
+        Ok(ast::Statement::Delete { table, using, r#where, returning })
     }
 
     /// Parses an INSERT statement.
@@ -271,15 +594,16 @@ impl Parser<'_> {
         let table = self.next_ident()?;
 
         let mut columns = None;
-        if self.next_is(Token::OpenParen) {
-            let columns = columns.insert(Vec::new());
-            loop {
-                columns.push(self.next_ident()?);
-                if !self.next_is(Token::Comma) {
-                    break;
-                }
-            }
-            self.expect(Token::CloseParen)?;
+        if self.peek()? == Some(&Token::OpenParen) {
+            columns = Some(self.parse_column_list()?);
+        }
+
+        // INSERT ... SELECT.
+        if self.peek()? == Some(&Token::Keyword(Keyword::Select)) {
+            let source = ast::InsertSource::Select(Box::new(self.parse_select()?));
+            let on_conflict = self.parse_on_conflict_clause()?;
+            let returning = self.parse_returning_clause()?;
+            return Ok(ast::Statement::Insert { table, columns, source, on_conflict, returning });
         }
 
         self.expect(Keyword::Values.into())?;
@@ -301,7 +625,46 @@ impl Parser<'_> {
             }
         }
 
-        Ok(ast::Statement::Insert { table, columns, values })
+        let source = ast::InsertSource::Values(values);
+        let on_conflict = self.parse_on_conflict_clause()?;
+        let returning = self.parse_returning_clause()?;
+        Ok(ast::Statement::Insert { table, columns, source, on_conflict, returning })
+    }
+
+    /// Parses a RETURNING clause, if present. Returns an empty vector if not.
+    fn parse_returning_clause(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
+        if !self.next_is(Keyword::Returning.into()) {
+            return Ok(Vec::new());
+        }
+        self.parse_select_clause()
+    }
+
+    /// Parses an INSERT ON CONFLICT clause, if present.
+    fn parse_on_conflict_clause(&mut self) -> Result<Option<ast::OnConflict>> {
+        if !self.next_is(Keyword::On.into()) {
+            return Ok(None);
+        }
+        self.expect(Keyword::Conflict.into())?;
+        let mut columns = None;
+        if self.peek()? == Some(&Token::OpenParen) {
+            columns = Some(self.parse_column_list()?);
+        }
+        self.expect(Keyword::Do.into())?;
+        let action = match self.next()? {
+            Token::Keyword(Keyword::Nothing) => ast::ConflictAction::Nothing,
+            Token::Keyword(Keyword::Update) => {
+                // Like Postgres, require a conflict target, to make it clear
+                // which existing row is updated.
+                if columns.is_none() {
+                    return errinput!("ON CONFLICT DO UPDATE requires conflict columns");
+                }
+                self.expect(Keyword::Set.into())?;
+                let set = self.parse_set_clause()?;
+                ast::ConflictAction::Update { set, r#where: self.parse_where_clause()? }
+            }
+            token => return errinput!("expected NOTHING or UPDATE, found {token}"),
+        };
+        Ok(Some(ast::OnConflict { columns, action }))
     }
 
     /// Parses an UPDATE statement.
@@ -309,7 +672,17 @@ impl Parser<'_> {
         self.expect(Keyword::Update.into())?;
         let table = self.next_ident()?;
         self.expect(Keyword::Set.into())?;
-        let mut set = std::collections::BTreeMap::new();
+        let set = self.parse_set_clause()?;
+        let from = self.parse_from_clause()?;
+        let r#where = self.parse_where_clause()?;
+        let returning = self.parse_returning_clause()?;
+        Ok(ast::Statement::Update { table, set, from, r#where, returning })
+    }
+
+    /// Parses the column assignments of a SET clause, following the SET
+    /// keyword. None is used for DEFAULT.
+    fn parse_set_clause(&mut self) -> Result<BTreeMap<String, Option<ast::Expression>>> {
+        let mut set = BTreeMap::new();
         loop {
             let column = self.next_ident()?;
             self.expect(Token::Equal)?;
@@ -324,12 +697,14 @@ impl Parser<'_> {
                 break;
             }
         }
-        Ok(ast::Statement::Update { table, set, r#where: self.parse_where_clause()? })
+        Ok(set)
     }
 
     /// Parses a SELECT statement.
     fn parse_select(&mut self) -> Result<ast::Statement> {
+        self.expect(Keyword::Select.into())?;
         Ok(ast::Statement::Select {
+            distinct: self.next_is(Keyword::Distinct.into()),
             select: self.parse_select_clause()?,
             from: self.parse_from_clause()?,
             r#where: self.parse_where_clause()?,
@@ -341,17 +716,14 @@ impl Parser<'_> {
         })
     }
 
-    /// Parses a SELECT clause, if present.
+    /// Parses a SELECT clause, following the SELECT [DISTINCT] keywords.
     fn parse_select_clause(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
-        if !self.next_is(Keyword::Select.into()) {
-            return Ok(Vec::new());
-        }
         let mut select = Vec::new();
         loop {
             let expr = self.parse_expression()?;
             let mut alias = None;
             if self.next_is(Keyword::As.into()) || matches!(self.peek()?, Some(Token::Ident(_))) {
-                if expr == ast::Expression::All {
+                if matches!(expr, ast::Expression::All | ast::Expression::QualifiedAll(_)) {
                     return errinput!("can't alias *");
                 }
                 alias = Some(self.next_ident()?);
@@ -369,18 +741,37 @@ impl Parser<'_> {
         if !self.next_is(Keyword::From.into()) {
             return Ok(Vec::new());
         }
+        self.parse_from_items()
+    }
+
+    /// Parses one or more comma-separated FROM items, each a table or join.
+    fn parse_from_items(&mut self) -> Result<Vec<ast::From>> {
         let mut from = Vec::new();
         loop {
             let mut from_item = self.parse_from_table()?;
-            while let Some(r#type) = self.parse_from_join()? {
+            loop {
+                let natural = self.next_is(Keyword::Natural.into());
+                let Some(r#type) = self.parse_from_join()? else {
+                    if natural {
+                        self.expect(Keyword::Join.into())?; // errors
+                    }
+                    break;
+                };
                 let left = Box::new(from_item);
                 let right = Box::new(self.parse_from_table()?);
-                let mut predicate = None;
-                if r#type != ast::JoinType::Cross {
-                    self.expect(Keyword::On.into())?;
-                    predicate = Some(self.parse_expression()?)
-                }
-                from_item = ast::From::Join { left, right, r#type, predicate };
+                let condition = match r#type {
+                    ast::JoinType::Cross if natural => return errinput!("unexpected NATURAL"),
+                    ast::JoinType::Cross => None,
+                    _ if natural => Some(ast::JoinCondition::Natural),
+                    _ if self.next_is(Keyword::Using.into()) => {
+                        Some(ast::JoinCondition::Using(self.parse_column_list()?))
+                    }
+                    _ => {
+                        self.expect(Keyword::On.into())?;
+                        Some(ast::JoinCondition::On(self.parse_expression()?))
+                    }
+                };
+                from_item = ast::From::Join { left, right, r#type, condition };
             }
             from.push(from_item);
             if !self.next_is(Token::Comma) {
@@ -423,6 +814,11 @@ impl Parser<'_> {
             self.expect(Keyword::Join.into())?;
             return Ok(Some(ast::JoinType::Right));
         }
+        if self.next_is(Keyword::Full.into()) {
+            self.skip(Keyword::Outer.into());
+            self.expect(Keyword::Join.into())?;
+            return Ok(Some(ast::JoinType::Full));
+        }
         Ok(None)
     }
 
@@ -533,13 +929,14 @@ impl Parser<'_> {
     /// * 1: OR
     /// * 2: AND
     /// * 3: NOT
-    /// * 4: =, !=, LIKE, IS
+    /// * 4: =, !=, LIKE, IS, IN, BETWEEN
     /// * 5: <, <=, >, >=
     /// * 6: +, -
     /// * 7: *, /, %
     /// * 8: ^
     /// * 9: !
     /// * 10: +, - (prefix)
+    /// * 11: :: (cast)
     ///
     /// We also have to specify the associativity of operators:
     ///
@@ -646,8 +1043,10 @@ impl Parser<'_> {
     /// Parses an expression atom. This is either:
     ///
     /// * A literal value.
-    /// * A column name.
+    /// * A column name, or table.* for all table columns.
     /// * A function call.
+    /// * A CASE or CAST expression.
+    /// * An EXISTS subquery.
     /// * A parenthesized expression.
     fn parse_expression_atom(&mut self) -> Result<ast::Expression> {
         Ok(match self.next()? {
@@ -666,8 +1065,26 @@ impl Parser<'_> {
             Token::Keyword(Keyword::NaN) => ast::Literal::Float(f64::NAN).into(),
             Token::Keyword(Keyword::Null) => ast::Literal::Null.into(),
 
+            // Parameter placeholder. Positional placeholders are numbered in
+            // order of appearance.
+            Token::Question if self.numbered > 0 => {
+                return errinput!("cannot mix ? and $n parameters");
+            }
+            Token::Question => {
+                self.positional += 1;
+                ast::Expression::Parameter(self.positional)
+            }
+            Token::Parameter(_) if self.positional > 0 => {
+                return errinput!("cannot mix ? and $n parameters");
+            }
+            Token::Parameter(n) => {
+                self.numbered = self.numbered.max(n);
+                ast::Expression::Parameter(n)
+            }
+
             // Function call.
             Token::Ident(name) if self.next_is(Token::OpenParen) => {
+                let distinct = self.next_is(Keyword::Distinct.into());
                 let mut args = Vec::new();
                 while !self.next_is(Token::CloseParen) {
                     if !args.is_empty() {
@@ -675,12 +1092,58 @@ impl Parser<'_> {
                     }
                     args.push(self.parse_expression()?);
                 }
-                ast::Expression::Function(name, args)
+                ast::Expression::Function(name, args, distinct)
+            }
+
+            // CASE expression, with an optional operand to compare with.
+            Token::Keyword(Keyword::Case) => {
+                let mut operand = None;
+                if !matches!(self.peek()?, Some(Token::Keyword(Keyword::When))) {
+                    operand = Some(Box::new(self.parse_expression()?));
+                }
+                let mut when = Vec::new();
+                while self.next_is(Keyword::When.into()) {
+                    let condition = self.parse_expression()?;
+                    self.expect(Keyword::Then.into())?;
+                    when.push((condition, self.parse_expression()?));
+                }
+                if when.is_empty() {
+                    return errinput!("CASE requires at least one WHEN clause");
+                }
+                let mut r#else = None;
+                if self.next_is(Keyword::Else.into()) {
+                    r#else = Some(Box::new(self.parse_expression()?));
+                }
+                self.expect(Keyword::End.into())?;
+                ast::Expression::Case { operand, when, r#else }
+            }
+
+            // CAST expression.
+            Token::Keyword(Keyword::Cast) => {
+                self.expect(Token::OpenParen)?;
+                let expr = self.parse_expression()?;
+                self.expect(Keyword::As.into())?;
+                let datatype = self.parse_datatype()?;
+                self.expect(Token::CloseParen)?;
+                ast::Expression::Cast(Box::new(expr), datatype)
+            }
+
+            // EXISTS subquery.
+            Token::Keyword(Keyword::Exists) => {
+                self.expect(Token::OpenParen)?;
+                let select = self.parse_select()?;
+                self.expect(Token::CloseParen)?;
+                ast::Expression::Exists(Box::new(select))
             }
 
             // Column name, either qualified as table.column or unqualified.
+            // table.* refers to all columns of the table.
             Token::Ident(table) if self.next_is(Token::Period) => {
-                ast::Expression::Column(Some(table), self.next_ident()?)
+                if self.next_is(Token::Asterisk) {
+                    ast::Expression::QualifiedAll(table)
+                } else {
+                    ast::Expression::Column(Some(table), self.next_ident()?)
+                }
             }
             Token::Ident(column) => ast::Expression::Column(None, column),
 
@@ -727,9 +1190,13 @@ impl Parser<'_> {
                 Token::LessThanOrEqual => InfixOperator::LessThanOrEqual,
                 Token::Minus => InfixOperator::Subtract,
                 Token::NotEqual => InfixOperator::NotEqual,
+                Token::NotTilde => InfixOperator::NotRegexMatch,
+                Token::NotTildeAsterisk => InfixOperator::NotRegexMatchInsensitive,
                 Token::Percent => InfixOperator::Remainder,
                 Token::Plus => InfixOperator::Add,
                 Token::Slash => InfixOperator::Divide,
+                Token::Tilde => InfixOperator::RegexMatch,
+                Token::TildeAsterisk => InfixOperator::RegexMatchInsensitive,
                 _ => return None,
             };
             Some(operator).filter(|op| op.precedence() >= min_precedence)
@@ -763,6 +1230,73 @@ impl Parser<'_> {
             return Ok(Some(operator));
         }
 
+        // Handle (NOT) IN, (NOT) BETWEEN, and (NOT) SIMILAR TO separately,
+        // since they're multiple tokens and contain expressions.
+        // NOT is only consumed if followed by one of these, since it can
+        // follow an expression elsewhere too, e.g. DEFAULT 1 NOT NULL.
+        let is_operator = |token: Option<&Token>| {
+            matches!(token, Some(Token::Keyword(Keyword::Between | Keyword::In | Keyword::Similar)))
+        };
+        if is_operator(self.peek()?)
+            || (self.peek()? == Some(&Token::Keyword(Keyword::Not))
+                && is_operator(self.peek_second()?.as_ref()))
+        {
+            // We can't consume tokens unless the precedence is satisfied, so we
+            // assume IN (they all have the same precedence).
+            if PostfixOperator::In(Vec::new()).precedence() < min_precedence {
+                return Ok(None);
+            }
+            let not = self.next_is(Keyword::Not.into());
+            let operator = match self.next()? {
+                Token::Keyword(Keyword::In) => {
+                    self.expect(Token::OpenParen)?;
+                    if self.peek()? == Some(&Token::Keyword(Keyword::Select)) {
+                        let select = self.parse_select()?;
+                        self.expect(Token::CloseParen)?;
+                        PostfixOperator::InSubquery(select)
+                    } else {
+                        let mut list = Vec::new();
+                        loop {
+                            list.push(self.parse_expression()?);
+                            if !self.next_is(Token::Comma) {
+                                break;
+                            }
+                        }
+                        self.expect(Token::CloseParen)?;
+                        PostfixOperator::In(list)
+                    }
+                }
+                Token::Keyword(Keyword::Between) => {
+                    // Parse the bounds above the precedence of AND, to avoid
+                    // consuming the BETWEEN's AND as a logical operator.
+                    let next_precedence = PostfixOperator::In(Vec::new()).precedence() + 1;
+                    let low = self.parse_expression_at(next_precedence)?;
+                    self.expect(Keyword::And.into())?;
+                    let high = self.parse_expression_at(next_precedence)?;
+                    PostfixOperator::Between(low, high)
+                }
+                Token::Keyword(Keyword::Similar) => {
+                    self.expect(Keyword::To.into())?;
+                    let next_precedence = PostfixOperator::In(Vec::new()).precedence() + 1;
+                    PostfixOperator::SimilarTo(self.parse_expression_at(next_precedence)?)
+                }
+                token => return errinput!("unexpected token {token}"),
+            };
+            return Ok(Some(match not {
+                false => operator,
+                true => PostfixOperator::Not(Box::new(operator)),
+            }));
+        }
+
+        // Handle ::type casts separately, since the type is a separate token.
+        if self.peek()? == Some(&Token::DoubleColon) {
+            if PostfixOperator::Cast(DataType::Boolean).precedence() < min_precedence {
+                return Ok(None);
+            }
+            self.expect(Token::DoubleColon)?;
+            return Ok(Some(PostfixOperator::Cast(self.parse_datatype()?)));
+        }
+
         Ok(self.next_if_map(|token| {
             let operator = match token {
                 Token::Exclamation => PostfixOperator::Factorial,
@@ -830,21 +1364,25 @@ impl PrefixOperator {
 
 /// Infix operators.
 enum InfixOperator {
-    Add,                // a + b
-    And,                // a AND b
-    Divide,             // a / b
-    Equal,              // a = b
-    Exponentiate,       // a ^ b
-    GreaterThan,        // a > b
-    GreaterThanOrEqual, // a >= b
-    LessThan,           // a < b
-    LessThanOrEqual,    // a <= b
-    Like,               // a LIKE b
-    Multiply,           // a * b
-    NotEqual,           // a != b
-    Or,                 // a OR b
-    Remainder,          // a % b
-    Subtract,           // a - b
+    Add,                      // a + b
+    And,                      // a AND b
+    Divide,                   // a / b
+    Equal,                    // a = b
+    Exponentiate,             // a ^ b
+    GreaterThan,              // a > b
+    GreaterThanOrEqual,       // a >= b
+    LessThan,                 // a < b
+    LessThanOrEqual,          // a <= b
+    Like,                     // a LIKE b
+    Multiply,                 // a * b
+    NotEqual,                 // a != b
+    NotRegexMatch,            // a !~ b
+    NotRegexMatchInsensitive, // a !~* b
+    Or,                       // a OR b
+    RegexMatch,               // a ~ b
+    RegexMatchInsensitive,    // a ~* b
+    Remainder,                // a % b
+    Subtract,                 // a - b
 }
 
 impl InfixOperator {
@@ -857,7 +1395,14 @@ impl InfixOperator {
             Self::Or => 1,
             Self::And => 2,
             // Self::Not => 3
-            Self::Equal | Self::NotEqual | Self::Like => 4, // also Self::Is
+            // also IS, IN, BETWEEN, SIMILAR TO
+            Self::Equal
+            | Self::NotEqual
+            | Self::Like
+            | Self::RegexMatch
+            | Self::RegexMatchInsensitive
+            | Self::NotRegexMatch
+            | Self::NotRegexMatchInsensitive => 4,
             Self::GreaterThan
             | Self::GreaterThanOrEqual
             | Self::LessThan
@@ -892,7 +1437,15 @@ impl InfixOperator {
             Self::Like => ast::Operator::Like(lhs, rhs).into(),
             Self::Multiply => ast::Operator::Multiply(lhs, rhs).into(),
             Self::NotEqual => ast::Operator::NotEqual(lhs, rhs).into(),
+            Self::NotRegexMatch => {
+                ast::Operator::Not(ast::Operator::RegexMatch(lhs, rhs, false).into()).into()
+            }
+            Self::NotRegexMatchInsensitive => {
+                ast::Operator::Not(ast::Operator::RegexMatch(lhs, rhs, true).into()).into()
+            }
             Self::Or => ast::Operator::Or(lhs, rhs).into(),
+            Self::RegexMatch => ast::Operator::RegexMatch(lhs, rhs, false).into(),
+            Self::RegexMatchInsensitive => ast::Operator::RegexMatch(lhs, rhs, true).into(),
             Self::Remainder => ast::Operator::Remainder(lhs, rhs).into(),
             Self::Subtract => ast::Operator::Subtract(lhs, rhs).into(),
         }
@@ -901,17 +1454,30 @@ impl InfixOperator {
 
 /// Postfix operators.
 enum PostfixOperator {
-    Factorial,           // a!
-    Is(ast::Literal),    // a IS NULL | NAN
-    IsNot(ast::Literal), // a IS NOT NULL | NAN
+    Between(ast::Expression, ast::Expression), // a BETWEEN b AND c
+    Cast(DataType),                            // a::type
+    Factorial,                                 // a!
+    In(Vec<ast::Expression>),                  // a IN (b, c, ...)
+    InSubquery(ast::Statement),                // a IN (SELECT ...)
+    Is(ast::Literal),                          // a IS NULL | NAN
+    IsNot(ast::Literal),                       // a IS NOT NULL | NAN
+    Not(Box<PostfixOperator>),                 // a NOT IN | BETWEEN | SIMILAR TO ...
+    SimilarTo(ast::Expression),                // a SIMILAR TO b
 }
 
 impl PostfixOperator {
     // The operator precedence.
     fn precedence(&self) -> Precedence {
         match self {
-            Self::Is(_) | Self::IsNot(_) => 4,
+            Self::Between(_, _)
+            | Self::In(_)
+            | Self::InSubquery(_)
+            | Self::Is(_)
+            | Self::IsNot(_)
+            | Self::SimilarTo(_) => 4,
+            Self::Not(operator) => operator.precedence(),
             Self::Factorial => 9,
+            Self::Cast(_) => 11,
         }
     }
 
@@ -919,6 +1485,16 @@ impl PostfixOperator {
     fn into_expression(self, lhs: ast::Expression) -> ast::Expression {
         let lhs = Box::new(lhs);
         match self {
+            Self::Between(low, high) => {
+                ast::Operator::Between(lhs, Box::new(low), Box::new(high)).into()
+            }
+            Self::Cast(datatype) => ast::Expression::Cast(lhs, datatype),
+            Self::In(list) => ast::Operator::In(lhs, list).into(),
+            Self::InSubquery(select) => ast::Operator::InSubquery(lhs, Box::new(select)).into(),
+            Self::SimilarTo(rhs) => ast::Operator::SimilarTo(lhs, Box::new(rhs)).into(),
+            Self::Not(operator) => {
+                ast::Operator::Not(Box::new(operator.into_expression(*lhs))).into()
+            }
             Self::Factorial => ast::Operator::Factorial(lhs).into(),
             Self::Is(v) => ast::Operator::Is(lhs, v).into(),
             Self::IsNot(v) => ast::Operator::Not(ast::Operator::Is(lhs, v).into()).into(),
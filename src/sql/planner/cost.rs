@@ -0,0 +1,453 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+use super::Node;
+use crate::error::Result;
+use crate::sql::engine::Catalog;
+use crate::sql::types::{ColumnStatistics, Expression, Statistics, Table, Value};
+
+/// The cost of reading a row sequentially from storage, e.g. during a scan.
+/// Costs are in arbitrary units relative to this.
+const SEQ_ROW_COST: f64 = 1.0;
+/// The cost of reading a row or index entry via a random storage lookup.
+const RANDOM_ROW_COST: f64 = 2.0;
+/// The cost of processing a row in memory, e.g. evaluating an expression.
+const CPU_ROW_COST: f64 = 0.1;
+/// The cost of inserting a row into an in-memory hash table.
+const HASH_ROW_COST: f64 = 0.2;
+
+/// The assumed number of rows in a table without statistics.
+const DEFAULT_ROWS: f64 = 1000.0;
+/// The assumed selectivity of an equality predicate without statistics.
+const DEFAULT_EQUAL_SELECTIVITY: f64 = 0.1;
+/// The assumed selectivity of a range predicate without statistics.
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+/// The assumed selectivity of any other predicate.
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+/// An estimate of a plan node's output rows and execution cost.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    /// The estimated number of rows emitted by the node.
+    pub rows: f64,
+    /// The estimated total cost of executing the node, including its children.
+    pub cost: f64,
+}
+
+/// A cost model, which estimates the number of rows and execution cost of plan
+/// nodes. It uses table statistics collected by ANALYZE where available, and
+/// otherwise falls back to default estimates.
+///
+/// The model is intentionally simple: it assumes column values are uniformly
+/// distributed (within histogram buckets) and independent of each other.
+#[derive(Debug, Default)]
+pub struct CostModel {
+    /// Table statistics, keyed by table name.
+    statistics: HashMap<String, Statistics>,
+}
+
+impl CostModel {
+    /// Creates a cost model for a plan node, loading statistics for the tables
+    /// it reads from the catalog.
+    pub fn load(catalog: &impl Catalog, node: &Node) -> Result<Self> {
+        let mut tables = BTreeSet::new();
+        let mut nodes = vec![node];
+        while let Some(node) = nodes.pop() {
+            if let Some(table) = Self::table(node) {
+                tables.insert(table.name.as_str());
+            }
+            nodes.extend(node.children());
+        }
+        let mut statistics = HashMap::new();
+        for table in tables {
+            if let Some(stats) = catalog.get_statistics(table)? {
+                statistics.insert(table.to_string(), stats);
+            }
+        }
+        Ok(Self { statistics })
+    }
+
+    /// Estimates the output rows and cost of a plan node.
+    pub fn estimate(&self, node: &Node) -> Estimate {
+        match node {
+            Node::Aggregate { source, group_by, .. } => {
+                let source = self.estimate(source);
+                let rows = match group_by.is_empty() {
+                    true => 1.0,
+                    false => self.distinct_rows(node, 0..group_by.len(), source.rows),
+                };
+                Estimate { rows, cost: source.cost + source.rows * CPU_ROW_COST }
+            }
+
+            Node::Distinct { source: inner, .. } => {
+                let source = self.estimate(inner);
+                let rows = self.distinct_rows(inner, 0..inner.columns(), source.rows);
+                Estimate { rows, cost: source.cost + source.rows * CPU_ROW_COST }
+            }
+
+            Node::Filter { source: inner, predicate } => {
+                let source = self.estimate(inner);
+                Estimate {
+                    rows: source.rows * self.selectivity(inner, predicate),
+                    cost: source.cost + source.rows * CPU_ROW_COST,
+                }
+            }
+
+            // Hash joins build a hash table of the right source, and probe it
+            // with each row of the left source.
+            Node::HashJoin { left: l, left_column, right: r, right_column, outer, full } => {
+                let (left, right) = (self.estimate(l), self.estimate(r));
+                let selectivity = self.join_selectivity(l, *left_column, r, *right_column);
+                let mut rows = left.rows * right.rows * selectivity;
+                if *outer {
+                    rows = rows.max(left.rows);
+                }
+                if *full {
+                    rows = rows.max(right.rows);
+                }
+                let cost =
+                    left.cost + right.cost + right.rows * HASH_ROW_COST + left.rows * CPU_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            // Hash semi joins build a hash set of the right source, and probe
+            // it with each row of the left source.
+            Node::HashSemiJoin { left: l, left_column, right: r, right_column, anti, .. } => {
+                let (left, right) = (self.estimate(l), self.estimate(r));
+                let selectivity = self.join_selectivity(l, *left_column, r, *right_column);
+                let rows = left.rows * Self::semi_join_fraction(right.rows * selectivity, *anti);
+                let cost =
+                    left.cost + right.cost + right.rows * HASH_ROW_COST + left.rows * CPU_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            Node::IndexLookup { table, index, values, .. } => {
+                let index = table.secondary_indexes().into_iter().find(|i| &i.name == index);
+                let columns = index.map(|i| i.columns).unwrap_or_default();
+                let rows = values
+                    .iter()
+                    .map(|v| self.lookup_rows(node, table, &columns, v))
+                    .sum::<f64>()
+                    .min(self.table_rows(table));
+                let cost = values.len() as f64 * RANDOM_ROW_COST + rows * RANDOM_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            Node::IndexRangeScan { table, index, range, .. } => {
+                let index = table.secondary_indexes().into_iter().find(|i| &i.name == index);
+                let rows = match index {
+                    Some(index) => self.range_rows(node, table, index.columns[0], range),
+                    None => self.table_rows(table),
+                };
+                Estimate { rows, cost: RANDOM_ROW_COST + rows * RANDOM_ROW_COST }
+            }
+
+            Node::KeyLookup { table, keys, .. } => {
+                let rows = keys
+                    .iter()
+                    .map(|key| match key.len() == table.primary_key.len() {
+                        true => 1.0,
+                        false => self.lookup_rows(node, table, &table.primary_key, key),
+                    })
+                    .sum::<f64>()
+                    .min(self.table_rows(table));
+                let cost = keys.len() as f64 * RANDOM_ROW_COST + rows * SEQ_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            Node::KeyRangeScan { table, range, .. } => {
+                let rows = self.range_rows(node, table, table.primary_key[0], range);
+                Estimate { rows, cost: RANDOM_ROW_COST + rows * SEQ_ROW_COST }
+            }
+
+            Node::Limit { source, limit } => {
+                let source = self.estimate(source);
+                Estimate { rows: source.rows.min(*limit as f64), cost: source.cost }
+            }
+
+            // Merge joins make a single pass over both sorted sources.
+            Node::MergeJoin { left: l, left_column, right: r, right_column, outer } => {
+                let (left, right) = (self.estimate(l), self.estimate(r));
+                let selectivity = self.join_selectivity(l, *left_column, r, *right_column);
+                let mut rows = left.rows * right.rows * selectivity;
+                if *outer {
+                    rows = rows.max(left.rows);
+                }
+                let cost = left.cost + right.cost + (left.rows + right.rows) * CPU_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            // Nested loop joins evaluate the predicate for every combination of
+            // left and right rows.
+            Node::NestedLoopJoin { left, right, predicate, outer, full } => {
+                let (left, right) = (self.estimate(left), self.estimate(right));
+                let selectivity = predicate.as_ref().map_or(1.0, |p| self.selectivity(node, p));
+                let mut rows = left.rows * right.rows * selectivity;
+                if *outer {
+                    rows = rows.max(left.rows);
+                }
+                if *full {
+                    rows = rows.max(right.rows);
+                }
+                let cost = left.cost + right.cost + left.rows * right.rows * CPU_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            // Nested loop semi joins evaluate the predicate for every
+            // combination of left and right rows (pessimistically, since they
+            // stop at the first match).
+            Node::NestedLoopSemiJoin { left, right, predicate, anti, .. } => {
+                let (left, right) = (self.estimate(left), self.estimate(right));
+                let selectivity = predicate.as_ref().map_or(1.0, |p| self.selectivity(node, p));
+                let rows = left.rows * Self::semi_join_fraction(right.rows * selectivity, *anti);
+                let cost = left.cost + right.cost + left.rows * right.rows * CPU_ROW_COST;
+                Estimate { rows, cost }
+            }
+
+            Node::Nothing { .. } => Estimate { rows: 0.0, cost: 0.0 },
+
+            Node::Offset { source, offset } => {
+                let source = self.estimate(source);
+                Estimate { rows: (source.rows - *offset as f64).max(0.0), cost: source.cost }
+            }
+
+            Node::Order { source, .. } => {
+                let source = self.estimate(source);
+                let comparisons = source.rows * source.rows.max(2.0).log2();
+                Estimate { rows: source.rows, cost: source.cost + comparisons * CPU_ROW_COST }
+            }
+
+            Node::Projection { source, .. } => {
+                let source = self.estimate(source);
+                Estimate { rows: source.rows, cost: source.cost + source.rows * CPU_ROW_COST }
+            }
+
+            Node::Remap { source, .. } => self.estimate(source),
+
+            Node::Scan { table, filter, .. } => {
+                let rows = self.table_rows(table);
+                match filter {
+                    Some(filter) => Estimate {
+                        rows: rows * self.selectivity(node, filter),
+                        cost: rows * (SEQ_ROW_COST + CPU_ROW_COST),
+                    },
+                    None => Estimate { rows, cost: rows * SEQ_ROW_COST },
+                }
+            }
+
+            // Top-N sorts compare each row against a heap of limit + offset rows.
+            Node::TopN { source, limit, offset, .. } => {
+                let source = self.estimate(source);
+                let rows = (source.rows - *offset as f64).clamp(0.0, *limit as f64);
+                let comparisons = source.rows * ((limit + offset) as f64).max(2.0).log2();
+                Estimate { rows, cost: source.cost + comparisons * CPU_ROW_COST }
+            }
+
+            Node::Values { rows } => {
+                let rows = rows.len() as f64;
+                Estimate { rows, cost: rows * CPU_ROW_COST }
+            }
+        }
+    }
+
+    /// Returns the table read by a source node, if any.
+    fn table(node: &Node) -> Option<&Table> {
+        match node {
+            Node::IndexLookup { table, .. }
+            | Node::IndexRangeScan { table, .. }
+            | Node::KeyLookup { table, .. }
+            | Node::KeyRangeScan { table, .. }
+            | Node::Scan { table, .. } => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Returns the (estimated) number of rows in a table.
+    fn table_rows(&self, table: &Table) -> f64 {
+        self.statistics.get(&table.name).map_or(DEFAULT_ROWS, |s| s.rows as f64)
+    }
+
+    /// Looks up statistics for a node's output column, by tracing it back to a
+    /// table column. Returns the column statistics and the table's row count.
+    fn column(&self, node: &Node, index: usize) -> Option<(&ColumnStatistics, u64)> {
+        match node {
+            Node::IndexLookup { table, .. }
+            | Node::IndexRangeScan { table, .. }
+            | Node::KeyLookup { table, .. }
+            | Node::KeyRangeScan { table, .. }
+            | Node::Scan { table, .. } => {
+                let stats = self.statistics.get(&table.name)?;
+                Some((stats.columns.get(index)?, stats.rows))
+            }
+
+            Node::Aggregate { source, group_by, .. } => match group_by.get(index)? {
+                Expression::Column(index) => self.column(source, *index),
+                _ => None,
+            },
+            Node::Projection { source, expressions, .. } => match expressions.get(index)? {
+                Expression::Column(index) => self.column(source, *index),
+                _ => None,
+            },
+            Node::Remap { source, targets } => {
+                self.column(source, targets.iter().position(|t| *t == Some(index))?)
+            }
+
+            Node::HashJoin { left, right, .. }
+            | Node::HashSemiJoin { left, right, .. }
+            | Node::MergeJoin { left, right, .. }
+            | Node::NestedLoopJoin { left, right, .. }
+            | Node::NestedLoopSemiJoin { left, right, .. } => {
+                match index.checked_sub(left.columns()) {
+                    Some(index) => self.column(right, index),
+                    None => self.column(left, index),
+                }
+            }
+
+            Node::Distinct { source, .. }
+            | Node::Filter { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Order { source, .. }
+            | Node::TopN { source, .. } => self.column(source, index),
+
+            Node::Nothing { .. } | Node::Values { .. } => None,
+        }
+    }
+
+    /// Estimates the fraction of a node's rows that satisfy a predicate, whose
+    /// column references refer to the node's columns.
+    pub fn selectivity(&self, node: &Node, predicate: &Expression) -> f64 {
+        use Expression::*;
+
+        if let Some((column, range)) = predicate.as_column_range() {
+            return match self.column(node, column) {
+                Some((stats, rows)) => stats.range_selectivity(rows, &range),
+                None => DEFAULT_RANGE_SELECTIVITY,
+            };
+        }
+
+        match predicate {
+            Constant(Value::Boolean(true)) => 1.0,
+            Constant(_) => 0.0,
+
+            And(lhs, rhs) => self.selectivity(node, lhs) * self.selectivity(node, rhs),
+            Or(lhs, rhs) => {
+                let (lhs, rhs) = (self.selectivity(node, lhs), self.selectivity(node, rhs));
+                lhs + rhs - lhs * rhs
+            }
+            Not(expr) => 1.0 - self.selectivity(node, expr),
+
+            Equal(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (Column(lhs), Column(rhs)) => self.join_selectivity(node, *lhs, node, *rhs),
+                (Column(_), Constant(value)) | (Constant(value), Column(_))
+                    if value.is_undefined() =>
+                {
+                    0.0
+                }
+                (Column(column), Constant(value)) | (Constant(value), Column(column)) => {
+                    match self.column(node, *column) {
+                        Some((stats, rows)) => stats.equal_selectivity(rows, Some(value)),
+                        None => DEFAULT_EQUAL_SELECTIVITY,
+                    }
+                }
+                _ => DEFAULT_EQUAL_SELECTIVITY,
+            },
+
+            Is(expr, Value::Null) => match expr.as_ref() {
+                Column(column) => match self.column(node, *column) {
+                    Some((stats, rows)) => stats.null_selectivity(rows),
+                    None => DEFAULT_EQUAL_SELECTIVITY,
+                },
+                _ => DEFAULT_EQUAL_SELECTIVITY,
+            },
+
+            In(expr, list) => list
+                .iter()
+                .map(|item| self.selectivity(node, &Equal(expr.clone(), item.clone().into())))
+                .sum::<f64>()
+                .min(1.0),
+
+            _ => DEFAULT_SELECTIVITY,
+        }
+    }
+
+    /// Estimates the selectivity of an equijoin between two columns, i.e. the
+    /// fraction of the cross product of the left and right rows that match.
+    /// Assumes each value in the column with fewer distinct values has a match
+    /// in the other column.
+    fn join_selectivity(
+        &self,
+        left: &Node,
+        left_column: usize,
+        right: &Node,
+        right_column: usize,
+    ) -> f64 {
+        let distinct = |node, column| self.column(node, column).map(|(s, _)| s.distinct as f64);
+        match (distinct(left, left_column), distinct(right, right_column)) {
+            (Some(left), Some(right)) => 1.0 / left.max(right).max(1.0),
+            (Some(distinct), None) | (None, Some(distinct)) => 1.0 / distinct.max(1.0),
+            // Assume a join on a key column of a default-sized table.
+            (None, None) => 1.0 / DEFAULT_ROWS,
+        }
+    }
+
+    /// Estimates the fraction of left rows emitted by a semi join, given the
+    /// estimated number of right matches per left row. For anti joins, this is
+    /// the fraction of left rows without a match.
+    fn semi_join_fraction(matches: f64, anti: bool) -> f64 {
+        let matched = matches.min(1.0);
+        if anti { 1.0 - matched } else { matched }
+    }
+
+    /// Estimates the number of distinct combinations of the given node
+    /// columns, limited by the given number of rows. NULLs count as a value.
+    fn distinct_rows(&self, node: &Node, columns: impl Iterator<Item = usize>, rows: f64) -> f64 {
+        let mut distinct = 1.0;
+        for column in columns {
+            let Some((stats, _)) = self.column(node, column) else {
+                return rows;
+            };
+            distinct *= (stats.distinct + (stats.nulls > 0) as u64) as f64;
+        }
+        distinct.min(rows)
+    }
+
+    /// Estimates the number of rows returned by a lookup of a single value
+    /// tuple (possibly a prefix) for the given table columns, in a source node
+    /// for the table.
+    fn lookup_rows(&self, node: &Node, table: &Table, columns: &[usize], values: &[Value]) -> f64 {
+        let selectivity: f64 = columns
+            .iter()
+            .zip(values)
+            .map(|(&column, value)| match self.column(node, column) {
+                Some((stats, rows)) => stats.equal_selectivity(rows, Some(value)),
+                None => DEFAULT_EQUAL_SELECTIVITY,
+            })
+            .product();
+        self.table_rows(table) * selectivity
+    }
+
+    /// Estimates the number of rows in a key or index range whose first column
+    /// is the given table column, in a source node for the table. Only the
+    /// first value of the range bounds is considered.
+    fn range_rows(
+        &self,
+        node: &Node,
+        table: &Table,
+        column: usize,
+        range: &(Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> f64 {
+        if let (Bound::Unbounded, Bound::Unbounded) = range {
+            return self.table_rows(table);
+        }
+        let range = (
+            range.0.as_ref().map(|values| values[0].clone()),
+            range.1.as_ref().map(|values| values[0].clone()),
+        );
+        let selectivity = match self.column(node, column) {
+            Some((stats, rows)) => stats.range_selectivity(rows, &range),
+            None => DEFAULT_RANGE_SELECTIVITY,
+        };
+        self.table_rows(table) * selectivity
+    }
+}
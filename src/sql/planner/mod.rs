@@ -1,11 +1,15 @@
 //! The planner builds and optimizes an execution plan based on a SQL
 //! statement's Abstract Syntax Tree (AST) generated by the parser.
 
+mod cost;
 mod optimizer;
 mod plan;
 mod planner;
 
+pub use cost::{CostModel, Estimate};
 #[cfg(test)]
 pub use optimizer::OPTIMIZERS;
-pub use plan::{Aggregate, Direction, Node, Plan};
+pub use plan::{
+    Aggregate, ConflictAction, ConflictTarget, Direction, Node, OnConflict, Plan, Returning,
+};
 pub use planner::{Planner, Scope};
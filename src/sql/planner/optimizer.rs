@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::ops::{Bound, Range};
 use std::sync::LazyLock;
 
-use super::Node;
+use itertools::Itertools as _;
+
+use super::plan::invert_remap;
+use super::{CostModel, Direction, Node};
 use crate::error::Result;
-use crate::sql::types::{Expression, Label, Value};
+use crate::sql::types::{self, DataType, Expression, Label, Table, Value};
 
 /// The set of optimizers, and the order in which they are applied.
 pub static OPTIMIZERS: LazyLock<Vec<Box<dyn Optimizer>>> = LazyLock::new(|| {
@@ -12,16 +16,20 @@ pub static OPTIMIZERS: LazyLock<Vec<Box<dyn Optimizer>>> = LazyLock::new(|| {
         Box::new(ConstantFolding),
         Box::new(FilterPushdown),
         Box::new(IndexLookup),
+        Box::new(JoinOrder),
         Box::new(HashJoin),
         Box::new(ShortCircuit),
+        Box::new(OrderElision),
+        Box::new(TopN),
     ]
 });
 
 /// A node optimizer, which recursively transforms a plan node to make plan
 /// execution more efficient where possible.
 pub trait Optimizer: Debug + Send + Sync {
-    /// Optimizes a node, returning the optimized node.
-    fn optimize(&self, node: Node) -> Result<Node>;
+    /// Optimizes a node, returning the optimized node. Cost-based optimizers
+    /// use the cost model to choose between alternative plans.
+    fn optimize(&self, node: Node, cost_model: &CostModel) -> Result<Node>;
 }
 
 /// Folds constant expressions by pre-evaluating them once now, instead of
@@ -30,7 +38,7 @@ pub trait Optimizer: Debug + Send + Sync {
 pub struct ConstantFolding;
 
 impl Optimizer for ConstantFolding {
-    fn optimize(&self, node: Node) -> Result<Node> {
+    fn optimize(&self, node: Node, _: &CostModel) -> Result<Node> {
         // Recursively transform expressions in the node tree. Post-order to
         // partially fold child expressions as far as possible, and avoid
         // quadratic costs.
@@ -51,7 +59,9 @@ impl ConstantFolding {
         // expression as 1 - 2 + a to evaluate the 1 - 2 branch).
         //
         // TODO: consider doing something better.
-        if !expr.contains(&|expr| matches!(expr, Column(_))) {
+        //
+        // Sequence calls aren't constant, and are evaluated by the executor.
+        if !expr.contains(&|expr| matches!(expr, Column(_))) && !expr.has_sequence() {
             return expr.evaluate(None).map(Constant);
         }
 
@@ -80,6 +90,72 @@ impl ConstantFolding {
                 (lhs, rhs) => Or(lhs.into(), rhs.into()),
             },
 
+            // CASE branches with a constant FALSE or NULL condition are never
+            // taken and can be removed. A constant TRUE condition is always
+            // taken, so it replaces the ELSE and ends the CASE. If no branches
+            // remain, the CASE is just the ELSE.
+            Case(when, mut r#else) => {
+                let mut branches = Vec::with_capacity(when.len());
+                for (condition, result) in when {
+                    match condition {
+                        Constant(Boolean(false) | Null) => {}
+                        Constant(Boolean(true)) => {
+                            *r#else = result;
+                            break;
+                        }
+                        condition => branches.push((condition, result)),
+                    }
+                }
+                match branches.is_empty() {
+                    true => *r#else,
+                    false => Case(branches, r#else),
+                }
+            }
+
+            // Constant NULLs in a COALESCE are skipped, and a constant non-NULL
+            // value is always returned so any later arguments can be removed.
+            // If only one argument remains, the COALESCE is redundant.
+            Coalesce(exprs) => {
+                let mut args = Vec::with_capacity(exprs.len());
+                for expr in exprs {
+                    match expr {
+                        Constant(Null) => {}
+                        expr @ Constant(_) => {
+                            args.push(expr);
+                            break;
+                        }
+                        expr => args.push(expr),
+                    }
+                }
+                match args.len() {
+                    0 => Constant(Null),
+                    1 => args.remove(0),
+                    _ => Coalesce(args),
+                }
+            }
+
+            // Constant regular expression patterns are compiled once now,
+            // instead of for every row during execution.
+            RegexMatch(lhs, rhs, case_insensitive) => match *rhs {
+                Constant(String(pattern)) => {
+                    let pattern = types::Pattern::regex(&pattern, case_insensitive)?;
+                    RegexMatch(lhs, Pattern(pattern).into(), case_insensitive)
+                }
+                rhs => RegexMatch(lhs, rhs.into(), case_insensitive),
+            },
+            SimilarTo(lhs, rhs) => match *rhs {
+                Constant(String(pattern)) => {
+                    SimilarTo(lhs, Pattern(types::Pattern::similar_to(&pattern)?).into())
+                }
+                rhs => SimilarTo(lhs, rhs.into()),
+            },
+            Function(function, mut args) => {
+                if let Some(pattern) = function.compile_pattern(&args)? {
+                    args[1] = Pattern(pattern);
+                }
+                Function(function, args)
+            }
+
             expr => expr,
         };
 
@@ -95,7 +171,7 @@ impl ConstantFolding {
 pub struct FilterPushdown;
 
 impl Optimizer for FilterPushdown {
-    fn optimize(&self, node: Node) -> Result<Node> {
+    fn optimize(&self, node: Node, _: &CostModel) -> Result<Node> {
         // Push down before descending, so we can keep recursively pushing down.
         node.transform(&|node| Ok(Self::push_filters(node)), &Ok)
     }
@@ -118,12 +194,33 @@ impl FilterPushdown {
                 let rhs = std::mem::replace(predicate, Expression::Constant(Value::Null));
                 *predicate = Expression::And(expr.into(), rhs.into());
             }
-            Node::NestedLoopJoin { predicate, .. } => {
+            Node::NestedLoopJoin { predicate, outer: false, .. } => {
                 *predicate = match predicate.take() {
                     Some(predicate) => Some(Expression::And(expr.into(), predicate.into())),
                     None => Some(expr),
                 };
             }
+            // Outer joins emit unmatched rows regardless of the join predicate,
+            // so filters can't be pushed into it. However, left outer joins
+            // can push filters that only reference the left source into it.
+            Node::NestedLoopJoin { left, outer: true, full: false, .. } => {
+                let size = left.columns();
+                let (push, mut keep): (Vec<_>, Vec<_>) =
+                    expr.into_cnf_vec().into_iter().partition(|expr| {
+                        !expr.contains(&|e| matches!(e, Expression::Column(i) if *i >= size))
+                    });
+                if let Some(expr) = Expression::and_vec(push)
+                    && let Some(expr) = Self::push_into(expr, left)
+                {
+                    keep.push(expr);
+                }
+                return Expression::and_vec(keep);
+            }
+            // Semi joins only emit left rows, so filters can be pushed into
+            // the left source.
+            Node::HashSemiJoin { left, .. } | Node::NestedLoopSemiJoin { left, .. } => {
+                return Self::push_into(expr, left);
+            }
             Node::Scan { filter, .. } => {
                 *filter = match filter.take() {
                     Some(filter) => Some(Expression::And(expr.into(), filter.into())),
@@ -153,10 +250,20 @@ impl FilterPushdown {
     }
 
     // Pushes down parts of a join predicate into the left or right sources
-    // where possible.
-    fn maybe_push_join(node: Node) -> Node {
-        let Node::NestedLoopJoin { mut left, mut right, predicate: Some(predicate), outer } = node
-        else {
+    // where possible. Anti joins emit left rows without a match, like outer
+    // joins. Null-aware anti joins consider NULL predicate results matches,
+    // so their predicates can't be split.
+    fn maybe_push_join(mut node: Node) -> Node {
+        let (left, right, join_predicate, outer) = match &mut node {
+            Node::NestedLoopJoin { left, right, predicate, outer, full: false } => {
+                (left, right, predicate, *outer)
+            }
+            Node::NestedLoopSemiJoin { left, right, predicate, anti, null_aware: false } => {
+                (left, right, predicate, *anti)
+            }
+            _ => return node,
+        };
+        let Some(predicate) = join_predicate.take() else {
             return node;
         };
         // Convert the predicate into conjunctive normal form (an AND vector).
@@ -223,9 +330,15 @@ impl FilterPushdown {
             }
         }
 
+        // Outer joins emit all left rows, even those that don't match the
+        // predicate, so we can't push predicates into the left source.
+        if outer {
+            predicate.append(&mut push_left);
+        }
+
         // Push predicates down into the sources if possible.
         if let Some(expr) = Expression::and_vec(push_left)
-            && let Some(expr) = Self::push_into(expr, &mut left)
+            && let Some(expr) = Self::push_into(expr, left)
         {
             // Pushdown failed, put it back into the join predicate.
             predicate.push(expr)
@@ -234,7 +347,7 @@ impl FilterPushdown {
         if let Some(mut expr) = Expression::and_vec(push_right) {
             // Right columns have indexes in the joined row; shift them left.
             expr = expr.shift_column(-(left.columns() as isize));
-            if let Some(mut expr) = Self::push_into(expr, &mut right) {
+            if let Some(mut expr) = Self::push_into(expr, right) {
                 // Pushdown failed, undo the column index shift.
                 expr = expr.shift_column(left.columns() as isize);
                 predicate.push(expr)
@@ -242,27 +355,43 @@ impl FilterPushdown {
         }
 
         // Leave any remaining predicates in the join node.
-        let predicate = Expression::and_vec(predicate);
-        Node::NestedLoopJoin { left, right, predicate, outer }
+        *join_predicate = Expression::and_vec(predicate);
+        node
     }
 }
 
-/// Uses a primary key or secondary index lookup where possible.
+/// Uses a primary key or secondary index lookup where possible, unless the
+/// cost model estimates that a full table scan is cheaper (e.g. when most
+/// rows match the lookup).
 #[derive(Debug)]
 pub struct IndexLookup;
 
 impl Optimizer for IndexLookup {
-    fn optimize(&self, node: Node) -> Result<Node> {
+    fn optimize(&self, node: Node, cost_model: &CostModel) -> Result<Node> {
         // Recursively transform expressions in the node tree. Post-order to
         // partially fold child expressions as far as possible, and avoid
         // quadratic costs.
-        node.transform(&|node| Ok(Self::index_lookup(node)), &Ok)
+        node.transform(&|node| Ok(Self::index_lookup(node, cost_model)), &Ok)
     }
 }
 
 impl IndexLookup {
+    /// Rewrites a filtered scan node into a key or index lookup, if possible
+    /// and estimated to be cheaper than the scan.
+    fn index_lookup(node: Node, cost_model: &CostModel) -> Node {
+        if !matches!(node, Node::Scan { filter: Some(_), .. }) {
+            return node;
+        }
+        let lookup = Self::lookup(node.clone());
+        if cost_model.estimate(&lookup).cost <= cost_model.estimate(&node).cost {
+            lookup
+        } else {
+            node
+        }
+    }
+
     /// Rewrites a filtered scan node into a key or index lookup if possible.
-    fn index_lookup(mut node: Node) -> Node {
+    fn lookup(mut node: Node) -> Node {
         // Only handle scan filters. Assume FilterPushdown has pushed filters
         // into scan nodes first.
         let Node::Scan { table, alias, filter: Some(filter) } = node else {
@@ -272,25 +401,96 @@ impl IndexLookup {
         // Convert the filter into conjunctive normal form (a list of ANDs).
         let mut cnf = filter.clone().into_cnf_vec();
 
-        // Find the first expression that's either a primary key or secondary
-        // index lookup. We could be more clever here, but this is fine.
-        let Some((i, column)) = cnf.iter().enumerate().find_map(|(i, expr)| {
-            expr.is_column_lookup()
-                .filter(|&c| c == table.primary_key || table.columns[c].index)
-                .map(|column| (i, column))
-        }) else {
-            // No index lookups found, return the original node.
-            return Node::Scan { table, alias, filter: Some(filter) };
-        };
+        // Find the expressions that are column lookups, keyed by column. If a
+        // column has several, use the first. Like in find_range(), the lookup
+        // values must have the column's datatype, since values of different
+        // datatypes (e.g. integers and floats) are keyed differently in
+        // storage. NULL and NaN values are for IS NULL and IS NAN lookups.
+        let mut lookups = HashMap::new();
+        for (i, expr) in cnf.iter().enumerate() {
+            let Some(column) = expr.is_column_lookup() else {
+                continue;
+            };
+            let datatype = Some(table.columns[column].datatype);
+            let values = expr.clone().into_column_values(column);
+            if values.iter().all(|v| v.is_undefined() || v.datatype() == datatype) {
+                lookups.entry(column).or_insert(i);
+            }
+        }
+
+        // Find the primary key or secondary index with the longest prefix of
+        // lookup columns, preferring the primary key on ties (max_by_key picks
+        // the last maximum, so iterate in reverse). We could be more clever
+        // here, e.g. by considering selectivity, but this is fine.
+        let indexes = table.secondary_indexes();
+        let lookup = std::iter::once((None, table.primary_key.as_slice()))
+            .chain(indexes.iter().map(|i| (Some(&i.name), i.columns.as_slice())))
+            .map(|(index, columns)| {
+                let len = columns.iter().take_while(|c| lookups.contains_key(c)).count();
+                (index.cloned(), columns[..len].to_vec())
+            })
+            .filter(|(_, columns)| !columns.is_empty())
+            .rev()
+            .max_by_key(|(_, columns)| columns.len());
 
-        // Extract the lookup values and expression from the cnf vector.
-        let values = cnf.remove(i).into_column_values(column);
+        if let Some((index, columns)) = lookup {
+            // Extract the lookup expressions from the cnf vector, and look up
+            // the cartesian product of their column values.
+            let positions: HashSet<usize> = columns.iter().map(|c| lookups[c]).collect();
+            let values = columns
+                .iter()
+                .map(|&c| cnf[lookups[&c]].clone().into_column_values(c))
+                .multi_cartesian_product()
+                .collect();
+            cnf = cnf
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !positions.contains(i))
+                .map(|(_, expr)| expr)
+                .collect();
 
-        // Build the primary key or secondary index lookup node.
-        if column == table.primary_key {
-            node = Node::KeyLookup { table, keys: values, alias };
+            // Build the primary key or secondary index lookup node.
+            node = match index {
+                None => Node::KeyLookup { table, keys: values, alias },
+                Some(index) => Node::IndexLookup { table, index, values, alias },
+            };
+        } else if let Some((index, column, range, positions)) = Self::find_range(&cnf, &table) {
+            // Otherwise, use a key or index range scan for range predicates.
+            cnf = cnf
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !positions.contains(i))
+                .map(|(_, expr)| expr)
+                .collect();
+
+            let (start, end) = range;
+            node = match index {
+                None => Node::KeyRangeScan {
+                    table,
+                    range: (start.map(|v| vec![v]), end.map(|v| vec![v])),
+                    alias,
+                },
+                Some(index) => {
+                    // Unlike primary keys, index values can be NULL or NaN,
+                    // which sort first and last respectively. Exclude them,
+                    // since they never match a range predicate.
+                    let start = match start {
+                        Bound::Unbounded => Bound::Excluded(vec![Value::Null]),
+                        bound => bound.map(|v| vec![v]),
+                    };
+                    let end = match end {
+                        Bound::Unbounded => match table.columns[column].datatype {
+                            DataType::Float => Bound::Excluded(vec![Value::Float(f64::NAN)]),
+                            _ => Bound::Unbounded,
+                        },
+                        bound => bound.map(|v| vec![v]),
+                    };
+                    Node::IndexRangeScan { table, index, range: (start, end), alias }
+                }
+            };
         } else {
-            node = Node::IndexLookup { table, column, values, alias };
+            // No index lookups found, return the original node.
+            return Node::Scan { table, alias, filter: Some(filter) };
         }
 
         // If there's any remaining CNF expressions, add a filter node for them.
@@ -300,50 +500,484 @@ impl IndexLookup {
 
         node
     }
+
+    /// Finds range predicates (e.g. >, <=, BETWEEN) on the first column of the
+    /// primary key or a secondary index, preferring the primary key. Returns
+    /// the index name (None for the primary key), the column, the
+    /// intersection of the column's ranges, and their CNF positions.
+    ///
+    /// The bounds must be constants of the column's datatype, since values of
+    /// different datatypes (e.g. integers and floats) don't sort together in
+    /// storage.
+    fn find_range(
+        cnf: &[Expression],
+        table: &Table,
+    ) -> Option<(Option<String>, usize, (Bound<Value>, Bound<Value>), Vec<usize>)> {
+        let mut ranges: HashMap<usize, Vec<(usize, (Bound<Value>, Bound<Value>))>> = HashMap::new();
+        for (i, expr) in cnf.iter().enumerate() {
+            let Some((column, range)) = expr.as_column_range() else {
+                continue;
+            };
+            let datatype = Some(table.columns[column].datatype);
+            let valid = |bound: &Bound<Value>| match bound {
+                Bound::Included(v) | Bound::Excluded(v) => v.datatype() == datatype,
+                Bound::Unbounded => true,
+            };
+            if valid(&range.0) && valid(&range.1) {
+                ranges.entry(column).or_default().push((i, range));
+            }
+        }
+
+        let (index, column) = std::iter::once((None, table.primary_key[0]))
+            .chain(table.secondary_indexes().into_iter().map(|i| (Some(i.name), i.columns[0])))
+            .find(|(_, column)| ranges.contains_key(column))?;
+        let (positions, ranges): (Vec<_>, Vec<_>) = ranges.remove(&column)?.into_iter().unzip();
+        let range = ranges.into_iter().reduce(Self::intersect_range)?;
+        Some((index, column, range, positions))
+    }
+
+    /// Intersects two ranges, i.e. takes the greater start bound and the
+    /// lesser end bound. Excluded bounds are tighter than included bounds for
+    /// the same value.
+    fn intersect_range(
+        a: (Bound<Value>, Bound<Value>),
+        b: (Bound<Value>, Bound<Value>),
+    ) -> (Bound<Value>, Bound<Value>) {
+        use Bound::*;
+
+        let start = match (a.0, b.0) {
+            (Unbounded, bound) | (bound, Unbounded) => bound,
+            (Included(a), Included(b)) => Included(a.max(b)),
+            (Excluded(a), Excluded(b)) => Excluded(a.max(b)),
+            (Included(a), Excluded(b)) | (Excluded(b), Included(a)) => {
+                if a > b {
+                    Included(a)
+                } else {
+                    Excluded(b)
+                }
+            }
+        };
+        let end = match (a.1, b.1) {
+            (Unbounded, bound) | (bound, Unbounded) => bound,
+            (Included(a), Included(b)) => Included(a.min(b)),
+            (Excluded(a), Excluded(b)) => Excluded(a.min(b)),
+            (Included(a), Excluded(b)) | (Excluded(b), Included(a)) => {
+                if a < b {
+                    Included(a)
+                } else {
+                    Excluded(b)
+                }
+            }
+        };
+        (start, end)
+    }
 }
 
-/// Uses a hash join instead of a nested loop join for single-column equijoins.
+/// The maximum number of join sources to use dynamic programming for when
+/// reordering joins. Beyond this, joins are reordered greedily.
+const JOIN_ORDER_DP_LIMIT: usize = 8;
+
+/// Reorders trees of inner joins to reduce the size of intermediate results,
+/// based on join predicate connectivity and estimated row counts. The join tree
+/// is flattened into its sources and join predicates, and a new join tree is
+/// built, using dynamic programming to find the best order for small numbers
+/// of sources and greedily joining the smallest results otherwise. Each
+/// predicate is evaluated at the lowest join where all of its columns are
+/// available. Joins that are connected by a predicate are preferred over cross
+/// joins, and the original column order is restored with a Remap node.
+///
+/// A join tree's cost is the total number of rows emitted by its joins. The
+/// join algorithm and hash join build side is chosen later by HashJoin.
+#[derive(Debug)]
+pub struct JoinOrder;
+
+impl Optimizer for JoinOrder {
+    fn optimize(&self, node: Node, cost_model: &CostModel) -> Result<Node> {
+        // Pre-order transform, to reorder the entire join tree at its root.
+        // The transform then descends into the reordered joins, but these are
+        // subtrees of the cheapest join tree and thus retain their order.
+        node.transform(&|node| Ok(Self::join_order(node, cost_model)), &Ok)
+    }
+}
+
+impl JoinOrder {
+    /// Reorders a tree of inner joins, if it has at least 3 sources.
+    fn join_order(node: Node, cost_model: &CostModel) -> Node {
+        if !matches!(node, Node::NestedLoopJoin { outer: false, .. }) {
+            return node;
+        }
+        let JoinTree { leaves, predicates, joins } = Self::flatten(&node);
+        let size = leaves.len();
+        if size < 3 || size > u64::BITS as usize {
+            return node;
+        }
+
+        // Determine which sources each predicate references, as a bitmask.
+        // Constant predicates are evaluated at the root join.
+        let all = u64::MAX >> (u64::BITS as usize - size);
+        let offsets = leaves
+            .iter()
+            .scan(0, |offset, leaf| {
+                *offset += leaf.columns();
+                Some(*offset - leaf.columns())
+            })
+            .collect_vec();
+        let masks = predicates
+            .iter()
+            .map(|predicate| {
+                let mut mask = 0;
+                predicate.walk(&mut |expr| {
+                    if let Expression::Column(index) = expr {
+                        mask |= 1 << (offsets.partition_point(|offset| offset <= index) - 1);
+                    }
+                    true
+                });
+                if mask == 0 { all } else { mask }
+            })
+            .collect_vec();
+
+        // Estimate the selectivity of each predicate using a cross join of the
+        // sources, whose columns are the flattened column indexes.
+        let cross = leaves
+            .iter()
+            .cloned()
+            .reduce(|left, right| Node::NestedLoopJoin {
+                left: left.into(),
+                right: right.into(),
+                predicate: None,
+                outer: false,
+                full: false,
+            })
+            .expect("no join sources");
+        let selectivities =
+            predicates.iter().map(|p| cost_model.selectivity(&cross, p)).collect_vec();
+        let leaf_rows = leaves.iter().map(|leaf| cost_model.estimate(leaf).rows).collect_vec();
+
+        // Estimates the number of rows emitted by joining a set of sources.
+        let rows = |set: u64| -> f64 {
+            let rows: f64 = (0..size).filter(|i| set & 1 << i != 0).map(|i| leaf_rows[i]).product();
+            let selectivity: f64 = (masks.iter().zip(&selectivities))
+                .filter(|(mask, _)| *mask & !set == 0)
+                .map(|(_, selectivity)| selectivity)
+                .product();
+            rows * selectivity
+        };
+        // Checks whether two sets of sources are connected by a predicate.
+        let connected = |a: u64, b: u64| {
+            masks.iter().any(|mask| mask & a != 0 && mask & b != 0 && mask & !(a | b) == 0)
+        };
+
+        let splits = match size {
+            ..=JOIN_ORDER_DP_LIMIT => Self::dynamic(all, &rows, connected),
+            _ => Self::greedy(size, &rows, connected),
+        };
+
+        // Retain the original join order unless the new one is cheaper.
+        let (mut cost, mut sets) = (0.0, vec![all]);
+        while let Some(set) = sets.pop() {
+            if let Some((left, right)) = splits.get(&set) {
+                cost += rows(set);
+                sets.extend([*left, *right]);
+            }
+        }
+        let original_cost = joins
+            .into_iter()
+            .map(|range| rows(range.map(|i| 1 << i).fold(0, |mask, bit| mask | bit)))
+            .sum::<f64>();
+        if cost >= original_cost {
+            return node;
+        }
+
+        // Build the new join tree, and remap the columns to the original order.
+        let mut leaves = leaves.into_iter().map(Some).collect_vec();
+        let mut predicates = masks.into_iter().zip(predicates).map(Some).collect_vec();
+        let (node, layout) = Self::build(all, &splits, &mut leaves, &offsets, &mut predicates);
+        assert!(predicates.iter().all(Option::is_none), "unapplied join predicate");
+
+        if layout.iter().enumerate().all(|(i, column)| i == *column) {
+            return node;
+        }
+        Node::Remap { source: node.into(), targets: layout.into_iter().map(Some).collect() }
+    }
+
+    /// Flattens a tree of inner joins.
+    fn flatten(node: &Node) -> JoinTree {
+        match node {
+            Node::NestedLoopJoin { left, right, predicate, outer: false, .. } => {
+                let JoinTree { mut leaves, mut predicates, mut joins } = Self::flatten(left);
+                let right = Self::flatten(right);
+                let offset: usize = leaves.iter().map(|leaf| leaf.columns()).sum();
+                let shift = leaves.len();
+                leaves.extend(right.leaves);
+                predicates.extend(
+                    right.predicates.into_iter().map(|expr| expr.shift_column(offset as isize)),
+                );
+                if let Some(predicate) = predicate {
+                    predicates.extend(predicate.clone().into_cnf_vec());
+                }
+                joins.extend(right.joins.into_iter().map(|r| r.start + shift..r.end + shift));
+                joins.push(0..leaves.len());
+                JoinTree { leaves, predicates, joins }
+            }
+
+            node => {
+                JoinTree { leaves: vec![node.clone()], predicates: Vec::new(), joins: Vec::new() }
+            }
+        }
+    }
+
+    /// Finds the cheapest join tree for all sources via dynamic programming,
+    /// by computing the cheapest join tree for every subset of sources from
+    /// the cheapest trees of their subsets. Returns the split of each set of
+    /// sources into the left and right join sources.
+    fn dynamic(
+        all: u64,
+        rows: &impl Fn(u64) -> f64,
+        connected: impl Fn(u64, u64) -> bool,
+    ) -> HashMap<u64, (u64, u64)> {
+        let mut costs = vec![0.0_f64; all as usize + 1];
+        let mut splits = HashMap::new();
+        for set in 1..=all {
+            if set.count_ones() == 1 {
+                continue;
+            }
+            // Consider all splits into two non-empty sets, where the left set
+            // contains the first source (to skip mirrored splits). Only use
+            // cross joins if there are no connected splits.
+            let first = set & set.wrapping_neg();
+            let mut candidates = Vec::new();
+            let mut left = (set - 1) & set;
+            while left > 0 {
+                if left & first != 0 {
+                    let right = set & !left;
+                    let cost = costs[left as usize] + costs[right as usize];
+                    candidates.push((left, right, cost, connected(left, right)));
+                }
+                left = (left - 1) & set;
+            }
+            let any_connected = candidates.iter().any(|(_, _, _, connected)| *connected);
+            let (left, right, cost, _) = candidates
+                .into_iter()
+                .filter(|(_, _, _, connected)| *connected || !any_connected)
+                .min_by(|a, b| a.2.total_cmp(&b.2))
+                .expect("no join candidates");
+            costs[set as usize] = cost + rows(set);
+            splits.insert(set, (left, right));
+        }
+        splits
+    }
+
+    /// Greedily builds a join tree for the given number of sources, by
+    /// repeatedly joining the pair of join trees with the smallest result,
+    /// preferring pairs that are connected by a predicate. Returns the split
+    /// of each set of sources into the left and right join sources.
+    fn greedy(
+        size: usize,
+        rows: &impl Fn(u64) -> f64,
+        connected: impl Fn(u64, u64) -> bool,
+    ) -> HashMap<u64, (u64, u64)> {
+        let mut trees = (0..size).map(|i| 1 << i).collect_vec();
+        let mut splits = HashMap::new();
+        while trees.len() > 1 {
+            let (i, j) = (0..trees.len())
+                .tuple_combinations()
+                .map(|(i, j)| (i, j, !connected(trees[i], trees[j]), rows(trees[i] | trees[j])))
+                .min_by(|a, b| a.2.cmp(&b.2).then(a.3.total_cmp(&b.3)))
+                .map(|(i, j, _, _)| (i, j))
+                .expect("no join candidates");
+            let (left, right) = (trees[i], trees.remove(j));
+            trees[i] = left | right;
+            splits.insert(left | right, (left, right));
+        }
+        splits
+    }
+
+    /// Builds a join tree for a set of sources, using the given splits.
+    /// Predicates are taken from the given vector and applied at the lowest
+    /// join where their sources are available. Returns the join node and the
+    /// flattened column indexes of its output columns.
+    fn build(
+        set: u64,
+        splits: &HashMap<u64, (u64, u64)>,
+        leaves: &mut [Option<Node>],
+        offsets: &[usize],
+        predicates: &mut [Option<(u64, Expression)>],
+    ) -> (Node, Vec<usize>) {
+        let Some((left, right)) = splits.get(&set) else {
+            let index = set.trailing_zeros() as usize;
+            let leaf = leaves[index].take().expect("join source already used");
+            let columns = (offsets[index]..offsets[index] + leaf.columns()).collect();
+            return (leaf, columns);
+        };
+        let (left, mut columns) = Self::build(*left, splits, leaves, offsets, predicates);
+        let (right, right_columns) = Self::build(*right, splits, leaves, offsets, predicates);
+        columns.extend(right_columns);
+
+        let predicate = Expression::and_vec(
+            predicates
+                .iter_mut()
+                .filter(|predicate| predicate.as_ref().is_some_and(|(mask, _)| mask & !set == 0))
+                .map(|predicate| predicate.take().expect("predicate").1)
+                .map(|expr| {
+                    expr.map_column(|index| {
+                        columns.iter().position(|c| *c == index).expect("missing column")
+                    })
+                })
+                .collect(),
+        );
+        (
+            Node::NestedLoopJoin {
+                left: left.into(),
+                right: right.into(),
+                predicate,
+                outer: false,
+                full: false,
+            },
+            columns,
+        )
+    }
+}
+
+/// A flattened tree of inner joins, see JoinOrder::flatten().
+struct JoinTree {
+    /// The join sources, in order.
+    leaves: Vec<Node>,
+    /// The join predicates in conjunctive normal form, using column indexes in
+    /// the concatenated source rows.
+    predicates: Vec<Expression>,
+    /// The joins in the tree, as ranges of source indexes.
+    joins: Vec<Range<usize>>,
+}
+
+/// Uses a hash join or merge join instead of a nested loop join for
+/// single-column equijoins, if the cost model estimates it to be cheaper. For
+/// inner joins, the hash table is built from the side with the fewest estimated
+/// rows. Merge joins are only possible when both sources are already sorted by
+/// their join column, e.g. primary key scans or index range scans. Similarly,
+/// nested loop semi joins use a hash semi join.
 #[derive(Debug)]
 pub struct HashJoin;
 
 impl Optimizer for HashJoin {
-    fn optimize(&self, node: Node) -> Result<Node> {
-        node.transform(&|node| Ok(Self::hash_join(node)), &Ok)
+    fn optimize(&self, node: Node, cost_model: &CostModel) -> Result<Node> {
+        // Post-order, such that the child joins have been chosen when costing.
+        node.transform(&Ok, &|node| Ok(Self::hash_join(node, cost_model)))
     }
 }
 
 impl HashJoin {
-    /// Rewrites a nested loop join into a hash join if possible.
-    pub fn hash_join(node: Node) -> Node {
-        let Node::NestedLoopJoin {
+    /// Rewrites a nested loop join into a hash join or merge join if possible
+    /// and cheaper.
+    pub fn hash_join(node: Node, cost_model: &CostModel) -> Node {
+        if let Node::NestedLoopSemiJoin {
             left,
             right,
-            predicate: Some(Expression::Equal(lhs, rhs)),
-            outer,
-        } = node
+            predicate: Some(predicate),
+            anti,
+            null_aware,
+        } = &node
+        {
+            let Some((left_column, right_column)) = Self::equijoin(left, predicate) else {
+                return node;
+            };
+            let hash_join = Node::HashSemiJoin {
+                left: left.clone(),
+                left_column,
+                right: right.clone(),
+                right_column,
+                anti: *anti,
+                null_aware: *null_aware,
+            };
+            let cost = |node: &Node| cost_model.estimate(node).cost;
+            return if cost(&hash_join) <= cost(&node) { hash_join } else { node };
+        }
+
+        let Node::NestedLoopJoin { left, right, predicate: Some(predicate), outer, full } = &node
         else {
             return node;
         };
+        let Some((left_column, right_column)) = Self::equijoin(left, predicate) else {
+            return node;
+        };
+        let left_size = left.columns();
 
-        match (*lhs, *rhs) {
-            // If this is a single-column equijoin, use a hash join.
-            (Expression::Column(mut left_column), Expression::Column(mut right_column)) => {
-                // The LHS column may be a column in the right table; swap them.
-                if right_column < left_column {
-                    (left_column, right_column) = (right_column, left_column);
-                }
-                // The NestedLoopJoin predicate uses column indexes in the
-                // joined row, while the HashJoin uses column indexes in each
-                // individual table. Adjust the RHS column reference.
-                right_column -= left.columns();
-                Node::HashJoin { left, left_column, right, right_column, outer }
-            }
-            // Otherwise, retain the nested loop join.
-            (lhs, rhs) => {
-                let predicate = Some(Expression::Equal(lhs.into(), rhs.into()));
-                Node::NestedLoopJoin { left, right, predicate, outer }
-            }
+        let mut candidates = Vec::new();
+
+        // If both sources are sorted by the join columns, we can merge them.
+        // Full joins are not supported by merge joins.
+        if !full
+            && left.ordering().first() == Some(&left_column)
+            && right.ordering().first() == Some(&right_column)
+        {
+            candidates.push(Node::MergeJoin {
+                left: left.clone(),
+                left_column,
+                right: right.clone(),
+                right_column,
+                outer: *outer,
+            });
         }
+
+        candidates.push(Node::HashJoin {
+            left: left.clone(),
+            left_column,
+            right: right.clone(),
+            right_column,
+            outer: *outer,
+            full: *full,
+        });
+
+        // For inner joins, we can also build the hash table from the left
+        // source by swapping the sources, and remap the swapped columns back
+        // to their original positions.
+        if !outer {
+            let right_size = right.columns();
+            let targets =
+                (0..right_size).map(|i| Some(left_size + i)).chain((0..left_size).map(Some));
+            candidates.push(Node::Remap {
+                source: Box::new(Node::HashJoin {
+                    left: right.clone(),
+                    left_column: right_column,
+                    right: left.clone(),
+                    right_column: left_column,
+                    outer: false,
+                    full: false,
+                }),
+                targets: targets.collect(),
+            });
+        }
+
+        // Pick the cheapest join, preferring earlier candidates on ties.
+        candidates.push(node);
+        let cost = |node: &Node| cost_model.estimate(node).cost;
+        candidates.into_iter().min_by(|a, b| cost(a).total_cmp(&cost(b))).expect("no candidates")
+    }
+
+    /// If a nested loop join predicate is a single-column equijoin, returns the
+    /// left and right join columns, as column indexes in each source.
+    fn equijoin(left: &Node, predicate: &Expression) -> Option<(usize, usize)> {
+        let Expression::Equal(lhs, rhs) = predicate else {
+            return None;
+        };
+        let (&Expression::Column(mut left_column), &Expression::Column(mut right_column)) =
+            (lhs.as_ref(), rhs.as_ref())
+        else {
+            return None;
+        };
+        // The LHS column may be a column in the right table; swap them.
+        if right_column < left_column {
+            (left_column, right_column) = (right_column, left_column);
+        }
+        // The columns must be from different sides of the join.
+        let left_size = left.columns();
+        if left_column >= left_size || right_column < left_size {
+            return None;
+        }
+        // The NestedLoopJoin predicate uses column indexes in the joined row,
+        // while the HashJoin and MergeJoin use column indexes in each
+        // individual table. Adjust the RHS column reference.
+        right_column -= left_size;
+        Some((left_column, right_column))
     }
 }
 
@@ -354,7 +988,7 @@ impl HashJoin {
 pub struct ShortCircuit;
 
 impl Optimizer for ShortCircuit {
-    fn optimize(&self, node: Node) -> Result<Node> {
+    fn optimize(&self, node: Node, _: &CostModel) -> Result<Node> {
         // Post-order transform, to pull Nothing nodes upwards in the tree.
         node.transform(&Ok, &|node| Ok(Self::short_circuit(node)))
     }
@@ -380,7 +1014,58 @@ impl ShortCircuit {
                 right,
                 predicate: Some(Constant(Boolean(true))),
                 outer,
-            } => Node::NestedLoopJoin { left, right, predicate: None, outer },
+                full,
+            } => Node::NestedLoopJoin { left, right, predicate: None, outer, full },
+            Node::NestedLoopSemiJoin {
+                left,
+                right,
+                predicate: Some(Constant(Boolean(true))),
+                anti,
+                null_aware,
+            } => Node::NestedLoopSemiJoin { left, right, predicate: None, anti, null_aware },
+
+            // Anti joins where nothing can match emit all left rows: remove
+            // them. With null-aware joins, a NULL predicate is a match.
+            Node::HashSemiJoin { left, right, anti: true, .. }
+            | Node::NestedLoopSemiJoin { left, right, anti: true, .. }
+                if matches!(*right, Node::Nothing { .. }) =>
+            {
+                *left
+            }
+            Node::NestedLoopSemiJoin {
+                left,
+                predicate: Some(Constant(Boolean(false))),
+                anti: true,
+                ..
+            }
+            | Node::NestedLoopSemiJoin {
+                left,
+                predicate: Some(Constant(Null)),
+                anti: true,
+                null_aware: false,
+                ..
+            } => *left,
+
+            // Merge nested Remap nodes.
+            Node::Remap { source, targets } if matches!(*source, Node::Remap { .. }) => {
+                let Node::Remap { source, targets: inner } = *source else { unreachable!() };
+                let targets = inner.into_iter().map(|t| t.and_then(|i| targets[i])).collect();
+                Node::Remap { source, targets }
+            }
+
+            // Remap nodes below projections are unnecessary: fold them into
+            // the projection's column references.
+            Node::Projection { source, expressions, aliases }
+                if matches!(*source, Node::Remap { .. }) =>
+            {
+                let Node::Remap { source, targets } = *source else { unreachable!() };
+                let sources = invert_remap(&targets);
+                let expressions = expressions
+                    .into_iter()
+                    .map(|expr| expr.map_column(|i| sources[i].expect("dropped column")))
+                    .collect();
+                Node::Projection { source, expressions, aliases }
+            }
 
             // Remove noop projections that simply pass through the source columns.
             Node::Projection { source, expressions, aliases }
@@ -404,19 +1089,37 @@ impl ShortCircuit {
             Node::IndexLookup { values, .. } if values.is_empty() => true,
             Node::KeyLookup { keys, .. } if keys.is_empty() => true,
             Node::Limit { limit: 0, .. } => true,
-            Node::NestedLoopJoin { predicate: Some(Constant(Boolean(false) | Null)), .. } => true,
+            Node::NestedLoopJoin {
+                predicate: Some(Constant(Boolean(false) | Null)),
+                outer: false,
+                ..
+            } => true,
+            Node::NestedLoopSemiJoin {
+                predicate: Some(Constant(Boolean(false) | Null)),
+                anti: false,
+                ..
+            } => true,
             Node::Scan { filter: Some(Constant(Boolean(false) | Null)), .. } => true,
             Node::Values { rows } if rows.is_empty() => true,
 
-            // Nodes that pull from a Nothing node can't produce anything.
+            // Nodes that pull from a Nothing node can't produce anything. Outer
+            // joins emit all left rows (and full joins all right rows) even if
+            // the other source is empty, and so do anti joins (handled above).
             //
             // NB: does not short-circuit aggregation, since an aggregation over 0
             // rows should produce a result.
-            Node::Filter { source, .. }
-            | Node::HashJoin { left: source, .. }
-            | Node::HashJoin { right: source, .. }
-            | Node::NestedLoopJoin { left: source, .. }
-            | Node::NestedLoopJoin { right: source, .. }
+            Node::Distinct { source, .. }
+            | Node::Filter { source, .. }
+            | Node::HashJoin { left: source, full: false, .. }
+            | Node::HashJoin { right: source, outer: false, .. }
+            | Node::HashSemiJoin { left: source, .. }
+            | Node::HashSemiJoin { right: source, anti: false, .. }
+            | Node::MergeJoin { left: source, .. }
+            | Node::MergeJoin { right: source, outer: false, .. }
+            | Node::NestedLoopJoin { left: source, full: false, .. }
+            | Node::NestedLoopJoin { right: source, outer: false, .. }
+            | Node::NestedLoopSemiJoin { left: source, .. }
+            | Node::NestedLoopSemiJoin { right: source, anti: false, .. }
             | Node::Offset { source, .. }
             | Node::Order { source, .. }
             | Node::Projection { source, .. }
@@ -436,3 +1139,60 @@ impl ShortCircuit {
         node
     }
 }
+
+/// Removes Order nodes whose source already emits rows in the requested order,
+/// for example a primary key or index range scan ordered by the key column.
+#[derive(Debug)]
+pub struct OrderElision;
+
+impl Optimizer for OrderElision {
+    fn optimize(&self, node: Node, _: &CostModel) -> Result<Node> {
+        node.transform(&Ok, &|node| Ok(Self::order_elision(node)))
+    }
+}
+
+impl OrderElision {
+    /// Replaces an Order node with its source if the source is already sorted
+    /// by the (ascending) sort columns.
+    fn order_elision(node: Node) -> Node {
+        let Node::Order { source, key } = node else {
+            return node;
+        };
+        let ordering = source.ordering();
+        let sorted = key.len() <= ordering.len()
+            && key.iter().zip(&ordering).all(|((expr, direction), column)| {
+                *expr == Expression::Column(*column) && *direction == Direction::Ascending
+            });
+        if sorted { *source } else { Node::Order { source, key } }
+    }
+}
+
+/// Fuses an Order node followed by a Limit node, possibly with an Offset node
+/// in between, into a TopN node. This only buffers the limit + offset lowest
+/// rows rather than sorting the entire row set.
+#[derive(Debug)]
+pub struct TopN;
+
+impl Optimizer for TopN {
+    fn optimize(&self, node: Node, _: &CostModel) -> Result<Node> {
+        node.transform(&Ok, &|node| Ok(Self::top_n(node)))
+    }
+}
+
+impl TopN {
+    /// Replaces a Limit node over an Order node (or Offset and Order nodes)
+    /// with a TopN node.
+    fn top_n(node: Node) -> Node {
+        let Node::Limit { source, limit } = node else {
+            return node;
+        };
+        match *source {
+            Node::Order { source, key } => Node::TopN { source, key, limit, offset: 0 },
+            Node::Offset { source, offset } if matches!(*source, Node::Order { .. }) => {
+                let Node::Order { source, key } = *source else { unreachable!() };
+                Node::TopN { source, key, limit, offset }
+            }
+            source => Node::Limit { source: Box::new(source), limit },
+        }
+    }
+}
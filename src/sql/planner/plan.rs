@@ -1,16 +1,21 @@
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::ops::Bound;
 
 use itertools::Itertools as _;
 use serde::{Deserialize, Serialize};
 
+use super::cost::{CostModel, Estimate};
 use super::optimizer::OPTIMIZERS;
 use super::planner::Planner;
+use crate::errinput;
 use crate::error::Result;
 use crate::sql::engine::{Catalog, Transaction};
-use crate::sql::execution::{ExecutionResult, Executor};
+use crate::sql::execution::{ExecutionResult, Executor, MemoryBudget, NodeStats, SequenceValues};
 use crate::sql::parser::ast;
-use crate::sql::types::{Expression, Label, Table, Value};
+use crate::sql::types::{
+    Alteration, DataType, Expression, Index, Label, Sequence, Table, Value, format_tuple,
+};
 
 /// A statement execution plan.
 ///
@@ -46,26 +51,71 @@ pub enum Plan {
     /// if the table already exists or the schema is invalid.
     CreateTable { schema: Table },
 
+    /// An ALTER TABLE plan. Changes the given table's schema. Errors if the
+    /// table does not exist or the change is invalid.
+    AlterTable { name: String, alteration: Alteration },
+
     /// A DROP TABLE plan. Drops the given table. Errors if the table does not
     /// exist, unless if_exists is true.
     DropTable { name: String, if_exists: bool },
 
+    /// A CREATE INDEX plan. Creates a named secondary index on the given table
+    /// columns, backfilling it from existing rows. Errors if the index name is
+    /// taken, the columns are already indexed, or unique values are violated.
+    CreateIndex { name: String, table: String, columns: Vec<String>, unique: bool },
+
+    /// A DROP INDEX plan. Drops the given index. Errors if the index does not
+    /// exist, unless if_exists is true.
+    DropIndex { name: String, if_exists: bool },
+
+    /// A CREATE SEQUENCE plan. Creates a new sequence. Errors if it already
+    /// exists or is invalid.
+    CreateSequence { sequence: Sequence },
+
+    /// A DROP SEQUENCE plan. Drops the given sequence. Errors if it does not
+    /// exist, unless if_exists is true, or if it's owned by a table column.
+    DropSequence { name: String, if_exists: bool },
+
+    /// An ANALYZE plan. Collects and stores statistics for the given tables,
+    /// which the optimizer uses to estimate plan costs.
+    Analyze { tables: Vec<String> },
+
     /// A DELETE plan. Deletes rows in table that match the rows from source.
-    /// primary_key specifies the primary key column index in the source rows.
-    Delete { table: String, primary_key: usize, source: Node },
+    /// primary_key specifies the primary key column indexes in the source rows.
+    /// The source rows may have additional columns after the table columns
+    /// (e.g. from joins), and may contain duplicate rows. If returning is
+    /// given, the deleted rows are returned.
+    Delete { table: Table, primary_key: Vec<usize>, source: Node, returning: Option<Returning> },
 
     /// An INSERT plan. Inserts rows from source (typically a Values node) into
     /// table. If column_map is given, it maps table → source column indexes and
     /// must have one entry for every column in source. Table columns not
     /// present in source will get the column's default value if set, or error.
-    Insert { table: Table, column_map: Option<HashMap<usize, usize>>, source: Node },
+    /// If on_conflict is given, it handles unique constraint conflicts. If
+    /// returning is given, the inserted (or updated) rows are returned.
+    Insert {
+        table: Table,
+        column_map: Option<HashMap<usize, usize>>,
+        source: Node,
+        on_conflict: Option<OnConflict>,
+        returning: Option<Returning>,
+    },
 
     /// An UPDATE plan. Updates rows in table that match the rows from source,
-    /// where primary_key specifies the primary key column index in the source
-    /// rows. The given column/expression pairs specify the row updates to make,
-    /// evaluated using the existing source row, which must be a complete row
-    /// from the update table.
-    Update { table: Table, primary_key: usize, source: Node, expressions: Vec<(usize, Expression)> },
+    /// where primary_key specifies the primary key column indexes in the
+    /// source rows. The given column/expression pairs specify the row updates
+    /// to make, evaluated using the existing source row, which must begin with
+    /// a complete row from the update table and may have additional columns
+    /// (e.g. from joins). If a table row occurs multiple times in the source,
+    /// only one of the updates is applied. If returning is given, the updated
+    /// rows are returned.
+    Update {
+        table: Table,
+        primary_key: Vec<usize>,
+        source: Node,
+        expressions: Vec<(usize, Expression)>,
+        returning: Option<Returning>,
+    },
 
     /// A SELECT plan. Recursively executes the query plan tree and returns the
     /// resulting rows.
@@ -78,29 +128,230 @@ impl Plan {
         Planner::new(catalog).build(statement)
     }
 
-    /// Executes the plan, consuming it.
-    pub fn execute(self, txn: &impl Transaction) -> Result<ExecutionResult> {
-        Executor::new(txn).execute(self)
+    /// Executes the plan, consuming it. Operators that buffer rows spill
+    /// them to disk if they exceed the memory budget.
+    /// Sequence function calls use the session's sequence values.
+    pub fn execute(
+        self,
+        txn: &impl Transaction,
+        budget: MemoryBudget,
+        sequences: SequenceValues,
+    ) -> Result<ExecutionResult> {
+        Executor::new(txn, budget, sequences).execute(self)
     }
 
     /// Optimizes the plan, consuming it. See OPTIMIZERS for the list of
-    /// optimizers.
-    pub fn optimize(self) -> Result<Self> {
-        let optimize = |node| OPTIMIZERS.iter().try_fold(node, |node, opt| opt.optimize(node));
+    /// optimizers. Cost-based optimizers use table statistics from the catalog.
+    pub fn optimize(self, catalog: &impl Catalog) -> Result<Self> {
+        let optimize = |node| {
+            let cost_model = CostModel::load(catalog, &node)?;
+            OPTIMIZERS.iter().try_fold(node, |node, opt| opt.optimize(node, &cost_model))
+        };
         Ok(match self {
-            Self::CreateTable { .. } | Self::DropTable { .. } => self,
-            Self::Delete { table, primary_key, source } => {
-                Self::Delete { table, primary_key, source: optimize(source)? }
+            Self::AlterTable { .. }
+            | Self::CreateTable { .. }
+            | Self::DropTable { .. }
+            | Self::CreateIndex { .. }
+            | Self::DropIndex { .. }
+            | Self::CreateSequence { .. }
+            | Self::DropSequence { .. }
+            | Self::Analyze { .. } => self,
+            Self::Delete { table, primary_key, source, returning } => {
+                Self::Delete { table, primary_key, source: optimize(source)?, returning }
             }
-            Self::Insert { table, column_map, source } => {
-                Self::Insert { table, column_map, source: optimize(source)? }
+            Self::Insert { table, column_map, source, on_conflict, returning } => {
+                let source = optimize(source)?;
+                Self::Insert { table, column_map, source, on_conflict, returning }
             }
-            Self::Update { table, primary_key, source, expressions } => {
-                Self::Update { table, primary_key, source: optimize(source)?, expressions }
+            Self::Update { table, primary_key, source, expressions, returning } => {
+                let source = optimize(source)?;
+                Self::Update { table, primary_key, source, expressions, returning }
             }
             Self::Select(root) => Self::Select(optimize(root)?),
         })
     }
+
+    /// Binds parameter values to the plan's parameter placeholders, replacing
+    /// them with constants ($1 is params[0]). This must be done before
+    /// optimizing the plan, since optimizers make use of constant values (e.g.
+    /// for index lookups).
+    pub fn bind(self, params: &[Value]) -> Result<Self> {
+        let bind = |expr| match expr {
+            Expression::Parameter(n) => match params.get(n - 1) {
+                Some(value) => Ok(Expression::Constant(value.clone())),
+                None => errinput!("no value given for parameter ${n}"),
+            },
+            expr => Ok(expr),
+        };
+        let bind_node = |node: Node| {
+            node.transform(
+                &|node| match node {
+                    // Aggregate expressions aren't transformed by
+                    // transform_expressions(), so bind them here.
+                    Node::Aggregate { source, group_by, aggregates } => {
+                        let group_by = group_by
+                            .into_iter()
+                            .map(|expr| expr.transform(&Ok, &bind))
+                            .try_collect()?;
+                        let aggregates = aggregates
+                            .into_iter()
+                            .map(|aggregate| aggregate.transform(&Ok, &bind))
+                            .try_collect()?;
+                        Ok(Node::Aggregate { source, group_by, aggregates })
+                    }
+                    node => node.transform_expressions(&Ok, &bind),
+                },
+                &Ok,
+            )
+        };
+        Ok(match self {
+            Self::AlterTable { .. }
+            | Self::CreateTable { .. }
+            | Self::DropTable { .. }
+            | Self::CreateIndex { .. }
+            | Self::DropIndex { .. }
+            | Self::CreateSequence { .. }
+            | Self::DropSequence { .. }
+            | Self::Analyze { .. } => self,
+            Self::Delete { table, primary_key, source, returning } => {
+                let source = bind_node(source)?;
+                let returning =
+                    returning.map(|r| r.transform_expressions(&Ok, &bind)).transpose()?;
+                Self::Delete { table, primary_key, source, returning }
+            }
+            Self::Insert { table, column_map, source, on_conflict, returning } => {
+                let source = bind_node(source)?;
+                let on_conflict = on_conflict
+                    .map(|on_conflict| on_conflict.transform_expressions(&Ok, &bind))
+                    .transpose()?;
+                let returning =
+                    returning.map(|r| r.transform_expressions(&Ok, &bind)).transpose()?;
+                Self::Insert { table, column_map, source, on_conflict, returning }
+            }
+            Self::Update { table, primary_key, source, expressions, returning } => {
+                let source = bind_node(source)?;
+                let expressions = expressions
+                    .into_iter()
+                    .map(|(i, expr)| Ok((i, expr.transform(&Ok, &bind)?)))
+                    .collect::<Result<_>>()?;
+                let returning =
+                    returning.map(|r| r.transform_expressions(&Ok, &bind)).transpose()?;
+                Self::Update { table, primary_key, source, expressions, returning }
+            }
+            Self::Select(root) => Self::Select(bind_node(root)?),
+        })
+    }
+}
+
+/// An INSERT ON CONFLICT clause. Inserted rows that conflict with an existing
+/// row on one of the target constraints are handled by the action, instead of
+/// erroring. Conflicts with other constraints error as usual.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OnConflict {
+    /// The unique constraints to check for conflicts.
+    pub targets: Vec<ConflictTarget>,
+    /// The action to take on conflict.
+    pub action: ConflictAction,
+}
+
+/// An ON CONFLICT target constraint.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConflictTarget {
+    /// The table's primary key.
+    PrimaryKey,
+    /// A unique secondary index.
+    Index(Index),
+}
+
+/// An ON CONFLICT action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConflictAction {
+    /// Skips the inserted row.
+    Nothing,
+    /// Updates the existing row, if the filter (if any) is true. The update
+    /// expressions and filter are evaluated on the existing row followed by
+    /// the inserted row (i.e. the excluded row).
+    Update { expressions: Vec<(usize, Expression)>, filter: Option<Expression> },
+}
+
+impl OnConflict {
+    /// Transforms the action's expressions, see Expression::transform().
+    fn transform_expressions(
+        self,
+        before: &impl Fn(Expression) -> Result<Expression>,
+        after: &impl Fn(Expression) -> Result<Expression>,
+    ) -> Result<Self> {
+        let action = match self.action {
+            ConflictAction::Nothing => ConflictAction::Nothing,
+            ConflictAction::Update { expressions, filter } => ConflictAction::Update {
+                expressions: expressions
+                    .into_iter()
+                    .map(|(i, expr)| Ok((i, expr.transform(before, after)?)))
+                    .collect::<Result<_>>()?,
+                filter: filter.map(|expr| expr.transform(before, after)).transpose()?,
+            },
+        };
+        Ok(Self { targets: self.targets, action })
+    }
+
+    /// Formats the clause for display, using the given insert table's column
+    /// labels for the existing and excluded rows.
+    fn display(&self, table: &Table) -> String {
+        let ConflictAction::Update { expressions, filter } = &self.action else {
+            return "on conflict do nothing".to_string();
+        };
+        let labels = Node::labels(table, &[&table.name, "excluded"]);
+        let mut s = format!(
+            "on conflict do update {}",
+            expressions
+                .iter()
+                .map(|(i, expr)| format!("{}={}", table.columns[*i].name, expr.display(&labels)))
+                .join(", ")
+        );
+        if let Some(filter) = filter {
+            s += &format!(" where {}", filter.display(&labels));
+        }
+        s
+    }
+}
+
+/// A RETURNING clause for INSERT, UPDATE, and DELETE, which returns the
+/// affected table rows projected through the given expressions. For UPDATE
+/// FROM and DELETE USING, the rows also contain the joined columns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Returning {
+    /// The expressions to evaluate on each affected row.
+    pub expressions: Vec<Expression>,
+    /// The result column labels.
+    pub labels: Vec<Label>,
+}
+
+impl Returning {
+    /// Transforms the expressions, see Expression::transform().
+    fn transform_expressions(
+        self,
+        before: &impl Fn(Expression) -> Result<Expression>,
+        after: &impl Fn(Expression) -> Result<Expression>,
+    ) -> Result<Self> {
+        let expressions =
+            self.expressions.into_iter().map(|expr| expr.transform(before, after)).try_collect()?;
+        Ok(Self { expressions, labels: self.labels })
+    }
+
+    /// Formats the clause for display, using the given node for input column
+    /// labels.
+    fn display(&self, node: &Node) -> String {
+        let expressions = self
+            .expressions
+            .iter()
+            .zip(&self.labels)
+            .map(|(expr, label)| match label {
+                Label::Unqualified(alias) => format!("{} as {alias}", expr.display(node)),
+                _ => expr.display(node).to_string(),
+            })
+            .join(", ");
+        format!("returning {expressions}")
+    }
 }
 
 /// A query plan node. Returns a row iterator, and can be nested.
@@ -108,9 +359,16 @@ impl Plan {
 pub enum Node {
     /// Aggregates values for the given group_by buckets, across all rows in the
     /// source node. The group_by columns are emitted first, followed by the
-    /// aggregate columns, in the given order.
+    /// aggregate columns, in the given order. Buckets beyond the memory budget
+    /// are spilled to disk in hash partitions.
     Aggregate { source: Box<Node>, group_by: Vec<Expression>, aggregates: Vec<Aggregate> },
 
+    /// Discards duplicate source rows. If sorted is true, the source is sorted
+    /// such that duplicates are adjacent, and they can be discarded by comparing
+    /// each row with the previous one. Otherwise, it keeps an in-memory hash set
+    /// of all distinct rows seen so far.
+    Distinct { source: Box<Node>, sorted: bool },
+
     /// Filters source rows, by discarding rows for which the predicate
     /// evaluates to false.
     Filter { source: Box<Node>, predicate: Expression },
@@ -119,30 +377,108 @@ pub enum Node {
     /// in-memory hashmap of the right source and looking up matches for each
     /// row in the left source. When outer is true (e.g. LEFT JOIN), a left row
     /// without a right match is emitted anyway, with NULLs for the right row.
+    /// When full is true (FULL JOIN), so is a right row without a left match,
+    /// with NULLs for the left row. Requires outer. If the hashmap exceeds the
+    /// memory budget, both sources are spilled to disk in hash partitions.
     HashJoin {
         left: Box<Node>,
         left_column: usize,
         right: Box<Node>,
         right_column: usize,
         outer: bool,
+        full: bool,
     },
 
-    /// Looks up the given values in a secondary index and emits matching rows.
-    /// NULL and NaN values are considered equal, to allow IS NULL and IS NAN
-    /// index lookups, as is -0.0 and 0.0.
-    IndexLookup { table: Table, column: usize, values: Vec<Value>, alias: Option<String> },
+    /// Semi-joins the left and right sources on the given columns, by building
+    /// an in-memory hash set of the right source's join values. Emits each
+    /// left row at most once, if it has a right match, or if anti is true, if
+    /// it doesn't (e.g. NOT EXISTS). Only emits the left columns. When
+    /// null_aware is true (NOT IN), NULL join values match anything, such that
+    /// a left row with a NULL value or a right source with a NULL value never
+    /// emits anything unless the right source is empty. Requires anti.
+    HashSemiJoin {
+        left: Box<Node>,
+        left_column: usize,
+        right: Box<Node>,
+        right_column: usize,
+        anti: bool,
+        null_aware: bool,
+    },
+
+    /// Looks up the given values in the named secondary index and emits
+    /// matching rows. Each lookup may be a prefix of the index columns. NULL
+    /// and NaN values are considered equal, to allow IS NULL and IS NAN index
+    /// lookups, as is -0.0 and 0.0.
+    IndexLookup { table: Table, index: String, values: Vec<Vec<Value>>, alias: Option<String> },
+
+    /// Looks up the given primary keys and emits their rows. Each key may be a
+    /// prefix of a composite primary key, which matches all rows with it.
+    KeyLookup { table: Table, keys: Vec<Vec<Value>>, alias: Option<String> },
+
+    /// Scans a range of table rows by primary key, in primary key order. The
+    /// bounds may be primary key prefixes.
+    KeyRangeScan {
+        table: Table,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+        alias: Option<String>,
+    },
 
-    /// Looks up the given primary keys and emits their rows.
-    KeyLookup { table: Table, keys: Vec<Value>, alias: Option<String> },
+    /// Scans a range of entries in the named secondary index and emits the
+    /// matching rows, in index order (then primary key order). The bounds may
+    /// be index column prefixes. Unlike KeyRangeScan, the index may contain
+    /// NULL and NaN values, which the bounds must exclude where appropriate.
+    IndexRangeScan {
+        table: Table,
+        index: String,
+        range: (Bound<Vec<Value>>, Bound<Vec<Value>>),
+        alias: Option<String>,
+    },
 
     /// Only emits the first limit rows from the source, discards the rest.
     Limit { source: Box<Node>, limit: usize },
 
+    /// Joins the left and right sources on the given columns by merging them,
+    /// which requires both sources to be sorted in ascending order by their
+    /// join column. Only the run of right rows matching the current left row
+    /// is buffered in memory. When outer is true (e.g. LEFT JOIN), a left row
+    /// without a right match is emitted anyway, with NULLs for the right row.
+    /// Emits rows in the left source's order.
+    MergeJoin {
+        left: Box<Node>,
+        left_column: usize,
+        right: Box<Node>,
+        right_column: usize,
+        outer: bool,
+    },
+
     /// Joins the left and right sources on the given predicate by buffering the
     /// right source and iterating over it for every row in the left source.
     /// When outer is true (e.g. LEFT JOIN), a left row without a right match is
-    /// emitted anyway, with NULLs for the right row.
-    NestedLoopJoin { left: Box<Node>, right: Box<Node>, predicate: Option<Expression>, outer: bool },
+    /// emitted anyway, with NULLs for the right row. When full is true (FULL
+    /// JOIN), so is a right row without a left match, with NULLs for the left
+    /// row. Requires outer.
+    NestedLoopJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        outer: bool,
+        full: bool,
+    },
+
+    /// Semi-joins the left and right sources on the given predicate, by
+    /// iterating over the right source for every row in the left source until
+    /// a match is found. Emits each left row at most once, if it has a right
+    /// match, or if anti is true, if it doesn't (e.g. NOT EXISTS). Only emits
+    /// the left columns, but the predicate is evaluated on joined rows. When
+    /// null_aware is true (NOT IN), a NULL predicate result is considered a
+    /// match. Requires anti.
+    NestedLoopSemiJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        anti: bool,
+        null_aware: bool,
+    },
 
     /// Nothing does not emit anything, and is used to short-circuit nodes that
     /// can't emit anything during optimization. It retains the column names of
@@ -153,7 +489,8 @@ pub enum Node {
     Offset { source: Box<Node>, offset: usize },
 
     /// Sorts the source rows by the given sort key. Buffers the entire row set
-    /// in memory.
+    /// in memory, or spills sorted runs to disk and merges them if it exceeds
+    /// the memory budget.
     Order { source: Box<Node>, key: Vec<(Expression, Direction)> },
 
     /// Projects the input rows by evaluating the given expressions. Aliases are
@@ -170,17 +507,38 @@ pub enum Node {
     /// used during plan optimization. The alias is only used for formatting.
     Scan { table: Table, filter: Option<Expression>, alias: Option<String> },
 
+    /// Sorts the source rows by the given sort key, skips the first offset
+    /// rows, and emits the next limit rows (i.e. an Order node followed by
+    /// Offset and Limit nodes). Only buffers the limit + offset lowest rows, in
+    /// a bounded heap.
+    TopN { source: Box<Node>, key: Vec<(Expression, Direction)>, limit: usize, offset: usize },
+
     /// A constant set of values.
     Values { rows: Vec<Vec<Expression>> },
 }
 
 impl Node {
+    /// Returns a Nothing node with the given table's columns, once for each of
+    /// the given table names. Used to provide column labels when displaying
+    /// expressions that aren't evaluated on a node's rows.
+    fn labels(table: &Table, names: &[&str]) -> Self {
+        let columns = names
+            .iter()
+            .flat_map(|name| {
+                table.columns.iter().map(|c| Label::Qualified(name.to_string(), c.name.clone()))
+            })
+            .collect();
+        Self::Nothing { columns }
+    }
+
     /// Returns the number of columns emitted by the node.
     pub fn columns(&self) -> usize {
         match self {
             // Source nodes emit all table columns.
             Self::IndexLookup { table, .. }
+            | Self::IndexRangeScan { table, .. }
             | Self::KeyLookup { table, .. }
+            | Self::KeyRangeScan { table, .. }
             | Self::Scan { table, .. } => table.columns.len(),
 
             // These nodes modify the set of columns.
@@ -191,8 +549,13 @@ impl Node {
             }
 
             // Join nodes emit the combined columns.
-            Self::HashJoin { left, right, .. } | Self::NestedLoopJoin { left, right, .. } => {
-                left.columns() + right.columns()
+            Self::HashJoin { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. } => left.columns() + right.columns(),
+
+            // Semi joins only emit the left columns.
+            Self::HashSemiJoin { left, .. } | Self::NestedLoopSemiJoin { left, .. } => {
+                left.columns()
             }
 
             // Constant nodes have a predefined number of columns.
@@ -200,10 +563,12 @@ impl Node {
             Self::Values { rows } => rows.first().map(|row| row.len()).unwrap_or(0),
 
             // Simple nodes just pass through the source columns.
-            Self::Filter { source, .. }
+            Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
             | Self::Limit { source, .. }
             | Self::Offset { source, .. }
-            | Self::Order { source, .. } => source.columns(),
+            | Self::Order { source, .. }
+            | Self::TopN { source, .. } => source.columns(),
         }
     }
 
@@ -214,7 +579,9 @@ impl Node {
         match self {
             // Source nodes use the table/column name.
             Self::IndexLookup { table, alias, .. }
+            | Self::IndexRangeScan { table, alias, .. }
             | Self::KeyLookup { table, alias, .. }
+            | Self::KeyRangeScan { table, alias, .. }
             | Self::Scan { table, alias, .. } => Label::Qualified(
                 alias.as_ref().unwrap_or(&table.name).clone(),
                 table.columns[index].name.clone(),
@@ -243,8 +610,14 @@ impl Node {
                 .map(|i| source.column_label(i))
                 .unwrap_or(Label::None),
 
-            // Joins dispatch to the appropriate source.
-            Self::HashJoin { left, right, .. } | Self::NestedLoopJoin { left, right, .. } => {
+            // Joins dispatch to the appropriate source. Semi joins only emit
+            // the left columns, but their predicates refer to the right columns
+            // following the left columns, as in other joins.
+            Self::HashJoin { left, right, .. }
+            | Self::HashSemiJoin { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. }
+            | Self::NestedLoopSemiJoin { left, right, .. } => {
                 if index < left.columns() {
                     left.column_label(index)
                 } else {
@@ -253,10 +626,12 @@ impl Node {
             }
 
             // Simple nodes just dispatch to the source.
-            Self::Filter { source, .. }
+            Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
             | Self::Limit { source, .. }
             | Self::Offset { source, .. }
-            | Self::Order { source, .. } => source.column_label(index),
+            | Self::Order { source, .. }
+            | Self::TopN { source, .. } => source.column_label(index),
 
             // Nothing nodes contain the original columns of replaced nodes.
             Self::Nothing { columns } => columns.get(index).cloned().unwrap_or(Label::None),
@@ -266,6 +641,154 @@ impl Node {
         }
     }
 
+    /// Returns a column's datatype, if it can be determined at plan time, by
+    /// tracing the column through the plan tree. Returns None for computed
+    /// columns whose datatype depends on column values (see
+    /// Expression::datatype). Used for plan-time type checking.
+    pub fn column_datatype(&self, index: usize) -> Option<DataType> {
+        match self {
+            // Source nodes use the table column's datatype.
+            Self::IndexLookup { table, .. }
+            | Self::IndexRangeScan { table, .. }
+            | Self::KeyLookup { table, .. }
+            | Self::KeyRangeScan { table, .. }
+            | Self::Scan { table, .. } => table.columns.get(index).map(|c| c.datatype),
+
+            // These nodes rearrange columns. Route them to the correct upstream
+            // column where appropriate.
+            Self::Aggregate { source, group_by, aggregates } => match group_by.get(index) {
+                Some(Expression::Column(index)) => source.column_datatype(*index),
+                Some(expr) => expr.datatype(),
+                None => match aggregates.get(index - group_by.len()) {
+                    Some(Aggregate::Count { .. }) => Some(DataType::Integer),
+                    Some(_) | None => None,
+                },
+            },
+            Self::Projection { source, expressions, .. } => match expressions.get(index) {
+                Some(Expression::Column(index)) => source.column_datatype(*index),
+                Some(expr) => expr.datatype(),
+                None => None,
+            },
+            Self::Remap { source, targets } => targets
+                .iter()
+                .copied()
+                .position(|t| t == Some(index))
+                .and_then(|i| source.column_datatype(i)),
+
+            // Joins dispatch to the appropriate source.
+            Self::HashJoin { left, right, .. }
+            | Self::HashSemiJoin { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. }
+            | Self::NestedLoopSemiJoin { left, right, .. } => {
+                if index < left.columns() {
+                    left.column_datatype(index)
+                } else {
+                    right.column_datatype(index - left.columns())
+                }
+            }
+
+            // Simple nodes just dispatch to the source.
+            Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::TopN { source, .. } => source.column_datatype(index),
+
+            // Constant rows may have different datatypes, and are checked
+            // during execution.
+            Self::Nothing { .. } | Self::Values { .. } => None,
+        }
+    }
+
+    /// Returns the node's child nodes, in order.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Self::HashJoin { left, right, .. }
+            | Self::HashSemiJoin { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. }
+            | Self::NestedLoopSemiJoin { left, right, .. } => vec![left, right],
+            Self::Aggregate { source, .. }
+            | Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::Projection { source, .. }
+            | Self::Remap { source, .. }
+            | Self::TopN { source, .. } => vec![source],
+            Self::IndexLookup { .. }
+            | Self::IndexRangeScan { .. }
+            | Self::KeyLookup { .. }
+            | Self::KeyRangeScan { .. }
+            | Self::Nothing { .. }
+            | Self::Scan { .. }
+            | Self::Values { .. } => Vec::new(),
+        }
+    }
+
+    /// Returns the columns that the node's output rows are known to be sorted
+    /// by, in ascending order (i.e. lexicographically by the first column,
+    /// then the second, and so on). Empty if the order is unknown. Used to
+    /// elide unnecessary Order nodes.
+    pub fn ordering(&self) -> Vec<usize> {
+        match self {
+            // Table and key range scans emit rows in primary key order.
+            Self::KeyRangeScan { table, .. } | Self::Scan { table, .. } => {
+                table.primary_key.clone()
+            }
+
+            // Index range scans emit rows in index order, then by primary key.
+            Self::IndexRangeScan { table, index, .. } => {
+                match table.secondary_indexes().into_iter().find(|i| &i.name == index) {
+                    Some(index) => {
+                        index.columns.into_iter().chain(table.primary_key.clone()).collect()
+                    }
+                    None => Vec::new(),
+                }
+            }
+
+            // Ascending column sort keys sort by those columns.
+            Self::Order { key, .. } | Self::TopN { key, .. } => key
+                .iter()
+                .map_while(|(expr, direction)| match (expr, direction) {
+                    (Expression::Column(index), Direction::Ascending) => Some(*index),
+                    _ => None,
+                })
+                .collect(),
+
+            // Projections preserve the order of projected source columns.
+            Self::Projection { source, expressions, .. } => source
+                .ordering()
+                .into_iter()
+                .map_while(|index| expressions.iter().position(|e| e == &Expression::Column(index)))
+                .collect(),
+
+            // These nodes preserve the source order.
+            Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. } => source.ordering(),
+
+            // Merge joins and semi joins emit rows in the left source's order.
+            Self::HashSemiJoin { left, .. }
+            | Self::MergeJoin { left, .. }
+            | Self::NestedLoopSemiJoin { left, .. } => left.ordering(),
+
+            // Other nodes don't have a known order.
+            Self::Aggregate { .. }
+            | Self::HashJoin { .. }
+            | Self::IndexLookup { .. }
+            | Self::KeyLookup { .. }
+            | Self::NestedLoopJoin { .. }
+            | Self::Nothing { .. }
+            | Self::Remap { .. }
+            | Self::Values { .. } => Vec::new(),
+        }
+    }
+
     /// Recursively transforms query nodes depth-first by applying the given
     /// closures before and after descending.
     pub fn transform(
@@ -284,19 +807,53 @@ impl Node {
             Self::Aggregate { source, group_by, aggregates } => {
                 Self::Aggregate { source: xform(source)?, group_by, aggregates }
             }
+            Self::Distinct { source, sorted } => Self::Distinct { source: xform(source)?, sorted },
             Self::Filter { source, predicate } => {
                 Self::Filter { source: xform(source)?, predicate }
             }
-            Self::HashJoin { left, left_column, right, right_column, outer } => Self::HashJoin {
+            Self::HashJoin { left, left_column, right, right_column, outer, full } => {
+                Self::HashJoin {
+                    left: xform(left)?,
+                    left_column,
+                    right: xform(right)?,
+                    right_column,
+                    outer,
+                    full,
+                }
+            }
+            Self::HashSemiJoin { left, left_column, right, right_column, anti, null_aware } => {
+                Self::HashSemiJoin {
+                    left: xform(left)?,
+                    left_column,
+                    right: xform(right)?,
+                    right_column,
+                    anti,
+                    null_aware,
+                }
+            }
+            Self::Limit { source, limit } => Self::Limit { source: xform(source)?, limit },
+            Self::MergeJoin { left, left_column, right, right_column, outer } => Self::MergeJoin {
                 left: xform(left)?,
                 left_column,
                 right: xform(right)?,
                 right_column,
                 outer,
             },
-            Self::Limit { source, limit } => Self::Limit { source: xform(source)?, limit },
-            Self::NestedLoopJoin { left, right, predicate, outer } => {
-                Self::NestedLoopJoin { left: xform(left)?, right: xform(right)?, predicate, outer }
+            Self::NestedLoopJoin { left, right, predicate, outer, full } => Self::NestedLoopJoin {
+                left: xform(left)?,
+                right: xform(right)?,
+                predicate,
+                outer,
+                full,
+            },
+            Self::NestedLoopSemiJoin { left, right, predicate, anti, null_aware } => {
+                Self::NestedLoopSemiJoin {
+                    left: xform(left)?,
+                    right: xform(right)?,
+                    predicate,
+                    anti,
+                    null_aware,
+                }
             }
             Self::Offset { source, offset } => Self::Offset { source: xform(source)?, offset },
             Self::Order { source, key } => Self::Order { source: xform(source)?, key },
@@ -304,9 +861,14 @@ impl Node {
                 Self::Projection { source: xform(source)?, expressions, aliases }
             }
             Self::Remap { source, targets } => Self::Remap { source: xform(source)?, targets },
+            Self::TopN { source, key, limit, offset } => {
+                Self::TopN { source: xform(source)?, key, limit, offset }
+            }
 
             Self::IndexLookup { .. }
+            | Self::IndexRangeScan { .. }
             | Self::KeyLookup { .. }
+            | Self::KeyRangeScan { .. }
             | Self::Nothing { .. }
             | Self::Scan { .. }
             | Self::Values { .. } => self,
@@ -327,9 +889,19 @@ impl Node {
                 predicate = predicate.transform(before, after)?;
                 Self::Filter { source, predicate }
             }
-            Self::NestedLoopJoin { left, right, predicate: Some(predicate), outer } => {
+            Self::NestedLoopJoin { left, right, predicate: Some(predicate), outer, full } => {
                 let predicate = Some(predicate.transform(before, after)?);
-                Self::NestedLoopJoin { left, right, predicate, outer }
+                Self::NestedLoopJoin { left, right, predicate, outer, full }
+            }
+            Self::NestedLoopSemiJoin {
+                left,
+                right,
+                predicate: Some(predicate),
+                anti,
+                null_aware,
+            } => {
+                let predicate = Some(predicate.transform(before, after)?);
+                Self::NestedLoopSemiJoin { left, right, predicate, anti, null_aware }
             }
             Self::Order { source, mut key } => {
                 key = key
@@ -349,6 +921,13 @@ impl Node {
                 let filter = Some(filter.transform(before, after)?);
                 Self::Scan { table, alias, filter }
             }
+            Self::TopN { source, mut key, limit, offset } => {
+                key = key
+                    .into_iter()
+                    .map(|(expr, dir)| expr.transform(before, after).map(|expr| (expr, dir)))
+                    .try_collect()?;
+                Self::TopN { source, key, limit, offset }
+            }
             Self::Values { mut rows } => {
                 rows = rows
                     .into_iter()
@@ -358,11 +937,17 @@ impl Node {
             }
 
             Self::Aggregate { .. }
+            | Self::Distinct { .. }
             | Self::HashJoin { .. }
+            | Self::HashSemiJoin { .. }
             | Self::IndexLookup { .. }
+            | Self::IndexRangeScan { .. }
             | Self::KeyLookup { .. }
+            | Self::KeyRangeScan { .. }
             | Self::Limit { .. }
+            | Self::MergeJoin { .. }
             | Self::NestedLoopJoin { predicate: None, .. }
+            | Self::NestedLoopSemiJoin { predicate: None, .. }
             | Self::Nothing { .. }
             | Self::Offset { .. }
             | Self::Remap { .. }
@@ -371,35 +956,75 @@ impl Node {
     }
 }
 
-/// An aggregate function.
+/// An aggregate function. If distinct is true, duplicate input values are
+/// only counted once, e.g. COUNT(DISTINCT a).
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Aggregate {
-    Average(Expression),
-    Count(Expression),
-    Max(Expression),
-    Min(Expression),
-    Sum(Expression),
+    Average { expr: Expression, distinct: bool },
+    Count { expr: Expression, distinct: bool },
+    Max { expr: Expression, distinct: bool },
+    Min { expr: Expression, distinct: bool },
+    Sum { expr: Expression, distinct: bool },
 }
 
 impl Aggregate {
+    /// Recursively transforms the aggregate's expression, see
+    /// Expression::transform().
+    fn transform(
+        self,
+        before: &impl Fn(Expression) -> Result<Expression>,
+        after: &impl Fn(Expression) -> Result<Expression>,
+    ) -> Result<Self> {
+        Ok(match self {
+            Self::Average { expr, distinct } => {
+                Self::Average { expr: expr.transform(before, after)?, distinct }
+            }
+            Self::Count { expr, distinct } => {
+                Self::Count { expr: expr.transform(before, after)?, distinct }
+            }
+            Self::Max { expr, distinct } => {
+                Self::Max { expr: expr.transform(before, after)?, distinct }
+            }
+            Self::Min { expr, distinct } => {
+                Self::Min { expr: expr.transform(before, after)?, distinct }
+            }
+            Self::Sum { expr, distinct } => {
+                Self::Sum { expr: expr.transform(before, after)?, distinct }
+            }
+        })
+    }
+
     fn format(&self, node: &Node) -> String {
-        match self {
-            Self::Average(expr) => format!("avg({})", expr.display(node)),
-            Self::Count(expr) => format!("count({})", expr.display(node)),
-            Self::Max(expr) => format!("max({})", expr.display(node)),
-            Self::Min(expr) => format!("min({})", expr.display(node)),
-            Self::Sum(expr) => format!("sum({})", expr.display(node)),
-        }
+        let name = match self {
+            Self::Average { .. } => "avg",
+            Self::Count { .. } => "count",
+            Self::Max { .. } => "max",
+            Self::Min { .. } => "min",
+            Self::Sum { .. } => "sum",
+        };
+        let distinct = if self.distinct() { "DISTINCT " } else { "" };
+        format!("{name}({distinct}{})", self.expr().display(node))
     }
 
     /// Returns the inner expression.
     pub fn expr(&self) -> &Expression {
         match self {
-            Self::Average(expr)
-            | Self::Count(expr)
-            | Self::Max(expr)
-            | Self::Min(expr)
-            | Self::Sum(expr) => expr,
+            Self::Average { expr, .. }
+            | Self::Count { expr, .. }
+            | Self::Max { expr, .. }
+            | Self::Min { expr, .. }
+            | Self::Sum { expr, .. } => expr,
+        }
+    }
+
+    /// Returns true if the aggregate only considers distinct values.
+    pub fn distinct(&self) -> bool {
+        match self {
+            Self::Average { distinct, .. }
+            | Self::Count { distinct, .. }
+            | Self::Max { distinct, .. }
+            | Self::Min { distinct, .. }
+            | Self::Sum { distinct, .. } => *distinct,
         }
     }
 }
@@ -429,49 +1054,141 @@ impl From<ast::Direction> for Direction {
     }
 }
 
-/// Formats the plan as an EXPLAIN tree.
+/// Formats the plan as a tree, without cost estimates.
 impl Display for Plan {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.format(f, None, &mut std::iter::empty())
+    }
+}
+
+impl Plan {
+    /// Formats the plan as an EXPLAIN tree, annotating each node with its
+    /// estimated cost and row count using table statistics from the catalog.
+    pub fn explain(&self, catalog: &impl Catalog) -> Result<String> {
+        Ok(Explain(self, &self.cost_model(catalog)?, &[]).to_string())
+    }
+
+    /// Executes the plan and formats it as an EXPLAIN tree (EXPLAIN ANALYZE),
+    /// annotating each node with both its estimates and its actual runtime
+    /// statistics. Writes are applied, while SELECT rows are discarded.
+    pub fn explain_analyze(
+        self,
+        txn: &impl Transaction,
+        budget: MemoryBudget,
+        sequences: SequenceValues,
+    ) -> Result<String> {
+        let cost_model = self.cost_model(txn)?;
+        let plan = self.clone();
+        let mut executor = Executor::new(txn, budget, sequences).analyze();
+        if let ExecutionResult::Select { rows, .. } = executor.execute(self)? {
+            for row in rows {
+                row?;
+            }
+        }
+        Ok(Explain(&plan, &cost_model, &executor.stats()).to_string())
+    }
+
+    /// Loads a cost model for the plan's nodes.
+    fn cost_model(&self, catalog: &impl Catalog) -> Result<CostModel> {
+        Ok(match self {
+            Self::Delete { source, .. }
+            | Self::Insert { source, .. }
+            | Self::Update { source, .. }
+            | Self::Select(source) => CostModel::load(catalog, source)?,
+            Self::AlterTable { .. }
+            | Self::CreateTable { .. }
+            | Self::DropTable { .. }
+            | Self::CreateIndex { .. }
+            | Self::DropIndex { .. }
+            | Self::CreateSequence { .. }
+            | Self::DropSequence { .. }
+            | Self::Analyze { .. } => CostModel::default(),
+        })
+    }
+
+    /// Formats the plan, annotating nodes with cost estimates if a cost model
+    /// is given, and with runtime statistics from the given pre-order stats
+    /// iterator, if any.
+    fn format(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        cost_model: Option<&CostModel>,
+        stats: &mut dyn Iterator<Item = &NodeStats>,
+    ) -> std::fmt::Result {
         match self {
+            Self::AlterTable { name, alteration } => write!(f, "AlterTable: {name} {alteration}"),
             Self::CreateTable { schema } => write!(f, "CreateTable: {}", schema.name),
             Self::DropTable { name: table, .. } => write!(f, "DropTable: {table}"),
-            Self::Delete { table, source, .. } => {
-                write!(f, "Delete: {table}")?;
-                source.format(f, "", false, true)
+            Self::CreateIndex { name, table, columns, .. } => {
+                write!(f, "CreateIndex: {name} on {table} ({})", columns.join(", "))
+            }
+            Self::DropIndex { name, .. } => write!(f, "DropIndex: {name}"),
+            Self::CreateSequence { sequence } => write!(f, "CreateSequence: {}", sequence.name),
+            Self::DropSequence { name, .. } => write!(f, "DropSequence: {name}"),
+            Self::Analyze { tables } => write!(f, "Analyze: {}", tables.join(", ")),
+            Self::Delete { table, source, returning, .. } => {
+                write!(f, "Delete: {}", table.name)?;
+                if let Some(returning) = returning {
+                    write!(f, " {}", returning.display(source))?;
+                }
+                source.format(f, "", false, true, cost_model, stats)
             }
-            Self::Insert { table, source, .. } => {
+            Self::Insert { table, source, on_conflict, returning, .. } => {
                 write!(f, "Insert: {}", table.name)?;
-                source.format(f, "", false, true)
+                if let Some(on_conflict) = on_conflict {
+                    write!(f, " ({})", on_conflict.display(table))?;
+                }
+                if let Some(returning) = returning {
+                    write!(f, " {}", returning.display(&Node::labels(table, &[&table.name])))?;
+                }
+                source.format(f, "", false, true, cost_model, stats)
             }
-            Self::Update { table, source, expressions, .. } => {
+            Self::Update { table, source, expressions, returning, .. } => {
                 let expressions = expressions
                     .iter()
                     .map(|(i, expr)| format!("{}={}", table.columns[*i].name, expr.display(source)))
                     .join(", ");
                 write!(f, "Update: {} ({expressions})", table.name)?;
-                source.format(f, "", false, true)
+                if let Some(returning) = returning {
+                    write!(f, " {}", returning.display(source))?;
+                }
+                source.format(f, "", false, true, cost_model, stats)
             }
-            Self::Select(root) => root.format(f, "", true, true),
+            Self::Select(root) => root.format(f, "", true, true, cost_model, stats),
         }
     }
 }
 
+/// Formats a plan for EXPLAIN, with a cost model and runtime statistics (for
+/// EXPLAIN ANALYZE, otherwise empty).
+struct Explain<'a>(&'a Plan, &'a CostModel, &'a [NodeStats]);
+
+impl Display for Explain<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.format(f, Some(self.1), &mut self.2.iter())
+    }
+}
+
 impl Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.format(f, "", true, true)
+        self.format(f, "", true, true, None, &mut std::iter::empty())
     }
 }
 
 impl Node {
     /// Recursively formats the node. Prefix is used for tree branch lines. root
     /// is true if this is the root (first) node, and last_child is true if this
-    /// is the last child node of the parent.
+    /// is the last child node of the parent. Nodes are annotated with cost
+    /// estimates if a cost model is given, and with runtime statistics taken
+    /// in pre-order from the stats iterator, if any.
     pub fn format(
         &self,
         f: &mut std::fmt::Formatter<'_>,
         prefix: &str,
         root: bool,
         last_child: bool,
+        cost_model: Option<&CostModel>,
+        stats: &mut dyn Iterator<Item = &NodeStats>,
     ) -> std::fmt::Result {
         // If this is not the root node, emit a newline after the previous node.
         // This avoids a spurious newline at the end of the plan.
@@ -501,16 +1218,37 @@ impl Node {
                     .chain(aggregates.iter().map(|agg| agg.format(source)))
                     .join(", ");
                 write!(f, "Aggregate: {aggregates}")?;
-                source.format(f, &prefix, false, true)?;
+            }
+
+            Self::Distinct { sorted, .. } => {
+                write!(f, "Distinct")?;
+                if *sorted {
+                    write!(f, " (sorted)")?;
+                }
             }
 
             Self::Filter { source, predicate } => {
                 write!(f, "Filter: {}", predicate.display(source))?;
-                source.format(f, &prefix, false, true)?;
             }
 
-            Self::HashJoin { left, left_column, right, right_column, outer } => {
-                let kind = if *outer { "outer" } else { "inner" };
+            Self::HashJoin { left, left_column, right, right_column, .. }
+            | Self::HashSemiJoin { left, left_column, right, right_column, .. }
+            | Self::MergeJoin { left, left_column, right, right_column, .. } => {
+                let name = match self {
+                    Self::HashSemiJoin { .. } => "HashSemiJoin",
+                    Self::MergeJoin { .. } => "MergeJoin",
+                    _ => "HashJoin",
+                };
+                let kind = match self {
+                    Self::HashJoin { full: true, .. } => "full",
+                    Self::HashJoin { outer: true, .. } | Self::MergeJoin { outer: true, .. } => {
+                        "outer"
+                    }
+                    Self::HashSemiJoin { null_aware: true, .. } => "null-aware anti",
+                    Self::HashSemiJoin { anti: true, .. } => "anti",
+                    Self::HashSemiJoin { .. } => "semi",
+                    _ => "inner",
+                };
                 let left_column = match left.column_label(*left_column) {
                     Label::None => format!("left #{left_column}"),
                     label => format!("{label}"),
@@ -519,19 +1257,16 @@ impl Node {
                     Label::None => format!("right #{right_column}"),
                     label => format!("{label}"),
                 };
-                write!(f, "HashJoin: {kind} on {left_column} = {right_column}")?;
-                left.format(f, &prefix, false, false)?;
-                right.format(f, &prefix, false, true)?;
+                write!(f, "{name}: {kind} on {left_column} = {right_column}")?;
             }
 
-            Self::IndexLookup { table, column, alias, values } => {
-                let column = &table.columns[*column].name;
-                write!(f, "IndexLookup: {}.{column}", table.name)?;
+            Self::IndexLookup { table, index, alias, values } => {
+                write!(f, "IndexLookup: {}.{index}", table.name)?;
                 if let Some(alias) = alias {
-                    write!(f, " as {alias}.{column}")?;
+                    write!(f, " as {alias}.{index}")?;
                 }
                 if !values.is_empty() && values.len() < 10 {
-                    write!(f, " ({})", values.iter().join(", "))?;
+                    write!(f, " ({})", values.iter().map(|v| format_tuple(v)).join(", "))?;
                 } else {
                     write!(f, " ({} values)", values.len())?;
                 }
@@ -543,32 +1278,62 @@ impl Node {
                     write!(f, " as {alias}")?;
                 }
                 if !keys.is_empty() && keys.len() < 10 {
-                    write!(f, " ({})", keys.iter().join(", "))?;
+                    write!(f, " ({})", keys.iter().map(|k| format_tuple(k)).join(", "))?;
                 } else {
                     write!(f, " ({} keys)", keys.len())?;
                 }
             }
 
-            Self::Limit { source, limit } => {
+            Self::IndexRangeScan { table, index, range, alias } => {
+                write!(f, "IndexRangeScan: {}.{index}", table.name)?;
+                if let Some(alias) = alias {
+                    write!(f, " as {alias}.{index}")?;
+                }
+                let index = table.secondary_indexes().into_iter().find(|i| &i.name == index);
+                let columns = index.map(|i| i.columns).unwrap_or_default();
+                write!(f, " {}", Self::format_range(table, &columns, range))?;
+            }
+
+            Self::KeyRangeScan { table, range, alias } => {
+                write!(f, "KeyRangeScan: {}", table.name)?;
+                if let Some(alias) = alias {
+                    write!(f, " as {alias}")?;
+                }
+                write!(f, " {}", Self::format_range(table, &table.primary_key, range))?;
+            }
+
+            Self::Limit { limit, .. } => {
                 write!(f, "Limit: {limit}")?;
-                source.format(f, &prefix, false, true)?;
             }
 
-            Self::NestedLoopJoin { left, right, predicate, outer, .. } => {
-                let kind = if *outer { "outer" } else { "inner" };
+            Self::NestedLoopJoin { predicate, outer, full, .. } => {
+                let kind = match (outer, full) {
+                    (_, true) => "full",
+                    (true, false) => "outer",
+                    (false, false) => "inner",
+                };
                 write!(f, "NestedLoopJoin: {kind}")?;
                 if let Some(predicate) = predicate {
                     write!(f, " on {}", predicate.display(self))?;
                 }
-                left.format(f, &prefix, false, false)?;
-                right.format(f, &prefix, false, true)?;
+            }
+
+            Self::NestedLoopSemiJoin { predicate, anti, null_aware, .. } => {
+                let kind = match (anti, null_aware) {
+                    (_, true) => "null-aware anti",
+                    (true, false) => "anti",
+                    (false, false) => "semi",
+                };
+                write!(f, "NestedLoopSemiJoin: {kind}")?;
+                if let Some(predicate) = predicate {
+                    write!(f, " on {}", predicate.display(self))?;
+                }
             }
 
             Self::Nothing { .. } => write!(f, "Nothing")?,
 
-            Self::Offset { source, offset } => {
+            Self::Offset { offset, .. } => {
                 write!(f, "Offset: {offset}")?;
-                source.format(f, &prefix, false, true)?;
             }
 
             Self::Order { source, key: orders } => {
@@ -577,7 +1342,6 @@ impl Node {
                     .map(|(expr, dir)| format!("{} {dir}", expr.display(source)))
                     .join(", ");
                 write!(f, "Order: {orders}")?;
-                source.format(f, &prefix, false, true)?;
             }
 
             Self::Projection { source, expressions, aliases } => {
@@ -590,7 +1354,6 @@ impl Node {
                     })
                     .join(", ");
                 write!(f, "Projection: {expressions}")?;
-                source.format(f, &prefix, false, true)?;
             }
 
             Self::Remap { source, targets } => {
@@ -618,7 +1381,6 @@ impl Node {
                 if !dropped.is_empty() {
                     write!(f, " (dropped: {dropped})")?;
                 }
-                source.format(f, &prefix, false, true)?;
             }
 
             Self::Scan { table, alias, filter } => {
@@ -631,6 +1393,18 @@ impl Node {
                 }
             }
 
+            Self::TopN { source, key: orders, limit, offset } => {
+                let orders = orders
+                    .iter()
+                    .map(|(expr, dir)| format!("{} {dir}", expr.display(source)))
+                    .join(", ");
+                write!(f, "TopN: {limit}")?;
+                if *offset > 0 {
+                    write!(f, " offset {offset}")?;
+                }
+                write!(f, " by {orders}")?;
+            }
+
             Self::Values { rows, .. } => {
                 write!(f, "Values: ")?;
                 match rows.len() {
@@ -640,8 +1414,60 @@ impl Node {
                 }
             }
         };
+
+        // Annotate the node with its estimated cost, if requested.
+        if let Some(cost_model) = cost_model {
+            let Estimate { rows, cost } = cost_model.estimate(self);
+            write!(f, " (cost={cost:.2} rows={rows:.0})")?;
+        }
+
+        // Annotate the node with its runtime statistics, if any.
+        if let Some(NodeStats { rows, time, keys_read, round_trips }) = stats.next() {
+            let time = time.as_secs_f64() * 1000.0;
+            write!(
+                f,
+                " (actual rows={rows} keys_read={keys_read} round_trips={round_trips} time={time:.3}ms)"
+            )?;
+        }
+
+        // Format the child nodes.
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            child.format(f, &prefix, false, i == children.len() - 1, cost_model, stats)?;
+        }
         Ok(())
     }
+
+    /// Formats a key or index range scan range, e.g. (1 < id <= 10). The
+    /// columns are the key or index columns, which are named for the prefix
+    /// covered by the bounds.
+    fn format_range(
+        table: &Table,
+        columns: &[usize],
+        range: &(Bound<Vec<Value>>, Bound<Vec<Value>>),
+    ) -> String {
+        let len = match range {
+            (Bound::Unbounded, Bound::Unbounded) => return "(all)".to_string(),
+            (Bound::Included(key) | Bound::Excluded(key), _)
+            | (_, Bound::Included(key) | Bound::Excluded(key)) => key.len(),
+        };
+        let mut names = columns.iter().take(len).map(|&i| &table.columns[i].name);
+        let names = match len {
+            1 => names.join(""),
+            _ => format!("({})", names.join(", ")),
+        };
+        let start = match &range.0 {
+            Bound::Included(key) => format!("{} <= ", format_tuple(key)),
+            Bound::Excluded(key) => format!("{} < ", format_tuple(key)),
+            Bound::Unbounded => String::new(),
+        };
+        let end = match &range.1 {
+            Bound::Included(key) => format!(" <= {}", format_tuple(key)),
+            Bound::Excluded(key) => format!(" < {}", format_tuple(key)),
+            Bound::Unbounded => String::new(),
+        };
+        format!("({start}{names}{end})")
+    }
 }
 
 /// Inverts a Remap targets vector to a vector of source indexes, with None
@@ -1,13 +1,18 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 
-use itertools::{Either, Itertools as _};
+use itertools::Itertools as _;
 
-use super::plan::{Aggregate, Node, Plan, invert_remap};
+use super::plan::{
+    Aggregate, ConflictAction, ConflictTarget, Direction, Node, OnConflict, Plan, Returning,
+    invert_remap,
+};
 use crate::errinput;
 use crate::error::Result;
 use crate::sql::engine::Catalog;
 use crate::sql::parser::ast;
-use crate::sql::types::{Column, Expression, Label, Table, Value};
+use crate::sql::types::{
+    self, Alteration, Check, Column, Expression, ForeignKey, Label, Sequence, Table, Value,
+};
 
 /// The planner builds an execution plan from a parsed Abstract Syntax Tree,
 /// using the catalog for schema information.
@@ -29,52 +34,221 @@ impl<'a, C: Catalog> Planner<'a, C> {
     pub fn build(&mut self, statement: ast::Statement) -> Result<Plan> {
         use ast::Statement::*;
         match statement {
-            CreateTable { name, columns } => self.build_create_table(name, columns),
+            AlterTable { name, alteration } => self.build_alter_table(name, alteration),
+            CreateTable { name, columns, primary_key, foreign_keys, checks } => {
+                self.build_create_table(name, columns, primary_key, foreign_keys, checks)
+            }
             DropTable { name, if_exists } => self.build_drop_table(name, if_exists),
-
-            Delete { table, r#where } => self.build_delete(table, r#where),
-            Insert { table, columns, values } => self.build_insert(table, columns, values),
-            Update { table, set, r#where } => self.build_update(table, set, r#where),
-            Select { select, from, r#where, group_by, having, order_by, offset, limit } => {
-                self.build_select(select, from, r#where, group_by, having, order_by, offset, limit)
+            CreateIndex { name, table, columns, unique } => {
+                self.build_create_index(name, table, columns, unique)
             }
+            DropIndex { name, if_exists } => self.build_drop_index(name, if_exists),
+            CreateSequence { name, start, increment } => {
+                self.build_create_sequence(name, start, increment)
+            }
+            DropSequence { name, if_exists } => self.build_drop_sequence(name, if_exists),
+            Analyze { table } => self.build_analyze(table),
 
-            // Transaction and explain statements are handled by Session.
-            Begin { .. } | Commit | Rollback | Explain(_) => {
+            Delete { table, using, r#where, returning } => {
+                self.build_delete(table, using, r#where, returning)
+            }
+            Insert { table, columns, source, on_conflict, returning } => {
+                self.build_insert(table, columns, source, on_conflict, returning)
+            }
+            Update { table, set, from, r#where, returning } => {
+                self.build_update(table, set, from, r#where, returning)
+            }
+            Select {
+                distinct,
+                select,
+                from,
+                r#where,
+                group_by,
+                having,
+                order_by,
+                offset,
+                limit,
+            } => self.build_select(
+                distinct, select, from, r#where, group_by, having, order_by, offset, limit,
+            ),
+
+            // Transaction, explain, and prepared statements are handled by
+            // Session.
+            Begin { .. }
+            | Commit
+            | Rollback
+            | Explain { .. }
+            | Prepare { .. }
+            | Execute { .. }
+            | Deallocate { .. } => {
                 panic!("unexpected statement {statement:?}")
             }
         }
     }
 
-    /// Builds a CREATE TABLE plan.
-    fn build_create_table(&self, name: String, columns: Vec<ast::Column>) -> Result<Plan> {
+    /// Builds a CREATE TABLE plan. The primary key is either given by a single
+    /// column's PRIMARY KEY, or by a table-level PRIMARY KEY (columns).
+    fn build_create_table(
+        &self,
+        name: String,
+        mut columns: Vec<ast::Column>,
+        primary_key: Option<Vec<String>>,
+        foreign_keys: Vec<ast::ForeignKey>,
+        checks: Vec<ast::Check>,
+    ) -> Result<Plan> {
         // Most schema validation happens during execution via Table.validate().
-        let Some(primary_key) = columns.iter().position(|c| c.primary_key) else {
-            return errinput!("no primary key for table {name}");
+        let column_keys = columns.iter().positions(|c| c.primary_key).collect_vec();
+        let primary_key = match (primary_key, column_keys.len()) {
+            (None, 0) => return errinput!("no primary key for table {name}"),
+            (None, 1) => column_keys,
+            (Some(keys), 0) => {
+                let mut primary_key = Vec::with_capacity(keys.len());
+                for key in keys {
+                    match columns.iter().position(|c| c.name == key) {
+                        Some(index) => primary_key.push(index),
+                        None => return errinput!("unknown primary key column {key}"),
+                    }
+                }
+                primary_key
+            }
+            (_, _) => return errinput!("multiple primary keys for table {name}"),
         };
-        if columns.iter().filter(|c| c.primary_key).count() > 1 {
-            return errinput!("multiple primary keys for table {name}");
+
+        // Single-column foreign keys are stored as inline column references.
+        let mut multi_column_keys = Vec::new();
+        for foreign_key in foreign_keys {
+            let mut indexes = Vec::with_capacity(foreign_key.columns.len());
+            for column in &foreign_key.columns {
+                match columns.iter().position(|c| &c.name == column) {
+                    Some(index) => indexes.push(index),
+                    None => return errinput!("unknown foreign key column {column}"),
+                }
+            }
+            if let &[index] = indexes.as_slice() {
+                let column = &mut columns[index];
+                if column.references.is_some() {
+                    return errinput!("multiple references for column {}", column.name);
+                }
+                column.references = Some(foreign_key.reference);
+                continue;
+            }
+            multi_column_keys
+                .push(ForeignKey { columns: indexes, reference: foreign_key.reference });
         }
+
+        // Collect column and table checks, paired with the constrained column
+        // (if any). They're built once the table columns are known.
+        let checks = columns
+            .iter_mut()
+            .flat_map(|c| {
+                std::mem::take(&mut c.checks).into_iter().map(|check| (Some(c.name.clone()), check))
+            })
+            .chain(checks.into_iter().map(|check| (None, check)))
+            .collect_vec();
+
         let columns = columns
             .into_iter()
-            .map(|c| {
-                let nullable = c.nullable.unwrap_or(!c.primary_key);
-                Ok(Column {
-                    name: c.name,
-                    datatype: c.datatype,
-                    nullable,
-                    default: match c.default {
-                        Some(expr) => Some(Self::build_constant_value(expr)?),
-                        None if nullable => Some(Value::Null),
-                        None => None,
-                    },
-                    unique: c.unique || c.primary_key,
-                    index: (c.index || c.unique || c.references.is_some()) && !c.primary_key,
-                    references: c.references,
-                })
+            .enumerate()
+            .map(|(i, c)| {
+                Self::build_column(c, &name, primary_key.contains(&i), primary_key == [i])
             })
             .collect::<Result<_>>()?;
-        Ok(Plan::CreateTable { schema: Table { name, primary_key, columns } })
+        let mut table = Table {
+            name,
+            primary_key,
+            columns,
+            indexes: Vec::new(),
+            checks: Vec::new(),
+            foreign_keys: multi_column_keys,
+            version: 0,
+        };
+        table.checks = Self::build_checks(&table, checks)?;
+        Ok(Plan::CreateTable { schema: table })
+    }
+
+    /// Builds CHECK constraints for a table, given as pairs of the constrained
+    /// column name (if any) and the AST check. Unnamed checks get the name
+    /// {table}_{column}_check or {table}_check, with a numeric suffix if the
+    /// name is already taken. Validation happens during execution.
+    fn build_checks(
+        table: &Table,
+        checks: Vec<(Option<String>, ast::Check)>,
+    ) -> Result<Vec<Check>> {
+        let scope = Scope::from_table(table)?;
+        let mut names: HashSet<String> =
+            checks.iter().filter_map(|(_, c)| c.name.clone()).collect();
+        let mut built = Vec::with_capacity(checks.len());
+        for (column, check) in checks {
+            let name = match check.name {
+                Some(name) => name,
+                None => {
+                    let base = match column {
+                        Some(column) => format!("{}_{column}_check", table.name),
+                        None => format!("{}_check", table.name),
+                    };
+                    let mut name = base.clone();
+                    for i in 1.. {
+                        if !names.contains(&name) {
+                            break;
+                        }
+                        name = format!("{base}{i}");
+                    }
+                    names.insert(name.clone());
+                    name
+                }
+            };
+            let expression = Self::build_expression(check.expression, &scope)?;
+            built.push(Check { name, expression });
+        }
+        Ok(built)
+    }
+
+    /// Builds a table column from an AST column definition. in_key specifies
+    /// whether the column is part of the primary key, and sole_key whether it
+    /// is the entire primary key (which acts as the primary index). Identity
+    /// columns get an owned sequence named {table}_{column}_seq.
+    fn build_column(c: ast::Column, table: &str, in_key: bool, sole_key: bool) -> Result<Column> {
+        let nullable = c.nullable.unwrap_or(!in_key && !c.identity);
+        let sequence = c.identity.then(|| format!("{table}_{}_seq", c.name));
+        Ok(Column {
+            name: c.name,
+            datatype: c.datatype,
+            nullable,
+            default: match c.default {
+                Some(expr) => Some(Self::build_constant_value(expr)?),
+                None if nullable && sequence.is_none() => Some(Value::Null),
+                None => None,
+            },
+            unique: c.unique || sole_key,
+            index: (c.index || c.unique || c.references.is_some()) && !sole_key,
+            references: c.references,
+            sequence,
+        })
+    }
+
+    /// Builds an ALTER TABLE plan. Most validation happens during execution.
+    fn build_alter_table(&self, name: String, alteration: ast::Alteration) -> Result<Plan> {
+        let alteration = match alteration {
+            ast::Alteration::AddColumn(column) if column.primary_key => {
+                return errinput!("can't add primary key column {}", column.name);
+            }
+            ast::Alteration::AddColumn(column) if !column.checks.is_empty() => {
+                return errinput!("can't add column {} with CHECK constraint", column.name);
+            }
+            ast::Alteration::AddColumn(column) => {
+                Alteration::AddColumn(Self::build_column(column, &name, false, false)?)
+            }
+            ast::Alteration::DropColumn(column) => Alteration::DropColumn(column),
+            ast::Alteration::RenameColumn { from, to } => Alteration::RenameColumn { from, to },
+            ast::Alteration::RenameTable(to) => Alteration::RenameTable(to),
+            ast::Alteration::SetDefault(column, default) => {
+                Alteration::SetDefault(column, default.map(Self::build_constant_value).transpose()?)
+            }
+            ast::Alteration::SetNullable(column, nullable) => {
+                Alteration::SetNullable(column, nullable)
+            }
+        };
+        Ok(Plan::AlterTable { name, alteration })
     }
 
     /// Builds a DROP TABLE plan.
@@ -82,26 +256,85 @@ impl<'a, C: Catalog> Planner<'a, C> {
         Ok(Plan::DropTable { name, if_exists })
     }
 
-    /// Builds a DELETE plan.
-    fn build_delete(&self, table: String, r#where: Option<ast::Expression>) -> Result<Plan> {
+    /// Builds a CREATE INDEX plan. Validation happens during execution.
+    fn build_create_index(
+        &self,
+        name: String,
+        table: String,
+        columns: Vec<String>,
+        unique: bool,
+    ) -> Result<Plan> {
+        Ok(Plan::CreateIndex { name, table, columns, unique })
+    }
+
+    /// Builds a DROP INDEX plan.
+    fn build_drop_index(&self, name: String, if_exists: bool) -> Result<Plan> {
+        Ok(Plan::DropIndex { name, if_exists })
+    }
+
+    /// Builds a CREATE SEQUENCE plan. The start value defaults to 1, or -1 for
+    /// descending sequences.
+    fn build_create_sequence(
+        &self,
+        name: String,
+        start: Option<ast::Expression>,
+        increment: Option<ast::Expression>,
+    ) -> Result<Plan> {
+        let integer = |expr: Option<ast::Expression>, default: i64| -> Result<i64> {
+            match expr.map(Self::build_constant_value).transpose()? {
+                Some(Value::Integer(i)) => Ok(i),
+                Some(value) => errinput!("sequence value must be an integer, got {value}"),
+                None => Ok(default),
+            }
+        };
+        let increment = integer(increment, 1)?;
+        let start = integer(start, increment.signum())?;
+        Ok(Plan::CreateSequence { sequence: Sequence { start, increment, ..Sequence::new(name) } })
+    }
+
+    /// Builds a DROP SEQUENCE plan.
+    fn build_drop_sequence(&self, name: String, if_exists: bool) -> Result<Plan> {
+        Ok(Plan::DropSequence { name, if_exists })
+    }
+
+    /// Builds an ANALYZE plan, for the given table or all tables.
+    fn build_analyze(&self, table: Option<String>) -> Result<Plan> {
+        let tables = match table {
+            Some(table) => vec![self.catalog.must_get_table(&table)?.name],
+            None => self.catalog.list_tables()?.into_iter().map(|t| t.name).collect(),
+        };
+        Ok(Plan::Analyze { tables })
+    }
+
+    /// Builds a DELETE plan. With USING, the rows to delete are joined with
+    /// the USING tables, see build_write_source().
+    fn build_delete(
+        &self,
+        table: String,
+        using: Vec<ast::From>,
+        r#where: Option<ast::Expression>,
+        returning: Vec<(ast::Expression, Option<String>)>,
+    ) -> Result<Plan> {
         let table = self.catalog.must_get_table(&table)?;
-        let scope = Scope::from_table(&table)?;
-        let filter = r#where.map(|expr| Self::build_expression(expr, &scope)).transpose()?;
-        Ok(Plan::Delete {
-            table: table.name.clone(),
-            primary_key: table.primary_key,
-            source: Node::Scan { table, alias: None, filter },
-        })
+        let (source, scope) = self.build_write_source(table.clone(), using, r#where)?;
+        let returning = Self::build_returning(&scope, returning)?;
+        Ok(Plan::Delete { primary_key: table.primary_key.clone(), table, source, returning })
     }
 
-    /// Builds an INSERT plan.
+    /// Builds an INSERT plan. For INSERT ... SELECT, the number of query
+    /// columns and their datatypes (where known) are checked against the table
+    /// columns. VALUES rows are checked during execution.
     fn build_insert(
         &self,
         table: String,
         columns: Option<Vec<String>>,
-        values: Vec<Vec<ast::Expression>>,
+        source: ast::InsertSource,
+        on_conflict: Option<ast::OnConflict>,
+        returning: Vec<(ast::Expression, Option<String>)>,
     ) -> Result<Plan> {
         let table = self.catalog.must_get_table(&table)?;
+        let on_conflict = on_conflict.map(|c| Self::build_on_conflict(&table, c)).transpose()?;
+        let returning = Self::build_returning(&Scope::from_table(&table)?, returning)?;
         let mut column_map = None;
         if let Some(columns) = columns {
             let column_map = column_map.insert(HashMap::new());
@@ -114,14 +347,115 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 }
             }
         }
-        let scope = Scope::new();
-        let rows = values
-            .into_iter()
-            .map(|exprs| {
-                exprs.into_iter().map(|expr| Self::build_expression(expr, &scope)).collect()
-            })
-            .try_collect()?;
-        Ok(Plan::Insert { table, column_map, source: Node::Values { rows } })
+        let source = match source {
+            ast::InsertSource::Values(values) => {
+                let scope = Scope::new();
+                let rows = values
+                    .into_iter()
+                    .map(|exprs| {
+                        exprs.into_iter().map(|expr| Self::build_expression(expr, &scope)).collect()
+                    })
+                    .try_collect()?;
+                let source = Node::Values { rows };
+                return Ok(Plan::Insert { table, column_map, source, on_conflict, returning });
+            }
+            ast::InsertSource::Select(select) => {
+                let ast::Statement::Select {
+                    distinct,
+                    select,
+                    from,
+                    r#where,
+                    group_by,
+                    having,
+                    order_by,
+                    offset,
+                    limit,
+                } = *select
+                else {
+                    panic!("insert source must be SELECT"); // enforced by parser
+                };
+                let Plan::Select(source) = self.build_select(
+                    distinct, select, from, r#where, group_by, having, order_by, offset, limit,
+                )?
+                else {
+                    panic!("expected SELECT plan");
+                };
+                source
+            }
+        };
+
+        // Check that the query columns match the table columns.
+        match &column_map {
+            Some(column_map) if column_map.len() != source.columns() => {
+                return errinput!("column and value counts do not match");
+            }
+            None if source.columns() > table.columns.len() => {
+                return errinput!("too many values for table {}", table.name);
+            }
+            Some(_) | None => {}
+        }
+        for (cindex, column) in table.columns.iter().enumerate() {
+            let vindex = match &column_map {
+                Some(column_map) => column_map.get(&cindex).copied(),
+                None => Some(cindex).filter(|i| *i < source.columns()),
+            };
+            let ctype = column.datatype;
+            if let Some(vtype) = vindex.and_then(|i| source.column_datatype(i))
+                && vtype != ctype
+            {
+                return errinput!("invalid datatype {vtype} for {ctype} column {}", column.name);
+            }
+        }
+        Ok(Plan::Insert { table, column_map, source, on_conflict, returning })
+    }
+
+    /// Builds an INSERT ON CONFLICT clause. The conflict columns must match
+    /// the primary key or a unique index, in any order. If no columns are
+    /// given, conflicts with the primary key and all unique indexes are
+    /// handled. DO UPDATE expressions can refer to the existing row by the
+    /// table name, and the inserted row as excluded.
+    fn build_on_conflict(table: &Table, on_conflict: ast::OnConflict) -> Result<OnConflict> {
+        let unique = table.secondary_indexes().into_iter().filter(|index| index.unique);
+        let targets = match on_conflict.columns {
+            Some(columns) => {
+                let columns: Vec<usize> =
+                    columns.iter().map(|c| table.column_index(c)).try_collect()?;
+                let matches = |other: &[usize]| {
+                    columns.len() == other.len() && columns.iter().all(|c| other.contains(c))
+                };
+                if matches(&table.primary_key) {
+                    vec![ConflictTarget::PrimaryKey]
+                } else if let Some(index) = unique.clone().find(|index| matches(&index.columns)) {
+                    vec![ConflictTarget::Index(index)]
+                } else {
+                    return errinput!("no primary key or unique index matches ON CONFLICT columns");
+                }
+            }
+            None => std::iter::once(ConflictTarget::PrimaryKey)
+                .chain(unique.map(ConflictTarget::Index))
+                .collect(),
+        };
+
+        let action = match on_conflict.action {
+            ast::ConflictAction::Nothing => ConflictAction::Nothing,
+            ast::ConflictAction::Update { set, r#where } => {
+                let mut scope = Scope::from_table(table)?;
+                scope.add_table(table, Some("excluded"))?;
+                let filter =
+                    r#where.map(|expr| Self::build_expression(expr, &scope)).transpose()?;
+                let mut expressions = Vec::with_capacity(set.len());
+                for (column, expr) in set {
+                    let index = table.column_index(&column)?;
+                    let expr = match expr {
+                        Some(expr) => Self::build_expression(expr, &scope)?,
+                        None => Self::build_default(table, index)?,
+                    };
+                    expressions.push((index, expr));
+                }
+                ConflictAction::Update { expressions, filter }
+            }
+        };
+        Ok(OnConflict { targets, action })
     }
 
     /// Builds an UPDATE plan.
@@ -129,35 +463,114 @@ impl<'a, C: Catalog> Planner<'a, C> {
         &self,
         table: String,
         set: BTreeMap<String, Option<ast::Expression>>,
+        from: Vec<ast::From>,
         r#where: Option<ast::Expression>,
+        returning: Vec<(ast::Expression, Option<String>)>,
     ) -> Result<Plan> {
         let table = self.catalog.must_get_table(&table)?;
-        let scope = Scope::from_table(&table)?;
-        let filter = r#where.map(|expr| Self::build_expression(expr, &scope)).transpose()?;
+        let (source, scope) = self.build_write_source(table.clone(), from, r#where)?;
+        let returning = Self::build_returning(&scope, returning)?;
         let mut expressions = Vec::with_capacity(set.len());
         for (column, expr) in set {
-            let index = scope.lookup_column(None, &column)?;
+            // SET columns always refer to the updated table, not FROM tables.
+            let Some(index) = table.columns.iter().position(|c| c.name == column) else {
+                return errinput!("unknown column {column}");
+            };
             let expr = match expr {
                 Some(expr) => Self::build_expression(expr, &scope)?,
-                None => match &table.columns[index].default {
-                    Some(default) => Expression::Constant(default.clone()),
-                    None => return errinput!("column {column} has no default value"),
-                },
+                None => Self::build_default(&table, index)?,
             };
             expressions.push((index, expr));
         }
         Ok(Plan::Update {
-            table: table.clone(),
-            primary_key: table.primary_key,
-            source: Node::Scan { table, alias: None, filter },
+            primary_key: table.primary_key.clone(),
+            table,
+            source,
             expressions,
+            returning,
         })
     }
 
+    /// Builds a SET column = DEFAULT expression, drawing from the column's
+    /// sequence if any.
+    fn build_default(table: &Table, index: usize) -> Result<Expression> {
+        let column = &table.columns[index];
+        match (&column.sequence, &column.default) {
+            (Some(sequence), _) => Ok(Expression::NextValue(sequence.clone())),
+            (None, Some(default)) => Ok(Expression::Constant(default.clone())),
+            (None, None) => errinput!("column {} has no default value", column.name),
+        }
+    }
+
+    /// Builds the source node for an UPDATE or DELETE, which emits the table
+    /// rows to write, filtered by the WHERE clause. If additional FROM items
+    /// are given (i.e. UPDATE FROM or DELETE USING), the table is joined with
+    /// them. The table's columns come first in the joined rows, such that the
+    /// primary key column indexes are unchanged, followed by the columns of
+    /// the FROM items. A table row may be emitted multiple times if it matches
+    /// multiple joined rows. Returns the node and its scope.
+    fn build_write_source(
+        &self,
+        table: Table,
+        from: Vec<ast::From>,
+        r#where: Option<ast::Expression>,
+    ) -> Result<(Node, Scope)> {
+        let mut scope = Scope::from_table(&table)?;
+        let mut node = Node::Scan { table, alias: None, filter: None };
+        for from in from {
+            let right = self.build_from(from, &mut scope)?;
+            node = Node::NestedLoopJoin {
+                left: Box::new(node),
+                right: Box::new(right),
+                predicate: None,
+                outer: false,
+                full: false,
+            };
+        }
+        if let Some(r#where) = r#where {
+            node = self.build_where(node, r#where, &scope)?;
+        }
+        Ok((node, scope))
+    }
+
+    /// Builds a RETURNING clause for an INSERT, UPDATE, or DELETE. The
+    /// expressions are evaluated on the affected rows, using the given scope:
+    /// the table columns, followed by the columns of any UPDATE FROM or DELETE
+    /// USING items. Returns None if there are no RETURNING expressions.
+    fn build_returning(
+        scope: &Scope,
+        returning: Vec<(ast::Expression, Option<String>)>,
+    ) -> Result<Option<Returning>> {
+        if returning.is_empty() {
+            return Ok(None);
+        }
+        let (mut expressions, mut labels) = (Vec::new(), Vec::new());
+        for (expr, alias) in returning {
+            let indexes = match expr {
+                ast::Expression::All => (0..scope.columns.len()).collect(),
+                ast::Expression::QualifiedAll(table) => scope.lookup_table(&table)?,
+                expr => {
+                    let expr = Self::build_expression(expr, scope)?;
+                    labels.push(match (alias, &expr) {
+                        (Some(alias), _) => Label::Unqualified(alias),
+                        (None, Expression::Column(index)) => scope.columns[*index].clone(),
+                        (None, _) => Label::None,
+                    });
+                    expressions.push(expr);
+                    continue;
+                }
+            };
+            expressions.extend(indexes.iter().copied().map(Expression::Column));
+            labels.extend(indexes.iter().map(|&index| scope.columns[index].clone()));
+        }
+        Ok(Some(Returning { expressions, labels }))
+    }
+
     /// Builds a SELECT plan.
     #[allow(clippy::too_many_arguments)]
     fn build_select(
         &self,
+        distinct: bool,
         mut select: Vec<(ast::Expression, Option<String>)>,
         from: Vec<ast::From>,
         r#where: Option<ast::Expression>,
@@ -180,29 +593,35 @@ impl<'a, C: Catalog> Planner<'a, C> {
 
         // Expand out SELECT * to all FROM columns if there are multiple SELECT
         // expressions or a GROUP BY clause (to ensure all columns are in GROUP
-        // BY). For simplicity, expressions only supports scalar values, so we
-        // special-case the * tuple here.
-        if select.contains(&(ast::Expression::All, None)) {
-            if node.columns() == 0 {
-                return errinput!("SELECT * requires a FROM clause");
-            }
-            if select.len() > 1 || !group_by.is_empty() {
-                select = select
-                    .into_iter()
-                    .flat_map(|(expr, alias)| match expr {
-                        ast::Expression::All => Either::Left(
-                            (0..node.columns()).map(|i| (node.column_label(i).into(), None)),
-                        ),
-                        expr => Either::Right(std::iter::once((expr, alias))),
-                    })
-                    .collect();
+        // BY), and table.* to the table's columns. For simplicity, expressions
+        // only supports scalar values, so we special-case the * tuple here.
+        let all = select.contains(&(ast::Expression::All, None));
+        if all && node.columns() == 0 {
+            return errinput!("SELECT * requires a FROM clause");
+        }
+        if all && (select.len() > 1 || !group_by.is_empty())
+            || select.iter().any(|(expr, _)| matches!(expr, ast::Expression::QualifiedAll(_)))
+        {
+            let mut expanded = Vec::with_capacity(select.len());
+            for (expr, alias) in select {
+                let indexes = match expr {
+                    ast::Expression::All => {
+                        (0..node.columns()).filter(|i| !scope.hidden.contains(i)).collect()
+                    }
+                    ast::Expression::QualifiedAll(table) => scope.lookup_table(&table)?,
+                    expr => {
+                        expanded.push((expr, alias));
+                        continue;
+                    }
+                };
+                expanded.extend(indexes.into_iter().map(|i| (node.column_label(i).into(), None)));
             }
+            select = expanded;
         }
 
         // Build WHERE clause.
         if let Some(r#where) = r#where {
-            let predicate = Self::build_expression(r#where, &scope)?;
-            node = Node::Filter { source: Box::new(node), predicate };
+            node = self.build_where(node, r#where, &scope)?;
         }
 
         // Build aggregate functions and GROUP BY clause.
@@ -216,6 +635,16 @@ impl<'a, C: Catalog> Planner<'a, C> {
             // Prepare the post-projection scope.
             let mut child_scope = scope.project(&select);
 
+            // With DISTINCT, ORDER BY can't use hidden columns, since these
+            // would be considered when discarding duplicate rows.
+            if distinct {
+                let mut check_scope = scope.project(&select);
+                if !self.build_select_hidden(&None, &order_by, &scope, &mut check_scope).is_empty()
+                {
+                    return errinput!("ORDER BY expressions must be in SELECT list with DISTINCT");
+                }
+            }
+
             // Build the SELECT column expressions and aliases.
             let mut expressions = Vec::with_capacity(select.len());
             let mut aliases = Vec::with_capacity(select.len());
@@ -242,30 +671,47 @@ impl<'a, C: Catalog> Planner<'a, C> {
             node = Node::Filter { source: Box::new(node), predicate };
         }
 
+        // Remove any hidden HAVING columns before DISTINCT, since they would
+        // otherwise be considered when discarding duplicate rows.
+        if distinct && let Some(targets) = scope.remap_hidden() {
+            node = Node::Remap { source: Box::new(node), targets }
+        }
+
         // Build ORDER BY clause.
+        let sorted = !order_by.is_empty();
         if !order_by.is_empty() {
-            let key = order_by
+            let mut key: Vec<(Expression, Direction)> = order_by
                 .into_iter()
                 .map(|(expr, dir)| Ok((Self::build_expression(expr, &scope)?, dir.into())))
                 .collect::<Result<_>>()?;
+            // With DISTINCT, also sort by any remaining columns. This only
+            // affects the order of rows with equal sort keys (which is
+            // undefined anyway), but ensures duplicate rows are adjacent such
+            // that DISTINCT can discard them without buffering the rows.
+            if distinct {
+                for index in 0..node.columns() {
+                    if !key.iter().any(|(expr, _)| *expr == Expression::Column(index)) {
+                        key.push((Expression::Column(index), Direction::Ascending));
+                    }
+                }
+            }
             node = Node::Order { source: Box::new(node), key };
         }
 
+        // Build DISTINCT clause.
+        if distinct {
+            node = Node::Distinct { source: Box::new(node), sorted };
+        }
+
         // Build OFFSET clause.
         if let Some(offset) = offset {
-            let offset = match Self::build_constant_value(offset)? {
-                Value::Integer(offset) if offset >= 0 => offset as usize,
-                offset => return errinput!("invalid offset {offset}"),
-            };
+            let offset = Self::build_row_count(offset, "offset")?;
             node = Node::Offset { source: Box::new(node), offset }
         }
 
         // Build LIMIT clause.
         if let Some(limit) = limit {
-            let limit = match Self::build_constant_value(limit)? {
-                Value::Integer(limit) if limit >= 0 => limit as usize,
-                limit => return errinput!("invalid limit {limit}"),
-            };
+            let limit = Self::build_row_count(limit, "limit")?;
             node = Node::Limit { source: Box::new(node), limit }
         }
 
@@ -296,11 +742,167 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 right: Box::new(right),
                 predicate: None,
                 outer: false,
+                full: false,
             };
         }
         Ok(node)
     }
 
+    /// Builds a WHERE clause. Top-level [NOT] EXISTS and [NOT] IN subquery
+    /// conditions are built as semi joins or anti joins, above a Filter node
+    /// for the remaining conditions.
+    fn build_where(&self, mut node: Node, r#where: ast::Expression, scope: &Scope) -> Result<Node> {
+        // Split the top-level AND conditions, in order.
+        let (mut conditions, mut subqueries) = (Vec::new(), Vec::new());
+        let mut stack = vec![r#where];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                ast::Expression::Operator(ast::Operator::And(lhs, rhs)) => {
+                    stack.push(*rhs);
+                    stack.push(*lhs);
+                }
+                expr if Self::is_subquery(&expr) => subqueries.push(expr),
+                expr => conditions.push(expr),
+            }
+        }
+
+        if let Some(condition) = conditions
+            .into_iter()
+            .reduce(|lhs, rhs| ast::Operator::And(lhs.into(), rhs.into()).into())
+        {
+            let predicate = Self::build_expression(condition, scope)?;
+            node = Node::Filter { source: Box::new(node), predicate };
+        }
+        for subquery in subqueries {
+            node = self.build_subquery(node, subquery, scope)?;
+        }
+        Ok(node)
+    }
+
+    /// Returns true if the expression is a (possibly negated) EXISTS or IN
+    /// subquery.
+    fn is_subquery(expr: &ast::Expression) -> bool {
+        match expr {
+            ast::Expression::Operator(ast::Operator::Not(expr)) => Self::is_subquery(expr),
+            ast::Expression::Exists(_)
+            | ast::Expression::Operator(ast::Operator::InSubquery(_, _)) => true,
+            _ => false,
+        }
+    }
+
+    /// Builds a (possibly negated) EXISTS or IN subquery condition as a semi
+    /// join or anti join of the source node and the subquery, which only emits
+    /// source rows. For example:
+    ///
+    /// SELECT * FROM movies m WHERE NOT EXISTS (SELECT * FROM ratings r WHERE r.movie_id = m.id)
+    ///
+    /// The subquery's WHERE clause may reference source columns (i.e. a
+    /// correlated subquery) if the subquery only has FROM and WHERE clauses,
+    /// in which case the WHERE clause is used as the join predicate. Other
+    /// subqueries are built independently, with an IN subquery joined on its
+    /// single column.
+    ///
+    /// NOT IN is built as a null-aware anti join: x NOT IN (1, NULL) is NULL
+    /// rather than true, since the NULL could be x, so it never matches.
+    fn build_subquery(&self, left: Node, expr: ast::Expression, scope: &Scope) -> Result<Node> {
+        // Strip NOT operators, and use an anti join for an odd number of them.
+        let (mut expr, mut anti) = (expr, false);
+        while let ast::Expression::Operator(ast::Operator::Not(inner)) = expr {
+            (expr, anti) = (*inner, !anti);
+        }
+        let (lhs, select) = match expr {
+            ast::Expression::Exists(select) => (None, *select),
+            ast::Expression::Operator(ast::Operator::InSubquery(lhs, select)) => {
+                (Some(*lhs), *select)
+            }
+            expr => panic!("unexpected subquery {expr:?}"),
+        };
+        let ast::Statement::Select {
+            distinct,
+            select,
+            from,
+            r#where,
+            group_by,
+            having,
+            order_by,
+            offset,
+            limit,
+        } = select
+        else {
+            panic!("subquery must be SELECT"); // enforced by parser
+        };
+
+        // Build a correlated EXISTS subquery. Its SELECT expressions are
+        // irrelevant, unless they contain aggregates. Nested subqueries are
+        // built independently.
+        let is_nested = |expr: &ast::Expression| {
+            matches!(
+                expr,
+                ast::Expression::Exists(_)
+                    | ast::Expression::Operator(ast::Operator::InSubquery(..))
+            )
+        };
+        if lhs.is_none()
+            && r#where.as_ref().is_none_or(|expr| !expr.contains(&is_nested))
+            && group_by.is_empty()
+            && having.is_none()
+            && offset.is_none()
+            && limit.is_none()
+            && Self::collect_aggregates(&select, &None, &[]).is_empty()
+        {
+            let mut subquery_scope = Scope::new();
+            let right = match from.is_empty() {
+                true => Node::Values { rows: vec![vec![]] },
+                false => self.build_from_clause(from, &mut subquery_scope)?,
+            };
+            let predicate = match r#where {
+                Some(r#where) => {
+                    let scope = scope.correlate(subquery_scope)?;
+                    Some(Self::build_expression(r#where, &scope)?)
+                }
+                None => None,
+            };
+            return Ok(Node::NestedLoopSemiJoin {
+                left: Box::new(left),
+                right: Box::new(right),
+                predicate,
+                anti,
+                null_aware: false,
+            });
+        }
+
+        // Build an uncorrelated subquery.
+        let Plan::Select(right) = self.build_select(
+            distinct, select, from, r#where, group_by, having, order_by, offset, limit,
+        )?
+        else {
+            panic!("expected SELECT plan");
+        };
+        let Some(lhs) = lhs else {
+            return Ok(Node::NestedLoopSemiJoin {
+                left: Box::new(left),
+                right: Box::new(right),
+                predicate: None,
+                anti,
+                null_aware: false,
+            });
+        };
+        if right.columns() != 1 {
+            return errinput!("IN subquery must return 1 column, got {}", right.columns());
+        }
+        let predicate = Expression::Equal(
+            Self::build_expression(lhs, scope)?.into(),
+            Expression::Column(left.columns()).into(),
+        );
+        Ok(Node::NestedLoopSemiJoin {
+            left: Box::new(left),
+            right: Box::new(right),
+            predicate: Some(predicate),
+            anti,
+            null_aware: anti,
+        })
+    }
+
     /// Builds FROM items, which can either be a single table or a chained join
     /// of multiple tables, e.g. "SELECT * FROM a LEFT JOIN b ON b.a_id = a.id".
     fn build_from(&self, from: ast::From, parent_scope: &mut Scope) -> Result<Node> {
@@ -317,29 +919,81 @@ impl<'a, C: Catalog> Planner<'a, C> {
             }
 
             // A two-way join. The left or right nodes may be chained joins.
-            ast::From::Join { mut left, mut right, r#type, predicate } => {
+            ast::From::Join { mut left, mut right, r#type, condition } => {
                 // Right joins are built as a left join then column swap.
                 if r#type == ast::JoinType::Right {
                     (left, right) = (right, left)
                 }
 
-                // Build the left and right nodes.
-                let left = Box::new(self.build_from(*left, &mut scope)?);
-                let right = Box::new(self.build_from(*right, &mut scope)?);
+                // Build the left and right nodes, in separate scopes such that
+                // we can look up USING columns in each of them.
+                let (mut left_scope, mut right_scope) = (Scope::new(), Scope::new());
+                let left = Box::new(self.build_from(*left, &mut left_scope)?);
+                let right = Box::new(self.build_from(*right, &mut right_scope)?);
                 let (left_size, right_size) = (left.columns(), right.columns());
 
-                // Build the join node.
-                let predicate = predicate.map(|e| Self::build_expression(e, &scope)).transpose()?;
+                // Look up the USING columns, or the common columns for NATURAL
+                // joins, as (name, left index, right index) in the joined row.
+                let names = match &condition {
+                    Some(ast::JoinCondition::Using(names)) => names.clone(),
+                    Some(ast::JoinCondition::Natural) => left_scope.common_columns(&right_scope),
+                    Some(ast::JoinCondition::On(_)) | None => Vec::new(),
+                };
+                let mut using: Vec<(String, usize, usize)> = Vec::new();
+                for name in names {
+                    if using.iter().any(|(n, _, _)| *n == name) {
+                        return errinput!("duplicate USING column {name}");
+                    }
+                    let left_index = left_scope.lookup_column(None, &name)?;
+                    let right_index = left_size + right_scope.lookup_column(None, &name)?;
+                    using.push((name, left_index, right_index));
+                }
+
+                scope.merge(left_scope)?;
+                scope.merge(right_scope)?;
+
+                // Build the join node. USING and NATURAL joins join on equality
+                // of the columns. A NATURAL join without common columns is a
+                // cross join.
+                let predicate = match condition {
+                    Some(ast::JoinCondition::On(expr)) => {
+                        Some(Self::build_expression(expr, &scope)?)
+                    }
+                    Some(ast::JoinCondition::Using(_) | ast::JoinCondition::Natural) => {
+                        Expression::and_vec(
+                            using
+                                .iter()
+                                .map(|(_, l, r)| {
+                                    Expression::Equal(
+                                        Expression::Column(*l).into(),
+                                        Expression::Column(*r).into(),
+                                    )
+                                })
+                                .collect(),
+                        )
+                    }
+                    None => None,
+                };
                 let outer = r#type.is_outer();
-                let mut node = Node::NestedLoopJoin { left, right, predicate, outer };
+                let full = r#type == ast::JoinType::Full;
+                let mut node = Node::NestedLoopJoin { left, right, predicate, outer, full };
 
                 // For right joins, swap the columns.
                 if r#type == ast::JoinType::Right {
                     let size = left_size + right_size;
                     let targets = (0..size).map(|i| Some((i + right_size) % size)).collect_vec();
                     scope = scope.remap(&targets);
+                    for (_, left_index, right_index) in &mut using {
+                        *left_index = targets[*left_index].expect("dropped column");
+                        *right_index = targets[*right_index].expect("dropped column");
+                    }
                     node = Node::Remap { source: Box::new(node), targets }
                 }
+
+                // Merge each pair of USING columns into a single column.
+                if !using.is_empty() {
+                    node = Self::build_join_using(node, using, full, &mut scope);
+                }
                 node
             }
         };
@@ -348,6 +1002,60 @@ impl<'a, C: Catalog> Planner<'a, C> {
         Ok(node)
     }
 
+    /// Merges each pair of USING or NATURAL join columns, given as (name, left
+    /// index, right index), into a single column. The left column is from the
+    /// preserved side of an outer join (i.e. the right table of a RIGHT JOIN).
+    /// Unqualified names refer to the merged column, but the original columns
+    /// can still be referenced by qualified names as hidden columns.
+    ///
+    /// The merged column is simply the left column, except for full joins
+    /// where either column may be NULL. These use a projection that emits the
+    /// first non-NULL column value in place of the left column.
+    fn build_join_using(
+        node: Node,
+        using: Vec<(String, usize, usize)>,
+        full: bool,
+        scope: &mut Scope,
+    ) -> Node {
+        if !full {
+            for (_, _, right) in using {
+                scope.hidden.insert(right);
+                scope.remove_unqualified(right);
+            }
+            return node;
+        }
+
+        let mut child = scope.spawn();
+        let mut expressions = Vec::with_capacity(node.columns() + using.len());
+        let mut aliases = Vec::with_capacity(node.columns() + using.len());
+        for index in 0..node.columns() {
+            if let Some((name, left, right)) = using.iter().find(|(_, left, _)| *left == index) {
+                let label = Label::Unqualified(name.clone());
+                child.add_column(label.clone());
+                let (left, right) = (Expression::Column(*left), Expression::Column(*right));
+                expressions.push(Expression::Coalesce(vec![left, right]));
+                aliases.push(label);
+                continue;
+            }
+            let child_index = child.add_passthrough(scope, index, false);
+            if using.iter().any(|(_, _, right)| *right == index) {
+                child.hidden.insert(child_index);
+                child.remove_unqualified(child_index);
+            }
+            expressions.push(Expression::Column(index));
+            aliases.push(Label::None);
+        }
+        for (_, left, _) in using {
+            let child_index = child.add_passthrough(scope, left, true);
+            child.remove_unqualified(child_index);
+            expressions.push(Expression::Column(left));
+            aliases.push(Label::None);
+        }
+
+        *scope = child;
+        Node::Projection { source: Box::new(node), expressions, aliases }
+    }
+
     /// Builds an aggregate node, which computes aggregates for a set of GROUP
     /// BY buckets. The aggregate functions have been collected from the SELECT,
     /// HAVING, and ORDER BY clauses.
@@ -389,7 +1097,7 @@ impl<'a, C: Catalog> Planner<'a, C> {
 
     /// Builds an aggregate function from an AST expression.
     fn build_aggregate_function(expr: ast::Expression, scope: &Scope) -> Result<Aggregate> {
-        let ast::Expression::Function(name, mut args) = expr else {
+        let ast::Expression::Function(name, mut args, distinct) = expr else {
             panic!("aggregate expression must be function");
         };
         if args.len() != 1 {
@@ -400,22 +1108,25 @@ impl<'a, C: Catalog> Planner<'a, C> {
         }
         // Special-case COUNT(*) since expressions don't support tuples.
         let expr = match (name.as_str(), args.remove(0)) {
+            ("count", ast::Expression::All) if distinct => {
+                return errinput!("DISTINCT can't be used with COUNT(*)");
+            }
             ("count", ast::Expression::All) => Expression::Constant(Value::Boolean(true)),
             (_, arg) => Self::build_expression(arg, scope)?,
         };
         Ok(match name.as_str() {
-            "avg" => Aggregate::Average(expr),
-            "count" => Aggregate::Count(expr),
-            "min" => Aggregate::Min(expr),
-            "max" => Aggregate::Max(expr),
-            "sum" => Aggregate::Sum(expr),
+            "avg" => Aggregate::Average { expr, distinct },
+            "count" => Aggregate::Count { expr, distinct },
+            "min" => Aggregate::Min { expr, distinct },
+            "max" => Aggregate::Max { expr, distinct },
+            "sum" => Aggregate::Sum { expr, distinct },
             name => return errinput!("unknown aggregate function {name}"),
         })
     }
 
     /// Checks whether a given AST expression is an aggregate function.
     fn is_aggregate_function(expr: &ast::Expression) -> bool {
-        if let ast::Expression::Function(name, _) = expr {
+        if let ast::Expression::Function(name, _, _) = expr {
             return ["avg", "count", "max", "min", "sum"].contains(&name.as_str());
         }
         false
@@ -505,12 +1216,18 @@ impl<'a, C: Catalog> Planner<'a, C> {
         let build = |expr: Box<ast::Expression>| -> Result<Box<Expression>> {
             Ok(Box::new(Self::build_expression(*expr, scope)?))
         };
+        // Helper for building a vector of expressions.
+        let build_vec = |exprs: Vec<ast::Expression>| -> Result<Vec<Expression>> {
+            exprs.into_iter().map(|expr| Self::build_expression(expr, scope)).collect()
+        };
 
         Ok(match expr {
             // For simplicity, expression evaluation only supports scalar
             // values, not compound types like tuples. Support for * is
             // therefore special-cased in SELECT and COUNT(*).
-            ast::Expression::All => return errinput!("unsupported use of *"),
+            ast::Expression::All | ast::Expression::QualifiedAll(_) => {
+                return errinput!("unsupported use of *");
+            }
             ast::Expression::Literal(l) => Constant(match l {
                 ast::Literal::Null => Value::Null,
                 ast::Literal::Boolean(b) => Value::Boolean(b),
@@ -518,19 +1235,76 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 ast::Literal::Float(f) => Value::Float(f),
                 ast::Literal::String(s) => Value::String(s),
             }),
+            ast::Expression::Parameter(n) => Parameter(n),
             ast::Expression::Column(table, name) => {
                 Column(scope.lookup_column(table.as_deref(), &name)?)
             }
-            ast::Expression::Function(name, mut args) => match (name.as_str(), args.len()) {
+            ast::Expression::Function(name, _, true) => {
+                return errinput!("DISTINCT is only valid for aggregate functions, not {name}");
+            }
+            ast::Expression::Function(name, mut args, false) => match (name.as_str(), args.len()) {
                 // NB: aggregate functions are processed above.
                 ("sqrt", 1) => SquareRoot(build(Box::new(args.remove(0)))?),
-                (name, n) => return errinput!("unknown function {name} with {n} arguments"),
+                // Sequence names must be string literals, for simplicity.
+                ("nextval" | "currval", 1) => {
+                    let ast::Expression::Literal(ast::Literal::String(sequence)) = args.remove(0)
+                    else {
+                        return errinput!("{name} argument must be a sequence name string");
+                    };
+                    match name.as_str() {
+                        "nextval" => NextValue(sequence),
+                        _ => CurrentValue(sequence),
+                    }
+                }
+                ("coalesce", 1..) => Coalesce(build_vec(args)?),
+                ("greatest", 1..) => Greatest(build_vec(args)?),
+                ("least", 1..) => Least(build_vec(args)?),
+                ("nullif", 2) => {
+                    let rhs = build(Box::new(args.remove(1)))?;
+                    NullIf(build(Box::new(args.remove(0)))?, rhs)
+                }
+                (name, n) => {
+                    let Some(function) = types::Function::lookup(name, n) else {
+                        return errinput!("unknown function {name} with {n} arguments");
+                    };
+                    let args = build_vec(args)?;
+                    function.check(&args)?;
+                    Function(function, args)
+                }
             },
+            // A CASE with an operand is converted to equality comparisons
+            // against each WHEN value. A missing ELSE yields NULL.
+            ast::Expression::Case { operand, when, r#else } => {
+                let when = when
+                    .into_iter()
+                    .map(|(condition, result)| {
+                        let condition = match &operand {
+                            Some(operand) => {
+                                Equal(build(operand.clone())?, build(Box::new(condition))?)
+                            }
+                            None => Self::build_expression(condition, scope)?,
+                        };
+                        Ok((condition, Self::build_expression(result, scope)?))
+                    })
+                    .collect::<Result<_>>()?;
+                let r#else = match r#else {
+                    Some(r#else) => build(r#else)?,
+                    None => Box::new(Constant(Value::Null)),
+                };
+                Case(when, r#else)
+            }
+            ast::Expression::Cast(expr, datatype) => Cast(build(expr)?, datatype),
+            ast::Expression::Exists(_) => {
+                return errinput!("subqueries are only supported as WHERE conditions");
+            }
             ast::Expression::Operator(op) => match op {
                 ast::Operator::And(lhs, rhs) => And(build(lhs)?, build(rhs)?),
                 ast::Operator::Not(expr) => Not(build(expr)?),
                 ast::Operator::Or(lhs, rhs) => Or(build(lhs)?, build(rhs)?),
 
+                ast::Operator::Between(expr, low, high) => {
+                    Between(build(expr)?, build(low)?, build(high)?)
+                }
                 ast::Operator::Equal(lhs, rhs) => Equal(build(lhs)?, build(rhs)?),
                 ast::Operator::GreaterThan(lhs, rhs) => GreaterThan(build(lhs)?, build(rhs)?),
                 ast::Operator::GreaterThanOrEqual(lhs, rhs) => Or(
@@ -546,12 +1320,20 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     };
                     Is(expr, value)
                 }
+                ast::Operator::In(expr, list) => In(build(expr)?, build_vec(list)?),
+                ast::Operator::InSubquery(_, _) => {
+                    return errinput!("subqueries are only supported as WHERE conditions");
+                }
                 ast::Operator::LessThan(lhs, rhs) => LessThan(build(lhs)?, build(rhs)?),
                 ast::Operator::LessThanOrEqual(lhs, rhs) => Or(
                     LessThan(build(lhs.clone())?, build(rhs.clone())?).into(),
                     Equal(build(lhs)?, build(rhs)?).into(),
                 ),
                 ast::Operator::Like(lhs, rhs) => Like(build(lhs)?, build(rhs)?),
+                ast::Operator::RegexMatch(lhs, rhs, case_insensitive) => {
+                    RegexMatch(build(lhs)?, build(rhs)?, case_insensitive)
+                }
+                ast::Operator::SimilarTo(lhs, rhs) => SimilarTo(build(lhs)?, build(rhs)?),
                 ast::Operator::NotEqual(lhs, rhs) => Not(Equal(build(lhs)?, build(rhs)?).into()),
 
                 ast::Operator::Add(lhs, rhs) => Add(build(lhs)?, build(rhs)?),
@@ -569,9 +1351,22 @@ impl<'a, C: Catalog> Planner<'a, C> {
 
     /// Builds a constant value from an AST expression by evaluating it. The
     /// expression can't contain column references or aggregate functions.
-    fn build_constant_value(expr: ast::Expression) -> Result<Value> {
+    pub fn build_constant_value(expr: ast::Expression) -> Result<Value> {
         Self::build_expression(expr, &Scope::new())?.evaluate(None)
     }
+
+    /// Builds a LIMIT or OFFSET row count, which must be a non-negative
+    /// constant integer. It's evaluated when planning, so it can't contain
+    /// parameters, since plans are built before parameters are bound.
+    fn build_row_count(expr: ast::Expression, clause: &str) -> Result<usize> {
+        if expr.contains(&|expr| matches!(expr, ast::Expression::Parameter(_))) {
+            return errinput!("{clause} can't contain parameters");
+        }
+        match Self::build_constant_value(expr)? {
+            Value::Integer(count) if count >= 0 => Ok(count as usize),
+            count => errinput!("invalid {clause} {count}"),
+        }
+    }
 }
 
 /// A scope maps column/table names to input column indexes, for lookups during
@@ -585,7 +1380,7 @@ impl<'a, C: Catalog> Planner<'a, C> {
 /// row may come directly from a single table, or it may be the result of a long
 /// chain of joins and projections. The scope keeps track of which columns are
 /// currently visible and what names they have.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Scope {
     /// The currently visible columns. If empty, only constant expressions can
     /// be used (no column references).
@@ -657,6 +1452,18 @@ impl Scope {
         index
     }
 
+    /// Looks up the column indexes of a table by name, i.e. for table.*.
+    fn lookup_table(&self, table: &str) -> Result<Vec<usize>> {
+        let indexes = (0..self.columns.len())
+            .filter(|i| !self.hidden.contains(i))
+            .filter(|i| matches!(&self.columns[*i], Label::Qualified(t, _) if t == table))
+            .collect_vec();
+        if indexes.is_empty() {
+            return errinput!("unknown table {table}");
+        }
+        Ok(indexes)
+    }
+
     /// Looks up a column index by name, if possible.
     fn lookup_column(&self, table: Option<&str>, name: &str) -> Result<usize> {
         let fmtname = || table.map(|table| format!("{table}.{name}")).unwrap_or(name.to_string());
@@ -713,10 +1520,41 @@ impl Scope {
         self.aggregates.get(expr).copied()
     }
 
+    /// Returns true if the column can be referenced by an unqualified name.
+    fn has_unqualified(&self, index: usize) -> bool {
+        self.unqualified.values().any(|indexes| indexes.contains(&index))
+    }
+
+    /// Removes a column from unqualified name lookups, such that it can only
+    /// be referenced by its qualified name. Used for merged USING join columns.
+    fn remove_unqualified(&mut self, index: usize) {
+        self.unqualified.iter_mut().for_each(|(_, vec)| vec.retain(|i| *i != index));
+        self.unqualified.retain(|_, vec| !vec.is_empty());
+    }
+
+    /// Returns the unqualified column names that can be referenced in both
+    /// scopes, in column order. Used for NATURAL joins.
+    fn common_columns(&self, other: &Scope) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter_map(|label| match label {
+                Label::Qualified(_, name) | Label::Unqualified(name) => Some(name),
+                Label::None => None,
+            })
+            .filter(|name| self.unqualified.contains_key(*name))
+            .filter(|name| other.unqualified.contains_key(*name))
+            .unique()
+            .cloned()
+            .collect()
+    }
+
     /// Adds a column that passes through a column from the parent scope,
     /// retaining its properties. If hide is true, the column is hidden.
     fn add_passthrough(&mut self, parent: &Scope, parent_index: usize, hide: bool) -> usize {
         let index = self.add_column(parent.columns[parent_index].clone());
+        if !parent.has_unqualified(parent_index) {
+            self.remove_unqualified(index);
+        }
         for (expr, i) in &parent.aggregates {
             if *i == parent_index {
                 self.aggregates.entry(expr.clone()).or_insert(index);
@@ -730,15 +1568,18 @@ impl Scope {
 
     /// Merges two scopes, by appending the given scope to self.
     fn merge(&mut self, scope: Scope) -> Result<()> {
-        for table in scope.tables {
-            if self.tables.contains(&table) {
+        for table in &scope.tables {
+            if self.tables.contains(table) {
                 return errinput!("duplicate table name {table}");
             }
-            self.tables.insert(table);
+            self.tables.insert(table.clone());
         }
         let offset = self.columns.len();
-        for label in scope.columns {
-            self.add_column(label);
+        for (index, label) in scope.columns.iter().enumerate() {
+            let merged_index = self.add_column(label.clone());
+            if !scope.has_unqualified(index) {
+                self.remove_unqualified(merged_index);
+            }
         }
         for (expr, index) in scope.aggregates {
             self.aggregates.entry(expr).or_insert(index + offset);
@@ -747,6 +1588,23 @@ impl Scope {
         Ok(())
     }
 
+    /// Creates a scope for a correlated subquery, by appending the subquery's
+    /// scope to a copy of self. Subquery tables and columns shadow outer ones
+    /// with the same name.
+    fn correlate(&self, subquery: Scope) -> Result<Self> {
+        let mut scope = self.clone();
+        for table in &subquery.tables {
+            if scope.tables.remove(table) {
+                scope.qualified.retain(|(t, _), _| t != table);
+            }
+        }
+        for name in subquery.unqualified.keys() {
+            scope.unqualified.remove(name);
+        }
+        scope.merge(subquery)?;
+        Ok(scope)
+    }
+
     /// Projects the scope via the given expressions and aliases, creating a new
     /// child scope with one column per expression. These may be a simple column
     /// reference (e.g. "SELECT a, b FROM table"), which passes through the
@@ -1,9 +1,14 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::ops::Bound;
+
+use itertools::Itertools as _;
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use super::{Label, Row, Value};
+use super::{DataType, Function, Label, Pattern, Row, Value};
 use crate::errinput;
 use crate::error::Result;
 use crate::sql::planner::Node;
@@ -21,6 +26,16 @@ pub enum Expression {
     Constant(Value),
     /// A column reference. Looks up the value in a row during evaluation.
     Column(usize),
+    /// A parameter placeholder, numbered from 1. Replaced by a constant value
+    /// when binding a prepared statement's parameters, see Plan::bind().
+    Parameter(usize),
+    /// nextval('name'): draws the next value from a sequence. Sequence calls
+    /// are replaced by constants during execution, since they need
+    /// transaction access, see Executor::evaluate_sequences().
+    NextValue(String),
+    /// currval('name'): returns the value last drawn from a sequence via
+    /// nextval() in the current session.
+    CurrentValue(String),
 
     /// a AND b: logical AND of two booleans.
     And(Box<Expression>, Box<Expression>),
@@ -37,6 +52,10 @@ pub enum Expression {
     LessThan(Box<Expression>, Box<Expression>),
     /// a IS NULL or a IS NAN: checks for the given value.
     Is(Box<Expression>, Value),
+    /// a BETWEEN b AND c: checks if a >= b and a <= c.
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
+    /// a IN (b, c, ...): checks if a equals any of the values.
+    In(Box<Expression>, Vec<Expression>),
 
     /// a + b: adds two numbers.
     Add(Box<Expression>, Box<Expression>),
@@ -61,6 +80,32 @@ pub enum Expression {
 
     // a LIKE b: checks if a string matches a pattern.
     Like(Box<Expression>, Box<Expression>),
+    /// a ~ b: checks if a string matches a regular expression. If the bool is
+    /// true (a ~* b), the match is case-insensitive.
+    RegexMatch(Box<Expression>, Box<Expression>, bool),
+    /// a SIMILAR TO b: checks if a string matches a SQL regular expression.
+    SimilarTo(Box<Expression>, Box<Expression>),
+    /// A constant pattern string, precompiled by ConstantFolding for use as the
+    /// pattern of RegexMatch, SimilarTo, or a regexp function. Evaluates to the
+    /// pattern string.
+    Pattern(Pattern),
+
+    /// CASE WHEN a THEN b [...] ELSE c END: returns the result of the first
+    /// condition that evaluates to true, or the ELSE result (NULL if omitted).
+    Case(Vec<(Expression, Expression)>, Box<Expression>),
+    /// CAST(a AS type): converts a value to the given datatype.
+    Cast(Box<Expression>, DataType),
+    /// coalesce(a, b, ...): returns the first non-NULL value.
+    Coalesce(Vec<Expression>),
+    /// greatest(a, b, ...): returns the largest non-NULL value.
+    Greatest(Vec<Expression>),
+    /// least(a, b, ...): returns the smallest non-NULL value.
+    Least(Vec<Expression>),
+    /// nullif(a, b): returns NULL if a = b, otherwise a.
+    NullIf(Box<Expression>, Box<Expression>),
+
+    /// A built-in scalar function call, e.g. length(a).
+    Function(Function, Vec<Expression>),
 }
 
 impl Expression {
@@ -83,6 +128,17 @@ impl Expression {
             // only constant expressions are evaluated without a row.
             Self::Column(index) => row.and_then(|r| r.get(*index)).cloned().expect("invalid index"),
 
+            // Parameters must be bound before evaluation.
+            Self::Parameter(n) => return errinput!("unbound parameter ${n}"),
+
+            // Sequence calls must be replaced by the executor, which only does
+            // so in certain places.
+            Self::NextValue(_) | Self::CurrentValue(_) => {
+                return errinput!(
+                    "sequence functions are only supported in SELECT, VALUES, and SET"
+                );
+            }
+
             // Logical AND. Inputs must be boolean or NULL. NULLs generally
             // yield NULL, except the special case NULL AND false == false.
             Self::And(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
@@ -157,6 +213,40 @@ impl Expression {
             },
             Self::Is(_, v) => panic!("invalid IS value {v}"), // enforced by parser
 
+            // BETWEEN is equivalent to a >= b AND a <= c, and IN is equivalent
+            // to a = b OR a = c OR ..., including their NULL handling. The
+            // operand is only evaluated once.
+            Self::Between(expr, low, high) => {
+                let value = Self::Constant(expr.evaluate(row)?);
+                let (low, high) =
+                    (Self::Constant(low.evaluate(row)?), Self::Constant(high.evaluate(row)?));
+                let cmp = |op: fn(Box<Self>, Box<Self>) -> Self, lhs: &Self, rhs: &Self| {
+                    Box::new(op(Box::new(lhs.clone()), Box::new(rhs.clone())))
+                };
+                Self::And(
+                    Box::new(Self::Or(
+                        cmp(Self::GreaterThan, &value, &low),
+                        cmp(Self::Equal, &value, &low),
+                    )),
+                    Box::new(Self::Or(
+                        cmp(Self::LessThan, &value, &high),
+                        cmp(Self::Equal, &value, &high),
+                    )),
+                )
+                .evaluate(None)?
+            }
+            Self::In(expr, list) => {
+                let value = Self::Constant(expr.evaluate(row)?);
+                let mut result = Boolean(false);
+                for item in list {
+                    let item = Self::Constant(item.evaluate(row)?);
+                    let equal = Self::Equal(Box::new(value.clone()), Box::new(item));
+                    result = Self::Or(Box::new(Self::Constant(result)), Box::new(equal))
+                        .evaluate(None)?;
+                }
+                result
+            }
+
             // Mathematical operations. Inputs must be numbers, but integers and
             // floats are interchangeable (float when mixed). NULLs yield NULL.
             // Errors on integer overflow, but floats yield infinity or NaN.
@@ -204,9 +294,184 @@ impl Expression {
                 (String(_), Null) | (Null, String(_)) | (Null, Null) => Null,
                 (lhs, rhs) => return errinput!("can't LIKE {lhs} and {rhs}"),
             },
+
+            // Regular expression matching. Inputs must be strings. NULLs yield
+            // NULL. Constant patterns are precompiled by ConstantFolding,
+            // otherwise they're compiled for every row.
+            Self::RegexMatch(lhs, rhs, case_insensitive) => {
+                let pattern =
+                    Self::evaluate_pattern(rhs, row, |p| Pattern::regex(p, *case_insensitive))?;
+                match (lhs.evaluate(row)?, pattern) {
+                    (String(lhs), Some(pattern)) => Boolean(pattern.compiled().is_match(&lhs)),
+                    (String(_) | Null, None) | (Null, Some(_)) => Null,
+                    (lhs, _) => return errinput!("can't match {lhs} against a regular expression"),
+                }
+            }
+            Self::SimilarTo(lhs, rhs) => {
+                let pattern = Self::evaluate_pattern(rhs, row, Pattern::similar_to)?;
+                match (lhs.evaluate(row)?, pattern) {
+                    (String(lhs), Some(pattern)) => Boolean(pattern.compiled().is_match(&lhs)),
+                    (String(_) | Null, None) | (Null, Some(_)) => Null,
+                    (lhs, _) => return errinput!("can't match {lhs} against a SIMILAR TO pattern"),
+                }
+            }
+            Self::Pattern(pattern) => String(pattern.pattern().to_string()),
+
+            // Conditional expressions. CASE conditions must be boolean or
+            // NULL, where NULL is considered false.
+            Self::Case(when, r#else) => {
+                for (condition, result) in when {
+                    match condition.evaluate(row)? {
+                        Boolean(true) => return result.evaluate(row),
+                        Boolean(false) | Null => {}
+                        value => {
+                            return errinput!("CASE condition returned {value}, expected boolean");
+                        }
+                    }
+                }
+                r#else.evaluate(row)?
+            }
+            Self::Cast(expr, datatype) => expr.evaluate(row)?.cast(*datatype)?,
+            Self::Coalesce(exprs) => {
+                for expr in exprs {
+                    match expr.evaluate(row)? {
+                        Null => {}
+                        value => return Ok(value),
+                    }
+                }
+                Null
+            }
+            Self::Function(function, args) => {
+                // regexp functions can use a precompiled pattern argument.
+                let pattern = match args.get(1) {
+                    Some(Self::Pattern(pattern)) => Some(pattern),
+                    _ => None,
+                };
+                let args = args.iter().map(|arg| arg.evaluate(row)).collect::<Result<_>>()?;
+                function.evaluate(args, pattern)?
+            }
+            Self::Greatest(exprs) => Self::evaluate_extreme(exprs, row, Ordering::Greater)?,
+            Self::Least(exprs) => Self::evaluate_extreme(exprs, row, Ordering::Less)?,
+            Self::NullIf(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(row)?, rhs.evaluate(row)?);
+                match Self::Equal(lhs.clone().into(), rhs.into()).evaluate(None)? {
+                    Boolean(true) => Null,
+                    _ => lhs,
+                }
+            }
         })
     }
 
+    /// Evaluates GREATEST or LEAST, returning the value with the given ordering
+    /// relative to all other values. NULLs are ignored, and only yield NULL if
+    /// all values are NULL. Values must have the same type, except integers and
+    /// floats which can be mixed. The result is then a float, like arithmetic.
+    fn evaluate_extreme(
+        exprs: &[Expression],
+        row: Option<&Row>,
+        ordering: Ordering,
+    ) -> Result<Value> {
+        use Value::*;
+
+        let mut result = Null;
+        let mut float = false;
+        for expr in exprs {
+            let value = expr.evaluate(row)?;
+            float |= matches!(value, Float(_));
+            match (&result, &value) {
+                (_, Null) => {}
+                (Null, _) => result = value,
+                (Boolean(_), Boolean(_))
+                | (Integer(_) | Float(_), Integer(_) | Float(_))
+                | (String(_), String(_)) => {
+                    if value.cmp(&result) == ordering {
+                        result = value
+                    }
+                }
+                (lhs, rhs) => return errinput!("can't compare {lhs} and {rhs}"),
+            }
+        }
+        match result {
+            Integer(i) if float => Ok(Float(i as f64)),
+            result => Ok(result),
+        }
+    }
+
+    /// Evaluates a pattern operand, returning a compiled pattern or None if the
+    /// pattern is NULL. Uses the precompiled pattern if any, otherwise compiles
+    /// the pattern string with the given function.
+    fn evaluate_pattern<'a>(
+        expr: &'a Expression,
+        row: Option<&Row>,
+        compile: impl FnOnce(&str) -> Result<Pattern>,
+    ) -> Result<Option<Cow<'a, Pattern>>> {
+        if let Self::Pattern(pattern) = expr {
+            return Ok(Some(Cow::Borrowed(pattern)));
+        }
+        match expr.evaluate(row)? {
+            Value::String(pattern) => Ok(Some(Cow::Owned(compile(&pattern)?))),
+            Value::Null => Ok(None),
+            value => errinput!("invalid pattern {value}, expected string"),
+        }
+    }
+
+    /// Returns the expression's result datatype, if it can be determined
+    /// without evaluating it, e.g. for constants, casts, and comparisons. Since
+    /// the planner doesn't track column types, this returns None for column
+    /// references and expressions involving them (except where the result type
+    /// is fixed regardless of the inputs). Used for plan-time type checking.
+    pub fn datatype(&self) -> Option<DataType> {
+        self.datatype_with(&|_| None)
+    }
+
+    /// Like datatype(), but uses the given closure to look up the datatypes
+    /// of column references, e.g. for table schema expressions.
+    pub fn datatype_with(&self, column: &impl Fn(usize) -> Option<DataType>) -> Option<DataType> {
+        use DataType::{Float, Integer};
+
+        // Arithmetic on integers yields integers, otherwise floats.
+        let numeric = |lhs: &Self, rhs: &Self| {
+            let (lhs, rhs) = (lhs.datatype_with(column)?, rhs.datatype_with(column)?);
+            match (lhs, rhs) {
+                (Integer, Integer) => Some(Integer),
+                (Integer | Float, Integer | Float) => Some(Float),
+                _ => None,
+            }
+        };
+        match self {
+            Self::Column(index) => column(*index),
+            Self::Constant(value) => value.datatype(),
+            Self::Cast(_, datatype) => Some(*datatype),
+            Self::Function(function, args) => {
+                function.datatype(&args.iter().map(|arg| arg.datatype_with(column)).collect_vec())
+            }
+            // Integer division and exponentiation can return floats
+            // depending on the values, so only these are inferred.
+            Self::Add(lhs, rhs)
+            | Self::Multiply(lhs, rhs)
+            | Self::Remainder(lhs, rhs)
+            | Self::Subtract(lhs, rhs) => numeric(lhs, rhs),
+            Self::Identity(expr) | Self::Negate(expr) => {
+                expr.datatype_with(column).filter(|dt| matches!(dt, Integer | Float))
+            }
+            Self::And(_, _)
+            | Self::Or(_, _)
+            | Self::Not(_)
+            | Self::Equal(_, _)
+            | Self::GreaterThan(_, _)
+            | Self::LessThan(_, _)
+            | Self::Is(_, _)
+            | Self::Between(_, _, _)
+            | Self::In(_, _)
+            | Self::Like(_, _) => Some(DataType::Boolean),
+            Self::SquareRoot(_) => Some(DataType::Float),
+            Self::RegexMatch(_, _, _) | Self::SimilarTo(_, _) => Some(DataType::Boolean),
+            Self::Pattern(_) => Some(DataType::String),
+            Self::NextValue(_) | Self::CurrentValue(_) => Some(DataType::Integer),
+            _ => None,
+        }
+    }
+
     /// Recursively walks the expression tree depth-first, calling the given
     /// closure until it returns false. Returns true otherwise.
     pub fn walk(&self, visitor: &mut impl FnMut(&Expression) -> bool) -> bool {
@@ -227,14 +492,40 @@ impl Expression {
             | Self::Remainder(lhs, rhs)
             | Self::Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
 
-            Self::Factorial(expr)
+            Self::Cast(expr, _)
+            | Self::Factorial(expr)
             | Self::Identity(expr)
             | Self::Is(expr, _)
             | Self::Negate(expr)
             | Self::Not(expr)
             | Self::SquareRoot(expr) => expr.walk(visitor),
 
-            Self::Constant(_) | Self::Column(_) => true,
+            Self::Case(when, r#else) => {
+                when.iter()
+                    .all(|(condition, result)| condition.walk(visitor) && result.walk(visitor))
+                    && r#else.walk(visitor)
+            }
+            Self::Coalesce(exprs)
+            | Self::Function(_, exprs)
+            | Self::Greatest(exprs)
+            | Self::Least(exprs) => exprs.iter().all(|expr| expr.walk(visitor)),
+            Self::NullIf(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
+
+            Self::Between(expr, low, high) => {
+                expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+            }
+            Self::In(expr, list) => expr.walk(visitor) && list.iter().all(|e| e.walk(visitor)),
+
+            Self::RegexMatch(lhs, rhs, _) | Self::SimilarTo(lhs, rhs) => {
+                lhs.walk(visitor) && rhs.walk(visitor)
+            }
+
+            Self::Constant(_)
+            | Self::Column(_)
+            | Self::Parameter(_)
+            | Self::Pattern(_)
+            | Self::NextValue(_)
+            | Self::CurrentValue(_) => true,
         }
     }
 
@@ -245,6 +536,12 @@ impl Expression {
         !self.walk(&mut |e| !visitor(e))
     }
 
+    /// Returns true if the expression contains sequence function calls, i.e.
+    /// nextval() or currval().
+    pub fn has_sequence(&self) -> bool {
+        self.contains(&|expr| matches!(expr, Self::NextValue(_) | Self::CurrentValue(_)))
+    }
+
     /// Transforms the expression by recursively applying the given closures
     /// depth-first to each node before/after descending.
     pub fn transform(
@@ -257,6 +554,10 @@ impl Expression {
             *expr = expr.transform(before, after)?;
             Ok(expr)
         };
+        // Helper for transforming expression vectors.
+        let xform_vec = |exprs: Vec<Expression>| -> Result<Vec<Expression>> {
+            exprs.into_iter().map(|expr| expr.transform(before, after)).collect()
+        };
 
         self = before(self)?;
         self = match self {
@@ -280,7 +581,37 @@ impl Expression {
             Self::Negate(expr) => Self::Negate(xform(expr)?),
             Self::Not(expr) => Self::Not(xform(expr)?),
 
-            expr @ (Self::Constant(_) | Self::Column(_)) => expr,
+            Self::Case(when, r#else) => Self::Case(
+                when.into_iter()
+                    .map(|(condition, result)| {
+                        Ok((condition.transform(before, after)?, result.transform(before, after)?))
+                    })
+                    .collect::<Result<_>>()?,
+                xform(r#else)?,
+            ),
+            Self::Cast(expr, datatype) => Self::Cast(xform(expr)?, datatype),
+            Self::Coalesce(exprs) => Self::Coalesce(xform_vec(exprs)?),
+            Self::Function(function, args) => Self::Function(function, xform_vec(args)?),
+            Self::Greatest(exprs) => Self::Greatest(xform_vec(exprs)?),
+            Self::Least(exprs) => Self::Least(xform_vec(exprs)?),
+            Self::NullIf(lhs, rhs) => Self::NullIf(xform(lhs)?, xform(rhs)?),
+
+            Self::Between(expr, low, high) => {
+                Self::Between(xform(expr)?, xform(low)?, xform(high)?)
+            }
+            Self::In(expr, list) => Self::In(xform(expr)?, xform_vec(list)?),
+
+            Self::RegexMatch(lhs, rhs, case_insensitive) => {
+                Self::RegexMatch(xform(lhs)?, xform(rhs)?, case_insensitive)
+            }
+            Self::SimilarTo(lhs, rhs) => Self::SimilarTo(xform(lhs)?, xform(rhs)?),
+
+            expr @ (Self::Constant(_)
+            | Self::Column(_)
+            | Self::Parameter(_)
+            | Self::Pattern(_)
+            | Self::NextValue(_)
+            | Self::CurrentValue(_)) => expr,
         };
         self = after(self)?;
         Ok(self)
@@ -377,6 +708,11 @@ impl Expression {
                 Column(c) => Some(*c),
                 _ => None,
             },
+            // IN with a constant list can use index lookups.
+            In(expr, list) => match expr.as_ref() {
+                Column(c) if list.iter().all(|item| matches!(item, Constant(_))) => Some(*c),
+                _ => None,
+            },
             // All OR branches must be lookups on the same column:
             // id = 1 OR id = 2 OR id = 3.
             Or(lhs, rhs) => match (lhs.is_column_lookup(), rhs.is_column_lookup()) {
@@ -411,6 +747,22 @@ impl Expression {
                 }
                 expr => panic!("unexpected expression {expr:?}"),
             },
+            // Like equality, NULL and NAN values never match. Duplicate values
+            // are removed, to avoid emitting duplicate rows.
+            In(expr, list) => match *expr {
+                Column(column) => {
+                    assert_eq!(column, index, "unexpected column");
+                    list.into_iter()
+                        .map(|item| match item {
+                            Constant(value) => value,
+                            item => panic!("unexpected expression {item:?}"),
+                        })
+                        .filter(|value| !value.is_undefined())
+                        .unique()
+                        .collect()
+                }
+                expr => panic!("unexpected expression {expr:?}"),
+            },
             Or(lhs, rhs) => {
                 let mut values = lhs.into_column_values(index);
                 values.extend(rhs.into_column_values(index));
@@ -420,6 +772,51 @@ impl Expression {
         }
     }
 
+    /// Checks if an expression is a range comparison of a column against a
+    /// constant (>, >=, <, <=, or BETWEEN), returning the column index and the
+    /// range bounds. Comparisons with NULL and NaN never match, so they aren't
+    /// considered ranges. -0.0 is normalized to 0.0, which it's equal to.
+    pub fn as_column_range(&self) -> Option<(usize, (Bound<Value>, Bound<Value>))> {
+        use Expression::*;
+
+        let constant = |expr: &Expression| match expr {
+            Constant(Value::Float(f)) if *f == 0.0 => Some(Value::Float(0.0)),
+            Constant(value) if !value.is_undefined() => Some(value.clone()),
+            _ => None,
+        };
+        match self {
+            GreaterThan(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (Column(c), rhs) => Some((*c, (Bound::Excluded(constant(rhs)?), Bound::Unbounded))),
+                (lhs, Column(c)) => Some((*c, (Bound::Unbounded, Bound::Excluded(constant(lhs)?)))),
+                _ => None,
+            },
+            LessThan(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (Column(c), rhs) => Some((*c, (Bound::Unbounded, Bound::Excluded(constant(rhs)?)))),
+                (lhs, Column(c)) => Some((*c, (Bound::Excluded(constant(lhs)?), Bound::Unbounded))),
+                _ => None,
+            },
+            // >= and <= are planned as x > y OR x = y, see Planner.
+            Or(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (GreaterThan(a, b) | LessThan(a, b), Equal(c, d)) if a == c && b == d => {
+                    let (column, (start, end)) = lhs.as_column_range()?;
+                    let include = |bound| match bound {
+                        Bound::Excluded(value) => Bound::Included(value),
+                        bound => bound,
+                    };
+                    Some((column, (include(start), include(end))))
+                }
+                _ => None,
+            },
+            Between(expr, low, high) => match expr.as_ref() {
+                Column(c) => {
+                    Some((*c, (Bound::Included(constant(low)?), Bound::Included(constant(high)?))))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Replaces column references from → to.
     pub fn replace_column(self, from: usize, to: usize) -> Self {
         let xform = |expr| match expr {
@@ -429,6 +826,15 @@ impl Expression {
         self.transform(&|e| Ok(xform(e)), &Ok).unwrap() // infallible
     }
 
+    /// Maps column references using the given function.
+    pub fn map_column(self, f: impl Fn(usize) -> usize) -> Self {
+        let xform = |expr| match expr {
+            Expression::Column(i) => Expression::Column(f(i)),
+            expr => expr,
+        };
+        self.transform(&|e| Ok(xform(e)), &Ok).unwrap() // infallible
+    }
+
     /// Shifts column references by the given amount (can be negative).
     pub fn shift_column(self, diff: isize) -> Self {
         let xform = |expr| match expr {
@@ -468,6 +874,10 @@ impl<'a> Display for ExpressionDisplay<'a> {
 
         // Helper to display a boxed, grouped expression.
         let group = |expr: &'a Expression| ExpressionDisplay::new(expr, self.node, precedence);
+        // Helper to display an ungrouped expression, e.g. a function argument.
+        let plain = |expr: &'a Expression| ExpressionDisplay::new(expr, self.node, 0);
+        // Helper to display a comma-separated list of function arguments.
+        let list = |exprs: &'a [Expression]| exprs.iter().map(plain).join(", ");
 
         match self.expr {
             Constant(value) => write!(f, "{value}")?,
@@ -475,6 +885,9 @@ impl<'a> Display for ExpressionDisplay<'a> {
                 Label::None => write!(f, "#{index}")?,
                 label => write!(f, "{label}")?,
             },
+            Parameter(n) => write!(f, "${n}")?,
+            NextValue(name) => write!(f, "nextval({})", Value::String(name.clone()))?,
+            CurrentValue(name) => write!(f, "currval({})", Value::String(name.clone()))?,
 
             And(lhs, rhs) => write!(f, "{} AND {}", group(lhs), group(rhs))?,
             Or(lhs, rhs) => write!(f, "{} OR {}", group(lhs), group(rhs))?,
@@ -486,6 +899,13 @@ impl<'a> Display for ExpressionDisplay<'a> {
             Is(expr, Value::Null) => write!(f, "{} IS NULL", group(expr))?,
             Is(expr, Value::Float(n)) if n.is_nan() => write!(f, "{} IS NAN", group(expr))?,
             Is(_, v) => panic!("unexpected IS value {v}"),
+            Between(expr, low, high) => {
+                // The bounds are grouped at a higher precedence, to avoid
+                // ambiguity with the AND.
+                let bound = |expr| ExpressionDisplay::new(expr, self.node, precedence + 1);
+                write!(f, "{} BETWEEN {} AND {}", group(expr), bound(low), bound(high))?
+            }
+            In(expr, items) => write!(f, "{} IN ({})", group(expr), list(items))?,
 
             Add(lhs, rhs) => write!(f, "{} + {}", group(lhs), group(rhs))?,
             Divide(lhs, rhs) => write!(f, "{} / {}", group(lhs), group(rhs))?,
@@ -499,6 +919,27 @@ impl<'a> Display for ExpressionDisplay<'a> {
             Subtract(lhs, rhs) => write!(f, "{} - {}", group(lhs), group(rhs))?,
 
             Like(lhs, rhs) => write!(f, "{} LIKE {}", group(lhs), group(rhs))?,
+            RegexMatch(lhs, rhs, false) => write!(f, "{} ~ {}", group(lhs), group(rhs))?,
+            RegexMatch(lhs, rhs, true) => write!(f, "{} ~* {}", group(lhs), group(rhs))?,
+            SimilarTo(lhs, rhs) => write!(f, "{} SIMILAR TO {}", group(lhs), group(rhs))?,
+            Pattern(pattern) => write!(f, "{}", Value::String(pattern.pattern().to_string()))?,
+
+            Case(when, r#else) => {
+                write!(f, "CASE")?;
+                for (condition, result) in when {
+                    write!(f, " WHEN {} THEN {}", plain(condition), plain(result))?;
+                }
+                if **r#else != Constant(Value::Null) {
+                    write!(f, " ELSE {}", plain(r#else))?;
+                }
+                write!(f, " END")?;
+            }
+            Cast(expr, datatype) => write!(f, "CAST({} AS {datatype})", plain(expr))?,
+            Coalesce(exprs) => write!(f, "coalesce({})", list(exprs))?,
+            Function(function, args) => write!(f, "{function}({})", list(args))?,
+            Greatest(exprs) => write!(f, "greatest({})", list(exprs))?,
+            Least(exprs) => write!(f, "least({})", list(exprs))?,
+            NullIf(lhs, rhs) => write!(f, "nullif({}, {})", plain(lhs), plain(rhs))?,
         }
 
         if precedence < self.parent_precedence {
@@ -519,14 +960,17 @@ impl<'a> ExpressionDisplay<'a> {
     fn precedence(expr: &Expression) -> u8 {
         use Expression::*;
         match expr {
-            Column(_) | Constant(_) | SquareRoot(_) => 11,
+            Column(_) | Constant(_) | Parameter(_) | Pattern(_) | SquareRoot(_) => 11,
+            Case(_, _) | Cast(_, _) | Coalesce(_) | Greatest(_) | Least(_) | NullIf(_, _) => 11,
+            Function(_, _) | NextValue(_) | CurrentValue(_) => 11,
             Identity(_) | Negate(_) => 10,
             Factorial(_) => 9,
             Exponentiate(_, _) => 8,
             Multiply(_, _) | Divide(_, _) | Remainder(_, _) => 7,
             Add(_, _) | Subtract(_, _) => 6,
             GreaterThan(_, _) | LessThan(_, _) => 5,
-            Equal(_, _) | Like(_, _) | Is(_, _) => 4,
+            Equal(_, _) | Like(_, _) | Is(_, _) | Between(_, _, _) | In(_, _) => 4,
+            RegexMatch(_, _, _) | SimilarTo(_, _) => 4,
             Not(_) => 3,
             And(_, _) => 2,
             Or(_, _) => 1,
@@ -0,0 +1,430 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{DataType, Expression, Pattern, Value};
+use crate::errinput;
+use crate::error::Result;
+
+/// A built-in scalar function, which takes a list of argument values and
+/// returns a single value. Functions are looked up by name and number of
+/// arguments during planning, and evaluated via Expression::Function.
+///
+/// All functions return NULL if any argument is NULL, except concat() which
+/// ignores NULL arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Function {
+    /// abs(number): the absolute value of a number.
+    Abs,
+    /// ceil(number): rounds a number up to the nearest integer.
+    Ceil,
+    /// concat(value, ...): concatenates the values as strings.
+    Concat,
+    /// floor(number): rounds a number down to the nearest integer.
+    Floor,
+    /// length(string): the number of characters in a string.
+    Length,
+    /// ln(number): the natural logarithm of a number.
+    Ln,
+    /// lower(string): converts a string to lowercase.
+    Lower,
+    /// mod(a, b): the remainder after dividing two numbers, like a % b.
+    Mod,
+    /// position(substring, string): the 1-based character position of the
+    /// first occurrence of substring in string, or 0 if not found.
+    Position,
+    /// regexp_match(string, pattern [, flags]): the first match of a regular
+    /// expression in string, or its first capture group if any. NULL if there
+    /// is no match. The flag i matches case-insensitively.
+    RegexpMatch,
+    /// regexp_replace(string, pattern, replacement [, flags]): replaces the
+    /// first match of a regular expression in string, or all matches with the
+    /// flag g. The replacement can refer to capture groups as \1 to \9, and
+    /// to the entire match as \&. The flag i matches case-insensitively.
+    RegexpReplace,
+    /// replace(string, from, to): replaces all occurrences of from with to.
+    Replace,
+    /// round(number [, digits]): rounds a number half away from zero, to the
+    /// given number of decimal digits (0 by default, may be negative).
+    Round,
+    /// substr(string, start [, count]): extracts count characters (or the
+    /// rest of the string) starting at the 1-based character position start.
+    Substr,
+    /// trim(string [, characters]): removes leading and trailing whitespace,
+    /// or the given characters, from a string.
+    Trim,
+    /// upper(string): converts a string to uppercase.
+    Upper,
+}
+
+impl Function {
+    /// Looks up a function by name (lowercase) and number of arguments.
+    /// Returns None if there is no such function with these arguments.
+    pub fn lookup(name: &str, args: usize) -> Option<Self> {
+        Some(match (name, args) {
+            ("abs", 1) => Self::Abs,
+            ("ceil", 1) => Self::Ceil,
+            ("concat", 1..) => Self::Concat,
+            ("floor", 1) => Self::Floor,
+            ("length", 1) => Self::Length,
+            ("ln", 1) => Self::Ln,
+            ("lower", 1) => Self::Lower,
+            ("mod", 2) => Self::Mod,
+            ("position", 2) => Self::Position,
+            ("regexp_match", 2 | 3) => Self::RegexpMatch,
+            ("regexp_replace", 3 | 4) => Self::RegexpReplace,
+            ("replace", 3) => Self::Replace,
+            ("round", 1 | 2) => Self::Round,
+            ("substr", 2 | 3) => Self::Substr,
+            ("trim", 1 | 2) => Self::Trim,
+            ("upper", 1) => Self::Upper,
+            _ => return None,
+        })
+    }
+
+    /// Returns true if the function accepts the given datatype for the
+    /// argument at the given index.
+    fn accepts(&self, index: usize, datatype: DataType) -> bool {
+        use DataType::*;
+        match (self, index) {
+            (Self::Concat, _) => true,
+            (Self::Abs | Self::Ceil | Self::Floor | Self::Ln | Self::Mod, _) => {
+                matches!(datatype, Integer | Float)
+            }
+            (Self::Round, 0) => matches!(datatype, Integer | Float),
+            (Self::Round, _) => datatype == Integer,
+            (Self::Substr, 0) => datatype == String,
+            (Self::Substr, _) => datatype == Integer,
+            (
+                Self::Length
+                | Self::Lower
+                | Self::Position
+                | Self::RegexpMatch
+                | Self::RegexpReplace
+                | Self::Replace
+                | Self::Trim
+                | Self::Upper,
+                _,
+            ) => datatype == String,
+        }
+    }
+
+    /// Checks the argument types during planning. Only arguments with a
+    /// statically known datatype (see Expression::datatype) can be checked, the
+    /// remaining arguments are checked during evaluation.
+    pub fn check(&self, args: &[Expression]) -> Result<()> {
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(datatype) = arg.datatype()
+                && !self.accepts(index, datatype)
+            {
+                return errinput!("can't call {self} with {datatype} argument");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the function's return datatype for the given argument
+    /// datatypes, if it can be statically determined.
+    pub fn datatype(&self, args: &[Option<DataType>]) -> Option<DataType> {
+        use DataType::*;
+        match self {
+            Self::Concat
+            | Self::Lower
+            | Self::RegexpMatch
+            | Self::RegexpReplace
+            | Self::Replace
+            | Self::Substr
+            | Self::Trim
+            | Self::Upper => Some(String),
+            Self::Length | Self::Position => Some(Integer),
+            Self::Ln => Some(Float),
+            Self::Abs | Self::Ceil | Self::Floor | Self::Round => args[0],
+            Self::Mod => match (args[0]?, args[1]?) {
+                (Integer, Integer) => Some(Integer),
+                _ => Some(Float),
+            },
+        }
+    }
+
+    /// Precompiles the pattern argument of a regexp function, if it's a
+    /// constant string and any flags are constant too. Used by ConstantFolding,
+    /// which passes the pattern to evaluate() as an Expression::Pattern.
+    /// Returns None for non-regexp functions.
+    pub fn compile_pattern(&self, args: &[Expression]) -> Result<Option<Pattern>> {
+        use Expression::Constant;
+        use Value::String;
+
+        if !matches!(self, Self::RegexpMatch | Self::RegexpReplace) {
+            return Ok(None);
+        }
+        let Some(Constant(String(pattern))) = args.get(1) else {
+            return Ok(None);
+        };
+        let flags = match args.get(self.regexp_flags_index()) {
+            Some(Constant(String(flags))) => flags.as_str(),
+            Some(_) => return Ok(None),
+            None => "",
+        };
+        let (case_insensitive, _) = self.regexp_flags(flags)?;
+        Ok(Some(Pattern::regex(pattern, case_insensitive)?))
+    }
+
+    /// Returns the index of a regexp function's flags argument.
+    fn regexp_flags_index(&self) -> usize {
+        match self {
+            Self::RegexpMatch => 2,
+            Self::RegexpReplace => 3,
+            _ => panic!("{self} is not a regexp function"),
+        }
+    }
+
+    /// Parses regexp function flags, returning (case_insensitive, global).
+    fn regexp_flags(&self, flags: &str) -> Result<(bool, bool)> {
+        let (mut case_insensitive, mut global) = (false, false);
+        for flag in flags.chars() {
+            match flag {
+                'i' => case_insensitive = true,
+                'g' if *self == Self::RegexpReplace => global = true,
+                flag => return errinput!("invalid {self} flag {flag:?}"),
+            }
+        }
+        Ok((case_insensitive, global))
+    }
+
+    /// Evaluates the function with the given argument values. Regexp
+    /// functions can be given a precompiled pattern, which is used instead of
+    /// compiling the pattern argument.
+    pub fn evaluate(&self, args: Vec<Value>, pattern: Option<&Pattern>) -> Result<Value> {
+        use Value::*;
+
+        // concat() ignores NULLs, and converts all other values to strings.
+        if *self == Self::Concat {
+            let mut result = std::string::String::new();
+            for arg in args {
+                match arg.cast(DataType::String)? {
+                    String(s) => result.push_str(&s),
+                    Null => {}
+                    value => panic!("unexpected cast result {value}"),
+                }
+            }
+            return Ok(String(result));
+        }
+
+        // All other functions return NULL if any argument is NULL.
+        if args.contains(&Null) {
+            return Ok(Null);
+        }
+
+        // Check the argument types, since the planner may not know them all.
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(datatype) = arg.datatype()
+                && !self.accepts(index, datatype)
+            {
+                return errinput!("can't call {self} with {arg}");
+            }
+        }
+
+        if let Self::RegexpMatch | Self::RegexpReplace = self {
+            return self.evaluate_regexp(args, pattern);
+        }
+
+        // Match on all arguments at once, filling in missing ones with NULL
+        // (explicit NULL arguments were handled above).
+        let mut args = args.into_iter();
+        let mut arg = || args.next().unwrap_or(Null);
+
+        Ok(match (self, arg(), arg(), arg()) {
+            (Self::Abs, Integer(i), Null, Null) => match i.checked_abs() {
+                Some(i) => Integer(i),
+                None => return errinput!("integer overflow"),
+            },
+            (Self::Abs, Float(f), Null, Null) => Float(f.abs()),
+
+            (Self::Ceil, Integer(i), Null, Null) => Integer(i),
+            (Self::Ceil, Float(f), Null, Null) => Float(f.ceil()),
+
+            (Self::Floor, Integer(i), Null, Null) => Integer(i),
+            (Self::Floor, Float(f), Null, Null) => Float(f.floor()),
+
+            (Self::Length, String(s), Null, Null) => Integer(s.chars().count() as i64),
+
+            // Like sqrt(), negative integers error while negative floats
+            // return NaN.
+            (Self::Ln, Integer(i @ 0..), Null, Null) => Float((i as f64).ln()),
+            (Self::Ln, Float(f), Null, Null) => Float(f.ln()),
+            (Self::Ln, Integer(i), Null, Null) => return errinput!("can't take logarithm of {i}"),
+
+            (Self::Lower, String(s), Null, Null) => String(s.to_lowercase()),
+
+            (Self::Mod, lhs, rhs, Null) => lhs.checked_rem(&rhs)?,
+
+            (Self::Position, String(substring), String(s), Null) => match s.find(&substring) {
+                Some(index) => Integer(s[..index].chars().count() as i64 + 1),
+                None => Integer(0),
+            },
+
+            // Replacing an empty string is a noop.
+            (Self::Replace, String(s), String(from), String(_)) if from.is_empty() => String(s),
+            (Self::Replace, String(s), String(from), String(to)) => String(s.replace(&from, &to)),
+
+            (Self::Round, Integer(i), Null, Null) => Integer(i),
+            (Self::Round, Integer(i), Integer(digits), Null) => {
+                Integer(Self::round_integer(i, digits)?)
+            }
+            (Self::Round, Float(f), Null, Null) => Float(f.round()),
+            (Self::Round, Float(f), Integer(digits), Null) => {
+                // If the factor overflows, the float has no digits to round
+                // at that precision. If it underflows, the float rounds to 0.
+                let factor = 10_f64.powi(digits.clamp(-400, 400) as i32);
+                let scaled = f * factor;
+                if factor == 0.0 && f.is_finite() {
+                    Float(0.0_f64.copysign(f))
+                } else if !factor.is_finite() || !scaled.is_finite() {
+                    Float(f)
+                } else {
+                    Float(scaled.round() / factor)
+                }
+            }
+
+            // Characters before the start of the string are counted towards
+            // the count, like in PostgreSQL, so substr('abc', 0, 2) is 'a'.
+            (Self::Substr, String(_), Integer(_), Integer(count)) if count < 0 => {
+                return errinput!("negative substring length {count}");
+            }
+            (Self::Substr, String(s), Integer(start), count) => {
+                let skip = start.saturating_sub(1).max(0) as usize;
+                let take = match count {
+                    Integer(count) => {
+                        start.saturating_add(count).saturating_sub(start.max(1)).max(0) as usize
+                    }
+                    _ => usize::MAX,
+                };
+                String(s.chars().skip(skip).take(take).collect())
+            }
+
+            (Self::Trim, String(s), Null, Null) => String(s.trim().to_string()),
+            (Self::Trim, String(s), String(chars), Null) => {
+                String(s.trim_matches(|c| chars.contains(c)).to_string())
+            }
+
+            (Self::Upper, String(s), Null, Null) => String(s.to_uppercase()),
+
+            // The argument types were checked above.
+            (_, _, _, _) => panic!("unexpected arguments for {self}"),
+        })
+    }
+
+    /// Evaluates a regexp function. The arguments are non-NULL strings.
+    fn evaluate_regexp(&self, args: Vec<Value>, pattern: Option<&Pattern>) -> Result<Value> {
+        let args: Vec<_> = args
+            .into_iter()
+            .map(|arg| match arg {
+                Value::String(s) => s,
+                arg => panic!("unexpected argument {arg} for {self}"),
+            })
+            .collect();
+        let flags = args.get(self.regexp_flags_index()).map(|flags| flags.as_str());
+        let (case_insensitive, global) = self.regexp_flags(flags.unwrap_or_default())?;
+        let pattern = match pattern {
+            Some(pattern) => Cow::Borrowed(pattern),
+            None => Cow::Owned(Pattern::regex(&args[1], case_insensitive)?),
+        };
+        let regex = pattern.compiled();
+
+        Ok(match self {
+            Self::RegexpMatch => {
+                let Some(captures) = regex.captures(&args[0]) else {
+                    return Ok(Value::Null);
+                };
+                // Return the first capture group if any, or the entire match.
+                // An unmatched capture group yields NULL.
+                match captures.get(captures.len().min(2) - 1) {
+                    Some(m) => Value::String(m.as_str().to_string()),
+                    None => Value::Null,
+                }
+            }
+            Self::RegexpReplace => {
+                let replacement = Self::regexp_replacement(&args[2]);
+                let result = match global {
+                    true => regex.replace_all(&args[0], replacement.as_str()),
+                    false => regex.replace(&args[0], replacement.as_str()),
+                };
+                Value::String(result.into_owned())
+            }
+            _ => panic!("{self} is not a regexp function"),
+        })
+    }
+
+    /// Translates a SQL regexp replacement string into the regex crate syntax:
+    /// \1 to \9 refer to capture groups, \& to the entire match, and \\ is a
+    /// literal backslash. $ has no special meaning.
+    fn regexp_replacement(replacement: &str) -> std::string::String {
+        let mut result = std::string::String::with_capacity(replacement.len());
+        let mut chars = replacement.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some(&digit @ '0'..='9')) => {
+                    result.push_str(&format!("${{{digit}}}"));
+                    chars.next();
+                }
+                ('\\', Some('&')) => {
+                    result.push_str("${0}");
+                    chars.next();
+                }
+                ('\\', Some('\\')) => {
+                    result.push('\\');
+                    chars.next();
+                }
+                ('$', _) => result.push_str("$$"),
+                (c, _) => result.push(c),
+            }
+        }
+        result
+    }
+
+    /// Rounds an integer half away from zero to the given number of decimal
+    /// digits. Non-negative digits are a noop.
+    fn round_integer(i: i64, digits: i64) -> Result<i64> {
+        if digits >= 0 {
+            return Ok(i);
+        }
+        // i64 has at most 19 digits, so anything beyond rounds to 0.
+        let Some(factor) = 10_i128.checked_pow(digits.unsigned_abs().min(20) as u32) else {
+            return Ok(0);
+        };
+        let (quotient, remainder) = (i as i128 / factor, i as i128 % factor);
+        let mut rounded = quotient * factor;
+        if remainder.abs() * 2 >= factor {
+            rounded += remainder.signum() * factor;
+        }
+        match i64::try_from(rounded) {
+            Ok(rounded) => Ok(rounded),
+            Err(_) => errinput!("integer overflow"),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Abs => "abs",
+            Self::Ceil => "ceil",
+            Self::Concat => "concat",
+            Self::Floor => "floor",
+            Self::Length => "length",
+            Self::Ln => "ln",
+            Self::Lower => "lower",
+            Self::Mod => "mod",
+            Self::Position => "position",
+            Self::RegexpMatch => "regexp_match",
+            Self::RegexpReplace => "regexp_replace",
+            Self::Replace => "replace",
+            Self::Round => "round",
+            Self::Substr => "substr",
+            Self::Trim => "trim",
+            Self::Upper => "upper",
+        };
+        f.write_str(name)
+    }
+}
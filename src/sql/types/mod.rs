@@ -1,9 +1,17 @@
 //! The SQL data model, including data types, expressions, and schema objects.
 
 mod expression;
+mod function;
+mod pattern;
 mod schema;
+mod statistics;
 mod value;
 
 pub use expression::Expression;
-pub use schema::{Column, Table};
-pub use value::{DataType, Label, Row, Rows, Value};
+pub use function::Function;
+pub use pattern::Pattern;
+pub use schema::{
+    Alteration, Check, Column, ForeignKey, Index, Reference, ReferenceAction, Sequence, Table,
+};
+pub use statistics::{ColumnStatistics, Statistics};
+pub use value::{DataType, Label, Row, Rows, Value, format_tuple};
@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errinput;
+use crate::error::Result;
+
+/// A compiled regular expression for a SQL pattern string, used by the ~, ~*,
+/// and SIMILAR TO operators and the regexp functions. ConstantFolding compiles
+/// constant patterns once during planning, instead of recompiling them for
+/// every row (see Expression::Pattern).
+///
+/// The original SQL pattern string is retained, for display and evaluation.
+/// The compiled regex is (de)serialized via its regex string, and compiled
+/// again when deserialized.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    /// The original SQL pattern string.
+    pattern: String,
+    /// The compiled regex.
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Compiles a regular expression pattern, using the regex crate syntax. If
+    /// case_insensitive is true, ignores case when matching.
+    pub fn regex(pattern: &str, case_insensitive: bool) -> Result<Self> {
+        // Use an inline flag rather than RegexBuilder, such that it's retained
+        // in the regex string when (de)serialized.
+        let regex = match case_insensitive {
+            true => Regex::new(&format!("(?i){pattern}")),
+            false => Regex::new(pattern),
+        };
+        match regex {
+            Ok(regex) => Ok(Self { pattern: pattern.to_string(), regex }),
+            Err(err) => errinput!("invalid regular expression {pattern:?}: {err}"),
+        }
+    }
+
+    /// Compiles a SQL SIMILAR TO pattern. This is a mix of LIKE patterns and
+    /// regular expressions: % and _ match any string and any single character,
+    /// while |, *, +, ?, {m,n}, (...), and [...] have their regex meanings.
+    /// Other characters match themselves, and \ escapes the next character.
+    /// The pattern must match the entire string.
+    pub fn similar_to(pattern: &str) -> Result<Self> {
+        let mut regex = String::from("(?s)^(?:");
+        let mut in_bracket = false;
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                    None => {
+                        return errinput!("invalid SIMILAR TO pattern {pattern:?}: trailing \\");
+                    }
+                },
+                ']' if in_bracket => {
+                    in_bracket = false;
+                    regex.push(c)
+                }
+                c if in_bracket => regex.push(c),
+                '[' => {
+                    in_bracket = true;
+                    regex.push(c)
+                }
+                '%' => regex.push_str(".*"),
+                '_' => regex.push('.'),
+                '|' | '*' | '+' | '?' | '{' | '}' | '(' | ')' => regex.push(c),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push_str(")$");
+        match Regex::new(&regex) {
+            Ok(regex) => Ok(Self { pattern: pattern.to_string(), regex }),
+            Err(err) => errinput!("invalid SIMILAR TO pattern {pattern:?}: {err}"),
+        }
+    }
+
+    /// Returns the original SQL pattern string.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the compiled regex.
+    pub fn compiled(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Patterns are equal if they have the same SQL pattern and compiled regex.
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (&self.pattern, self.regex.as_str()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (pattern, regex) = <(String, String)>::deserialize(deserializer)?;
+        let regex = Regex::new(&regex).map_err(serde::de::Error::custom)?;
+        Ok(Self { pattern, regex })
+    }
+}
@@ -1,27 +1,50 @@
 use std::fmt::Display;
 
+use itertools::Itertools as _;
 use serde::{Deserialize, Serialize};
 
-use super::{DataType, Row, Value};
+use super::{DataType, Expression, Label, Row, Value, format_tuple};
 use crate::encoding;
 use crate::errinput;
 use crate::error::Result;
 use crate::sql::engine::{Catalog, Transaction};
 use crate::sql::parser::is_ident;
+use crate::sql::planner::Node;
 
 /// A table schema, which specifies its data structure and constraints.
 ///
-/// Tables can't change after they are created. There is no ALTER TABLE nor
-/// CREATE/DROP INDEX, only CREATE TABLE and DROP TABLE.
+/// Tables can be changed with ALTER TABLE (see Alteration). Single-column
+/// secondary indexes can be declared inline on columns, and single- or
+/// multi-column indexes can be created and dropped by name with CREATE/DROP
+/// INDEX (see indexes). Arbitrary row predicates can be declared with CHECK
+/// constraints (see checks). Similarly, single-column foreign keys are declared
+/// inline on columns, and multi-column foreign keys as table constraints (see
+/// foreign_keys).
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Table {
     /// The table name. Unique identifier for the table. Can't be empty.
     pub name: String,
-    /// The primary key column index. A table must have a primary key, and it
-    /// can only be a single column.
-    pub primary_key: usize,
+    /// The primary key column indexes, in key order. A table must have a
+    /// primary key, which can span multiple columns (a composite key). Rows
+    /// are stored in primary key order.
+    pub primary_key: Vec<usize>,
     /// The table's columns. Must have at least one.
     pub columns: Vec<Column>,
+    /// Named secondary indexes, created with CREATE INDEX. Index names are
+    /// unique across all tables, and can't be the same as an indexed column's
+    /// name, since inline column indexes are named after their column.
+    pub indexes: Vec<Index>,
+    /// CHECK constraints, declared at column or table level. Check names are
+    /// unique within the table.
+    pub checks: Vec<Check>,
+    /// Multi-column foreign keys, declared as table constraints. Single-column
+    /// foreign keys are stored inline as column references.
+    pub foreign_keys: Vec<ForeignKey>,
+    /// The schema version, starting at 0. It is incremented when the row
+    /// layout changes, i.e. when columns are added or dropped. Rows are stored
+    /// with the schema version they were written with, and upgraded to the
+    /// current version when read (see `sql::engine::Local`).
+    pub version: u64,
 }
 
 impl encoding::Value for Table {}
@@ -33,68 +56,401 @@ pub struct Column {
     pub name: String,
     /// Column datatype.
     pub datatype: DataType,
-    /// Whether the column allows null values. Not legal for primary keys.
+    /// Whether the column allows null values. Not legal for primary key
+    /// columns.
     pub nullable: bool,
     /// The column's default value. If None, the user must specify an explicit
     /// value. Must match the column datatype. Nullable columns require a
     /// default (often Null). Null is only a valid default when nullable.
     pub default: Option<Value>,
     /// Whether the column should only allow unique values (ignoring NULLs).
-    /// Must be true for a primary key column. Requires index.
+    /// Must be true for a single-column primary key. Otherwise, requires index.
     pub unique: bool,
-    /// Whether the column should have a secondary index. Must be false for
-    /// primary keys, which are the primary index. Must be true for unique or
-    /// reference columns.
+    /// Whether the column should have an inline secondary index, named after
+    /// the column. Must be false for a single-column primary key, which is the
+    /// primary index. Must be true for unique or reference columns.
     pub index: bool,
     /// If set, this column is a foreign key reference to the given table's
-    /// primary key. Must be of the same type as the target primary key.
-    /// Requires index.
-    pub references: Option<String>,
+    /// primary key, which must be a single column of the same type. Requires
+    /// an index, unless the column is the table's single-column primary key.
+    pub references: Option<Reference>,
+    /// If set, the column's default values are generated by the given
+    /// sequence (SERIAL or GENERATED AS IDENTITY), which is owned by the column
+    /// and dropped with it. Must be an INTEGER column without a default value.
+    pub sequence: Option<String>,
 }
 
-// Formats the table as a SQL CREATE TABLE statement.
-impl Display for Table {
+/// A secondary index over one or more table columns. Index entries map the
+/// indexed column values to the primary keys of the rows containing them.
+///
+/// Named indexes are created with CREATE INDEX and stored in `Table::indexes`,
+/// while inline column indexes are represented as single-column indexes named
+/// after their column (see `Table::secondary_indexes`).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Index {
+    /// The index name. Can't be empty.
+    pub name: String,
+    /// The indexed column indexes, in key order. Must have at least one.
+    /// Lookups can use a prefix of the columns.
+    pub columns: Vec<usize>,
+    /// Whether the indexed values must be unique. Rows where any of the
+    /// indexed values are NULL (or NaN) are exempt.
+    pub unique: bool,
+}
+
+impl Index {
+    /// Returns the index values for the given row.
+    pub fn values(&self, row: &Row) -> Vec<Value> {
+        self.columns.iter().map(|&i| row[i].clone()).collect()
+    }
+}
+
+/// A foreign key reference to a table's primary key, along with the actions to
+/// take when a referenced row is deleted or its primary key is changed.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct Reference {
+    /// The referenced table.
+    pub table: String,
+    /// The ON DELETE action.
+    pub on_delete: ReferenceAction,
+    /// The ON UPDATE action, taken when the referenced primary key changes.
+    pub on_update: ReferenceAction,
+}
+
+impl Reference {
+    /// Creates a new reference to the given table, with RESTRICT actions.
+    pub fn new(table: impl Into<String>) -> Self {
+        let action = ReferenceAction::Restrict;
+        Self { table: table.into(), on_delete: action, on_update: action }
+    }
+}
+
+/// An action to take on referencing rows when a referenced row is deleted or
+/// its primary key is changed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub enum ReferenceAction {
+    /// Error if the row is referenced. The default.
+    #[default]
+    Restrict,
+    /// Delete the referencing rows (ON DELETE), or change their references to
+    /// the new primary key (ON UPDATE).
+    Cascade,
+    /// Set the referencing columns to NULL. They must be nullable.
+    SetNull,
+    /// Set the referencing columns to their default values. They must have
+    /// default values, which must reference an existing row (if not NULL).
+    SetDefault,
+}
+
+// Formats the reference as a SQL REFERENCES clause, omitting RESTRICT actions.
+impl Display for Reference {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        /// Formats an identifier as valid SQL, quoting it if necessary.
-        fn format_ident(ident: &str) -> String {
-            match is_ident(ident) {
-                true => ident.to_string(),
-                false => format!("\"{}\"", ident.replace('\"', "\"\"")),
-            }
+        write!(f, "REFERENCES {}", format_ident(&self.table))?;
+        if self.on_delete != ReferenceAction::Restrict {
+            write!(f, " ON DELETE {}", self.on_delete)?;
+        }
+        if self.on_update != ReferenceAction::Restrict {
+            write!(f, " ON UPDATE {}", self.on_update)?;
         }
+        Ok(())
+    }
+}
+
+impl Display for ReferenceAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Restrict => "RESTRICT",
+            Self::Cascade => "CASCADE",
+            Self::SetNull => "SET NULL",
+            Self::SetDefault => "SET DEFAULT",
+        })
+    }
+}
+
+/// A foreign key from one or more columns to a table's primary key, which must
+/// have the same number of columns and datatypes. The key only applies when
+/// all of its column values are non-NULL.
+///
+/// Multi-column foreign keys are stored in `Table::foreign_keys`, while inline
+/// column references are represented as single-column foreign keys (see
+/// `Table::references`). Referencing rows are looked up via a primary key or
+/// secondary index on the foreign key columns, if any, otherwise via a table
+/// scan.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ForeignKey {
+    /// The referencing column indexes, in the referenced primary key's order.
+    /// Must have at least one.
+    pub columns: Vec<usize>,
+    /// The reference.
+    pub reference: Reference,
+}
+
+impl ForeignKey {
+    /// Returns the foreign key values for the given row, or None if any of
+    /// them are NULL (in which case the key doesn't apply).
+    pub fn values(&self, row: &Row) -> Option<Vec<Value>> {
+        let values = self.columns.iter().map(|&i| row[i].clone()).collect_vec();
+        (!values.contains(&Value::Null)).then_some(values)
+    }
+}
+
+/// A CHECK constraint, i.e. a predicate that every table row must satisfy.
+/// Like in SQL, a row satisfies the check unless the predicate evaluates to
+/// false, i.e. a NULL result passes.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Check {
+    /// The constraint name. Can't be empty. Unnamed constraints are named
+    /// {table}_{column}_check for column constraints and {table}_check for
+    /// table constraints, with a numeric suffix if taken.
+    pub name: String,
+    /// The predicate, a boolean expression over the table's columns. Can't
+    /// contain parameters or sequence functions.
+    pub expression: Expression,
+}
+
+/// A sequence, which generates a series of unique integers, e.g. for SERIAL
+/// columns. The sequence definition is stored in the catalog, but its current
+/// value is stored in a non-transactional key, such that concurrent
+/// transactions never conflict when drawing values. As a consequence, values
+/// drawn by a transaction that rolls back are lost, leaving gaps.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Sequence {
+    /// The sequence name. Unique identifier for the sequence. Can't be empty.
+    pub name: String,
+    /// The first value returned by the sequence.
+    pub start: i64,
+    /// The difference between consecutive values. Can't be 0.
+    pub increment: i64,
+    /// The MVCC version of the transaction that created the sequence, which
+    /// is assigned by the catalog. The sequence value is keyed by it, such that
+    /// a sequence that's dropped and recreated starts over, while a rolled
+    /// back drop and recreate doesn't affect the existing sequence.
+    pub version: u64,
+}
+
+impl encoding::Value for Sequence {}
+
+impl Sequence {
+    /// Creates a new sequence starting at 1 with an increment of 1.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), start: 1, increment: 1, version: 0 }
+    }
 
+    /// Validates the sequence.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return errinput!("sequence name can't be empty");
+        }
+        if self.increment == 0 {
+            return errinput!("sequence increment can't be 0");
+        }
+        Ok(())
+    }
+}
+
+// Formats the sequence as a SQL CREATE SEQUENCE statement.
+impl Display for Sequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, start, increment) = (format_ident(&self.name), self.start, self.increment);
+        write!(f, "CREATE SEQUENCE {name} START WITH {start} INCREMENT BY {increment}")
+    }
+}
+
+/// A table schema change, i.e. an ALTER TABLE operation.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Alteration {
+    /// ADD COLUMN: appends a column. Existing rows get the column's default
+    /// value, which is required if the table has rows. Can't be a primary key.
+    AddColumn(Column),
+    /// DROP COLUMN: removes a column, along with its index and check
+    /// constraints if any. Can't be the primary key.
+    DropColumn(String),
+    /// RENAME COLUMN: renames a column.
+    RenameColumn { from: String, to: String },
+    /// RENAME TO: renames the table. References from other tables follow it.
+    RenameTable(String),
+    /// ALTER COLUMN SET/DROP DEFAULT: sets or removes a column's default value.
+    /// Nullable columns default to NULL if removed.
+    SetDefault(String, Option<Value>),
+    /// ALTER COLUMN SET/DROP NOT NULL: sets whether a column is nullable.
+    /// Errors if it is set to non-nullable but contains NULL values.
+    SetNullable(String, bool),
+}
+
+/// Formats an identifier as valid SQL, quoting it if necessary.
+fn format_ident(ident: &str) -> String {
+    match is_ident(ident) {
+        true => ident.to_string(),
+        false => format!("\"{}\"", ident.replace('\"', "\"\"")),
+    }
+}
+
+// Formats the table as a SQL CREATE TABLE statement, followed by CREATE INDEX
+// statements for any named indexes. Check constraints are formatted as table
+// constraints.
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let columns = |columns: &[usize]| {
+            columns.iter().map(|&i| format_ident(&self.columns[i].name)).join(", ")
+        };
+        // Column labels for check expressions.
+        let labels = Node::Nothing {
+            columns: self
+                .columns
+                .iter()
+                .map(|c| Label::Unqualified(format_ident(&c.name)))
+                .collect(),
+        };
+        let constraints =
+            self.foreign_keys.len() + self.checks.len() + (self.primary_key.len() > 1) as usize;
         writeln!(f, "CREATE TABLE {} (", format_ident(&self.name))?;
         for (i, column) in self.columns.iter().enumerate() {
-            write!(f, "  {} {}", format_ident(&column.name), column.datatype)?;
-            if i == self.primary_key {
-                write!(f, " PRIMARY KEY")?;
-            } else if !column.nullable {
-                write!(f, " NOT NULL")?;
-            }
-            if let Some(default) = &column.default {
-                write!(f, " DEFAULT {default}")?;
-            }
-            if i != self.primary_key {
-                if column.unique {
-                    write!(f, " UNIQUE")?;
+            if self.primary_key == [i] {
+                write!(f, "  {} {} PRIMARY KEY", format_ident(&column.name), column.datatype)?;
+                if column.sequence.is_some() {
+                    write!(f, " GENERATED AS IDENTITY")?;
                 }
-                if column.index {
-                    write!(f, " INDEX")?;
+                if let Some(default) = &column.default {
+                    write!(f, " DEFAULT {default}")?;
                 }
+                if let Some(reference) = &column.references {
+                    write!(f, " {reference}")?;
+                }
+            } else {
+                write!(f, "  {column}")?;
             }
-            if let Some(reference) = &column.references {
-                write!(f, " REFERENCES {reference}")?;
-            }
-            if i < self.columns.len() - 1 {
+            if i < self.columns.len() - 1 || constraints > 0 {
                 write!(f, ",")?;
             }
             writeln!(f)?;
         }
-        write!(f, ")")
+        let mut constraints = Vec::with_capacity(constraints);
+        if self.primary_key.len() > 1 {
+            constraints.push(format!("PRIMARY KEY ({})", columns(&self.primary_key)));
+        }
+        for foreign_key in &self.foreign_keys {
+            let (columns, reference) = (columns(&foreign_key.columns), &foreign_key.reference);
+            constraints.push(format!("FOREIGN KEY ({columns}) {reference}"));
+        }
+        for check in &self.checks {
+            let (name, expression) = (format_ident(&check.name), check.expression.display(&labels));
+            constraints.push(format!("CONSTRAINT {name} CHECK ({expression})"));
+        }
+        for (i, constraint) in constraints.iter().enumerate() {
+            write!(f, "  {constraint}")?;
+            writeln!(f, "{}", if i < constraints.len() - 1 { "," } else { "" })?;
+        }
+        write!(f, ")")?;
+        for index in &self.indexes {
+            let unique = if index.unique { "UNIQUE " } else { "" };
+            let (name, table) = (format_ident(&index.name), format_ident(&self.name));
+            write!(f, ";\nCREATE {unique}INDEX {name} ON {table} ({})", columns(&index.columns))?;
+        }
+        Ok(())
+    }
+}
+
+// Formats a non-primary-key column as a SQL column definition.
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", format_ident(&self.name), self.datatype)?;
+        // Identity columns are non-nullable by default, and have no DEFAULT.
+        if !self.nullable {
+            write!(f, " NOT NULL")?;
+        } else if self.sequence.is_some() {
+            write!(f, " NULL")?;
+        }
+        if self.sequence.is_some() {
+            write!(f, " GENERATED AS IDENTITY")?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " DEFAULT {default}")?;
+        }
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        if self.index {
+            write!(f, " INDEX")?;
+        }
+        if let Some(reference) = &self.references {
+            write!(f, " {reference}")?;
+        }
+        Ok(())
+    }
+}
+
+// Formats the alteration as a SQL ALTER TABLE operation.
+impl Display for Alteration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddColumn(column) => write!(f, "ADD COLUMN {column}"),
+            Self::DropColumn(column) => write!(f, "DROP COLUMN {}", format_ident(column)),
+            Self::RenameColumn { from, to } => {
+                write!(f, "RENAME COLUMN {} TO {}", format_ident(from), format_ident(to))
+            }
+            Self::RenameTable(to) => write!(f, "RENAME TO {}", format_ident(to)),
+            Self::SetDefault(column, Some(default)) => {
+                write!(f, "ALTER COLUMN {} SET DEFAULT {default}", format_ident(column))
+            }
+            Self::SetDefault(column, None) => {
+                write!(f, "ALTER COLUMN {} DROP DEFAULT", format_ident(column))
+            }
+            Self::SetNullable(column, true) => {
+                write!(f, "ALTER COLUMN {} DROP NOT NULL", format_ident(column))
+            }
+            Self::SetNullable(column, false) => {
+                write!(f, "ALTER COLUMN {} SET NOT NULL", format_ident(column))
+            }
+        }
     }
 }
 
 impl Table {
+    /// Returns the primary key values for the given row.
+    pub fn primary_key_values(&self, row: &Row) -> Vec<Value> {
+        self.primary_key.iter().map(|&i| row[i].clone()).collect()
+    }
+
+    /// Returns all secondary indexes: inline column indexes, named after their
+    /// column, followed by named indexes.
+    pub fn secondary_indexes(&self) -> Vec<Index> {
+        let inline = self.columns.iter().enumerate().filter(|(_, c)| c.index).map(|(i, c)| Index {
+            name: c.name.clone(),
+            columns: vec![i],
+            unique: c.unique,
+        });
+        inline.chain(self.indexes.iter().cloned()).collect()
+    }
+
+    /// Returns all foreign keys: inline column references, as single-column
+    /// foreign keys, followed by multi-column foreign keys.
+    pub fn references(&self) -> Vec<ForeignKey> {
+        let inline = self.columns.iter().enumerate().filter_map(|(i, c)| {
+            let reference = c.references.clone()?;
+            Some(ForeignKey { columns: vec![i], reference })
+        });
+        inline.chain(self.foreign_keys.iter().cloned()).collect()
+    }
+
+    /// Changes foreign key references to the given table to the new name.
+    pub fn rename_references(&mut self, from: &str, to: &str) {
+        let column_references = self.columns.iter_mut().filter_map(|c| c.references.as_mut());
+        let table_references = self.foreign_keys.iter_mut().map(|fk| &mut fk.reference);
+        for reference in column_references.chain(table_references) {
+            if reference.table == from {
+                reference.table = to.to_string();
+            }
+        }
+    }
+
+    /// Returns the index of the given column, or errors if it doesn't exist.
+    pub fn column_index(&self, name: &str) -> Result<usize> {
+        match self.columns.iter().position(|c| c.name == name) {
+            Some(index) => Ok(index),
+            None => errinput!("unknown column {name} in table {}", self.name),
+        }
+    }
+
     /// Validates the table schema, using the catalog to validate foreign key
     /// references.
     pub fn validate(&self, catalog: &impl Catalog) -> Result<()> {
@@ -104,22 +460,33 @@ impl Table {
         if self.columns.is_empty() {
             return errinput!("table has no columns");
         }
-        if self.columns.get(self.primary_key).is_none() {
-            return errinput!("invalid primary key index");
+        if self.primary_key.is_empty() {
+            return errinput!("table has no primary key");
+        }
+        for (i, column) in self.primary_key.iter().enumerate() {
+            if self.columns.get(*column).is_none() {
+                return errinput!("invalid primary key index");
+            }
+            if self.primary_key[..i].contains(column) {
+                return errinput!("duplicate primary key column {}", self.columns[*column].name);
+            }
         }
 
         for (i, column) in self.columns.iter().enumerate() {
             if column.name.is_empty() {
                 return errinput!("column name can't be empty");
             }
+            if self.columns[..i].iter().any(|c| c.name == column.name) {
+                return errinput!("duplicate column {}", column.name);
+            }
             let (cname, ctype) = (&column.name, &column.datatype); // for formatting convenience
 
             // Validate primary key.
-            let is_primary_key = i == self.primary_key;
+            let is_primary_key = self.primary_key == [i];
+            if self.primary_key.contains(&i) && column.nullable {
+                return errinput!("primary key {cname} cannot be nullable");
+            }
             if is_primary_key {
-                if column.nullable {
-                    return errinput!("primary key {cname} cannot be nullable");
-                }
                 if !column.unique {
                     return errinput!("primary key {cname} must be unique");
                 }
@@ -130,7 +497,7 @@ impl Table {
 
             // Validate default value.
             match column.default.as_ref().map(|v| v.datatype()) {
-                None if column.nullable => {
+                None if column.nullable && column.sequence.is_none() => {
                     return errinput!("nullable column {cname} must have a default value");
                 }
                 Some(None) if !column.nullable => {
@@ -142,28 +509,160 @@ impl Table {
                 Some(_) | None => {}
             }
 
+            // Validate sequence.
+            if column.sequence.is_some() {
+                if column.datatype != DataType::Integer {
+                    return errinput!("identity column {cname} must be INTEGER, not {ctype}");
+                }
+                if column.default.is_some() {
+                    return errinput!("identity column {cname} can't have a default value");
+                }
+            }
+
             // Validate unique index.
             if column.unique && !column.index && !is_primary_key {
                 return errinput!("unique column {cname} must have a secondary index");
             }
 
-            // Validate references.
-            if let Some(reference) = &column.references {
-                if !column.index && !is_primary_key {
-                    return errinput!("reference column {cname} must have a secondary index");
+            // Validate reference index. References are validated below.
+            if column.references.is_some() && !column.index && !is_primary_key {
+                return errinput!("reference column {cname} must have a secondary index");
+            }
+        }
+
+        // Validate foreign keys, including inline column references.
+        for (i, foreign_key) in self.foreign_keys.iter().enumerate() {
+            if foreign_key.columns.is_empty() {
+                return errinput!("foreign key has no columns");
+            }
+            for (j, column) in foreign_key.columns.iter().enumerate() {
+                if self.columns.get(*column).is_none() {
+                    return errinput!("invalid column index for foreign key");
                 }
-                let reftype = if reference == &self.name {
-                    self.columns[self.primary_key].datatype
-                } else if let Some(target) = catalog.get_table(reference)? {
-                    target.columns[target.primary_key].datatype
-                } else {
-                    return errinput!("unknown table {reference} referenced by column {cname}");
-                };
-                if column.datatype != reftype {
+                if foreign_key.columns[..j].contains(column) {
+                    let cname = &self.columns[*column].name;
+                    return errinput!("duplicate column {cname} in foreign key");
+                }
+            }
+            if self.foreign_keys[..i].iter().any(|other| other.columns == foreign_key.columns) {
+                return errinput!("duplicate foreign key ({})", self.format_columns(foreign_key));
+            }
+        }
+        for foreign_key in self.references() {
+            let reference = &foreign_key.reference.table;
+            let source = match foreign_key.columns.as_slice() {
+                [column] => format!("column {}", self.columns[*column].name),
+                _ => format!("columns ({})", self.format_columns(&foreign_key)),
+            };
+            let target = if reference == &self.name {
+                self.clone()
+            } else if let Some(target) = catalog.get_table(reference)? {
+                target
+            } else {
+                return errinput!("unknown table {reference} referenced by {source}");
+            };
+            if target.primary_key.len() != foreign_key.columns.len() {
+                if foreign_key.columns.len() == 1 {
+                    return errinput!(
+                        "can't reference composite primary key of {reference} from {source}"
+                    );
+                }
+                return errinput!(
+                    "can't reference {}-column primary key of {reference} from {source}",
+                    target.primary_key.len()
+                );
+            }
+            for (&column, &key) in foreign_key.columns.iter().zip(&target.primary_key) {
+                let column = &self.columns[column];
+                let (cname, ctype) = (&column.name, &column.datatype);
+                let reftype = target.columns[key].datatype;
+                if *ctype != reftype {
                     return errinput!(
                         "can't reference {reftype} primary key of {reference} from {ctype} column {cname}"
                     );
                 }
+                let (on_delete, on_update) =
+                    (foreign_key.reference.on_delete, foreign_key.reference.on_update);
+                for action in [on_delete, on_update] {
+                    match action {
+                        ReferenceAction::SetNull if !column.nullable => {
+                            return errinput!(
+                                "SET NULL reference action requires nullable column {cname}"
+                            );
+                        }
+                        ReferenceAction::SetDefault if column.default.is_none() => {
+                            return errinput!(
+                                "SET DEFAULT reference action requires a default value for column {cname}"
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // Validate secondary indexes, including inline column indexes.
+        let indexes = self.secondary_indexes();
+        for (i, index) in indexes.iter().enumerate() {
+            let name = &index.name;
+            if name.is_empty() {
+                return errinput!("index name can't be empty");
+            }
+            if indexes[..i].iter().any(|other| &other.name == name) {
+                return errinput!("duplicate index {name}");
+            }
+            if index.columns.is_empty() {
+                return errinput!("index {name} has no columns");
+            }
+            for (j, column) in index.columns.iter().enumerate() {
+                if self.columns.get(*column).is_none() {
+                    return errinput!("invalid column index for index {name}");
+                }
+                if index.columns[..j].contains(column) {
+                    return errinput!(
+                        "duplicate column {} in index {name}",
+                        self.columns[*column].name
+                    );
+                }
+            }
+            if index.columns == self.primary_key {
+                return errinput!("index {name} can't be on the primary key");
+            }
+        }
+
+        // Validate check constraints.
+        for (i, check) in self.checks.iter().enumerate() {
+            let name = &check.name;
+            if name.is_empty() {
+                return errinput!("check constraint name can't be empty");
+            }
+            if self.checks[..i].iter().any(|other| &other.name == name) {
+                return errinput!("duplicate check constraint {name}");
+            }
+            if check.expression.contains(&|expr| match expr {
+                Expression::Column(index) => *index >= self.columns.len(),
+                _ => false,
+            }) {
+                return errinput!("invalid column index in check constraint {name}");
+            }
+            if check.expression.contains(&|expr| {
+                matches!(
+                    expr,
+                    Expression::Parameter(_)
+                        | Expression::NextValue(_)
+                        | Expression::CurrentValue(_)
+                )
+            }) {
+                return errinput!(
+                    "check constraint {name} can't use parameters or sequence functions"
+                );
+            }
+            let column_datatype = |index: usize| self.columns.get(index).map(|c| c.datatype);
+            match check.expression.datatype_with(&column_datatype) {
+                Some(DataType::Boolean) | None => {}
+                Some(datatype) => {
+                    return errinput!("check constraint {name} must be boolean, not {datatype}");
+                }
             }
         }
         Ok(())
@@ -184,18 +683,16 @@ impl Table {
         }
 
         // Validate primary key.
-        let id = &row[self.primary_key];
-        let idslice = &row[self.primary_key..=self.primary_key];
-        if id.is_undefined() {
-            return errinput!("invalid primary key {id}");
+        let id = self.primary_key_values(row);
+        if id.iter().any(|v| v.is_undefined()) {
+            return errinput!("invalid primary key {}", format_tuple(&id));
         }
-        if !update && !txn.get(&self.name, idslice)?.is_empty() {
-            return errinput!("primary key {id} already exists");
+        if !update && !txn.get(&self.name, std::slice::from_ref(&id))?.is_empty() {
+            return errinput!("primary key {} already exists", format_tuple(&id));
         }
 
-        for (i, (column, value)) in self.columns.iter().zip(row).enumerate() {
+        for (column, value) in self.columns.iter().zip(row) {
             let (cname, ctype) = (&column.name, &column.datatype);
-            let valueslice = &row[i..=i];
 
             // Validate datatype.
             if let Some(ref vtype) = value.datatype()
@@ -206,32 +703,80 @@ impl Table {
             if value == &Value::Null && !column.nullable {
                 return errinput!("NULL value not allowed for column {cname}");
             }
+        }
 
-            // Validate outgoing references.
-            if let Some(target) = &column.references {
-                match value {
-                    // NB: NaN is not a valid primary key, and not valid as a
-                    // missing foreign key marker.
-                    Value::Null => {}
-                    v if target == &self.name && v == id => {}
-                    v if txn.get(target, valueslice)?.is_empty() => {
-                        return errinput!("reference {v} not in table {target}");
-                    }
-                    _ => {}
-                }
+        // Validate outgoing references, unless any of the values are NULL.
+        // NB: NaN is not a valid primary key, and not valid as a missing
+        // foreign key marker.
+        for foreign_key in self.references() {
+            let target = &foreign_key.reference.table;
+            let Some(values) = foreign_key.values(row) else {
+                continue;
+            };
+            if target == &self.name && values == id {
+                continue;
+            }
+            if txn.get(target, std::slice::from_ref(&values))?.is_empty() {
+                return match values.as_slice() {
+                    [value] => errinput!("reference {value} not in table {target}"),
+                    values => errinput!("reference {} not in table {target}", format_tuple(values)),
+                };
             }
+        }
 
-            // Validate uniqueness constraints. Unique columns are indexed.
-            if column.unique && i != self.primary_key && !value.is_undefined() {
-                let mut index = txn.lookup_index(&self.name, &column.name, valueslice)?;
-                if update {
-                    index.remove(id); // ignore existing version of this row
+        // Validate check constraints. NULL results pass.
+        for check in &self.checks {
+            match check.expression.evaluate(Some(row))? {
+                Value::Boolean(true) | Value::Null => {}
+                Value::Boolean(false) => {
+                    return errinput!(
+                        "row {} violates check constraint {}",
+                        format_tuple(row),
+                        check.name
+                    );
                 }
-                if !index.is_empty() {
-                    return errinput!("value {value} already in unique column {cname}");
+                value => {
+                    return errinput!(
+                        "check constraint {} returned {value}, not boolean",
+                        check.name
+                    );
                 }
             }
         }
+
+        // Validate uniqueness constraints of unique indexes.
+        for index in self.secondary_indexes().into_iter().filter(|i| i.unique) {
+            let values = index.values(row);
+            if values.iter().any(|v| v.is_undefined()) {
+                continue;
+            }
+            let mut ids =
+                txn.lookup_index(&self.name, &index.name, std::slice::from_ref(&values))?;
+            if update {
+                ids.remove(&id); // ignore existing version of this row
+            }
+            if !ids.is_empty() {
+                return self.unique_error(&index, &values);
+            }
+        }
         Ok(())
     }
+
+    /// Formats the column names of a foreign key, comma-separated.
+    fn format_columns(&self, foreign_key: &ForeignKey) -> String {
+        foreign_key.columns.iter().map(|&i| &self.columns[i].name).join(", ")
+    }
+
+    /// Returns an error for a uniqueness violation of the given index values.
+    pub fn unique_error<T>(&self, index: &Index, values: &[Value]) -> Result<T> {
+        match index.columns.as_slice() {
+            [column] => {
+                let column = &self.columns[*column].name;
+                errinput!("value {} already in unique column {column}", values[0])
+            }
+            _ => {
+                errinput!("values {} already in unique index {}", format_tuple(values), index.name)
+            }
+        }
+    }
 }
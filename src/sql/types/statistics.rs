@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Row, Table, Value};
+use crate::encoding;
+use crate::error::Result;
+
+/// The maximum number of histogram buckets per column.
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Table statistics, collected by ANALYZE and used by the optimizer's cost
+/// model to estimate row counts. They're a snapshot taken at ANALYZE time, and
+/// are not updated as rows are written, so they can be stale.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Statistics {
+    /// The number of rows in the table.
+    pub rows: u64,
+    /// Per-column statistics, in table column order.
+    pub columns: Vec<ColumnStatistics>,
+}
+
+impl encoding::Value for Statistics {}
+
+/// Column statistics.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ColumnStatistics {
+    /// The number of distinct non-NULL values.
+    pub distinct: u64,
+    /// The number of NULL values.
+    pub nulls: u64,
+    /// An equi-depth histogram of the defined (non-NULL, non-NaN) values,
+    /// given as bucket boundaries in ascending order: each bucket spans
+    /// adjacent boundaries and holds roughly the same number of values. Empty
+    /// if there are no defined values.
+    pub histogram: Vec<Value>,
+}
+
+impl Statistics {
+    /// Collects statistics from a table's rows.
+    pub fn collect(table: &Table, rows: impl Iterator<Item = Result<Row>>) -> Result<Self> {
+        let mut count = 0;
+        let mut values = vec![Vec::new(); table.columns.len()];
+        for row in rows {
+            for (column, value) in values.iter_mut().zip(row?) {
+                column.push(value);
+            }
+            count += 1;
+        }
+        let columns = values.into_iter().map(ColumnStatistics::collect).collect();
+        Ok(Self { rows: count, columns })
+    }
+}
+
+impl ColumnStatistics {
+    /// Collects statistics from a column's values.
+    fn collect(mut values: Vec<Value>) -> Self {
+        let total = values.len();
+        values.retain(|v| *v != Value::Null);
+        let nulls = (total - values.len()) as u64;
+        let distinct = values.iter().collect::<HashSet<_>>().len() as u64;
+
+        values.retain(|v| !v.is_undefined());
+        values.sort();
+        let buckets = HISTOGRAM_BUCKETS.min(values.len());
+        let histogram = match buckets {
+            0 => Vec::new(),
+            _ => (0..=buckets).map(|i| values[i * (values.len() - 1) / buckets].clone()).collect(),
+        };
+        Self { distinct, nulls, histogram }
+    }
+
+    /// Estimates the fraction of the given number of rows that match a range
+    /// of values, by interpolating histogram buckets. Buckets that partially
+    /// overlap the range are assumed to be half in it. NULLs never match.
+    pub fn range_selectivity(&self, rows: u64, range: &(Bound<Value>, Bound<Value>)) -> f64 {
+        let buckets = self.histogram.windows(2).collect::<Vec<_>>();
+        if buckets.is_empty() || rows == 0 {
+            return 0.0;
+        }
+        let after_start = |value: &Value| match &range.0 {
+            Bound::Included(start) => value >= start,
+            Bound::Excluded(start) => value > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = |value: &Value| match &range.1 {
+            Bound::Included(end) => value <= end,
+            Bound::Excluded(end) => value < end,
+            Bound::Unbounded => true,
+        };
+        let contains = |value: &Value| after_start(value) && before_end(value);
+        let matches: f64 = buckets
+            .iter()
+            .map(|bucket| match (contains(&bucket[0]), contains(&bucket[1])) {
+                (true, true) => 1.0,
+                (true, false) | (false, true) => 0.5,
+                // The range may fall entirely within the bucket.
+                (false, false) if after_start(&bucket[1]) && before_end(&bucket[0]) => 0.5,
+                (false, false) => 0.0,
+            })
+            .sum();
+        matches / buckets.len() as f64 * self.defined_fraction(rows)
+    }
+
+    /// Estimates the fraction of the given number of rows that equal a single
+    /// value. Frequent values that fill entire histogram buckets are estimated
+    /// from the histogram, other values are assumed to be uniformly distributed
+    /// across the remaining rows. If the value isn't known, all values are
+    /// assumed to be uniformly distributed. NULLs never match.
+    pub fn equal_selectivity(&self, rows: u64, value: Option<&Value>) -> f64 {
+        let defined = self.defined_fraction(rows);
+        let Some(value) = value else {
+            return match self.distinct {
+                0 => 0.0,
+                distinct => defined / distinct as f64,
+            };
+        };
+        let frequent = self.frequent_values();
+        if let Some(fraction) = frequent.get(value) {
+            return fraction * defined;
+        }
+        let remaining = 1.0 - frequent.values().sum::<f64>();
+        match self.distinct.saturating_sub(frequent.len() as u64) {
+            0 => 0.0,
+            distinct => remaining * defined / distinct as f64,
+        }
+    }
+
+    /// Returns values that fill one or more entire histogram buckets, along
+    /// with the fraction of buckets they fill.
+    fn frequent_values(&self) -> HashMap<&Value, f64> {
+        let buckets = self.histogram.len().saturating_sub(1) as f64;
+        let mut frequent = HashMap::new();
+        for bucket in self.histogram.windows(2).filter(|b| b[0] == b[1]) {
+            *frequent.entry(&bucket[0]).or_default() += 1.0 / buckets;
+        }
+        frequent
+    }
+
+    /// Returns the fraction of the given number of rows that are NULL.
+    pub fn null_selectivity(&self, rows: u64) -> f64 {
+        match rows {
+            0 => 0.0,
+            rows => self.nulls as f64 / rows as f64,
+        }
+    }
+
+    /// Returns the fraction of the given number of rows that aren't NULL.
+    fn defined_fraction(&self, rows: u64) -> f64 {
+        1.0 - self.null_selectivity(rows)
+    }
+}
@@ -5,6 +5,7 @@ use std::hash::{Hash, Hasher};
 use std::result::Result as StdResult;
 
 use dyn_clone::DynClone;
+use itertools::Itertools as _;
 use serde::{Deserialize, Serialize, Serializer};
 
 use crate::encoding;
@@ -14,7 +15,7 @@ use crate::{errdata, errinput};
 
 /// A primitive SQL data type. For simplicity, only a handful of scalar types
 /// are supported (no compound types).
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     /// A boolean: true or false.
     Boolean,
@@ -182,6 +183,75 @@ impl Value {
         }
     }
 
+    /// Casts the value to the given datatype. Errors if the value can't be
+    /// represented in the datatype. NULL is always cast to NULL.
+    ///
+    /// The conversion rules are:
+    ///
+    /// * BOOLEAN → INTEGER/FLOAT: TRUE is 1 and FALSE is 0.
+    /// * BOOLEAN → STRING: 'TRUE' or 'FALSE'.
+    /// * INTEGER → BOOLEAN: 0 is FALSE, anything else is TRUE.
+    /// * INTEGER → FLOAT: the nearest float (may lose precision).
+    /// * INTEGER → STRING: the decimal representation.
+    /// * FLOAT → BOOLEAN: errors.
+    /// * FLOAT → INTEGER: rounded to the nearest integer, errors if out of
+    ///   range or NaN.
+    /// * FLOAT → STRING: the decimal representation, or 'NaN', 'Infinity', and
+    ///   '-Infinity'.
+    /// * STRING → BOOLEAN: 'true' or 'false', ignoring case and surrounding
+    ///   whitespace.
+    /// * STRING → INTEGER/FLOAT: parses the number, ignoring surrounding
+    ///   whitespace. Floats also accept NaN and Infinity, ignoring case.
+    pub fn cast(self, datatype: DataType) -> Result<Self> {
+        use Value::*;
+
+        Ok(match (self, datatype) {
+            (Null, _) => Null,
+
+            (Boolean(b), DataType::Boolean) => Boolean(b),
+            (Boolean(b), DataType::Integer) => Integer(b as i64),
+            (Boolean(b), DataType::Float) => Float(b as i64 as f64),
+            (Boolean(true), DataType::String) => String("TRUE".to_string()),
+            (Boolean(false), DataType::String) => String("FALSE".to_string()),
+
+            (Integer(i), DataType::Boolean) => Boolean(i != 0),
+            (Integer(i), DataType::Integer) => Integer(i),
+            (Integer(i), DataType::Float) => Float(i as f64),
+            (Integer(i), DataType::String) => String(i.to_string()),
+
+            (Float(f), DataType::Float) => Float(f),
+            // i64::MAX as f64 rounds up to 2^63, which is out of range.
+            (Float(f), DataType::Integer)
+                if f.round() >= -(2f64.powi(63)) && f.round() < 2f64.powi(63) =>
+            {
+                Integer(f.round() as i64)
+            }
+            (Float(f), DataType::String) if f.is_nan() => String("NaN".to_string()),
+            (Float(f), DataType::String) if f == f64::INFINITY => String("Infinity".to_string()),
+            (Float(f), DataType::String) if f == f64::NEG_INFINITY => {
+                String("-Infinity".to_string())
+            }
+            (Float(f), DataType::String) => String(format!("{f:?}")),
+
+            (String(s), DataType::Boolean) => match s.trim().to_lowercase().as_str() {
+                "true" => Boolean(true),
+                "false" => Boolean(false),
+                _ => return errinput!("invalid boolean {}", String(s)),
+            },
+            (String(s), DataType::Integer) => match s.trim().parse() {
+                Ok(i) => Integer(i),
+                Err(_) => return errinput!("invalid integer {}", String(s)),
+            },
+            (String(s), DataType::Float) => match s.trim().parse() {
+                Ok(f) => Float(f),
+                Err(_) => return errinput!("invalid float {}", String(s)),
+            },
+            (String(s), DataType::String) => String(s),
+
+            (value, datatype) => return errinput!("can't cast {value} to {datatype}"),
+        })
+    }
+
     /// Adds two values. Errors if invalid.
     pub fn checked_add(&self, other: &Self) -> Result<Self> {
         use Value::*;
@@ -378,6 +448,15 @@ impl<'a> From<&'a Value> for Cow<'a, Value> {
 /// A row of values.
 pub type Row = Vec<Value>;
 
+/// Formats a tuple of values, e.g. a composite primary key or index key. A
+/// single value is formatted as is, multiple values are parenthesized.
+pub fn format_tuple(values: &[Value]) -> String {
+    match values {
+        [value] => value.to_string(),
+        values => format!("({})", values.iter().join(", ")),
+    }
+}
+
 /// A row iterator.
 pub type Rows = Box<dyn RowIterator>;
 
@@ -521,6 +521,26 @@ impl<E: Engine> Transaction<E> {
         self.write_version(key, Some(value))
     }
 
+    /// Atomically updates an unversioned key (see `MVCC::set_unversioned`),
+    /// by passing its current value (if any) to the given closure and writing
+    /// the returned value. Returns the new value.
+    ///
+    /// This is not transactional: the write is immediately visible to all
+    /// transactions, is not undone if the transaction rolls back, and never
+    /// conflicts with other transactions. It is therefore also allowed in
+    /// read-only transactions. Used e.g. for SQL sequences.
+    pub fn update_unversioned(
+        &self,
+        key: &[u8],
+        f: impl FnOnce(Option<Vec<u8>>) -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        let mut engine = self.engine.lock()?;
+        let key = Key::Unversioned(key.into()).encode();
+        let value = f(engine.get(&key)?)?;
+        engine.set(&key, value.clone())?;
+        Ok(value)
+    }
+
     /// Writes a new version for a key at the transaction's version. None writes
     /// a deletion tombstone. If a write conflict is found (either a newer or
     /// uncommitted version), a serialization error is returned.  Replacing our
@@ -19,7 +19,8 @@ use itertools::Itertools as _;
 use test_each_file::test_each_path;
 
 use testcluster::TestCluster;
-use toydb::{Client, StatementResult};
+use toydb::sql::types::Value;
+use toydb::{Client, PreparedStatement, StatementResult};
 
 // Run goldenscript tests in tests/scripts.
 test_each_path! { in "tests/scripts" => test_goldenscript }
@@ -40,6 +41,7 @@ fn test_goldenscript(path: &Path) {
 struct Runner {
     cluster: Option<TestCluster>,
     clients: HashMap<String, Client>,
+    prepared: HashMap<String, PreparedStatement>,
 }
 
 impl Runner {
@@ -96,6 +98,54 @@ impl goldenscript::Runner for Runner {
                 return Ok(output);
             }
 
+            // execute NAME [VALUE...]
+            //
+            // Values are parsed as NULL, booleans, integers, floats, or
+            // otherwise strings.
+            "execute" => {
+                let mut args = command.consume_args();
+                let name = &args.next_pos().ok_or("name not given")?.value;
+                let params = args
+                    .rest_pos()
+                    .into_iter()
+                    .map(|arg| match arg.value.as_str() {
+                        "NULL" => Value::Null,
+                        "TRUE" => Value::Boolean(true),
+                        "FALSE" => Value::Boolean(false),
+                        v => v
+                            .parse()
+                            .map(Value::Integer)
+                            .or_else(|_| v.parse().map(Value::Float))
+                            .unwrap_or_else(|_| Value::String(v.to_string())),
+                    })
+                    .collect_vec();
+                args.reject_rest()?;
+                let statement = self.prepared.get(name).ok_or("unknown statement")?.clone();
+                let client = self.get_client(&command.prefix)?;
+                match client.execute_prepared(&statement, &params)? {
+                    StatementResult::Select { rows, .. } => {
+                        for row in rows {
+                            writeln!(output, "{}", row.into_iter().join(", "))?;
+                        }
+                    }
+                    result if tags.remove("result") => writeln!(output, "{result:?}")?,
+                    _ => {}
+                }
+                return Ok(output);
+            }
+
+            // prepare NAME STATEMENT
+            "prepare" => {
+                let mut args = command.consume_args();
+                let name = args.next_pos().ok_or("name not given")?.value.clone();
+                let statement = &args.next_pos().ok_or("statement not given")?.value;
+                args.reject_rest()?;
+                let prepared = self.get_client(&command.prefix)?.prepare(statement)?;
+                writeln!(output, "{} parameters", prepared.parameters())?;
+                self.prepared.insert(name, prepared);
+                return Ok(output);
+            }
+
             // status
             "status" => {
                 command.consume_args().reject_rest()?;